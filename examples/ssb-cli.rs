@@ -1,9 +1,7 @@
-extern crate kuska_handshake;
 extern crate kuska_ssb;
 
 extern crate base64;
 extern crate crossbeam;
-extern crate regex;
 extern crate structopt;
 
 use std::{fmt::Debug, io::prelude::*};
@@ -13,20 +11,20 @@ use async_std::{
     net::{TcpStream, UdpSocket},
 };
 
-use kuska_handshake::async_std::{handshake_client, BoxStream};
 use kuska_ssb::{
     api::{
         dto::{CreateHistoryStreamIn, CreateStreamIn, LatestOut, WhoAmIOut},
         ApiCaller,
     },
-    discovery::ssb_net_id,
-    feed::{is_privatebox, privatebox_decipher, Feed, Message},
-    keystore::{from_patchwork_local, OwnedIdentity},
-    rpc::{RecvMsg, RequestNo, RpcReader, RpcWriter},
+    connection::{self, Connection},
+    crypto::ed25519,
+    discovery::{LanBroadcast, NetworkConfig, NetworkKey},
+    feed::{privatebox_decipher, BoxAlgo, Content, KvtMessage, Message},
+    keystore::from_patchwork_local,
+    multiserver::MultiAddress,
+    rpc::{RecvMsg, RequestNo, RpcReader},
 };
 
-use kuska_sodiumoxide::crypto::sign::ed25519;
-use regex::Regex;
 use structopt::StructOpt;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -35,9 +33,13 @@ type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 #[structopt(name = "example", about = "An example of StructOpt usage.")]
 struct Opt {
     /// Connect to server
-    // format is: server:port:<server_id>
+    // format is: server:port:<server_id> or a multiserver address
+    // net:server:port~shs:<server_id>
     #[structopt(short, long)]
     connect: Option<String>,
+    /// Network key (caps.shs) in hex, the main network by default
+    #[structopt(long)]
+    network_key: Option<String>,
 }
 
 pub fn whoami_res_parse(body: &[u8]) -> Result<WhoAmIOut> {
@@ -46,8 +48,8 @@ pub fn whoami_res_parse(body: &[u8]) -> Result<WhoAmIOut> {
 pub fn message_res_parse(body: &[u8]) -> Result<Message> {
     Ok(Message::from_slice(body)?)
 }
-pub fn feed_res_parse(body: &[u8]) -> Result<Feed> {
-    Ok(Feed::from_slice(&body)?)
+pub fn feed_res_parse(body: &[u8]) -> Result<KvtMessage> {
+    Ok(KvtMessage::from_slice(&body)?)
 }
 pub fn latest_res_parse(body: &[u8]) -> Result<LatestOut> {
     Ok(serde_json::from_slice(body)?)
@@ -103,7 +105,7 @@ async fn print_source_until_eof<'a, R, T, F>(
 where
     R: Read + Unpin,
     F: Fn(&[u8]) -> Result<T>,
-    T: Debug + serde::Deserialize<'a>,
+    T: Debug,
 {
     loop {
         let (id, msg) = rpc_reader.recv().await?;
@@ -129,43 +131,45 @@ async fn main() -> Result<()> {
     env_logger::init();
     log::set_max_level(log::LevelFilter::max());
 
-    let OwnedIdentity { pk, sk, id } = from_patchwork_local().await.expect("read local secret");
+    let identity = from_patchwork_local().await.expect("read local secret");
+    let id = identity.id.clone();
     println!("connecting with identity {}", id);
 
     let opt = Opt::from_args();
-    let (ip, port, server_pk) = if let Some(connect) = opt.connect {
-        let connect: Vec<_> = connect.split(":").collect();
-        if connect.len() != 3 {
-            panic!("connection string should be server:port:id");
-        }
-        (
-            connect[0].to_string(),
-            connect[1].to_string(),
-            connect[2].to_string(),
-        )
-    } else {
-        println!("Waiting server broadcast...");
+    let network = match &opt.network_key {
+        Some(key) => NetworkConfig::new(NetworkKey::from_hex(key)?),
+        None => NetworkConfig::main_net(),
+    };
+    let (ip, port, server_pk) =
+        if let Some(connect) = opt.connect.as_ref().filter(|c| c.contains('~')) {
+            let address: MultiAddress = connect.parse()?;
+            let (ipport, server_pk) = address.net_shs().expect("no net address with shs key");
+            let (ip, port) = ipport.rsplit_once(':').unwrap();
+            (ip.to_string(), port.to_string(), base64::encode(&server_pk))
+        } else if let Some(connect) = opt.connect {
+            let connect: Vec<_> = connect.split(":").collect();
+            if connect.len() != 3 {
+                panic!("connection string should be server:port:id");
+            }
+            (
+                connect[0].to_string(),
+                connect[1].to_string(),
+                connect[2].to_string(),
+            )
+        } else {
+            println!("Waiting server broadcast...");
 
-        let socket = UdpSocket::bind("0.0.0.0:8008").await?;
-        socket.set_broadcast(true)?;
-        let mut buf = [0; 128];
-        let (amt, _) = socket.recv_from(&mut buf).await.unwrap();
+            let socket = UdpSocket::bind("0.0.0.0:8008").await?;
+            socket.set_broadcast(true)?;
+            let mut buf = [0; 128];
+            let (amt, _) = socket.recv_from(&mut buf).await.unwrap();
 
-        let msg = String::from_utf8(buf[..amt].to_vec())?;
+            let (ip, port, server_pk) =
+                LanBroadcast::parse_packet(&buf[..amt], &network.shs).expect("bad broadcast");
 
-        println!("got broadcasted {}", msg);
-        let broadcast_regexp =
-            r"net:([0-9]+\.[0-9]+\.[0-9]+\.[0-9]+):([0-9]+)~shs:([0-9a-zA-Z\+/]+)=";
-        let captures = Regex::new(broadcast_regexp)
-            .unwrap()
-            .captures(&msg)
-            .unwrap();
-        (
-            captures[1].to_string(),
-            captures[2].to_string(),
-            captures[3].to_string(),
-        )
-    };
+            println!("got broadcasted {}:{}", ip, port);
+            (ip, port.to_string(), base64::encode(&server_pk))
+        };
 
     let server_pk =
         ed25519::PublicKey::from_slice(&base64::decode(&server_pk)?).expect("bad public key");
@@ -173,17 +177,17 @@ async fn main() -> Result<()> {
 
     println!("server_ip_port={}", server_ipport);
 
-    let mut socket = TcpStream::connect(server_ipport).await?;
+    let socket = TcpStream::connect(server_ipport).await?;
 
-    let handshake = handshake_client(&mut socket, ssb_net_id(), pk, sk.clone(), server_pk).await?;
+    let Connection {
+        reader: mut rpc_reader,
+        writer,
+        ..
+    } = connection::connect(socket, &network, &identity, server_pk).await?;
 
     println!("💃 handshake complete");
 
-    let (box_stream_read, box_stream_write) =
-        BoxStream::from_handshake(&socket, &socket, handshake, 0x8000).split_read_write();
-
-    let mut rpc_reader = RpcReader::new(box_stream_read);
-    let mut client = ApiCaller::new(RpcWriter::new(box_stream_write));
+    let mut client = ApiCaller::new(writer);
 
     let req_id = client.whoami_req_send().await?;
     let whoami = match get_async(&mut rpc_reader, req_id, whoami_res_parse).await {
@@ -261,12 +265,15 @@ async fn main() -> Result<()> {
                 let user_id = if args[1] == "me" { &whoami } else { &args[1] };
 
                 let show_private = |body: &[u8]| {
-                    let msg = feed_res_parse(body)?.into_message()?;
-                    if let serde_json::Value::String(content) = msg.content() {
-                        if is_privatebox(&content) {
-                            let ret = privatebox_decipher(&content, &sk)?.unwrap_or("".to_string());
-                            return Ok(ret);
-                        }
+                    let msg = feed_res_parse(body)?.value;
+                    if let Content::Encrypted {
+                        algo: BoxAlgo::Box,
+                        ciphertext,
+                    } = msg.parse_content()
+                    {
+                        let ret = privatebox_decipher(&ciphertext, &identity.sk)?
+                            .unwrap_or("".to_string());
+                        return Ok(ret);
                     }
                     return Ok("".to_string());
                 };
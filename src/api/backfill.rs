@@ -0,0 +1,125 @@
+use futures::io::{AsyncRead, AsyncWrite};
+
+use super::{error::Result, helper::ApiCaller};
+use crate::{
+    feed::{Error as FeedError, Message},
+    rpc::{Error as RpcError, RecvMsg, RequestNo, RpcReader},
+    uri::MsgRef,
+};
+
+/// Fetch the message `key` with `get`, then the ones before it following
+/// `previous` links, until a message for which `is_known` returns true, the
+/// first message of the feed, or `max_depth` messages. Each message is
+/// checked against the key it was requested with. `key` may be a sigil or
+/// an `ssb:message` uri, `is_known` is always given sigils.
+///
+/// Returns the fetched messages, newest first. Frames that belong to other
+/// requests are handed to `other`.
+pub async fn backfill<R, W, K, F>(
+    api: &mut ApiCaller<W>,
+    reader: &mut RpcReader<R>,
+    key: &str,
+    max_depth: usize,
+    mut is_known: K,
+    mut other: F,
+) -> Result<Vec<Message>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    K: FnMut(&str) -> bool,
+    F: FnMut(RequestNo, RecvMsg),
+{
+    let mut messages: Vec<Message> = Vec::new();
+    let mut next = Some(key.parse::<MsgRef>()?.to_string());
+    while let Some(key) = next {
+        if messages.len() >= max_depth || is_known(&key) {
+            break;
+        }
+        let req_no = api.get_req_send(&key).await?;
+        let body = loop {
+            match reader.recv().await? {
+                (id, RecvMsg::RpcResponse(_, body)) if id == req_no => break body,
+                (id, RecvMsg::ErrorResponse(message)) if id == req_no => {
+                    return Err(RpcError::Remote(message).into())
+                }
+                (id, msg) => other(id, msg),
+            }
+        };
+        let msg = Message::from_slice(&body)?;
+        if msg.compute_id()? != key {
+            return Err(FeedError::FeedDigestMismatch.into());
+        }
+        next = msg.previous().cloned();
+        messages.push(msg);
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        api::ApiMethod,
+        connection::{
+            shs::handshake_pair,
+            stream::{BoxStreamReader, BoxStreamWriter},
+        },
+        keystore::OwnedIdentity,
+        rpc::RpcWriter,
+    };
+    use async_std::task;
+
+    #[async_std::test]
+    async fn test_backfill_from_uri() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let first = Message::sign(None, &id, serde_json::json!({"type": "post", "text": "1"}))?;
+        let second = Message::sign(
+            Some(&first),
+            &id,
+            serde_json::json!({"type": "post", "text": "2"}),
+        )?;
+        let feed = vec![first.clone(), second.clone()];
+
+        let ((client, client_keys), (server, server_keys)) = handshake_pair().await.unwrap();
+        task::spawn(async move {
+            let mut reader =
+                RpcReader::new(BoxStreamReader::new(server.clone(), server_keys.recv_keys));
+            let mut api = ApiCaller::new(RpcWriter::new(BoxStreamWriter::new(
+                server,
+                server_keys.send_keys,
+            )));
+            while let Ok((req_no, RecvMsg::RpcRequest(body))) = reader.recv().await {
+                assert_eq!(body.name, ApiMethod::Get.selector());
+                let wanted = body.args[0].as_str().unwrap().to_string();
+                let msg = feed.iter().find(|msg| msg.id().to_string() == wanted);
+                api.get_res_send(req_no, msg.unwrap()).await.unwrap();
+            }
+        });
+
+        let mut reader =
+            RpcReader::new(BoxStreamReader::new(client.clone(), client_keys.recv_keys));
+        let mut api = ApiCaller::new(RpcWriter::new(BoxStreamWriter::new(
+            client,
+            client_keys.send_keys,
+        )));
+        let key = second.id().to_string();
+        let uri = key.parse::<MsgRef>()?.to_uri().to_string();
+        let mut asked = Vec::new();
+        let messages = backfill(
+            &mut api,
+            &mut reader,
+            &uri,
+            10,
+            |key| {
+                asked.push(key.to_string());
+                false
+            },
+            |_, _| {},
+        )
+        .await?;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].id().to_string(), key);
+        assert_eq!(asked[0], key);
+        Ok(())
+    }
+}
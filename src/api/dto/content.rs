@@ -35,6 +35,40 @@ impl Post {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Contact {
+    #[serde(rename = "type")]
+    pub xtype: String,
+    pub contact: SsbId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub following: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blocking: Option<bool>,
+}
+
+/// Marks the message `target`, of the same author, as deleted.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Tombstone {
+    #[serde(rename = "type")]
+    pub xtype: String,
+    pub target: SsbHash,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Tombstone {
+    pub fn new(target: SsbHash, reason: Option<String>) -> Self {
+        Tombstone {
+            xtype: String::from("tombstone"),
+            target,
+            reason,
+        }
+    }
+    pub fn to_msg(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PubAddress {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -52,10 +86,20 @@ pub enum VoteValue {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Vote {
-    link: SsbHash,
-    value: VoteValue,
+    pub link: SsbHash,
+    pub value: VoteValue,
     #[serde(skip_serializing_if = "Option::is_none")]
-    expression: Option<String>,
+    pub expression: Option<String>,
+}
+
+impl VoteValue {
+    /// Whether the vote counts as a like.
+    pub fn is_positive(&self) -> bool {
+        match self {
+            VoteValue::Numeric(value) => *value > 0,
+            VoteValue::Boolean(value) => *value,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -142,6 +186,12 @@ pub enum TypedMessage {
     Channel { channel: String, subscribed: bool },
     #[serde(rename = "vote")]
     Vote { vote: Vote },
+    #[serde(rename = "tombstone", alias = "delete")]
+    Tombstone {
+        target: SsbHash,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
 }
 
 /// An ssb-ql-1 query as defined by the 'Subset replication for SSB'
@@ -1,9 +1,13 @@
-#[derive(Debug, Serialize, Deserialize)]
+use serde_json::{json, Value};
+
+use crate::feed::Feed;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateHistoryStreamIn {
     // id (FeedID, required): The id of the feed to fetch.
     pub id: String,
 
-    /// (number, default: 0): If seq > 0, then only stream messages with sequence numbers greater than seq.
+    /// (number, default: 0): If seq > 0, then only stream messages with sequence numbers greater than or equal to seq.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub seq: Option<u64>,
 
@@ -59,4 +63,63 @@ impl CreateHistoryStreamIn {
             ..self
         }
     }
+
+    /// Whether the message with sequence `sequence` is requested.
+    pub fn selects(&self, sequence: u64) -> bool {
+        sequence >= self.seq.unwrap_or(0)
+    }
+
+    /// Whether no more messages should be sent after `sent` ones.
+    pub fn limit_reached(&self, sent: u64) -> bool {
+        match self.limit {
+            Some(limit) if limit >= 0 => sent >= limit as u64,
+            _ => false,
+        }
+    }
+
+    /// The response item for `feed`: the whole `{key, value, timestamp}`,
+    /// only the key or only the value, depending on `keys` and `values`.
+    pub fn response_item(&self, feed: &Feed) -> Value {
+        match (self.keys.unwrap_or(true), self.values.unwrap_or(true)) {
+            (true, true) => json!({
+                "key": feed.key,
+                "value": feed.value,
+                "timestamp": feed.timestamp,
+            }),
+            (true, false) => Value::String(feed.key.clone()),
+            _ => feed.value.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history_stream_response() {
+        let feed = Feed {
+            key: "%a.sha256".to_string(),
+            value: json!({"sequence": 3}),
+            timestamp: 1.0,
+            rts: None,
+        };
+
+        let args = CreateHistoryStreamIn::new("@a".to_string())
+            .after_seq(3)
+            .limit(1);
+        assert!(!args.selects(2));
+        assert!(args.selects(3));
+        assert!(!args.limit_reached(0));
+        assert!(args.limit_reached(1));
+        assert_eq!(
+            args.response_item(&feed),
+            json!({"key": "%a.sha256", "value": {"sequence": 3}, "timestamp": 1.0})
+        );
+
+        let args = args.keys_values(false, true);
+        assert_eq!(args.response_item(&feed), json!({"sequence": 3}));
+        let args = args.keys_values(true, false);
+        assert_eq!(args.response_item(&feed), json!("%a.sha256"));
+    }
 }
@@ -4,8 +4,12 @@ use thiserror::Error;
 pub enum Error {
     #[error("rpc")]
     Rpc(#[from] crate::rpc::Error),
+    #[error("feed")]
+    Feed(#[from] crate::feed::Error),
     #[error("json decode")]
     Json(#[from] serde_json::Error),
+    #[error("invalid id")]
+    Uri(#[from] crate::uri::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -3,12 +3,21 @@ use crate::{
         FriendsHops, InviteCreateOptions, RelationshipQuery, SubsetQuery, SubsetQueryOptions,
         TypedMessage,
     },
-    feed::Message,
-    rpc::{ArgType, Body, BodyType, RequestNo, RpcType, RpcWriter},
+    crypto::secretblob,
+    feed::{Feed, Message},
+    rpc::{ArgType, Body, BodyType, RequestNo, RpcErrorBody, RpcType, RpcWriter},
+    uri::{BlobRef, FeedRef, MsgRef},
+};
+use futures::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite},
+    Stream, StreamExt,
 };
-use async_std::io::Write;
 
-use super::{dto, error::Result};
+use super::{
+    dto,
+    error::Result,
+    method::{MethodName, RpcMethod},
+};
 
 const MAX_RPC_BODY_LEN: usize = 65536;
 
@@ -82,11 +91,11 @@ impl ApiMethod {
     }
 }
 
-pub struct ApiCaller<W: Write + Unpin> {
+pub struct ApiCaller<W: AsyncWrite + Unpin> {
     rpc: RpcWriter<W>,
 }
 
-impl<W: Write + Unpin> ApiCaller<W> {
+impl<W: AsyncWrite + Unpin> ApiCaller<W> {
     pub fn new(rpc: RpcWriter<W>) -> Self {
         Self { rpc }
     }
@@ -95,12 +104,50 @@ impl<W: Write + Unpin> ApiCaller<W> {
         &mut self.rpc
     }
 
+    /// Send a request for a method defined with `define_rpc_method!`.
+    pub async fn req_send<M: RpcMethod>(&mut self, args: &M::Args) -> Result<RequestNo> {
+        let req_no = self
+            .rpc
+            .send_request(M::SELECTOR, M::RPC_TYPE, M::ARG_TYPE, args, &None::<()>)
+            .await?;
+        Ok(req_no)
+    }
+
+    /// Send a request for any method, known or not by `ApiMethod`.
+    pub async fn method_req_send<T: serde::Serialize>(
+        &mut self,
+        method: &MethodName,
+        rpc_type: RpcType,
+        arg_type: ArgType,
+        args: &T,
+    ) -> Result<RequestNo> {
+        let req_no = self
+            .rpc
+            .send_request(&method.selector(), rpc_type, arg_type, args, &None::<()>)
+            .await?;
+        Ok(req_no)
+    }
+
+    /// Send an error response, terminating the request.
+    pub async fn error_res_send(
+        &mut self,
+        req_no: RequestNo,
+        rpc_type: RpcType,
+        error: &RpcErrorBody,
+    ) -> Result<()> {
+        Ok(self.rpc.send_error_body(req_no, rpc_type, error).await?)
+    }
+
     /// Send ["private", "publish"] request.
     pub async fn private_publish_req_send(
         &mut self,
         msg: TypedMessage,
         recipients: Vec<String>,
     ) -> Result<RequestNo> {
+        let recipients = recipients
+            .iter()
+            .map(|recipient| Ok(recipient.parse::<FeedRef>()?.into()))
+            .collect::<Result<Vec<String>>>()?;
         let req_no = self
             .rpc
             .send_request(
@@ -151,6 +198,7 @@ impl<W: Write + Unpin> ApiCaller<W> {
         &mut self,
         args: RelationshipQuery,
     ) -> Result<RequestNo> {
+        let args = normalize_relationship(args)?;
         let req_no = self
             .rpc
             .send_request(
@@ -169,6 +217,7 @@ impl<W: Write + Unpin> ApiCaller<W> {
         &mut self,
         args: RelationshipQuery,
     ) -> Result<RequestNo> {
+        let args = normalize_relationship(args)?;
         let req_no = self
             .rpc
             .send_request(
@@ -184,6 +233,13 @@ impl<W: Write + Unpin> ApiCaller<W> {
 
     /// Send ["friends", "hops"] request
     pub async fn friends_hops_req_send(&mut self, args: FriendsHops) -> Result<RequestNo> {
+        let args = FriendsHops {
+            start: match args.start {
+                Some(start) => Some(start.parse::<FeedRef>()?.into()),
+                None => None,
+            },
+            ..args
+        };
         let req_no = self
             .rpc
             .send_request(
@@ -282,6 +338,7 @@ impl<W: Write + Unpin> ApiCaller<W> {
 
     /// Send ["get"] request.
     pub async fn get_req_send(&mut self, msg_id: &str) -> Result<RequestNo> {
+        let msg_id = msg_id.parse::<MsgRef>()?;
         let req_no = self
             .rpc
             .send_request(
@@ -313,6 +370,10 @@ impl<W: Write + Unpin> ApiCaller<W> {
         &mut self,
         args: &dto::CreateHistoryStreamIn,
     ) -> Result<RequestNo> {
+        let args = dto::CreateHistoryStreamIn {
+            id: args.id.parse::<FeedRef>()?.into(),
+            ..args.clone()
+        };
         let req_no = self
             .rpc
             .send_request(
@@ -362,6 +423,16 @@ impl<W: Write + Unpin> ApiCaller<W> {
 
     /// Send ["blobs","get"] request.
     pub async fn blobs_get_req_send(&mut self, args: &dto::BlobsGetIn) -> Result<RequestNo> {
+        // secret blob references name the encrypted blob before `?unbox=`
+        let key = args
+            .key
+            .split(secretblob::UNBOX_PARAM)
+            .next()
+            .unwrap_or_default();
+        let args = dto::BlobsGetIn {
+            key: key.parse::<BlobRef>()?.into(),
+            ..args.clone()
+        };
         let req_no = self
             .rpc
             .send_request(
@@ -383,6 +454,40 @@ impl<W: Write + Unpin> ApiCaller<W> {
         Ok(())
     }
 
+    /// Answer a ["createHistoryStream"] request with the messages of the
+    /// feed in `feed`, sorted by sequence. Messages are filtered and shaped
+    /// according to `args`; the stream is ended unless `args.live` is set
+    /// and the limit was not reached, in which case new messages can be sent
+    /// later with `feed_res_send`.
+    pub async fn create_history_stream_res_send<S>(
+        &mut self,
+        req_no: RequestNo,
+        args: &dto::CreateHistoryStreamIn,
+        mut feed: S,
+    ) -> Result<()>
+    where
+        S: Stream<Item = Feed> + Unpin,
+    {
+        let mut sent = 0;
+        while !args.limit_reached(sent) {
+            let msg = match feed.next().await {
+                Some(msg) => msg,
+                None => break,
+            };
+            let sequence = msg.value["sequence"].as_u64().unwrap_or(0);
+            if !args.selects(sequence) {
+                continue;
+            }
+            let item = serde_json::to_string(&args.response_item(&msg))?;
+            self.feed_res_send(req_no, &item).await?;
+            sent += 1;
+        }
+        if !args.live.unwrap_or(false) || args.limit_reached(sent) {
+            self.rpc.send_stream_eof(req_no).await?;
+        }
+        Ok(())
+    }
+
     /// Send blob create wants
     pub async fn blob_create_wants_req_send(&mut self) -> Result<RequestNo> {
         let args: [&str; 0] = [];
@@ -422,4 +527,41 @@ impl<W: Write + Unpin> ApiCaller<W> {
         self.rpc.send_stream_eof(req_no).await?;
         Ok(())
     }
+
+    /// Send blob response reading it from `reader`, e.g. a file, without
+    /// loading the whole blob in memory. If reading fails the stream is
+    /// ended with an error.
+    pub async fn blobs_get_res_send_from_reader<R: AsyncRead + Unpin>(
+        &mut self,
+        req_no: RequestNo,
+        mut reader: R,
+    ) -> Result<()> {
+        let mut buffer = vec![0u8; MAX_RPC_BODY_LEN];
+        loop {
+            let len = match reader.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(err) => {
+                    let error = RpcErrorBody::new(format!("cannot read blob: {}", err));
+                    self.rpc
+                        .send_error_body(req_no, RpcType::Source, &error)
+                        .await?;
+                    return Err(crate::rpc::Error::Io(err).into());
+                }
+            };
+            self.rpc
+                .send_response(req_no, RpcType::Source, BodyType::Binary, &buffer[..len])
+                .await?;
+        }
+        self.rpc.send_stream_eof(req_no).await?;
+        Ok(())
+    }
+}
+
+/// The query with both feeds as sigils, whichever form they were given in.
+fn normalize_relationship(args: RelationshipQuery) -> Result<RelationshipQuery> {
+    Ok(RelationshipQuery {
+        source: args.source.parse::<FeedRef>()?.into(),
+        dest: args.dest.parse::<FeedRef>()?.into(),
+    })
 }
@@ -0,0 +1,216 @@
+use std::{fmt, str::FromStr};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::rpc::{ArgType, Body, RpcType};
+
+use super::{error::Result, helper::ApiMethod};
+
+/// The name of any muxrpc method, of any depth (e.g. `["plugins", "install"]`),
+/// for calling methods that are not in `ApiMethod`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodName(pub Vec<String>);
+
+impl MethodName {
+    pub fn new<S: AsRef<str>>(path: &[S]) -> Self {
+        MethodName(path.iter().map(|s| s.as_ref().to_string()).collect())
+    }
+
+    pub fn selector(&self) -> Vec<&str> {
+        self.0.iter().map(|s| s.as_str()).collect()
+    }
+
+    pub fn from_rpc_body(body: &Body) -> Self {
+        MethodName(body.name.clone())
+    }
+
+    /// The known method with this name, if any.
+    pub fn to_api_method(&self) -> Option<ApiMethod> {
+        ApiMethod::from_selector(&self.selector())
+    }
+}
+
+impl From<ApiMethod> for MethodName {
+    fn from(method: ApiMethod) -> Self {
+        MethodName::new(method.selector())
+    }
+}
+
+impl fmt::Display for MethodName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join("."))
+    }
+}
+
+/// Parses the dotted form used by js, e.g. `plugins.install`.
+impl FromStr for MethodName {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(MethodName(s.split('.').map(|s| s.to_string()).collect()))
+    }
+}
+
+/// A muxrpc method described at the type level, so methods that `ApiMethod`
+/// does not know about can be called with `ApiCaller::req_send` and their
+/// responses parsed with `parse_response`.
+///
+/// Implement it with the `define_rpc_method!` macro.
+pub trait RpcMethod {
+    type Args: Serialize;
+    type Response: DeserializeOwned;
+
+    const SELECTOR: &'static [&'static str];
+    const RPC_TYPE: RpcType;
+    const ARG_TYPE: ArgType;
+
+    /// Parse one response body (one item, for sources).
+    fn parse_response(body: &[u8]) -> Result<Self::Response> {
+        Ok(serde_json::from_slice(body)?)
+    }
+
+    /// Whether an incoming request is a call to this method.
+    fn matches(body: &Body) -> bool {
+        body.name
+            .iter()
+            .map(|v| v.as_str())
+            .eq(Self::SELECTOR.iter().copied())
+    }
+}
+
+/// Define a type implementing `RpcMethod`, with a `req_send` function to
+/// call the method and a conversion into `MethodName` to register it in a
+/// `Router`. Methods defined this way are not `ApiMethod` variants.
+///
+/// ```ignore
+/// define_rpc_method! {
+///     /// ["plugins", "install"]
+///     pub PluginsInstall, ["plugins", "install"], Async, Array, String => String
+/// }
+/// let req_no = PluginsInstall::req_send(&mut api, &"ssb-foo".to_string()).await?;
+/// router.register(PluginsInstall, handler);
+/// ```
+#[macro_export]
+macro_rules! define_rpc_method {
+    (
+        $(#[$meta:meta])*
+        $vis:vis $name:ident,
+        [$($selector:literal),+ $(,)?],
+        $rpc_type:ident,
+        $arg_type:ident,
+        $args:ty => $response:ty $(,)?
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        $vis struct $name;
+
+        impl $crate::api::RpcMethod for $name {
+            type Args = $args;
+            type Response = $response;
+
+            const SELECTOR: &'static [&'static str] = &[$($selector),+];
+            const RPC_TYPE: $crate::rpc::RpcType = $crate::rpc::RpcType::$rpc_type;
+            const ARG_TYPE: $crate::rpc::ArgType = $crate::rpc::ArgType::$arg_type;
+        }
+
+        impl $name {
+            /// Send a request for this method.
+            #[allow(dead_code)]
+            pub async fn req_send<W: $crate::__private::AsyncWrite + Unpin>(
+                api: &mut $crate::api::ApiCaller<W>,
+                args: &$args,
+            ) -> $crate::api::Result<$crate::rpc::RequestNo> {
+                api.req_send::<Self>(args).await
+            }
+        }
+
+        impl From<$name> for $crate::api::MethodName {
+            fn from(_: $name) -> Self {
+                $crate::api::MethodName::new(<$name as $crate::api::RpcMethod>::SELECTOR)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::dto::LatestOut;
+
+    define_rpc_method! {
+        /// ["plugins", "install"]
+        PluginsInstall, ["plugins", "install"], Async, Array, String => bool
+    }
+
+    define_rpc_method! {
+        Latest, ["latest"], Source, Array, () => LatestOut
+    }
+
+    #[test]
+    fn test_method_name() {
+        let name: MethodName = "plugins.install".parse().unwrap();
+        assert_eq!(name, MethodName::new(&["plugins", "install"]));
+        assert_eq!(name.to_string(), "plugins.install");
+        assert_eq!(name.to_api_method().is_none(), true);
+
+        let name = MethodName::from(ApiMethod::BlobsGet);
+        assert_eq!(name.selector(), vec!["blobs", "get"]);
+        assert!(matches!(name.to_api_method(), Some(ApiMethod::BlobsGet)));
+    }
+
+    #[test]
+    fn test_defined_method() -> Result<()> {
+        assert_eq!(PluginsInstall::SELECTOR, &["plugins", "install"]);
+        assert_eq!(PluginsInstall::RPC_TYPE, RpcType::Async);
+        assert_eq!(PluginsInstall::parse_response(b"true")?, true);
+
+        let body: Body = serde_json::from_str(
+            r#"{"name":["plugins","install"],"type":"async","args":["ssb-foo"]}"#,
+        )?;
+        assert!(PluginsInstall::matches(&body));
+        assert!(!Latest::matches(&body));
+
+        let latest = Latest::parse_response(br#"{"id":"@a","sequence":2,"ts":1.0}"#)?;
+        assert_eq!(latest.sequence, 2);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_defined_method_req_send() -> Result<()> {
+        use crate::{
+            api::{ApiCaller, Router},
+            connection::{
+                boxstream::BoxStreamKeys,
+                stream::{BoxStreamReader, BoxStreamWriter},
+            },
+            crypto::backend::secretbox,
+            rpc::{RecvMsg, RpcReader, RpcWriter},
+        };
+        use async_std::net::{TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (receiver, _) = listener.accept().await.unwrap();
+        let keys = BoxStreamKeys {
+            key: secretbox::gen_key(),
+            nonce: secretbox::gen_nonce(),
+        };
+        let mut api = ApiCaller::new(RpcWriter::new(BoxStreamWriter::new(sender, keys.clone())));
+        let mut reader = RpcReader::new(BoxStreamReader::new(receiver, keys));
+
+        let req_no = PluginsInstall::req_send(&mut api, &"ssb-foo".to_string()).await?;
+        let mut router = Router::new();
+        router.register(PluginsInstall, ());
+        match reader.recv().await? {
+            (received, RecvMsg::RpcRequest(body)) => {
+                assert_eq!(received, req_no);
+                assert!(PluginsInstall::matches(&body));
+                assert!(router.route(&body).is_some());
+            }
+            other => panic!("{:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,12 @@
+mod backfill;
 pub mod dto;
 mod error;
 mod helper;
+mod method;
+mod router;
 
+pub use backfill::backfill;
 pub use error::{Error, Result};
 pub use helper::{ApiCaller, ApiMethod};
+pub use method::{MethodName, RpcMethod};
+pub use router::{no_such_method, Router};
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use futures::io::AsyncWrite;
+
+use crate::rpc::{Body, RequestNo, RpcErrorBody};
+
+use super::{error::Result, helper::ApiCaller, method::MethodName};
+
+/// Maps the methods served to the remote peer to their handlers.
+///
+/// `H` is whatever the application dispatches on, e.g. an enum of the
+/// supported operations or a boxed closure.
+pub struct Router<H> {
+    handlers: HashMap<MethodName, H>,
+}
+
+impl<H> Default for Router<H> {
+    fn default() -> Self {
+        Router {
+            handlers: HashMap::new(),
+        }
+    }
+}
+
+impl<H> Router<H> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<M: Into<MethodName>>(&mut self, method: M, handler: H) {
+        self.handlers.insert(method.into(), handler);
+    }
+
+    pub fn is_registered(&self, method: &MethodName) -> bool {
+        self.handlers.contains_key(method)
+    }
+
+    /// The handler for an incoming request, if any.
+    pub fn route(&self, body: &Body) -> Option<&H> {
+        self.handlers.get(&MethodName::from_rpc_body(body))
+    }
+
+    /// Like `route`, but requests to unknown methods are answered with
+    /// the same error js muxrpc sends, terminating them as a stream or an
+    /// async response according to the type of the request.
+    pub async fn route_or_reject<W: AsyncWrite + Unpin>(
+        &self,
+        api: &mut ApiCaller<W>,
+        req_no: RequestNo,
+        body: &Body,
+    ) -> Result<Option<&H>> {
+        match self.route(body) {
+            Some(handler) => Ok(Some(handler)),
+            None => {
+                let error = no_such_method(&MethodName::from_rpc_body(body));
+                api.error_res_send(req_no, body.rpc_type, &error).await?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// The error body for a call to a method that is not served.
+pub fn no_such_method(method: &MethodName) -> RpcErrorBody {
+    RpcErrorBody::new(format!(
+        "method:{} is not in list of allowed methods",
+        method
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::ApiMethod;
+
+    #[test]
+    fn test_route() -> Result<()> {
+        let mut router = Router::new();
+        router.register(ApiMethod::WhoAmI, 1);
+        router.register(MethodName::new(&["plugins", "install"]), 2);
+
+        let body: Body = serde_json::from_str(r#"{"name":["whoami"],"type":"async","args":[]}"#)?;
+        assert_eq!(router.route(&body), Some(&1));
+        let body: Body =
+            serde_json::from_str(r#"{"name":["ebt","replicate"],"type":"duplex","args":[]}"#)?;
+        assert_eq!(router.route(&body), None);
+
+        let error = no_such_method(&MethodName::from_rpc_body(&body));
+        assert_eq!(
+            error.message,
+            "method:ebt.replicate is not in list of allowed methods"
+        );
+        Ok(())
+    }
+}
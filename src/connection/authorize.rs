@@ -0,0 +1,83 @@
+//! What a handshake server asks before accepting a client: whether its
+//! public key may connect, e.g. to turn away blocked peers.
+
+use std::{collections::HashSet, future::Future};
+
+use futures::future::{self, BoxFuture, FutureExt};
+
+use crate::crypto::backend::sign::ed25519;
+use crate::crypto::ToSsbId;
+
+pub trait Authorizer: Send + Sync {
+    /// Whether the client with `peer` as public key may proceed.
+    fn authorize<'a>(&'a self, peer: &'a ed25519::PublicKey) -> BoxFuture<'a, bool>;
+}
+
+fn feed_id(peer: &ed25519::PublicKey) -> String {
+    format!("@{}", peer.to_ssb_id())
+}
+
+/// Accepts every client.
+pub struct AllowAll;
+
+impl Authorizer for AllowAll {
+    fn authorize<'a>(&'a self, _peer: &'a ed25519::PublicKey) -> BoxFuture<'a, bool> {
+        future::ready(true).boxed()
+    }
+}
+
+/// Accepts only the clients with the given `@...ed25519` ids.
+#[derive(Debug, Clone, Default)]
+pub struct AllowList(pub HashSet<String>);
+
+impl Authorizer for AllowList {
+    fn authorize<'a>(&'a self, peer: &'a ed25519::PublicKey) -> BoxFuture<'a, bool> {
+        future::ready(self.0.contains(&feed_id(peer))).boxed()
+    }
+}
+
+/// Accepts every client but the ones with the given `@...ed25519` ids.
+#[derive(Debug, Clone, Default)]
+pub struct BlockList(pub HashSet<String>);
+
+impl Authorizer for BlockList {
+    fn authorize<'a>(&'a self, peer: &'a ed25519::PublicKey) -> BoxFuture<'a, bool> {
+        future::ready(!self.0.contains(&feed_id(peer))).boxed()
+    }
+}
+
+/// An async callback given the `@...ed25519` id of the client.
+impl<F, Fut> Authorizer for F
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = bool> + Send + 'static,
+{
+    fn authorize<'a>(&'a self, peer: &'a ed25519::PublicKey) -> BoxFuture<'a, bool> {
+        self(feed_id(peer)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keystore::OwnedIdentity;
+
+    #[async_std::test]
+    async fn test_authorizers() {
+        let (friend, blocked) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let list: HashSet<String> = [blocked.id.clone()].into_iter().collect();
+
+        assert!(AllowAll.authorize(&blocked.pk).await);
+        assert!(AllowList(list.clone()).authorize(&blocked.pk).await);
+        assert!(!AllowList(list.clone()).authorize(&friend.pk).await);
+        assert!(!BlockList(list.clone()).authorize(&blocked.pk).await);
+        assert!(BlockList(list.clone()).authorize(&friend.pk).await);
+
+        let callback = move |peer: String| {
+            let blocked = list.contains(&peer);
+            async move { !blocked }
+        };
+        assert!(!callback.authorize(&blocked.pk).await);
+        assert!(callback.authorize(&friend.pk).await);
+    }
+}
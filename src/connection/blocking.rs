@@ -0,0 +1,137 @@
+//! Blocking `connect` and `accept` over std tcp streams, for tools and
+//! programs without an async runtime. The handshake and the box stream run
+//! on the calling thread; the box stream halves are std `Read` and `Write`.
+//! Reads fail once the idle timeout of the network passes; keepalives are
+//! left to the caller, with no task around to send them.
+
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream as StdTcpStream,
+};
+
+use async_std::{net::TcpStream, task};
+use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::{
+    client_handshake,
+    error::Result,
+    keepalive, server_handshake,
+    shs::HandshakeOutcome,
+    stream::{BoxStreamReader, BoxStreamWriter},
+    Authorizer,
+};
+use crate::crypto::backend::sign::ed25519;
+use crate::{crypto::ToSsbId, discovery::NetworkConfig, keystore::OwnedIdentity};
+
+/// Decrypting half of a box stream.
+pub struct BlockingReader(BoxStreamReader<TcpStream>);
+
+/// Encrypting half of a box stream.
+pub struct BlockingWriter(BoxStreamWriter<TcpStream>);
+
+impl Read for BlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        task::block_on(self.0.read(buf))
+    }
+}
+
+impl Write for BlockingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        task::block_on(self.0.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        task::block_on(self.0.flush())
+    }
+}
+
+/// An authenticated connection with a blocking box stream.
+pub struct BlockingConnection {
+    /// `@...ed25519` id of the peer.
+    pub peer_id: String,
+    pub reader: BlockingReader,
+    pub writer: BlockingWriter,
+}
+
+impl BlockingConnection {
+    fn new(stream: TcpStream, handshake: HandshakeOutcome, network: &NetworkConfig) -> Self {
+        let writer = BoxStreamWriter::new(stream.clone(), handshake.send_keys)
+            .with_segment_len(network.max_segment_len());
+        let mut reader = BoxStreamReader::new(stream, handshake.recv_keys);
+        if let Some(timeout) = network.idle_timeout {
+            reader.set_idle_timeout(timeout);
+        }
+        BlockingConnection {
+            peer_id: format!("@{}", handshake.peer_pk.to_ssb_id()),
+            reader: BlockingReader(reader),
+            writer: BlockingWriter(writer),
+        }
+    }
+
+    /// The owned halves of the connection, to be moved into separate
+    /// threads.
+    pub fn into_split(self) -> (BlockingReader, BlockingWriter) {
+        (self.reader, self.writer)
+    }
+}
+
+/// Like `connection::connect`, blocking on a std tcp stream.
+pub fn connect(
+    stream: StdTcpStream,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    server_pk: ed25519::PublicKey,
+) -> Result<BlockingConnection> {
+    keepalive::configure(&stream, network)?;
+    let mut stream = TcpStream::from(stream);
+    let handshake = task::block_on(client_handshake(&mut stream, network, identity, server_pk))?;
+    Ok(BlockingConnection::new(stream, handshake, network))
+}
+
+/// Like `connection::accept`, blocking on a std tcp stream.
+pub fn accept(
+    stream: StdTcpStream,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    authorizer: &dyn Authorizer,
+) -> Result<BlockingConnection> {
+    keepalive::configure(&stream, network)?;
+    let mut stream = TcpStream::from(stream);
+    let handshake = task::block_on(server_handshake(&mut stream, network, identity, authorizer))?;
+    Ok(BlockingConnection::new(stream, handshake, network))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::connection::AllowAll;
+    use std::{net::TcpListener, thread, time::Duration};
+
+    #[test]
+    fn test_blocking_connection() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let network = NetworkConfig::main_net().tcp_keepalive(Duration::from_secs(60));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server_pk = server.pk;
+        let accepting = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut conn = accept(stream, &NetworkConfig::main_net(), &server, &AllowAll)?;
+            let mut hello = [0u8; 5];
+            conn.reader.read_exact(&mut hello).unwrap();
+            Ok::<_, crate::connection::Error>(hello)
+        });
+
+        let mut conn = connect(
+            StdTcpStream::connect(addr).unwrap(),
+            &network,
+            &client,
+            server_pk,
+        )?;
+        conn.writer.write_all(b"hello").unwrap();
+        conn.writer.flush().unwrap();
+        assert_eq!(&accepting.join().unwrap()?, b"hello");
+        Ok(())
+    }
+}
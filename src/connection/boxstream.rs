@@ -0,0 +1,439 @@
+//! Box stream framing, the encrypted transport set up by the handshake:
+//! the stream is cut in segments of up to `MAX_SEGMENT_LEN` bytes, each
+//! sent as a boxed header (body length and mac) followed by the boxed
+//! body. A header of zeros, the goodbye, ends the stream; a stream that
+//! ends without it was cut by someone else than the peer.
+//!
+//! Nothing here does io: `Sealer` gives the bytes to send and `Decoder`
+//! takes received bytes as they come, so the box stream can run over any
+//! transport or event loop. `connection::stream` runs it over async
+//! streams.
+
+use zeroize::{Zeroize, Zeroizing};
+
+use super::error::{Error, Result};
+use crate::crypto::backend::{
+    auth,
+    hash::sha256,
+    secretbox::{self, Tag},
+};
+use crate::discovery::NetworkKey;
+
+pub const MAX_SEGMENT_LEN: usize = 4096;
+/// Length of the boxed header preceding each body.
+pub const HEADER_LEN: usize = HEADER_PLAIN_LEN + secretbox::MACBYTES;
+const HEADER_PLAIN_LEN: usize = 2 + secretbox::MACBYTES;
+const GOODBYE: [u8; HEADER_PLAIN_LEN] = [0u8; HEADER_PLAIN_LEN];
+
+/// Key and starting nonce of one direction of a box stream. The key is
+/// zeroized on drop.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoxStreamKeys {
+    pub key: secretbox::Key,
+    pub nonce: secretbox::Nonce,
+}
+
+impl BoxStreamKeys {
+    /// The keys to send to `peer_pk`, whose ephemeral key is
+    /// `peer_ephemeral_pk`, from the three shared secrets of the handshake.
+    pub fn derive(
+        network_key: &NetworkKey,
+        secrets: [&[u8]; 3],
+        peer_pk: &[u8],
+        peer_ephemeral_pk: &[u8],
+    ) -> Self {
+        let shared =
+            Zeroizing::new([network_key.as_bytes(), secrets[0], secrets[1], secrets[2]].concat());
+        let shared = Zeroizing::new(sha256::hash(&shared).0);
+        let shared = Zeroizing::new(sha256::hash(&*shared).0);
+        let mut nonce = [0u8; secretbox::NONCEBYTES];
+        let mac = auth::authenticate(peer_ephemeral_pk, &network_key.key());
+        nonce.copy_from_slice(&mac.0[..secretbox::NONCEBYTES]);
+        BoxStreamKeys {
+            key: secretbox::Key(sha256::hash(&Zeroizing::new([&shared[..], peer_pk].concat())).0),
+            nonce: secretbox::Nonce(nonce),
+        }
+    }
+
+    /// The nonce to use now, moving on to the next one.
+    fn next_nonce(&mut self) -> secretbox::Nonce {
+        let nonce = self.nonce;
+        for byte in self.nonce.0.iter_mut().rev() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+        nonce
+    }
+}
+
+impl Drop for BoxStreamKeys {
+    fn drop(&mut self) {
+        self.key.0.zeroize();
+    }
+}
+
+/// Encrypts the outgoing side of a box stream.
+pub struct Sealer {
+    keys: BoxStreamKeys,
+    segment_len: usize,
+}
+
+impl Sealer {
+    pub fn new(keys: BoxStreamKeys) -> Self {
+        Sealer {
+            keys,
+            segment_len: MAX_SEGMENT_LEN,
+        }
+    }
+
+    /// Cuts the stream in segments of up to `len` bytes, for links where
+    /// `MAX_SEGMENT_LEN`, also the upper bound, is too much.
+    pub fn with_segment_len(self, len: usize) -> Self {
+        Self {
+            segment_len: len.clamp(1, MAX_SEGMENT_LEN),
+            ..self
+        }
+    }
+
+    pub fn segment_len(&self) -> usize {
+        self.segment_len
+    }
+
+    /// The boxed headers and bodies of `data`, in as many segments as
+    /// needed.
+    pub fn seal(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut sealed = Vec::new();
+        self.seal_into(data, &mut sealed);
+        sealed
+    }
+
+    /// Like `seal`, appending to `out`. Each header is followed by its body
+    /// in the same buffer, so a segment goes out in a single write, and
+    /// bodies are encrypted in place.
+    pub fn seal_into(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let segments = data.len().div_ceil(self.segment_len);
+        out.reserve(data.len() + HEADER_LEN * segments);
+        for segment in data.chunks(self.segment_len) {
+            self.seal_segment(segment, out);
+        }
+    }
+
+    fn seal_segment(&mut self, segment: &[u8], out: &mut Vec<u8>) {
+        let header_nonce = self.keys.next_nonce();
+        let body_nonce = self.keys.next_nonce();
+
+        let header_at = out.len();
+        let body_at = header_at + HEADER_LEN;
+        out.resize(body_at, 0);
+        out.extend_from_slice(segment);
+        let tag = secretbox::seal_detached(&mut out[body_at..], &body_nonce, &self.keys.key);
+
+        let mut header = [0u8; HEADER_PLAIN_LEN];
+        header[..2].copy_from_slice(&(segment.len() as u16).to_be_bytes());
+        header[2..].copy_from_slice(&tag.0);
+        let header_tag = secretbox::seal_detached(&mut header, &header_nonce, &self.keys.key);
+        out[header_at..header_at + secretbox::MACBYTES].copy_from_slice(&header_tag.0);
+        out[header_at + secretbox::MACBYTES..body_at].copy_from_slice(&header);
+    }
+
+    /// An empty segment, which peers skip, to keep a quiet link busy.
+    pub fn keepalive(&mut self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN);
+        self.seal_segment(&[], &mut out);
+        out
+    }
+
+    /// The goodbye, the last thing to send.
+    pub fn goodbye(&mut self) -> Vec<u8> {
+        let nonce = self.keys.next_nonce();
+        secretbox::seal(&GOODBYE, &nonce, &self.keys.key)
+    }
+}
+
+/// What a boxed header announces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Header {
+    /// A body of `len` bytes follows.
+    Segment { len: usize, tag: Tag },
+    /// The peer ended the stream.
+    Goodbye,
+}
+
+/// Decrypts the incoming side of a box stream.
+pub struct Opener {
+    keys: BoxStreamKeys,
+    goodbye: bool,
+}
+
+impl Opener {
+    pub fn new(keys: BoxStreamKeys) -> Self {
+        Opener {
+            keys,
+            goodbye: false,
+        }
+    }
+
+    /// Opens the next header, of `HEADER_LEN` bytes.
+    pub fn open_header(&mut self, header: &[u8]) -> Result<Header> {
+        if self.goodbye {
+            return Err(Error::BoxStream);
+        }
+        let nonce = self.keys.next_nonce();
+        let header =
+            secretbox::open(header, &nonce, &self.keys.key).map_err(|_| Error::BoxStream)?;
+        if header[..] == GOODBYE[..] {
+            self.goodbye = true;
+            return Ok(Header::Goodbye);
+        }
+        let len = u16::from_be_bytes([header[0], header[1]]) as usize;
+        if len > MAX_SEGMENT_LEN {
+            return Err(Error::BoxStream);
+        }
+        let tag = Tag::from_slice(&header[2..]).ok_or(Error::BoxStream)?;
+        Ok(Header::Segment { len, tag })
+    }
+
+    /// Opens the body announced by the `tag` of the last header.
+    pub fn open_body(&mut self, tag: &Tag, body: &[u8]) -> Result<Vec<u8>> {
+        let mut body = body.to_vec();
+        self.open_body_in_place(tag, &mut body)?;
+        Ok(body)
+    }
+
+    /// Like `open_body`, but decrypts `body` where it is.
+    pub fn open_body_in_place(&mut self, tag: &Tag, body: &mut [u8]) -> Result<()> {
+        let nonce = self.keys.next_nonce();
+        secretbox::open_detached(body, tag, &nonce, &self.keys.key).map_err(|_| Error::BoxStream)
+    }
+
+    /// Whether the peer said goodbye.
+    pub fn is_closed(&self) -> bool {
+        self.goodbye
+    }
+
+    /// Checks, once the underlying stream has ended, that it was ended by
+    /// the peer: fails with `Error::Truncated` if no goodbye was received.
+    pub fn finish(&self) -> Result<()> {
+        if self.goodbye {
+            Ok(())
+        } else {
+            Err(Error::Truncated)
+        }
+    }
+}
+
+/// What a `Decoder` got from the bytes fed to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decoded {
+    Segment(Vec<u8>),
+    Goodbye,
+}
+
+/// The incoming side of a box stream, fed with bytes as they are received.
+pub struct Decoder {
+    opener: Opener,
+    buffer: Vec<u8>,
+    /// Length and tag of the body whose header was opened.
+    pending: Option<(usize, Tag)>,
+}
+
+impl Decoder {
+    pub fn new(keys: BoxStreamKeys) -> Self {
+        Decoder {
+            opener: Opener::new(keys),
+            buffer: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Takes `ciphertext`, returning the segments, and the goodbye, it
+    /// completes. Incomplete headers and bodies wait for more bytes.
+    pub fn feed(&mut self, ciphertext: &[u8]) -> Result<Vec<Decoded>> {
+        let mut decoded = Vec::new();
+        let goodbye = self.decode(ciphertext, |opener, tag, body| {
+            let body = opener.open_body(tag, body)?;
+            if !body.is_empty() {
+                decoded.push(Decoded::Segment(body));
+            }
+            Ok(())
+        })?;
+        if goodbye {
+            decoded.push(Decoded::Goodbye);
+        }
+        Ok(decoded)
+    }
+
+    /// Like `feed`, but appends the plaintext of the completed segments to
+    /// `out`, decrypting it there, and returns how many bytes were added.
+    pub fn feed_into(&mut self, ciphertext: &[u8], out: &mut Vec<u8>) -> Result<usize> {
+        let start = out.len();
+        let result = self.decode(ciphertext, |opener, tag, body| {
+            let offset = out.len();
+            out.extend_from_slice(body);
+            opener.open_body_in_place(tag, &mut out[offset..])
+        });
+        if let Err(err) = result {
+            out.truncate(start);
+            return Err(err);
+        }
+        Ok(out.len() - start)
+    }
+
+    /// Buffers `ciphertext` and hands every complete body to `segment`,
+    /// returning whether the goodbye came in.
+    fn decode<F>(&mut self, ciphertext: &[u8], mut segment: F) -> Result<bool>
+    where
+        F: FnMut(&mut Opener, &Tag, &[u8]) -> Result<()>,
+    {
+        self.buffer.extend_from_slice(ciphertext);
+        let mut goodbye = false;
+        let mut consumed = 0;
+        loop {
+            let available = &self.buffer[consumed..];
+            match self.pending {
+                None if available.len() >= HEADER_LEN && !self.opener.is_closed() => {
+                    match self.opener.open_header(&available[..HEADER_LEN])? {
+                        Header::Segment { len, tag } => self.pending = Some((len, tag)),
+                        Header::Goodbye => goodbye = true,
+                    }
+                    consumed += HEADER_LEN;
+                }
+                Some((len, tag)) if available.len() >= len => {
+                    segment(&mut self.opener, &tag, &available[..len])?;
+                    self.pending = None;
+                    consumed += len;
+                }
+                _ => break,
+            }
+        }
+        self.buffer.drain(..consumed);
+        if self.opener.is_closed() && !self.buffer.is_empty() {
+            return Err(Error::BoxStream);
+        }
+        Ok(goodbye)
+    }
+
+    /// Whether the goodbye was received.
+    pub fn is_closed(&self) -> bool {
+        self.opener.is_closed()
+    }
+
+    /// Checks, once the transport has ended, that the peer said goodbye.
+    pub fn finish(&self) -> Result<()> {
+        self.opener.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        connection::shs::{handshake_pair, transcript, EphemeralKeys},
+        keystore::OwnedIdentity,
+    };
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn open_all(opener: &mut Opener, mut sealed: &[u8]) -> Result<Vec<u8>> {
+        let mut opened = Vec::new();
+        while !sealed.is_empty() {
+            let (header, rest) = sealed.split_at(HEADER_LEN);
+            match opener.open_header(header)? {
+                Header::Segment { len, tag } => {
+                    opened.extend(opener.open_body(&tag, &rest[..len])?);
+                    sealed = &rest[len..];
+                }
+                Header::Goodbye => sealed = rest,
+            }
+        }
+        Ok(opened)
+    }
+
+    #[test]
+    fn test_box_stream_goodbye() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let messages = transcript(
+            &NetworkKey::default(),
+            &client,
+            &EphemeralKeys::random(),
+            &server,
+            &EphemeralKeys::random(),
+        )?;
+        let mut sealer = Sealer::new(messages.client_keys.clone());
+        let data: Vec<u8> = (0..5000u32).map(|n| n as u8).collect();
+        let mut sealed = sealer.seal(&data[..100]);
+        sealer.seal_into(&data[100..], &mut sealed);
+        assert_eq!(sealed.len(), data.len() + 3 * HEADER_LEN);
+
+        let mut opener = Opener::new(messages.client_keys.clone());
+        assert_eq!(open_all(&mut opener, &sealed)?, data);
+        assert!(matches!(opener.finish(), Err(Error::Truncated)));
+
+        sealed.extend(sealer.keepalive());
+        sealed.extend(sealer.goodbye());
+        let mut opener = Opener::new(messages.client_keys.clone());
+        assert_eq!(open_all(&mut opener, &sealed)?, data);
+        assert!(opener.is_closed());
+        opener.finish()?;
+
+        // the same bytes, fed one at a time
+        let mut decoder = Decoder::new(messages.client_keys.clone());
+        let mut decoded = Vec::new();
+        for byte in &sealed {
+            decoded.extend(decoder.feed(&[*byte])?);
+        }
+        assert_eq!(decoded.len(), 4);
+        assert_eq!(decoded[0], Decoded::Segment(data[..100].to_vec()));
+        assert_eq!(decoded[3], Decoded::Goodbye);
+        decoder.finish()?;
+        assert!(decoder.feed(&[0]).is_err());
+
+        // and decrypted into a reused buffer
+        let mut decoder = Decoder::new(messages.client_keys.clone());
+        let mut plaintext = Vec::with_capacity(data.len());
+        for chunk in sealed.chunks(1000) {
+            decoder.feed_into(chunk, &mut plaintext)?;
+        }
+        assert_eq!(plaintext, data);
+        decoder.finish()?;
+
+        // smaller segments for a constrained link
+        let mut sealer = Sealer::new(messages.server_keys.clone()).with_segment_len(1000);
+        let small = sealer.seal(&data);
+        assert_eq!(small.len(), data.len() + 5 * HEADER_LEN);
+        let mut opener = Opener::new(messages.server_keys.clone());
+        assert_eq!(open_all(&mut opener, &small)?, data);
+
+        // the server keys cannot open what the client sent
+        let mut opener = Opener::new(messages.server_keys);
+        assert!(open_all(&mut opener, &sealed).is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_codec_over_handshake() -> Result<()> {
+        let ((mut client, client_outcome), (mut server, server_outcome)) = handshake_pair().await?;
+        let data: Vec<u8> = (0..10000u32).map(|n| n as u8).collect();
+
+        let mut sealer = Sealer::new(client_outcome.send_keys);
+        let mut sealed = sealer.seal(&data);
+        sealed.extend(sealer.goodbye());
+        client.write_all(&sealed).await?;
+
+        let mut decoder = Decoder::new(server_outcome.recv_keys);
+        let mut received = Vec::new();
+        let mut chunk = [0u8; 1500];
+        while !decoder.is_closed() {
+            let read = server.read(&mut chunk).await?;
+            assert!(read > 0);
+            for decoded in decoder.feed(&chunk[..read])? {
+                if let Decoded::Segment(segment) = decoded {
+                    received.extend(segment);
+                }
+            }
+        }
+        assert_eq!(received, data);
+        decoder.finish()
+    }
+}
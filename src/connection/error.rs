@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("handshake failed: {0}")]
+    Handshake(String),
+    #[error("peer {0} is not authorized")]
+    Unauthorized(String),
+    #[error("box stream cannot be decrypted")]
+    BoxStream,
+    #[error("box stream ended without goodbye")]
+    Truncated,
+    #[error("i/o")]
+    Io(#[from] std::io::Error),
+    #[error("invalid crypto format")]
+    CryptoFormat(#[from] crate::crypto::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
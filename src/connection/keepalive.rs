@@ -0,0 +1,185 @@
+//! Keeping connections alive, and dropping the dead ones.
+//!
+//! `set_tcp_keepalive` has the os probe a quiet peer. Box stream readers
+//! given an idle timeout, as `connect` and `accept` do with the one of
+//! `NetworkConfig`, fail reads with `io::ErrorKind::TimedOut` once nothing
+//! came in for a while, and resolve their `IdleSignal` so whoever manages
+//! the connection drops it. `IdleTimeout` does the same for any stream.
+//! Peers that send nothing for long keep the link busy with empty
+//! segments, see `SharedRpcWriter::spawn_keepalive`; the readers of this
+//! crate skip them, but kuska-handshake readers take them for the end of
+//! the stream, so keepalives are only sent when configured.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+#[cfg(unix)]
+use std::os::fd::AsFd as AsSocket;
+#[cfg(windows)]
+use std::os::windows::io::AsSocket;
+
+use async_std::stream::{interval, Interval};
+use futures::{
+    channel::oneshot,
+    future::Future,
+    io::{AsyncRead, AsyncWrite},
+    Stream,
+};
+use socket2::{SockRef, TcpKeepalive};
+
+use crate::discovery::NetworkConfig;
+
+/// Has the os start probing the peer of `stream` after `time` without
+/// traffic.
+pub fn set_tcp_keepalive<S: AsSocket>(stream: &S, time: Duration) -> io::Result<()> {
+    SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))
+}
+
+/// Sets the tcp keepalive of `network`, if any, on `stream`.
+pub(crate) fn configure<S: AsSocket>(stream: &S, network: &NetworkConfig) -> io::Result<()> {
+    match network.tcp_keepalive {
+        Some(time) => set_tcp_keepalive(stream, time),
+        None => Ok(()),
+    }
+}
+
+/// Resolves once a connection went idle for longer than its idle timeout,
+/// so whoever manages connections can drop it, e.g. telling the
+/// replication `Scheduler` with `peer_disconnected`. Resolves to `false`
+/// if the reader was dropped before.
+pub struct IdleSignal(oneshot::Receiver<()>);
+
+impl Future for IdleSignal {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        Pin::new(&mut self.0).poll(cx).map(|idle| idle.is_ok())
+    }
+}
+
+/// Fails reads after `timeout` without incoming bytes.
+pub(crate) struct IdleTimer {
+    timeout: Duration,
+    last_read: Instant,
+    /// Wakes pending reads to check for the timeout.
+    ticks: Interval,
+    signal: Option<oneshot::Sender<()>>,
+}
+
+impl IdleTimer {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        IdleTimer {
+            timeout,
+            last_read: Instant::now(),
+            ticks: interval(timeout / 4),
+            signal: None,
+        }
+    }
+
+    /// A signal resolved when reads time out.
+    pub(crate) fn signal(&mut self) -> IdleSignal {
+        let (sender, receiver) = oneshot::channel();
+        self.signal = Some(sender);
+        IdleSignal(receiver)
+    }
+
+    pub(crate) fn poll_read<S: AsyncRead + Unpin>(
+        &mut self,
+        inner: &mut S,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Poll::Ready(read) = Pin::new(inner).poll_read(cx, buf) {
+            self.last_read = Instant::now();
+            return Poll::Ready(read);
+        }
+        while Pin::new(&mut self.ticks).poll_next(cx).is_ready() {
+            if self.last_read.elapsed() >= self.timeout {
+                if let Some(signal) = self.signal.take() {
+                    let _ = signal.send(());
+                }
+                return Poll::Ready(Err(io::ErrorKind::TimedOut.into()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// A stream whose reads time out after `timeout` without incoming bytes.
+pub struct IdleTimeout<S> {
+    inner: S,
+    timer: IdleTimer,
+}
+
+impl<S> IdleTimeout<S> {
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        IdleTimeout {
+            inner,
+            timer: IdleTimer::new(timeout),
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Clone> Clone for IdleTimeout<S> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone(), self.timer.timeout)
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeout<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.timer.poll_read(&mut this.inner, cx, buf)
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::net::{TcpListener, TcpStream};
+    use futures::io::AsyncReadExt;
+
+    #[async_std::test]
+    async fn test_idle_timeout() -> io::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let client = TcpStream::connect(listener.local_addr()?).await?;
+        let (server, _) = listener.accept().await?;
+
+        let mut client = IdleTimeout::new(client, Duration::from_millis(100));
+        let mut byte = [0u8; 1];
+        let err = client.read_exact(&mut byte).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        drop(server);
+        Ok(())
+    }
+}
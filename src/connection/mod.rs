@@ -0,0 +1,373 @@
+//! Secret handshake and box stream setup over a connected stream, giving
+//! the rpc reader and writer to talk to the peer.
+
+mod authorize;
+pub mod blocking;
+pub mod boxstream;
+mod error;
+pub mod keepalive;
+pub mod shs;
+pub mod stream;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+use std::time::Duration;
+
+use futures::io::{AsyncRead, AsyncWrite};
+
+pub use authorize::{AllowAll, AllowList, Authorizer, BlockList};
+pub use error::{Error, Result};
+
+use self::{
+    keepalive::IdleSignal,
+    shs::{EphemeralKeys, HandshakeOutcome},
+    stream::{BoxStreamReader, BoxStreamWriter},
+};
+use crate::crypto::backend::sign::ed25519;
+use crate::{
+    crypto::ToSsbId,
+    discovery::NetworkConfig,
+    keystore::OwnedIdentity,
+    rpc::{RpcReader, RpcWriter, SharedRpcWriter},
+};
+
+/// An authenticated connection.
+pub struct Connection<R: AsyncRead + Unpin, W: AsyncWrite + Unpin = R> {
+    /// `@...ed25519` id of the peer.
+    pub peer_id: String,
+    pub reader: RpcReader<R>,
+    pub writer: RpcWriter<W>,
+    /// Resolves when the peer stays quiet past the idle timeout of the
+    /// network, if it has one.
+    pub idle: Option<IdleSignal>,
+    keepalive_interval: Option<Duration>,
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin> Connection<R, W> {
+    /// Sets up the box stream of `network` over the halves of a stream the
+    /// handshake was done on.
+    fn new(reader: R, writer: W, handshake: HandshakeOutcome, network: &NetworkConfig) -> Self {
+        let mut reader = BoxStreamReader::new(reader, handshake.recv_keys);
+        let idle = network
+            .idle_timeout
+            .map(|timeout| reader.set_idle_timeout(timeout));
+        let writer = BoxStreamWriter::new(writer, handshake.send_keys)
+            .with_segment_len(network.max_segment_len());
+        Connection {
+            peer_id: format!("@{}", handshake.peer_pk.to_ssb_id()),
+            reader: RpcReader::new(reader),
+            writer: RpcWriter::new(writer),
+            idle,
+            keepalive_interval: network.keepalive_interval,
+        }
+    }
+
+    /// The owned halves of the connection, `Send` and `'static` when the
+    /// stream halves are, to be moved into separate tasks.
+    pub fn into_split(self) -> (RpcReader<R>, RpcWriter<W>) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R: AsyncRead + Unpin, W: AsyncWrite + Unpin + Send + 'static> Connection<R, W> {
+    /// Like `into_split`, with a writer shared between tasks, which sends
+    /// keepalives if the network has a keepalive interval.
+    pub fn into_shared(self) -> (RpcReader<R>, SharedRpcWriter<W>) {
+        let writer = SharedRpcWriter::new(self.writer);
+        if let Some(interval) = self.keepalive_interval {
+            writer.spawn_keepalive(interval);
+        }
+        (self.reader, writer)
+    }
+}
+
+async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    server_pk: ed25519::PublicKey,
+) -> Result<HandshakeOutcome> {
+    let ephemeral = EphemeralKeys::random();
+    shs::client_handshake(stream, &network.shs, identity, &server_pk, ephemeral).await
+}
+
+async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    authorizer: &dyn Authorizer,
+) -> Result<HandshakeOutcome> {
+    let ephemeral = EphemeralKeys::random();
+    shs::server_handshake(stream, &network.shs, identity, ephemeral, authorizer).await
+}
+
+/// Connects as `identity` to the server with `server_pk` as public key, in
+/// the network of `network`.
+pub async fn connect<S: AsyncRead + AsyncWrite + Unpin + Clone>(
+    mut stream: S,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    server_pk: ed25519::PublicKey,
+) -> Result<Connection<S>> {
+    let handshake = client_handshake(&mut stream, network, identity, server_pk).await?;
+    Ok(Connection::new(stream.clone(), stream, handshake, network))
+}
+
+/// Accepts a client as `identity`, if `authorizer` lets it in. Rejected
+/// clients are dropped once their key is known, before the box stream is
+/// set up.
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin + Clone>(
+    mut stream: S,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    authorizer: &dyn Authorizer,
+) -> Result<Connection<S>> {
+    let handshake = server_handshake(&mut stream, network, identity, authorizer).await?;
+    Ok(Connection::new(stream.clone(), stream, handshake, network))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        connection::boxstream::HEADER_LEN,
+        rpc::{ArgType, BodyType, RecvMsg, RpcType},
+    };
+    use async_std::{
+        net::{TcpListener, TcpStream},
+        task,
+    };
+    use std::{
+        io,
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    /// A stream counting the bytes written to it.
+    #[derive(Clone)]
+    struct Counter {
+        inner: TcpStream,
+        written: Arc<Mutex<usize>>,
+    }
+
+    impl AsyncRead for Counter {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Counter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+            if let Poll::Ready(Ok(written)) = poll {
+                *self.written.lock().unwrap() += written;
+            }
+            poll
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_close(cx)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_split_connection() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let server_pk = server.pk;
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let accepting = task::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let network = NetworkConfig::main_net();
+            accept(stream, &network, &server, &AllowAll).await
+        });
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (_, mut writer) = connect(stream, &NetworkConfig::main_net(), &client, server_pk)
+            .await?
+            .into_split();
+        let (mut reader, _) = accepting.await?.into_split();
+
+        let receiving = task::spawn(async move { reader.recv().await });
+        let sending = task::spawn(async move {
+            let args: [(); 0] = [];
+            writer
+                .send_request(
+                    &["whoami"],
+                    RpcType::Async,
+                    ArgType::Array,
+                    &args,
+                    &None::<()>,
+                )
+                .await
+        });
+        let req_no = sending.await.unwrap();
+        match receiving.await.unwrap() {
+            (received, RecvMsg::RpcRequest(body)) => {
+                assert_eq!(received, req_no);
+                assert_eq!(body.name, vec!["whoami".to_string()]);
+            }
+            other => panic!("{:?}", other),
+        }
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_segment_len() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let server_pk = server.pk;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let accepting = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            accept(stream, &NetworkConfig::main_net(), &server, &AllowAll).await
+        });
+
+        let stream = Counter {
+            inner: TcpStream::connect(addr).await?,
+            written: Arc::new(Mutex::new(0)),
+        };
+        let written = stream.written.clone();
+        let network = NetworkConfig::main_net().segment_len(100);
+        let mut connection = connect(stream, &network, &client, server_pk).await?;
+        let mut accepted = accepting.await?;
+        let handshake_len = *written.lock().unwrap();
+
+        let body = vec![7u8; 1000];
+        connection
+            .writer
+            .send_response(1, RpcType::Async, BodyType::Binary, &body)
+            .await
+            .unwrap();
+        match accepted.reader.recv().await.unwrap() {
+            (1, RecvMsg::RpcResponse(BodyType::Binary, received)) => assert_eq!(received, body),
+            other => panic!("{:?}", other),
+        }
+        // the 9 bytes rpc header and the body, in segments of 100 bytes
+        let frame_len = 9 + body.len();
+        assert_eq!(
+            *written.lock().unwrap() - handshake_len,
+            frame_len + frame_len.div_ceil(100) * HEADER_LEN
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_corked_frames_share_a_segment() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let server_pk = server.pk;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let accepting = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            accept(stream, &NetworkConfig::main_net(), &server, &AllowAll).await
+        });
+
+        let stream = Counter {
+            inner: TcpStream::connect(addr).await?,
+            written: Arc::new(Mutex::new(0)),
+        };
+        let written = stream.written.clone();
+        let mut connection =
+            connect(stream, &NetworkConfig::main_net(), &client, server_pk).await?;
+        let mut accepted = accepting.await?;
+        let handshake_len = *written.lock().unwrap();
+
+        connection.writer.cork();
+        for req_no in 1..=3 {
+            connection
+                .writer
+                .send_response(req_no, RpcType::Async, BodyType::Binary, b"hello")
+                .await
+                .unwrap();
+        }
+        assert_eq!(*written.lock().unwrap(), handshake_len);
+        connection.writer.uncork().await.unwrap();
+        for req_no in 1..=3 {
+            match accepted.reader.recv().await.unwrap() {
+                (received, RecvMsg::RpcResponse(_, body)) => {
+                    assert_eq!((received, &body[..]), (req_no, &b"hello"[..]))
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        // three frames of 9 + 5 bytes behind a single header
+        assert_eq!(
+            *written.lock().unwrap() - handshake_len,
+            3 * 14 + HEADER_LEN
+        );
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_keepalive_is_not_eof() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let server_pk = server.pk;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let accepting = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let network = NetworkConfig::main_net().idle_timeout(Duration::from_millis(300));
+            accept(stream, &network, &server, &AllowAll).await
+        });
+        let network = NetworkConfig::main_net().keepalive_interval(Duration::from_millis(50));
+        let stream = TcpStream::connect(addr).await?;
+        let (_, writer) = connect(stream, &network, &client, server_pk)
+            .await?
+            .into_shared();
+        let mut accepted = accepting.await?;
+        let mut idle = accepted.idle.take().unwrap();
+
+        // only keepalives for longer than the idle timeout
+        let receiving = task::spawn(async move {
+            let received = accepted.reader.recv().await.unwrap();
+            (received, accepted)
+        });
+        task::sleep(Duration::from_millis(800)).await;
+        writer
+            .send_response(1, RpcType::Async, BodyType::Binary, b"hello")
+            .await
+            .unwrap();
+        let (received, _accepted) = receiving.await;
+        match received {
+            (1, RecvMsg::RpcResponse(_, body)) => assert_eq!(body, b"hello"),
+            other => panic!("{:?}", other),
+        }
+        assert!(futures::poll!(&mut idle).is_pending());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_idle_connection() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let server_pk = server.pk;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let accepting = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let network = NetworkConfig::main_net().idle_timeout(Duration::from_millis(100));
+            accept(stream, &network, &server, &AllowAll).await
+        });
+        let stream = TcpStream::connect(addr).await?;
+        let _connected = connect(stream, &NetworkConfig::main_net(), &client, server_pk).await?;
+        let mut accepted = accepting.await?;
+        let idle = accepted.idle.take().unwrap();
+
+        assert!(accepted.reader.recv().await.is_err());
+        assert!(idle.await);
+        Ok(())
+    }
+}
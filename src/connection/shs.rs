@@ -0,0 +1,606 @@
+//! The secret handshake: the client proves its key to a server it knows,
+//! the server proves its key back, and both derive the box stream keys.
+//!
+//! `client_handshake` and `server_handshake` run it over a stream with the
+//! ephemeral keys they are given; `connect` and `accept` pass random ones,
+//! tests pass fixed ones to compare with the shs test vectors.
+//! `transcript` computes the same messages without a stream.
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    authorize::Authorizer,
+    boxstream::BoxStreamKeys,
+    error::{Error, Result},
+};
+use crate::crypto::backend::{
+    auth,
+    hash::sha256,
+    scalarmult::curve25519::{scalarmult, scalarmult_base, GroupElement, Scalar},
+    secretbox,
+    sign::ed25519,
+};
+use crate::{
+    crypto::{
+        ed25519_pk_to_curve25519, ed25519_sk_to_curve25519, sign_detached, verify_detached, ToSsbId,
+    },
+    discovery::NetworkKey,
+    keystore::OwnedIdentity,
+};
+
+const HELLO_LEN: usize = 64;
+const CLIENT_AUTH_LEN: usize = 112;
+const SERVER_ACCEPT_LEN: usize = 80;
+
+/// An ephemeral curve25519 keypair of one side of the handshake. The
+/// secret key is zeroized on drop.
+#[derive(Debug, Clone)]
+pub struct EphemeralKeys {
+    pub pk: GroupElement,
+    pub sk: Scalar,
+}
+
+impl EphemeralKeys {
+    pub fn from_secret(sk: [u8; 32]) -> Self {
+        let sk = Scalar(sk);
+        EphemeralKeys {
+            pk: scalarmult_base(&sk),
+            sk,
+        }
+    }
+
+    pub fn random() -> Self {
+        let mut sk = Zeroizing::new([0u8; 32]);
+        crate::crypto::backend::randombytes::randombytes_into(&mut *sk);
+        Self::from_secret(*sk)
+    }
+}
+
+impl Drop for EphemeralKeys {
+    fn drop(&mut self) {
+        self.sk.0.zeroize();
+    }
+}
+
+/// The four messages of a handshake, and the box stream keys it results
+/// in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transcript {
+    pub client_hello: Vec<u8>,
+    pub server_hello: Vec<u8>,
+    pub client_auth: Vec<u8>,
+    pub server_accept: Vec<u8>,
+    /// Keys of the box stream from the client to the server.
+    pub client_keys: BoxStreamKeys,
+    /// Keys of the box stream from the server to the client.
+    pub server_keys: BoxStreamKeys,
+}
+
+/// What a handshake leaves each side with.
+#[derive(Debug, Clone)]
+pub struct HandshakeOutcome {
+    pub peer_pk: ed25519::PublicKey,
+    /// Keys of the box stream to the peer.
+    pub send_keys: BoxStreamKeys,
+    /// Keys of the box stream from the peer.
+    pub recv_keys: BoxStreamKeys,
+}
+
+/// The three shared secrets, named from the client side: `ab` between
+/// both ephemeral keys, `a_b` between the client ephemeral key and the
+/// server key, `a_b_long` between the client key and the server ephemeral
+/// key. Zeroized on drop.
+struct Secrets {
+    ab: GroupElement,
+    a_b: GroupElement,
+    a_b_long: GroupElement,
+}
+
+impl Drop for Secrets {
+    fn drop(&mut self) {
+        self.ab.0.zeroize();
+        self.a_b.0.zeroize();
+        self.a_b_long.0.zeroize();
+    }
+}
+
+impl Secrets {
+    fn box_stream_keys(
+        &self,
+        network_key: &NetworkKey,
+        pk: &ed25519::PublicKey,
+        ephemeral_pk: &GroupElement,
+    ) -> BoxStreamKeys {
+        BoxStreamKeys::derive(
+            network_key,
+            [&self.ab.0, &self.a_b.0, &self.a_b_long.0],
+            &pk.0,
+            &ephemeral_pk.0,
+        )
+    }
+}
+
+fn handshake_error(what: &str) -> Error {
+    Error::Handshake(what.to_string())
+}
+
+fn hello(network_key: &NetworkKey, ephemeral: &EphemeralKeys) -> Vec<u8> {
+    let mut msg = auth::authenticate(&ephemeral.pk.0, &network_key.key())
+        .0
+        .to_vec();
+    msg.extend_from_slice(&ephemeral.pk.0);
+    msg
+}
+
+/// The ephemeral key of the peer, if its hello is for our network.
+fn open_hello(network_key: &NetworkKey, msg: &[u8]) -> Result<GroupElement> {
+    let (mac, pk) = msg.split_at(auth::TAGBYTES);
+    let mac = auth::Tag::from_slice(mac).ok_or_else(|| handshake_error("bad hello"))?;
+    if !auth::verify(&mac, pk, &network_key.key()) {
+        return Err(handshake_error("hello from another network"));
+    }
+    GroupElement::from_slice(pk).ok_or_else(|| handshake_error("bad hello"))
+}
+
+fn dh(sk: &Scalar, pk: &GroupElement) -> Result<GroupElement> {
+    scalarmult(sk, pk).map_err(|_| handshake_error("weak public key"))
+}
+
+fn box_key(parts: &[&[u8]]) -> secretbox::Key {
+    secretbox::Key(sha256::hash(&Zeroizing::new(parts.concat())).0)
+}
+
+fn zero_nonce() -> secretbox::Nonce {
+    secretbox::Nonce([0u8; secretbox::NONCEBYTES])
+}
+
+/// What the client signs: the network, the server it means to reach and
+/// the ephemeral secret.
+fn client_signed(
+    network_key: &NetworkKey,
+    server_pk: &ed25519::PublicKey,
+    ab: &GroupElement,
+) -> Vec<u8> {
+    [network_key.as_bytes(), &server_pk.0, &sha256::hash(&ab.0).0].concat()
+}
+
+/// What the server signs: the client proof and the ephemeral secret.
+fn server_signed(
+    network_key: &NetworkKey,
+    client_signature: &ed25519::Signature,
+    client_pk: &ed25519::PublicKey,
+    ab: &GroupElement,
+) -> Vec<u8> {
+    [
+        network_key.as_bytes(),
+        &client_signature.0[..],
+        &client_pk.0,
+        &sha256::hash(&ab.0).0,
+    ]
+    .concat()
+}
+
+/// The client auth message and the signature in it.
+fn client_auth(
+    network_key: &NetworkKey,
+    client: &OwnedIdentity,
+    server_pk: &ed25519::PublicKey,
+    secrets: &Secrets,
+) -> (Vec<u8>, ed25519::Signature) {
+    let key = network_key.as_bytes();
+    let signature = sign_detached(
+        &client_signed(network_key, server_pk, &secrets.ab),
+        &client.sk,
+    );
+    let msg = secretbox::seal(
+        &[&signature.0[..], &client.pk.0].concat(),
+        &zero_nonce(),
+        &box_key(&[key, &secrets.ab.0, &secrets.a_b.0]),
+    );
+    (msg, signature)
+}
+
+/// The client key and signature of a client auth message, once checked.
+fn open_client_auth(
+    network_key: &NetworkKey,
+    server_pk: &ed25519::PublicKey,
+    secrets: &Secrets,
+    msg: &[u8],
+) -> Result<(ed25519::PublicKey, ed25519::Signature)> {
+    let key = network_key.as_bytes();
+    let auth_key = box_key(&[key, &secrets.ab.0, &secrets.a_b.0]);
+    let opened = secretbox::open(msg, &zero_nonce(), &auth_key)
+        .map_err(|_| handshake_error("client auth cannot be opened"))?;
+    let signature = ed25519::Signature::from_slice(&opened[..ed25519::SIGNATUREBYTES])
+        .ok_or_else(|| handshake_error("bad client auth"))?;
+    let client_pk = ed25519::PublicKey::from_slice(&opened[ed25519::SIGNATUREBYTES..])
+        .ok_or_else(|| handshake_error("bad client auth"))?;
+    if !verify_detached(
+        &signature,
+        &client_signed(network_key, server_pk, &secrets.ab),
+        &client_pk,
+    ) {
+        return Err(handshake_error("client auth is not signed by the client"));
+    }
+    Ok((client_pk, signature))
+}
+
+fn server_accept(
+    network_key: &NetworkKey,
+    server: &OwnedIdentity,
+    client_pk: &ed25519::PublicKey,
+    client_signature: &ed25519::Signature,
+    secrets: &Secrets,
+) -> Vec<u8> {
+    let key = network_key.as_bytes();
+    let signature = sign_detached(
+        &server_signed(network_key, client_signature, client_pk, &secrets.ab),
+        &server.sk,
+    );
+    secretbox::seal(
+        &signature.0,
+        &zero_nonce(),
+        &box_key(&[key, &secrets.ab.0, &secrets.a_b.0, &secrets.a_b_long.0]),
+    )
+}
+
+fn open_server_accept(
+    network_key: &NetworkKey,
+    server_pk: &ed25519::PublicKey,
+    client_pk: &ed25519::PublicKey,
+    client_signature: &ed25519::Signature,
+    secrets: &Secrets,
+    msg: &[u8],
+) -> Result<()> {
+    let key = network_key.as_bytes();
+    let opened = secretbox::open(
+        msg,
+        &zero_nonce(),
+        &box_key(&[key, &secrets.ab.0, &secrets.a_b.0, &secrets.a_b_long.0]),
+    )
+    .map_err(|_| handshake_error("server accept cannot be opened"))?;
+    let signature = ed25519::Signature::from_slice(&opened)
+        .ok_or_else(|| handshake_error("bad server accept"))?;
+    let signed = server_signed(network_key, client_signature, client_pk, &secrets.ab);
+    if !verify_detached(&signature, &signed, server_pk) {
+        return Err(handshake_error("server accept is not signed by the server"));
+    }
+    Ok(())
+}
+
+async fn send<S: AsyncWrite + Unpin>(stream: &mut S, msg: &[u8]) -> Result<()> {
+    stream.write_all(msg).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn recv<S: AsyncRead + Unpin>(stream: &mut S, len: usize) -> Result<Vec<u8>> {
+    let mut msg = vec![0u8; len];
+    stream.read_exact(&mut msg).await?;
+    Ok(msg)
+}
+
+/// Runs the client side of the handshake with the server with `server_pk`
+/// as public key.
+pub async fn client_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    network_key: &NetworkKey,
+    identity: &OwnedIdentity,
+    server_pk: &ed25519::PublicKey,
+    ephemeral: EphemeralKeys,
+) -> Result<HandshakeOutcome> {
+    send(stream, &hello(network_key, &ephemeral)).await?;
+    let server_ephemeral_pk = open_hello(network_key, &recv(stream, HELLO_LEN).await?)?;
+
+    let secrets = Secrets {
+        ab: dh(&ephemeral.sk, &server_ephemeral_pk)?,
+        a_b: dh(&ephemeral.sk, &ed25519_pk_to_curve25519(server_pk)?)?,
+        a_b_long: dh(
+            &ed25519_sk_to_curve25519(&identity.sk)?,
+            &server_ephemeral_pk,
+        )?,
+    };
+    let (msg, signature) = client_auth(network_key, identity, server_pk, &secrets);
+    send(stream, &msg).await?;
+    let msg = recv(stream, SERVER_ACCEPT_LEN).await?;
+    open_server_accept(
+        network_key,
+        server_pk,
+        &identity.pk,
+        &signature,
+        &secrets,
+        &msg,
+    )?;
+
+    Ok(HandshakeOutcome {
+        peer_pk: *server_pk,
+        send_keys: secrets.box_stream_keys(network_key, server_pk, &server_ephemeral_pk),
+        recv_keys: secrets.box_stream_keys(network_key, &identity.pk, &ephemeral.pk),
+    })
+}
+
+/// Runs the server side of the handshake. Clients `authorizer` turns away
+/// are dropped before the server proves its key.
+pub async fn server_handshake<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    network_key: &NetworkKey,
+    identity: &OwnedIdentity,
+    ephemeral: EphemeralKeys,
+    authorizer: &dyn Authorizer,
+) -> Result<HandshakeOutcome> {
+    let client_ephemeral_pk = open_hello(network_key, &recv(stream, HELLO_LEN).await?)?;
+    send(stream, &hello(network_key, &ephemeral)).await?;
+
+    // `a_b_long` needs the client key, known once its auth is opened
+    let mut secrets = Secrets {
+        ab: dh(&ephemeral.sk, &client_ephemeral_pk)?,
+        a_b: dh(
+            &ed25519_sk_to_curve25519(&identity.sk)?,
+            &client_ephemeral_pk,
+        )?,
+        a_b_long: GroupElement([0u8; 32]),
+    };
+    let msg = recv(stream, CLIENT_AUTH_LEN).await?;
+    let (client_pk, signature) = open_client_auth(network_key, &identity.pk, &secrets, &msg)?;
+    if !authorizer.authorize(&client_pk).await {
+        return Err(Error::Unauthorized(format!("@{}", client_pk.to_ssb_id())));
+    }
+    secrets.a_b_long = dh(&ephemeral.sk, &ed25519_pk_to_curve25519(&client_pk)?)?;
+    send(
+        stream,
+        &server_accept(network_key, identity, &client_pk, &signature, &secrets),
+    )
+    .await?;
+
+    Ok(HandshakeOutcome {
+        peer_pk: client_pk,
+        send_keys: secrets.box_stream_keys(network_key, &client_pk, &client_ephemeral_pk),
+        recv_keys: secrets.box_stream_keys(network_key, &identity.pk, &ephemeral.pk),
+    })
+}
+
+/// The handshake between `client` and `server` with the given ephemeral
+/// keys, as `client_handshake` and `server_handshake` would run it.
+pub fn transcript(
+    network_key: &NetworkKey,
+    client: &OwnedIdentity,
+    client_ephemeral: &EphemeralKeys,
+    server: &OwnedIdentity,
+    server_ephemeral: &EphemeralKeys,
+) -> Result<Transcript> {
+    let secrets = Secrets {
+        ab: dh(&client_ephemeral.sk, &server_ephemeral.pk)?,
+        a_b: dh(&client_ephemeral.sk, &ed25519_pk_to_curve25519(&server.pk)?)?,
+        a_b_long: dh(&ed25519_sk_to_curve25519(&client.sk)?, &server_ephemeral.pk)?,
+    };
+    let (client_auth, signature) = client_auth(network_key, client, &server.pk, &secrets);
+    Ok(Transcript {
+        client_hello: hello(network_key, client_ephemeral),
+        server_hello: hello(network_key, server_ephemeral),
+        client_auth,
+        server_accept: server_accept(network_key, server, &client.pk, &signature, &secrets),
+        client_keys: secrets.box_stream_keys(network_key, &server.pk, &server_ephemeral.pk),
+        server_keys: secrets.box_stream_keys(network_key, &client.pk, &client_ephemeral.pk),
+    })
+}
+
+/// Two ends of a tcp connection the handshake was run on, client first,
+/// for tests of what runs over it.
+#[cfg(test)]
+pub(crate) async fn handshake_pair() -> Result<(
+    (async_std::net::TcpStream, HandshakeOutcome),
+    (async_std::net::TcpStream, HandshakeOutcome),
+)> {
+    use async_std::net::{TcpListener, TcpStream};
+
+    let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+    let network_key = NetworkKey::default();
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let mut client_stream = TcpStream::connect(listener.local_addr()?).await?;
+    let (mut server_stream, _) = listener.accept().await?;
+    let (client_outcome, server_outcome) = futures::join!(
+        client_handshake(
+            &mut client_stream,
+            &network_key,
+            &client,
+            &server.pk,
+            EphemeralKeys::random()
+        ),
+        server_handshake(
+            &mut server_stream,
+            &network_key,
+            &server,
+            EphemeralKeys::random(),
+            &super::AllowAll
+        ),
+    );
+    Ok((
+        (client_stream, client_outcome?),
+        (server_stream, server_outcome?),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        connection::{AllowAll, BlockList},
+        crypto::testvectors::{assert_shs, shs_vectors},
+    };
+    use async_std::{
+        net::{TcpListener, TcpStream},
+        task,
+    };
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+
+    /// A stream keeping a copy of what is written to it.
+    struct Recorder<S> {
+        inner: S,
+        written: Vec<u8>,
+    }
+
+    impl<S: AsyncRead + Unpin> AsyncRead for Recorder<S> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.inner).poll_read(cx, buf)
+        }
+    }
+
+    impl<S: AsyncWrite + Unpin> AsyncWrite for Recorder<S> {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = &mut *self;
+            let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+            if let Poll::Ready(Ok(written)) = poll {
+                this.written.extend_from_slice(&buf[..written]);
+            }
+            poll
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_flush(cx)
+        }
+
+        fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Pin::new(&mut self.inner).poll_close(cx)
+        }
+    }
+
+    #[test]
+    fn test_transcript() -> Result<()> {
+        let network_key = NetworkKey::default();
+        let client = OwnedIdentity::from_seed(&[1u8; 32]);
+        let server = OwnedIdentity::from_seed(&[2u8; 32]);
+        let (client_ephemeral, server_ephemeral) = (
+            EphemeralKeys::from_secret([3u8; 32]),
+            EphemeralKeys::from_secret([4u8; 32]),
+        );
+        let run = || {
+            transcript(
+                &network_key,
+                &client,
+                &client_ephemeral,
+                &server,
+                &server_ephemeral,
+            )
+        };
+        let messages = run()?;
+        assert_eq!(run()?, messages);
+        assert_eq!(messages.client_hello.len(), 64);
+        assert_eq!(messages.client_auth.len(), 112);
+        assert_eq!(messages.server_accept.len(), 80);
+
+        // the server opens the client auth with its own view of the secrets
+        let ab = dh(&server_ephemeral.sk, &client_ephemeral.pk)?;
+        let a_b = dh(&ed25519_sk_to_curve25519(&server.sk)?, &client_ephemeral.pk)?;
+        let opened = secretbox::open(
+            &messages.client_auth,
+            &secretbox::Nonce([0u8; secretbox::NONCEBYTES]),
+            &box_key(&[network_key.as_bytes(), &ab.0, &a_b.0]),
+        )
+        .unwrap();
+        assert_eq!(&opened[64..], &client.pk.0[..]);
+        let signature = ed25519::Signature::from_slice(&opened[..64]).unwrap();
+        let signed = [network_key.as_bytes(), &server.pk.0, &sha256::hash(&ab.0).0].concat();
+        assert!(verify_detached(&signature, &signed, &client.pk));
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_shs1_vector() -> Result<()> {
+        let vector = shs_vectors().remove(0);
+        let network_key = vector.network_key();
+        let (client, server) = (vector.client(), vector.server());
+        let server_pk = server.pk;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (server_network_key, server_ephemeral) =
+            (network_key.clone(), vector.server_ephemeral());
+        let accepting = task::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            let mut stream = Recorder {
+                inner: stream,
+                written: Vec::new(),
+            };
+            let outcome = server_handshake(
+                &mut stream,
+                &server_network_key,
+                &server,
+                server_ephemeral,
+                &AllowAll,
+            )
+            .await?;
+            Ok::<_, Error>((outcome, stream.written))
+        });
+        let mut stream = Recorder {
+            inner: TcpStream::connect(addr).await?,
+            written: Vec::new(),
+        };
+        let client_outcome = client_handshake(
+            &mut stream,
+            &network_key,
+            &client,
+            &server_pk,
+            vector.client_ephemeral(),
+        )
+        .await?;
+        let (server_outcome, server_written) = accepting.await?;
+
+        assert_eq!(stream.written, vector.client_sent());
+        assert_eq!(server_written, vector.server_sent());
+        assert_eq!(client_outcome.peer_pk, server_pk);
+        assert_eq!(server_outcome.peer_pk, client.pk);
+        assert_eq!(client_outcome.send_keys, vector.client_keys());
+        assert_eq!(client_outcome.recv_keys, vector.server_keys());
+        assert_eq!(server_outcome.send_keys, vector.server_keys());
+        assert_eq!(server_outcome.recv_keys, vector.client_keys());
+        assert_shs(&vector);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_unauthorized_client() -> Result<()> {
+        let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let server_pk = server.pk;
+        let blocked = BlockList([client.id.clone()].into_iter().collect());
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let accepting = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let network_key = NetworkKey::default();
+            server_handshake(
+                &mut stream,
+                &network_key,
+                &server,
+                EphemeralKeys::random(),
+                &blocked,
+            )
+            .await
+        });
+
+        let mut stream = TcpStream::connect(addr).await?;
+        let network_key = NetworkKey::default();
+        let connecting = client_handshake(
+            &mut stream,
+            &network_key,
+            &client,
+            &server_pk,
+            EphemeralKeys::random(),
+        );
+        assert!(matches!(connecting.await, Err(Error::Io(_))));
+        assert!(matches!(accepting.await, Err(Error::Unauthorized(id)) if id == client.id));
+        Ok(())
+    }
+}
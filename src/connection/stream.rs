@@ -0,0 +1,297 @@
+//! Box streams over async streams: `BoxStreamReader` and `BoxStreamWriter`
+//! are `AsyncRead` and `AsyncWrite` themselves, so they can carry another
+//! handshake, an rpc session or anything else that runs over a stream.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{
+    future::poll_fn,
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    ready,
+};
+
+use super::{
+    boxstream::{BoxStreamKeys, Decoder, Sealer, HEADER_LEN, MAX_SEGMENT_LEN},
+    error::Error,
+    keepalive::{IdleSignal, IdleTimer},
+};
+
+fn io_error(err: Error) -> io::Error {
+    match err {
+        Error::Truncated => io::Error::new(io::ErrorKind::UnexpectedEof, err),
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+/// Decrypting half of a box stream.
+pub struct BoxStreamReader<R> {
+    inner: R,
+    decoder: Decoder,
+    ciphertext: Box<[u8]>,
+    plaintext: Vec<u8>,
+    /// Bytes of `plaintext` already read.
+    offset: usize,
+    idle: Option<IdleTimer>,
+}
+
+impl<R> BoxStreamReader<R> {
+    /// Reads the box stream sent with `keys` from `inner`.
+    pub fn new(inner: R, keys: BoxStreamKeys) -> Self {
+        BoxStreamReader {
+            inner,
+            decoder: Decoder::new(keys),
+            ciphertext: vec![0u8; HEADER_LEN + MAX_SEGMENT_LEN].into_boxed_slice(),
+            plaintext: Vec::with_capacity(MAX_SEGMENT_LEN),
+            offset: 0,
+            idle: None,
+        }
+    }
+
+    /// Fails reads with `io::ErrorKind::TimedOut` after `timeout` without
+    /// anything, keepalives included, coming in, and resolves the returned
+    /// signal then.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) -> IdleSignal {
+        self.idle.insert(IdleTimer::new(timeout)).signal()
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> BoxStreamReader<R> {
+    /// Reads ciphertext into its buffer, minding the idle timeout.
+    fn poll_ciphertext(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        match &mut self.idle {
+            Some(idle) => idle.poll_read(&mut self.inner, cx, &mut self.ciphertext),
+            None => Pin::new(&mut self.inner).poll_read(cx, &mut self.ciphertext),
+        }
+    }
+
+    /// Appends the plaintext of the next segments to `out`, decrypting it
+    /// there rather than in a buffer of the reader, and returns how many
+    /// bytes were added; 0 once the peer said goodbye.
+    pub async fn read_into(&mut self, out: &mut Vec<u8>) -> io::Result<usize> {
+        if self.offset < self.plaintext.len() {
+            out.extend_from_slice(&self.plaintext[self.offset..]);
+            let len = self.plaintext.len() - self.offset;
+            self.plaintext.clear();
+            self.offset = 0;
+            return Ok(len);
+        }
+        while !self.decoder.is_closed() {
+            let read = poll_fn(|cx| self.poll_ciphertext(cx)).await?;
+            if read == 0 {
+                return self.decoder.finish().map(|_| 0).map_err(io_error);
+            }
+            let len = self
+                .decoder
+                .feed_into(&self.ciphertext[..read], out)
+                .map_err(io_error)?;
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+        Ok(0)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BoxStreamReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.offset < this.plaintext.len() {
+                let len = buf.len().min(this.plaintext.len() - this.offset);
+                buf[..len].copy_from_slice(&this.plaintext[this.offset..this.offset + len]);
+                this.offset += len;
+                return Poll::Ready(Ok(len));
+            }
+            if this.decoder.is_closed() {
+                return Poll::Ready(Ok(0));
+            }
+            let read = ready!(this.poll_ciphertext(cx))?;
+            if read == 0 {
+                return Poll::Ready(this.decoder.finish().map(|_| 0).map_err(io_error));
+            }
+            this.plaintext.clear();
+            this.offset = 0;
+            this.decoder
+                .feed_into(&this.ciphertext[..read], &mut this.plaintext)
+                .map_err(io_error)?;
+        }
+    }
+}
+
+/// Encrypting half of a box stream. Written bytes are buffered and sealed
+/// once they fill a segment or on flush, so small writes between flushes
+/// share segments; closing flushes and says goodbye.
+pub struct BoxStreamWriter<W> {
+    inner: W,
+    sealer: Sealer,
+    /// Bytes written but not sealed yet, less than a segment.
+    plaintext: Vec<u8>,
+    sealed: Vec<u8>,
+    /// Bytes of `sealed` already written.
+    written: usize,
+    goodbye: bool,
+}
+
+impl<W> BoxStreamWriter<W> {
+    /// Writes a box stream sealed with `keys` to `inner`.
+    pub fn new(inner: W, keys: BoxStreamKeys) -> Self {
+        BoxStreamWriter {
+            inner,
+            sealer: Sealer::new(keys),
+            plaintext: Vec::with_capacity(MAX_SEGMENT_LEN),
+            sealed: Vec::new(),
+            written: 0,
+            goodbye: false,
+        }
+    }
+
+    /// Sends segments of up to `len` bytes, see `Sealer::with_segment_len`.
+    pub fn with_segment_len(self, len: usize) -> Self {
+        Self {
+            sealer: self.sealer.with_segment_len(len),
+            ..self
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Seals the buffered bytes, `all` of them or just the full segments.
+    fn seal(&mut self, all: bool) {
+        let len = match all {
+            true => self.plaintext.len(),
+            false => self.plaintext.len() - self.plaintext.len() % self.sealer.segment_len(),
+        };
+        if len > 0 {
+            self.sealer
+                .seal_into(&self.plaintext[..len], &mut self.sealed);
+            self.plaintext.drain(..len);
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BoxStreamWriter<W> {
+    /// Sends an empty segment, keeping a quiet link busy. Buffered bytes
+    /// stay buffered until the next flush.
+    pub async fn keepalive(&mut self) -> io::Result<()> {
+        if self.goodbye {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        poll_fn(|cx| self.poll_drain(cx)).await?;
+        self.sealed = self.sealer.keepalive();
+        poll_fn(|cx| self.poll_drain(cx)).await?;
+        self.inner.flush().await
+    }
+
+    /// Writes out what was sealed.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.written < self.sealed.len() {
+            let written =
+                ready!(Pin::new(&mut self.inner).poll_write(cx, &self.sealed[self.written..]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.written += written;
+        }
+        self.sealed.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BoxStreamWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.goodbye {
+            return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into()));
+        }
+        ready!(this.poll_drain(cx))?;
+        this.plaintext.extend_from_slice(buf);
+        this.seal(false);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.seal(true);
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.seal(true);
+        ready!(this.poll_drain(cx))?;
+        if !this.goodbye {
+            this.goodbye = true;
+            this.sealed = this.sealer.goodbye();
+            ready!(this.poll_drain(cx))?;
+        }
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::connection::shs::handshake_pair;
+    use async_std::task;
+    use futures::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[async_std::test]
+    async fn test_box_stream_halves() -> crate::connection::Result<()> {
+        let ((client, client_outcome), (server, server_outcome)) = handshake_pair().await?;
+        let receiving = task::spawn(async move {
+            let mut reader = BoxStreamReader::new(server, server_outcome.recv_keys);
+            let mut received = Vec::new();
+            reader.read_to_end(&mut received).await?;
+            Ok::<_, io::Error>(received)
+        });
+
+        let data: Vec<u8> = (0..10000u32).map(|n| n as u8).collect();
+        let mut writer =
+            BoxStreamWriter::new(client, client_outcome.send_keys).with_segment_len(1000);
+        writer.write_all(&data[..10]).await?;
+        writer.keepalive().await?;
+        writer.write_all(&data[10..]).await?;
+        writer.close().await?;
+        assert!(writer.write_all(&data).await.is_err());
+        assert_eq!(receiving.await?, data);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_read_into_over_handshake() -> crate::connection::Result<()> {
+        let ((client, client_outcome), (server, server_outcome)) = handshake_pair().await?;
+        let data: Vec<u8> = (0..10000u32).map(|n| n as u8).collect();
+        let mut writer = BoxStreamWriter::new(client, client_outcome.send_keys);
+        writer.write_all(&data).await?;
+        writer.close().await?;
+
+        let mut reader = BoxStreamReader::new(server, server_outcome.recv_keys);
+        let mut first = [0u8; 10];
+        reader.read_exact(&mut first).await?;
+        let mut received = first.to_vec();
+        while reader.read_into(&mut received).await? > 0 {}
+        assert_eq!(received, data);
+        Ok(())
+    }
+}
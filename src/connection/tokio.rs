@@ -0,0 +1,79 @@
+//! `connect` and `accept` over tokio tcp streams, through the
+//! `futures::io` compatibility layer of tokio-util. Enabled by the `tokio`
+//! feature.
+
+use ::tokio::net::{
+    tcp::{OwnedReadHalf, OwnedWriteHalf},
+    TcpStream,
+};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+use super::{client_handshake, error::Result, keepalive, server_handshake, Authorizer, Connection};
+use crate::crypto::backend::sign::ed25519;
+use crate::{discovery::NetworkConfig, keystore::OwnedIdentity};
+
+pub type TokioConnection = Connection<Compat<OwnedReadHalf>, Compat<OwnedWriteHalf>>;
+
+fn split(stream: Compat<TcpStream>) -> (Compat<OwnedReadHalf>, Compat<OwnedWriteHalf>) {
+    let (reader, writer) = stream.into_inner().into_split();
+    (reader.compat(), writer.compat_write())
+}
+
+/// Like `connection::connect`, over a tokio tcp stream.
+pub async fn connect(
+    stream: TcpStream,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    server_pk: ed25519::PublicKey,
+) -> Result<TokioConnection> {
+    keepalive::configure(&stream, network)?;
+    let mut stream = stream.compat();
+    let handshake = client_handshake(&mut stream, network, identity, server_pk).await?;
+    let (reader, writer) = split(stream);
+    Ok(Connection::new(reader, writer, handshake, network))
+}
+
+/// Like `connection::accept`, over a tokio tcp stream.
+pub async fn accept(
+    stream: TcpStream,
+    network: &NetworkConfig,
+    identity: &OwnedIdentity,
+    authorizer: &dyn Authorizer,
+) -> Result<TokioConnection> {
+    keepalive::configure(&stream, network)?;
+    let mut stream = stream.compat();
+    let handshake = server_handshake(&mut stream, network, identity, authorizer).await?;
+    let (reader, writer) = split(stream);
+    Ok(Connection::new(reader, writer, handshake, network))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::connection::AllowAll;
+    use ::tokio::{net::TcpListener, runtime::Builder};
+
+    #[test]
+    fn test_tokio_connection() -> Result<()> {
+        let runtime = Builder::new_current_thread().enable_io().build().unwrap();
+        runtime.block_on(async {
+            let (client, server) = (OwnedIdentity::create(), OwnedIdentity::create());
+            let network = NetworkConfig::main_net();
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            let accepting = async {
+                let (stream, _) = listener.accept().await.unwrap();
+                accept(stream, &network, &server, &AllowAll).await
+            };
+            let connecting = async {
+                let stream = TcpStream::connect(addr).await.unwrap();
+                connect(stream, &network, &client, server.pk).await
+            };
+            let (accepted, connected) = futures::join!(accepting, connecting);
+            assert_eq!(connected?.peer_id, server.id);
+            accepted?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,19 @@
+//! The primitives the crate is built on. By default these are libsodium's,
+//! through kuska-sodiumoxide; the `pure-rust` feature swaps in RustCrypto
+//! and dalek implementations of the same API, so that the crate builds
+//! without a C toolchain.
+
+#[cfg(not(any(feature = "sodium", feature = "pure-rust")))]
+compile_error!("kuska-ssb needs a crypto backend, enable the `sodium` or the `pure-rust` feature");
+
+#[cfg(feature = "pure-rust")]
+mod rust;
+
+#[cfg(feature = "pure-rust")]
+pub use rust::{auth, hash, pwhash, randombytes, scalarmult, secretbox, sign};
+
+#[cfg(not(feature = "pure-rust"))]
+pub use kuska_sodiumoxide::{
+    crypto::{auth, hash, pwhash, scalarmult, secretbox, sign},
+    randombytes,
+};
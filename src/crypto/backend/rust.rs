@@ -0,0 +1,802 @@
+//! The subset of the sodiumoxide API the crate uses, implemented with
+//! RustCrypto and dalek crates. Every function mirrors the libsodium
+//! function it replaces, including the edge cases libsodium rejects.
+
+// sodiumoxide reports failures as `Err(())`
+#![allow(clippy::result_unit_err)]
+
+macro_rules! newtype {
+    ($(#[$meta:meta])* public $name:ident($len:expr);) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy)]
+        pub struct $name(pub [u8; $len]);
+        newtype!(@common $name($len));
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+        impl std::hash::Hash for $name {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                self.0.hash(state)
+            }
+        }
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}({:?})", stringify!($name), &self.0[..])
+            }
+        }
+    };
+    ($(#[$meta:meta])* secret $name:ident($len:expr);) => {
+        $(#[$meta])*
+        #[derive(Clone)]
+        pub struct $name(pub [u8; $len]);
+        newtype!(@common $name($len));
+        impl Drop for $name {
+            fn drop(&mut self) {
+                zeroize::Zeroize::zeroize(&mut self.0);
+            }
+        }
+        impl std::fmt::Debug for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "{}(****)", stringify!($name))
+            }
+        }
+    };
+    ($(#[$meta:meta])* nonce $name:ident($len:expr);) => {
+        newtype!($(#[$meta])* public $name($len););
+        impl $name {
+            /// The nonce read as a little-endian number, plus one.
+            pub fn increment_le(&self) -> $name {
+                let mut res = *self;
+                res.increment_le_inplace();
+                res
+            }
+
+            pub fn increment_le_inplace(&mut self) {
+                for byte in self.0.iter_mut() {
+                    *byte = byte.wrapping_add(1);
+                    if *byte != 0 {
+                        break;
+                    }
+                }
+            }
+        }
+    };
+    (@common $name:ident($len:expr)) => {
+        impl $name {
+            /// `None` if `bs` is not exactly as long as the type.
+            pub fn from_slice(bs: &[u8]) -> Option<$name> {
+                <[u8; $len]>::try_from(bs).ok().map($name)
+            }
+        }
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                subtle::ConstantTimeEq::ct_eq(&self.0[..], &other.0[..]).into()
+            }
+        }
+        impl Eq for $name {}
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+        impl<I: std::slice::SliceIndex<[u8]>> std::ops::Index<I> for $name {
+            type Output = I::Output;
+            fn index(&self, index: I) -> &I::Output {
+                &self.0[index]
+            }
+        }
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&self.0)
+            }
+        }
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                struct Visitor;
+                impl<'de> serde::de::Visitor<'de> for Visitor {
+                    type Value = $name;
+                    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                        write!(f, stringify!($name))
+                    }
+                    fn visit_seq<V: serde::de::SeqAccess<'de>>(
+                        self,
+                        mut seq: V,
+                    ) -> Result<$name, V::Error> {
+                        let mut res = $name([0; $len]);
+                        for (i, byte) in res.0.iter_mut().enumerate() {
+                            *byte = seq
+                                .next_element()?
+                                .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+                        }
+                        Ok(res)
+                    }
+                    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<$name, E> {
+                        $name::from_slice(v).ok_or_else(|| E::invalid_length(v.len(), &self))
+                    }
+                }
+                deserializer.deserialize_bytes(Visitor)
+            }
+        }
+    };
+}
+
+pub mod randombytes {
+    pub fn randombytes(size: usize) -> Vec<u8> {
+        let mut buf = vec![0u8; size];
+        randombytes_into(&mut buf);
+        buf
+    }
+
+    pub fn randombytes_into(buf: &mut [u8]) {
+        getrandom::getrandom(buf).expect("the system random number generator failed");
+    }
+}
+
+pub mod hash {
+    pub mod sha256 {
+        use sha2::{Digest as _, Sha256};
+
+        pub const DIGESTBYTES: usize = 32;
+
+        newtype! {
+            public Digest(DIGESTBYTES);
+        }
+
+        pub fn hash(m: &[u8]) -> Digest {
+            Digest(Sha256::digest(m).into())
+        }
+    }
+}
+
+pub mod auth {
+    macro_rules! hmac {
+        ($hash:ty) => {
+            use hmac::{Hmac, Mac};
+
+            use super::super::randombytes::randombytes_into;
+
+            pub const KEYBYTES: usize = 32;
+            pub const TAGBYTES: usize = 32;
+
+            newtype! {
+                secret Key(KEYBYTES);
+            }
+
+            newtype! {
+                public Tag(TAGBYTES);
+            }
+
+            pub fn gen_key() -> Key {
+                let mut key = Key([0; KEYBYTES]);
+                randombytes_into(&mut key.0);
+                key
+            }
+
+            pub fn authenticate(m: &[u8], k: &Key) -> Tag {
+                let mut state = State::init(&k.0);
+                state.update(m);
+                state.finalize()
+            }
+
+            pub fn verify(tag: &Tag, m: &[u8], k: &Key) -> bool {
+                authenticate(m, k) == *tag
+            }
+
+            /// Multi-part authentication, taking keys of any length.
+            pub struct State(Hmac<$hash>);
+
+            impl State {
+                pub fn init(k: &[u8]) -> State {
+                    State(Mac::new_from_slice(k).expect("hmac takes keys of any length"))
+                }
+
+                pub fn update(&mut self, in_: &[u8]) {
+                    self.0.update(in_);
+                }
+
+                pub fn finalize(self) -> Tag {
+                    Tag::from_slice(&self.0.finalize().into_bytes()[..TAGBYTES]).unwrap()
+                }
+            }
+        };
+    }
+
+    pub use hmacsha512256::*;
+
+    pub mod hmacsha256 {
+        hmac!(sha2::Sha256);
+    }
+
+    /// HMAC-SHA-512 truncated to 256 bits, libsodium's default `crypto_auth`.
+    pub mod hmacsha512256 {
+        hmac!(sha2::Sha512);
+    }
+}
+
+pub mod secretbox {
+    pub use self::xsalsa20poly1305::*;
+
+    pub mod xsalsa20poly1305 {
+        use crypto_secretbox::{
+            aead::{generic_array::GenericArray, AeadInPlace, KeyInit},
+            XSalsa20Poly1305,
+        };
+
+        use super::super::randombytes::randombytes_into;
+
+        pub const KEYBYTES: usize = 32;
+        pub const NONCEBYTES: usize = 24;
+        pub const MACBYTES: usize = 16;
+
+        newtype! {
+            secret Key(KEYBYTES);
+        }
+
+        newtype! {
+            public Tag(MACBYTES);
+        }
+
+        newtype! {
+            nonce Nonce(NONCEBYTES);
+        }
+
+        pub fn gen_key() -> Key {
+            let mut key = Key([0; KEYBYTES]);
+            randombytes_into(&mut key.0);
+            key
+        }
+
+        pub fn gen_nonce() -> Nonce {
+            let mut nonce = Nonce([0; NONCEBYTES]);
+            randombytes_into(&mut nonce.0);
+            nonce
+        }
+
+        fn cipher(k: &Key) -> XSalsa20Poly1305 {
+            XSalsa20Poly1305::new(GenericArray::from_slice(&k.0))
+        }
+
+        /// `m` sealed with `k`, prefixed by its tag as NaCl does.
+        pub fn seal(m: &[u8], n: &Nonce, k: &Key) -> Vec<u8> {
+            let mut c = vec![0u8; MACBYTES + m.len()];
+            c[MACBYTES..].copy_from_slice(m);
+            let tag = seal_detached(&mut c[MACBYTES..], n, k);
+            c[..MACBYTES].copy_from_slice(&tag.0);
+            c
+        }
+
+        pub fn seal_detached(m: &mut [u8], n: &Nonce, k: &Key) -> Tag {
+            let tag = cipher(k)
+                .encrypt_in_place_detached(GenericArray::from_slice(&n.0), b"", m)
+                .expect("message too long for xsalsa20");
+            Tag(tag.into())
+        }
+
+        pub fn open(c: &[u8], n: &Nonce, k: &Key) -> Result<Vec<u8>, ()> {
+            if c.len() < MACBYTES {
+                return Err(());
+            }
+            let tag = Tag::from_slice(&c[..MACBYTES]).unwrap();
+            let mut m = c[MACBYTES..].to_vec();
+            open_detached(&mut m, &tag, n, k)?;
+            Ok(m)
+        }
+
+        /// Leaves `c` untouched when the tag does not match.
+        pub fn open_detached(c: &mut [u8], tag: &Tag, n: &Nonce, k: &Key) -> Result<(), ()> {
+            cipher(k)
+                .decrypt_in_place_detached(
+                    GenericArray::from_slice(&n.0),
+                    b"",
+                    c,
+                    GenericArray::from_slice(&tag.0),
+                )
+                .map_err(|_| ())
+        }
+    }
+}
+
+pub mod scalarmult {
+    pub mod curve25519 {
+        use curve25519_dalek::montgomery::MontgomeryPoint;
+        use subtle::ConstantTimeEq;
+
+        pub const GROUPELEMENTBYTES: usize = 32;
+        pub const SCALARBYTES: usize = 32;
+
+        newtype! {
+            secret Scalar(SCALARBYTES);
+        }
+
+        newtype! {
+            secret GroupElement(GROUPELEMENTBYTES);
+        }
+
+        /// Fails, as libsodium does, when the result is all zeros, which is
+        /// the case for every point of small order.
+        pub fn scalarmult(n: &Scalar, p: &GroupElement) -> Result<GroupElement, ()> {
+            let q = GroupElement(MontgomeryPoint(p.0).mul_clamped(n.0).to_bytes());
+            if bool::from(q.0[..].ct_eq(&[0; GROUPELEMENTBYTES][..])) {
+                return Err(());
+            }
+            Ok(q)
+        }
+
+        pub fn scalarmult_base(n: &Scalar) -> GroupElement {
+            GroupElement(MontgomeryPoint::mul_base_clamped(n.0).to_bytes())
+        }
+    }
+}
+
+pub mod pwhash {
+    pub mod argon2id13 {
+        use argon2::{Algorithm, Argon2, Params, Version};
+
+        use super::super::randombytes::randombytes_into;
+
+        pub const SALTBYTES: usize = 16;
+        pub const OPSLIMIT_INTERACTIVE: OpsLimit = OpsLimit(2);
+        pub const MEMLIMIT_INTERACTIVE: MemLimit = MemLimit(67108864);
+        pub const OPSLIMIT_MODERATE: OpsLimit = OpsLimit(3);
+        pub const MEMLIMIT_MODERATE: MemLimit = MemLimit(268435456);
+        pub const OPSLIMIT_SENSITIVE: OpsLimit = OpsLimit(4);
+        pub const MEMLIMIT_SENSITIVE: MemLimit = MemLimit(1073741824);
+
+        /// The number of passes over the memory.
+        #[derive(Copy, Clone, Debug)]
+        pub struct OpsLimit(pub usize);
+
+        /// The memory used, in bytes.
+        #[derive(Copy, Clone, Debug)]
+        pub struct MemLimit(pub usize);
+
+        newtype! {
+            public Salt(SALTBYTES);
+        }
+
+        pub fn gen_salt() -> Salt {
+            let mut salt = Salt([0; SALTBYTES]);
+            randombytes_into(&mut salt.0);
+            salt
+        }
+
+        /// Fills `key` with argon2id v1.3, single lane, as libsodium does.
+        pub fn derive_key<'a>(
+            key: &'a mut [u8],
+            passwd: &[u8],
+            salt: &Salt,
+            opslimit: OpsLimit,
+            memlimit: MemLimit,
+        ) -> Result<&'a [u8], ()> {
+            if key.len() < 16 || opslimit.0 < 1 {
+                return Err(());
+            }
+            let m_cost = u32::try_from(memlimit.0 / 1024).map_err(|_| ())?;
+            let t_cost = u32::try_from(opslimit.0).map_err(|_| ())?;
+            let params = Params::new(m_cost, t_cost, 1, Some(key.len())).map_err(|_| ())?;
+            Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                .hash_password_into(passwd, &salt.0, key)
+                .map_err(|_| ())?;
+            Ok(key)
+        }
+    }
+}
+
+pub mod sign {
+    pub use self::ed25519::*;
+
+    pub mod ed25519 {
+        use curve25519_dalek::{
+            edwards::{CompressedEdwardsY, EdwardsPoint},
+            scalar::{clamp_integer, Scalar},
+        };
+        use sha2::{Digest, Sha512};
+        use subtle::ConstantTimeEq;
+        use zeroize::{Zeroize, Zeroizing};
+
+        use super::super::{randombytes::randombytes_into, scalarmult::curve25519};
+
+        pub const SEEDBYTES: usize = 32;
+        pub const SECRETKEYBYTES: usize = 64;
+        pub const PUBLICKEYBYTES: usize = 32;
+        pub const SIGNATUREBYTES: usize = 64;
+
+        newtype! {
+            secret Seed(SEEDBYTES);
+        }
+
+        newtype! {
+            /// The seed followed by the public key, as in libsodium.
+            secret SecretKey(SECRETKEYBYTES);
+        }
+
+        newtype! {
+            public PublicKey(PUBLICKEYBYTES);
+        }
+
+        newtype! {
+            public Signature(SIGNATUREBYTES);
+        }
+
+        fn sha512(parts: &[&[u8]]) -> Zeroizing<[u8; 64]> {
+            let mut hasher = Sha512::new();
+            for part in parts {
+                hasher.update(part);
+            }
+            Zeroizing::new(hasher.finalize().into())
+        }
+
+        /// The clamped secret scalar of a seed and the prefix used to derive
+        /// signature nonces.
+        fn expand(seed: &[u8]) -> (Scalar, Zeroizing<[u8; 64]>) {
+            let az = sha512(&[seed]);
+            let mut a = Zeroizing::new([0u8; 32]);
+            a.copy_from_slice(&az[..32]);
+            (Scalar::from_bytes_mod_order(clamp_integer(*a)), az)
+        }
+
+        /// libsodium's `ge25519_is_canonical`: the encoded y is below p.
+        fn is_canonical(s: &[u8; 32]) -> bool {
+            !(s[0] >= 0xed && s[1..31].iter().all(|b| *b == 0xff) && s[31] & 0x7f == 0x7f)
+        }
+
+        /// libsodium's `ge25519_has_small_order`, which ignores the sign bit
+        /// and also catches the non-canonical encodings of 0 and 1.
+        fn has_small_order(s: &[u8; 32]) -> bool {
+            let mut y = *s;
+            y[31] &= 0x7f;
+            CompressedEdwardsY(y)
+                .decompress()
+                .is_some_and(|p| p.is_small_order())
+        }
+
+        impl SecretKey {
+            pub fn public_key(&self) -> PublicKey {
+                PublicKey::from_slice(&self.0[SEEDBYTES..]).unwrap()
+            }
+
+            /// kuska fork api, all zeros on failure.
+            pub fn to_curve25519(&self) -> curve25519::Scalar {
+                to_curve25519_sk(self).unwrap_or(curve25519::Scalar([0; 32]))
+            }
+        }
+
+        impl PublicKey {
+            /// kuska fork api, all zeros on failure.
+            pub fn to_curve25519(&self) -> curve25519::GroupElement {
+                to_curve25519_pk(self).unwrap_or(curve25519::GroupElement([0; 32]))
+            }
+        }
+
+        pub fn gen_keypair() -> (PublicKey, SecretKey) {
+            let mut seed = Seed([0; SEEDBYTES]);
+            randombytes_into(&mut seed.0);
+            keypair_from_seed(&seed)
+        }
+
+        pub fn keypair_from_seed(seed: &Seed) -> (PublicKey, SecretKey) {
+            let (mut a, _) = expand(&seed.0);
+            let pk = PublicKey(EdwardsPoint::mul_base(&a).compress().to_bytes());
+            a.zeroize();
+            let mut sk = SecretKey([0; SECRETKEYBYTES]);
+            sk.0[..SEEDBYTES].copy_from_slice(&seed.0);
+            sk.0[SEEDBYTES..].copy_from_slice(&pk.0);
+            (pk, sk)
+        }
+
+        /// Hashes the public key half of `sk`, as libsodium does.
+        pub fn sign_detached(m: &[u8], sk: &SecretKey) -> Signature {
+            let (mut a, az) = expand(&sk.0[..SEEDBYTES]);
+            let mut r = Scalar::from_bytes_mod_order_wide(&sha512(&[&az[32..], m]));
+            let big_r = EdwardsPoint::mul_base(&r).compress();
+            let k = Scalar::from_bytes_mod_order_wide(&sha512(&[
+                big_r.as_bytes(),
+                &sk.0[SEEDBYTES..],
+                m,
+            ]));
+            let s = r + k * a;
+            a.zeroize();
+            r.zeroize();
+
+            let mut signature = Signature([0; SIGNATUREBYTES]);
+            signature.0[..32].copy_from_slice(big_r.as_bytes());
+            signature.0[32..].copy_from_slice(s.as_bytes());
+            signature
+        }
+
+        /// libsodium's verification: rejects a non-canonical `S`, a small
+        /// order `R` or `A` and a non-canonical `A`, then checks the
+        /// cofactorless equation against the encoding of `R`.
+        pub fn verify_detached(sig: &Signature, m: &[u8], pk: &PublicKey) -> bool {
+            let r: &[u8; 32] = sig.0[..32].try_into().unwrap();
+            let s: [u8; 32] = sig.0[32..].try_into().unwrap();
+            let Some(s) = Option::<Scalar>::from(Scalar::from_canonical_bytes(s)) else {
+                return false;
+            };
+            if has_small_order(r) || !is_canonical(&pk.0) || has_small_order(&pk.0) {
+                return false;
+            }
+            let Some(a) = CompressedEdwardsY(pk.0).decompress() else {
+                return false;
+            };
+            let k = Scalar::from_bytes_mod_order_wide(&sha512(&[r, &pk.0, m]));
+            let check = EdwardsPoint::vartime_double_scalar_mul_basepoint(&k, &-a, &s);
+            check.compress().as_bytes()[..].ct_eq(&r[..]).into()
+        }
+
+        /// The birationally equivalent curve25519 key; fails on keys of
+        /// small order or with a torsion component. Like libsodium, which
+        /// only checks that `[L]A` has a zero x, it lets an order 2
+        /// component through.
+        pub fn to_curve25519_pk(pk: &PublicKey) -> Result<curve25519::GroupElement, ()> {
+            if has_small_order(&pk.0) {
+                return Err(());
+            }
+            let a = CompressedEdwardsY(pk.0).decompress().ok_or(())?;
+            if !(a + a).is_torsion_free() {
+                return Err(());
+            }
+            Ok(curve25519::GroupElement(a.to_montgomery().to_bytes()))
+        }
+
+        pub fn to_curve25519_sk(sk: &SecretKey) -> Result<curve25519::Scalar, ()> {
+            let az = sha512(&[&sk.0[..SEEDBYTES]]);
+            let mut scalar = curve25519::Scalar([0; 32]);
+            scalar.0.copy_from_slice(&az[..32]);
+            scalar.0 = clamp_integer(scalar.0);
+            Ok(scalar)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sodium"))]
+mod test {
+    use curve25519_dalek::{
+        constants::EIGHT_TORSION, edwards::EdwardsPoint, scalar::Scalar, traits::IsIdentity,
+    };
+    use kuska_sodiumoxide::crypto as sodium;
+
+    use super::*;
+
+    /// Edwards point encodings libsodium treats specially: the torsion
+    /// points, a key with a torsion component, and the non-canonical ones.
+    fn edge_encodings(valid: [u8; 32]) -> Vec<[u8; 32]> {
+        let mut encodings = vec![valid];
+        let point = sign::ed25519::PublicKey(valid);
+        let point = curve25519_dalek::edwards::CompressedEdwardsY(point.0)
+            .decompress()
+            .unwrap();
+        for torsion in EIGHT_TORSION {
+            encodings.push(torsion.compress().to_bytes());
+            encodings.push((point + torsion).compress().to_bytes());
+        }
+        for k in 0..19u8 {
+            let mut y = [0xff; 32];
+            y[0] = 0xed + k;
+            y[31] = 0x7f;
+            encodings.push(y);
+        }
+        let with_sign: Vec<_> = encodings
+            .iter()
+            .map(|e| {
+                let mut e = *e;
+                e[31] ^= 0x80;
+                e
+            })
+            .collect();
+        encodings.extend(with_sign);
+        encodings
+    }
+
+    #[test]
+    fn test_ed25519_matches_libsodium() {
+        for i in 0..8u8 {
+            let seed = [i; 32];
+            let (pk, sk) = sign::keypair_from_seed(&sign::Seed(seed));
+            let (sodium_pk, sodium_sk) = sodium::sign::keypair_from_seed(&sodium::sign::Seed(seed));
+            assert_eq!(pk.0, sodium_pk.0);
+            assert_eq!(sk.0, sodium_sk.0);
+            assert_eq!(sk.public_key().0, pk.0);
+
+            let signature = sign::sign_detached(&[i], &sk);
+            assert_eq!(signature.0, sodium::sign::sign_detached(&[i], &sodium_sk).0);
+            assert_eq!(
+                sign::to_curve25519_sk(&sk).unwrap().0,
+                sodium::sign::to_curve25519_sk(&sodium_sk).unwrap().0
+            );
+        }
+    }
+
+    #[test]
+    fn test_ed25519_edge_cases_match_libsodium() {
+        let (pk, sk) = sign::keypair_from_seed(&sign::Seed([7; 32]));
+        let signature = sign::sign_detached(b"msg", &sk);
+        let r =
+            curve25519_dalek::edwards::CompressedEdwardsY(signature.0[..32].try_into().unwrap())
+                .decompress()
+                .unwrap();
+
+        let mut rs: Vec<[u8; 32]> = edge_encodings(signature.0[..32].try_into().unwrap());
+        rs.push((r + EIGHT_TORSION[1]).compress().to_bytes());
+        for a in edge_encodings(pk.0) {
+            let ours = sign::to_curve25519_pk(&sign::PublicKey(a)).map(|k| k.0);
+            let theirs = sodium::sign::to_curve25519_pk(&sodium::sign::PublicKey(a)).map(|k| k.0);
+            assert_eq!(ours, theirs, "to_curve25519_pk {}", hex::encode(a));
+
+            for r in &rs {
+                for s in [&signature.0[32..], &[0; 32][..], &[0xff; 32][..]] {
+                    let mut sig = [0u8; 64];
+                    sig[..32].copy_from_slice(r);
+                    sig[32..].copy_from_slice(s);
+                    let ours =
+                        sign::verify_detached(&sign::Signature(sig), b"msg", &sign::PublicKey(a));
+                    let theirs = sodium::sign::verify_detached(
+                        &sodium::sign::Signature(sig),
+                        b"msg",
+                        &sodium::sign::PublicKey(a),
+                    );
+                    assert_eq!(
+                        ours,
+                        theirs,
+                        "verify {} {}",
+                        hex::encode(sig),
+                        hex::encode(a)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ed25519_accepts_torsion_component_like_libsodium() {
+        // A' = A + T with T of order 8 verifies with the cofactorless
+        // equation when the challenge is a multiple of 8
+        let a = Scalar::from_bytes_mod_order([3; 32]);
+        let torsion = EIGHT_TORSION[1];
+        let pk = (EdwardsPoint::mul_base(&a) + torsion).compress().to_bytes();
+        let r = Scalar::from_bytes_mod_order([5; 32]);
+        let big_r = EdwardsPoint::mul_base(&r).compress().to_bytes();
+        let (msg, k) = (0u32..)
+            .map(|i| {
+                let msg = i.to_le_bytes();
+                let mut hasher = <sha2::Sha512 as sha2::Digest>::new();
+                sha2::Digest::update(&mut hasher, big_r);
+                sha2::Digest::update(&mut hasher, pk);
+                sha2::Digest::update(&mut hasher, msg);
+                (
+                    msg,
+                    Scalar::from_bytes_mod_order_wide(&sha2::Digest::finalize(hasher).into()),
+                )
+            })
+            .find(|(_, k)| (k * torsion).is_identity())
+            .unwrap();
+        let mut sig = [0u8; 64];
+        sig[..32].copy_from_slice(&big_r);
+        sig[32..].copy_from_slice((r + k * a).as_bytes());
+
+        assert!(sign::verify_detached(
+            &sign::Signature(sig),
+            &msg,
+            &sign::PublicKey(pk)
+        ));
+        assert!(sodium::sign::verify_detached(
+            &sodium::sign::Signature(sig),
+            &msg,
+            &sodium::sign::PublicKey(pk)
+        ));
+    }
+
+    #[test]
+    fn test_scalarmult_matches_libsodium() {
+        let n = [9u8; 32];
+        let (pk, _) = sign::keypair_from_seed(&sign::Seed([1; 32]));
+        let mut points: Vec<[u8; 32]> = edge_encodings(pk.0)
+            .iter()
+            .filter_map(|a| {
+                sign::to_curve25519_pk(&sign::PublicKey(*a))
+                    .ok()
+                    .map(|k| k.0)
+            })
+            .collect();
+        points.extend([[0; 32], [1; 32], [0xff; 32]]);
+        for k in 0..19u8 {
+            let mut u = [0xff; 32];
+            u[0] = 0xed + k;
+            u[31] = 0x7f;
+            points.push(u);
+            let mut u = [0; 32];
+            u[0] = k;
+            points.push(u);
+        }
+        for p in points {
+            let ours = scalarmult::curve25519::scalarmult(
+                &scalarmult::curve25519::Scalar(n),
+                &scalarmult::curve25519::GroupElement(p),
+            )
+            .map(|q| q.0);
+            let theirs = sodium::scalarmult::curve25519::scalarmult(
+                &sodium::scalarmult::curve25519::Scalar(n),
+                &sodium::scalarmult::curve25519::GroupElement(p),
+            )
+            .map(|q| q.0);
+            assert_eq!(ours, theirs, "scalarmult {}", hex::encode(p));
+        }
+        assert_eq!(
+            scalarmult::curve25519::scalarmult_base(&scalarmult::curve25519::Scalar(n)).0,
+            sodium::scalarmult::curve25519::scalarmult_base(
+                &sodium::scalarmult::curve25519::Scalar(n)
+            )
+            .0
+        );
+    }
+
+    #[test]
+    fn test_hash_and_auth_match_libsodium() {
+        let msg = b"the quick brown fox";
+        assert_eq!(hash::sha256::hash(msg).0, sodium::hash::sha256::hash(msg).0);
+
+        let key = [4u8; 32];
+        assert_eq!(
+            auth::authenticate(msg, &auth::Key(key)).0,
+            sodium::auth::authenticate(msg, &sodium::auth::Key(key)).0
+        );
+        for key in [&[4u8; 32][..], &[5u8; 100][..]] {
+            let mut ours = auth::hmacsha256::State::init(key);
+            let mut theirs = sodium::auth::hmacsha256::State::init(key);
+            ours.update(msg);
+            theirs.update(msg);
+            assert_eq!(ours.finalize().0, theirs.finalize().0);
+        }
+    }
+
+    #[test]
+    fn test_secretbox_matches_libsodium() {
+        let (key, nonce) = ([6u8; 32], [7u8; 24]);
+        let ours = (secretbox::Key(key), secretbox::Nonce(nonce));
+        let theirs = (sodium::secretbox::Key(key), sodium::secretbox::Nonce(nonce));
+
+        for msg in [&b""[..], &b"hello"[..], &[8u8; 1000][..]] {
+            let sealed = secretbox::seal(msg, &ours.1, &ours.0);
+            assert_eq!(sealed, sodium::secretbox::seal(msg, &theirs.1, &theirs.0));
+            assert_eq!(secretbox::open(&sealed, &ours.1, &ours.0).unwrap(), msg);
+
+            let mut tampered = sealed.clone();
+            tampered[0] ^= 1;
+            assert!(secretbox::open(&tampered, &ours.1, &ours.0).is_err());
+        }
+        assert!(secretbox::open(&[0; 15], &ours.1, &ours.0).is_err());
+        assert_eq!(ours.1.increment_le().0, theirs.1.increment_le().0);
+    }
+
+    #[test]
+    fn test_argon2id13_matches_libsodium() {
+        use pwhash::argon2id13::*;
+
+        let salt = [1u8; SALTBYTES];
+        let (mut ours, mut theirs) = ([0u8; 32], [0u8; 32]);
+        derive_key(
+            &mut ours,
+            b"secret",
+            &Salt(salt),
+            OpsLimit(2),
+            MemLimit(1 << 20),
+        )
+        .unwrap();
+        sodium::pwhash::argon2id13::derive_key(
+            &mut theirs,
+            b"secret",
+            &sodium::pwhash::argon2id13::Salt(salt),
+            sodium::pwhash::argon2id13::OpsLimit(2),
+            sodium::pwhash::argon2id13::MemLimit(1 << 20),
+        )
+        .unwrap();
+        assert_eq!(ours, theirs);
+    }
+}
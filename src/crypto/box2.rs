@@ -0,0 +1,330 @@
+//! The envelope spec (box2) used by private groups: the body is encrypted
+//! with keys derived from a random message key, which is xored into one
+//! key slot per recipient key. Compatible with js envelope-js.
+//!
+//! All derivations are bound to the author and the previous message, so the
+//! same envelope can only be read in its position in the feed.
+
+use std::fmt;
+
+use zeroize::{Zeroize, Zeroizing};
+
+use super::backend::{auth::hmacsha256, secretbox};
+use super::error::{Error, Result};
+use crate::feed::bfe;
+
+pub const SUFFIX: &str = ".box2";
+
+/// Maximum number of key slots, as in ssb-box2.
+pub const MAX_SLOTS: usize = 16;
+
+/// Scheme of the symmetric key of a private group.
+pub const SCHEME_GROUP: &str = "envelope-large-symmetric-group";
+/// Scheme of the key shared by two feeds for direct messages.
+pub const SCHEME_DM: &str = "envelope-id-based-dm-converted-ed25519";
+
+const KEY_LEN: usize = 32;
+const SLOT_LEN: usize = 32;
+const HEADER_LEN: usize = 16;
+const HEADER_BOX_LEN: usize = HEADER_LEN + secretbox::MACBYTES;
+
+/// A key a message can be encrypted to, with the scheme it is used with.
+/// The key is zeroized on drop and hidden from `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RecipientKey {
+    pub key: [u8; KEY_LEN],
+    pub scheme: String,
+}
+
+impl RecipientKey {
+    pub fn new(key: [u8; KEY_LEN], scheme: &str) -> Self {
+        RecipientKey {
+            key,
+            scheme: scheme.to_string(),
+        }
+    }
+}
+
+impl fmt::Debug for RecipientKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecipientKey")
+            .field("key", &"****")
+            .field("scheme", &self.scheme)
+            .finish()
+    }
+}
+
+impl Drop for RecipientKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// Shallow length-prefixed encoding of `parts`.
+pub(crate) fn slp_encode(parts: &[&[u8]]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for part in parts {
+        encoded.extend_from_slice(&(part.len() as u16).to_le_bytes());
+        encoded.extend_from_slice(part);
+    }
+    encoded
+}
+
+/// HKDF-Expand with sha256 of a single block.
+pub(crate) fn hkdf_expand(key: &[u8], info: &[u8]) -> [u8; KEY_LEN] {
+    let mut state = hmacsha256::State::init(key);
+    state.update(info);
+    state.update(&[1]);
+    let mut out = [0u8; KEY_LEN];
+    out.copy_from_slice(state.finalize().as_ref());
+    out
+}
+
+/// The bfe of the previous message, a zeroed classic message id for the
+/// first message of a feed.
+fn prev_msg_bfe(prev_msg_id: Option<&str>) -> Vec<u8> {
+    match prev_msg_id {
+        Some(id) => bfe::encode_str(id),
+        None => {
+            let mut encoded = vec![1, 0];
+            encoded.extend_from_slice(&[0u8; 32]);
+            encoded
+        }
+    }
+}
+
+struct Derive {
+    feed_id: Vec<u8>,
+    prev_msg_id: Vec<u8>,
+}
+
+impl Derive {
+    fn new(feed_id: &str, prev_msg_id: Option<&str>) -> Self {
+        Derive {
+            feed_id: bfe::encode_str(feed_id),
+            prev_msg_id: prev_msg_bfe(prev_msg_id),
+        }
+    }
+
+    fn secret(&self, key: &[u8], labels: &[&[u8]]) -> [u8; KEY_LEN] {
+        let mut info: Vec<&[u8]> = vec![b"envelope", &self.feed_id, &self.prev_msg_id];
+        info.extend_from_slice(labels);
+        hkdf_expand(key, &slp_encode(&info))
+    }
+
+    fn slot_key(&self, recipient: &RecipientKey) -> [u8; KEY_LEN] {
+        self.secret(&recipient.key, &[b"slot_key", recipient.scheme.as_bytes()])
+    }
+
+    fn read_key(&self, msg_key: &[u8; KEY_LEN]) -> Zeroizing<[u8; KEY_LEN]> {
+        Zeroizing::new(self.secret(msg_key, &[b"read_key"]))
+    }
+
+    /// (header_key, body_key) of `read_key`.
+    fn message_keys(&self, read_key: &[u8; KEY_LEN]) -> (secretbox::Key, secretbox::Key) {
+        (
+            secretbox::Key(self.secret(read_key, &[b"header_key"])),
+            secretbox::Key(self.secret(read_key, &[b"body_key"])),
+        )
+    }
+
+    /// The read key and body offset of `ciphertext`, from the first of
+    /// `keys` that opens its header.
+    fn open_header(
+        &self,
+        ciphertext: &[u8],
+        keys: &[RecipientKey],
+    ) -> Result<Option<(Zeroizing<[u8; KEY_LEN]>, usize)>> {
+        if ciphertext.len() < HEADER_BOX_LEN + SLOT_LEN + secretbox::MACBYTES {
+            return Err(Error::FailedToDecipher);
+        }
+        let nonce = secretbox::Nonce([0u8; secretbox::NONCEBYTES]);
+        let header_box = &ciphertext[..HEADER_BOX_LEN];
+        let slots = &ciphertext[HEADER_BOX_LEN..];
+
+        for key in keys {
+            let slot_key = self.slot_key(key);
+            for slot in slots.chunks_exact(SLOT_LEN).take(MAX_SLOTS) {
+                let msg_key = Zeroizing::new(xor(slot, &slot_key));
+                let read_key = self.read_key(&msg_key);
+                let (header_key, _) = self.message_keys(&read_key);
+                if let Ok(header) = secretbox::open(header_box, &nonce, &header_key) {
+                    let offset = u16::from_le_bytes([header[0], header[1]]) as usize;
+                    return Ok(Some((read_key, offset)));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn xor(a: &[u8], b: &[u8; KEY_LEN]) -> [u8; KEY_LEN] {
+    let mut out = [0u8; KEY_LEN];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = a[i] ^ b[i];
+    }
+    out
+}
+
+/// Encrypts `plaintext`, the content of the message following
+/// `prev_msg_id` in `feed_id`, for `recipients`.
+pub fn encrypt(
+    plaintext: &[u8],
+    feed_id: &str,
+    prev_msg_id: Option<&str>,
+    recipients: &[RecipientKey],
+) -> Result<Vec<u8>> {
+    let msg_key = Zeroizing::new(secretbox::gen_key().0);
+    encrypt_with_key(plaintext, feed_id, prev_msg_id, &msg_key, recipients)
+}
+
+/// Like `encrypt`, with a given message key.
+pub fn encrypt_with_key(
+    plaintext: &[u8],
+    feed_id: &str,
+    prev_msg_id: Option<&str>,
+    msg_key: &[u8; KEY_LEN],
+    recipients: &[RecipientKey],
+) -> Result<Vec<u8>> {
+    if plaintext.is_empty() {
+        return Err(Error::EmptyPlaintext);
+    }
+    if recipients.is_empty() || recipients.len() > MAX_SLOTS {
+        return Err(Error::BadRecipientCount);
+    }
+
+    let derive = Derive::new(feed_id, prev_msg_id);
+    let (header_key, body_key) = derive.message_keys(&derive.read_key(msg_key));
+    let nonce = secretbox::Nonce([0u8; secretbox::NONCEBYTES]);
+
+    let offset = HEADER_BOX_LEN + SLOT_LEN * recipients.len();
+    let mut header = [0u8; HEADER_LEN];
+    header[..2].copy_from_slice(&(offset as u16).to_le_bytes());
+
+    let mut envelope = secretbox::seal(&header, &nonce, &header_key);
+    for recipient in recipients {
+        envelope.extend_from_slice(&xor(msg_key, &derive.slot_key(recipient)));
+    }
+    envelope.extend_from_slice(&secretbox::seal(plaintext, &nonce, &body_key));
+    Ok(envelope)
+}
+
+/// Decrypts an envelope with the first of `keys` it was encrypted to,
+/// `None` if there is none.
+pub fn decrypt(
+    ciphertext: &[u8],
+    feed_id: &str,
+    prev_msg_id: Option<&str>,
+    keys: &[RecipientKey],
+) -> Result<Option<Vec<u8>>> {
+    let derive = Derive::new(feed_id, prev_msg_id);
+    let (read_key, offset) = match derive.open_header(ciphertext, keys)? {
+        Some(opened) => opened,
+        None => return Ok(None),
+    };
+    let (_, body_key) = derive.message_keys(&read_key);
+    let nonce = secretbox::Nonce([0u8; secretbox::NONCEBYTES]);
+    let body = ciphertext.get(offset..).ok_or(Error::FailedToDecipher)?;
+    let plaintext =
+        secretbox::open(body, &nonce, &body_key).map_err(|_| Error::FailedToDecipher)?;
+    Ok(Some(plaintext))
+}
+
+/// The read key of an envelope, the secret its header and body keys are
+/// derived from, with the first of `keys` it was encrypted to.
+pub fn read_key(
+    ciphertext: &[u8],
+    feed_id: &str,
+    prev_msg_id: Option<&str>,
+    keys: &[RecipientKey],
+) -> Result<Option<Zeroizing<[u8; KEY_LEN]>>> {
+    let derive = Derive::new(feed_id, prev_msg_id);
+    Ok(derive
+        .open_header(ciphertext, keys)?
+        .map(|(read_key, _)| read_key))
+}
+
+/// Encrypts `plaintext` as a `<base64>.box2` string.
+pub fn box2_cipher(
+    plaintext: &str,
+    feed_id: &str,
+    prev_msg_id: Option<&str>,
+    recipients: &[RecipientKey],
+) -> Result<String> {
+    let ciphertext = encrypt(plaintext.as_bytes(), feed_id, prev_msg_id, recipients)?;
+    Ok(format!("{}{}", base64::encode(&ciphertext), SUFFIX))
+}
+
+/// Decrypts a `<base64>.box2` string.
+pub fn box2_decipher(
+    ciphertext: &str,
+    feed_id: &str,
+    prev_msg_id: Option<&str>,
+    keys: &[RecipientKey],
+) -> Result<Option<String>> {
+    let ciphertext = ciphertext
+        .strip_suffix(SUFFIX)
+        .ok_or(Error::InvalidSuffix)?;
+    let plaintext = decrypt(&base64::decode(ciphertext)?, feed_id, prev_msg_id, keys)?;
+    Ok(plaintext.map(|plaintext| String::from_utf8_lossy(&plaintext).to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keystore::OwnedIdentity;
+
+    #[test]
+    fn test_box2() -> Result<()> {
+        let author = OwnedIdentity::create().id;
+        let prev = "%R8heq/tQoxEIPkWf0Kxn1nCm/CsxG2CDpUYnAvdbXY8=.sha256";
+        let keys: Vec<_> = (1..=3u8)
+            .map(|n| RecipientKey::new([n; 32], SCHEME_GROUP))
+            .collect();
+
+        let ciphertext = box2_cipher("{\"type\":\"post\"}", &author, Some(prev), &keys[..2])?;
+        assert!(ciphertext.ends_with(SUFFIX));
+
+        // found in the second slot
+        let plaintext = box2_decipher(&ciphertext, &author, Some(prev), &keys[1..])?;
+        assert_eq!(plaintext.as_deref(), Some("{\"type\":\"post\"}"));
+        assert_eq!(
+            box2_decipher(&ciphertext, &author, Some(prev), &keys[2..])?,
+            None
+        );
+
+        // bound to the position in the feed and the scheme
+        assert_eq!(box2_decipher(&ciphertext, &author, None, &keys)?, None);
+        let other_scheme = RecipientKey::new([1; 32], SCHEME_DM);
+        assert_eq!(
+            box2_decipher(&ciphertext, &author, Some(prev), &[other_scheme])?,
+            None
+        );
+
+        assert!(encrypt(b"x", &author, None, &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_box2_fixed_key() -> Result<()> {
+        // output of this crate, to catch changes of the derivations
+        let author = "@iojj3XQJ8ZX9UtstPLpdcspnCb8dlBIb83SIAbQPb1w=.ed25519";
+        let prev = "%Z0rMVMDEO1Aj0uiO7eNxEjwm4i2PnK4TeuPDCu8sYBE=.sha256";
+        let keys = [RecipientKey::new([2; 32], SCHEME_GROUP)];
+        let ciphertext =
+            encrypt_with_key(b"squeamish ossifrage", author, Some(prev), &[3; 32], &keys)?;
+        assert_eq!(
+            base64::encode(&ciphertext),
+            concat!(
+                "A8JtXA1CZMM5wqExSheCdIvmkmrO49jLZdLFTFl2216+bLHecWnEheQ1F/TbdqKH",
+                "ozZRVnK7G2WhaDQswQcjrspPV6XKGONQll055IOOT0aqAGWHLmNdPUeKJSCWf8Me",
+                "Vk1K"
+            )
+        );
+        assert_eq!(
+            decrypt(&ciphertext, author, Some(prev), &keys)?.as_deref(),
+            Some(b"squeamish ossifrage".as_ref())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,54 @@
+//! Conversion of ed25519 keys to the curve25519 keys used for key
+//! agreement, as done by private-box, box2 direct messages and the
+//! secret handshake. Unlike the `to_curve25519` methods of the keys, these
+//! fail on keys that are not valid points.
+
+use super::backend::{scalarmult::curve25519, sign::ed25519};
+use super::{
+    error::{Error, Result},
+    ToSodiumObject,
+};
+
+/// The curve25519 public key of an ed25519 public key.
+pub fn ed25519_pk_to_curve25519(pk: &ed25519::PublicKey) -> Result<curve25519::GroupElement> {
+    let pk = ed25519::to_curve25519_pk(pk).map_err(|_| Error::BadPublicKey)?;
+    Ok(curve25519::GroupElement(pk.0))
+}
+
+/// The curve25519 secret key of an ed25519 secret key.
+pub fn ed25519_sk_to_curve25519(sk: &ed25519::SecretKey) -> Result<curve25519::Scalar> {
+    let sk = ed25519::to_curve25519_sk(sk).map_err(|_| Error::BadSecretKey)?;
+    Ok(curve25519::Scalar(sk.0))
+}
+
+/// The curve25519 public key of a `@...ed25519` feed id.
+pub fn feed_id_to_curve25519(id: &str) -> Result<curve25519::GroupElement> {
+    ed25519_pk_to_curve25519(&id.strip_prefix('@').unwrap_or(id).to_ed25519_pk()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keystore::OwnedIdentity;
+
+    #[test]
+    fn test_curve25519_agreement() -> Result<()> {
+        let (a, b) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let ab = curve25519::scalarmult(
+            &ed25519_sk_to_curve25519(&a.sk)?,
+            &feed_id_to_curve25519(&b.id)?,
+        )
+        .unwrap();
+        let ba = curve25519::scalarmult(
+            &ed25519_sk_to_curve25519(&b.sk)?,
+            &ed25519_pk_to_curve25519(&a.pk)?,
+        )
+        .unwrap();
+        assert_eq!(ab, ba);
+        assert_eq!(ed25519_pk_to_curve25519(&a.pk)?, a.pk.to_curve25519());
+
+        let not_a_point = ed25519::PublicKey([0xff; ed25519::PUBLICKEYBYTES]);
+        assert!(ed25519_pk_to_curve25519(&not_a_point).is_err());
+        Ok(())
+    }
+}
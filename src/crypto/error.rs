@@ -14,6 +14,32 @@ pub enum Error {
     InvalidSuffix,
     #[error("cannot create signature")]
     CannotCreateSignature,
+    #[error("failed to decipher")]
+    FailedToDecipher,
+    #[error("cannot create key")]
+    CannotCreateKey,
+    #[error("cannot read nonce")]
+    CannotReadNonce,
+    #[error("crypto scalar mult failed")]
+    CryptoScalarMultFailed,
+    #[error("empty plaintext")]
+    EmptyPlaintext,
+    #[error("bad recipent")]
+    BadRecipientCount,
+    #[error("invalid key from group")]
+    CryptoKeyFromGrupFailed,
+    #[error("unknown group {0}")]
+    UnknownGroup(String),
+    #[error("signer failed: {0}")]
+    Signer(String),
+    #[error("invalid fusion identity id")]
+    InvalidFusionId,
+    #[error("invalid proof of key")]
+    InvalidProofOfKey,
+    #[error("invalid attestation")]
+    InvalidAttestation,
+    #[error("invalid secret blob reference")]
+    InvalidSecretBlobRef,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
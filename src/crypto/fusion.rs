@@ -0,0 +1,206 @@
+//! Key material of fusion identities (ssb-fusion-identity): an ed25519
+//! keypair shared by the devices of a person, named by a
+//! `ssb:identity/fusion/...` id. Members prove they hold the fusion key
+//! with a signed nonce, and feeds attest to redirects with signed
+//! objects that can be checked outside any feed.
+
+use serde_json::{json, Value};
+
+use super::backend::sign::ed25519;
+use super::{
+    error::{Error, Result},
+    sign_detached, verify_detached, ToSodiumObject, ED25519_SIGNATURE_SUFFIX,
+};
+use crate::{
+    feed::{sign_obj, verify_obj},
+    keystore::OwnedIdentity,
+    uri::normalize_id,
+};
+
+pub const FUSION_ID_PREFIX: &str = "ssb:identity/fusion/";
+pub const TYPE_FUSION_INIT: &str = "fusion/init";
+pub const TYPE_PROOF_OF_KEY: &str = "fusion/proof-of-key";
+pub const TYPE_ATTESTATION: &str = "fusion/attestation";
+
+/// The fusion id of the fusion key `pk`.
+pub fn fusion_id(pk: &ed25519::PublicKey) -> String {
+    format!(
+        "{}{}",
+        FUSION_ID_PREFIX,
+        base64::encode_config(pk, base64::URL_SAFE)
+    )
+}
+
+/// The fusion key named by `id`.
+pub fn fusion_pk(id: &str) -> Result<ed25519::PublicKey> {
+    let data = id
+        .strip_prefix(FUSION_ID_PREFIX)
+        .ok_or(Error::InvalidFusionId)?;
+    let bytes = base64::decode_config(data, base64::URL_SAFE)?;
+    ed25519::PublicKey::from_slice(&bytes).ok_or(Error::BadPublicKey)
+}
+
+/// The content of the message of `member` starting the fusion identity
+/// of `fusion`.
+pub fn fusion_init_content(fusion: &OwnedIdentity, member: &str) -> Value {
+    json!({
+        "type": TYPE_FUSION_INIT,
+        "id": fusion_id(&fusion.pk),
+        "members": {"add": [normalize_id(member)]},
+        "tangles": {"fusion": {"root": null, "previous": null}},
+    })
+}
+
+fn proof_bytes(consent_id: &str, nonce: &[u8]) -> Vec<u8> {
+    [consent_id.as_bytes(), nonce].concat()
+}
+
+/// Signature by the fusion key of the id of a member's consent message
+/// followed by `nonce`, proving the member was entrusted with the key.
+pub fn proof_of_key(fusion: &OwnedIdentity, consent_id: &str, nonce: &[u8]) -> String {
+    let signature = sign_detached(&proof_bytes(consent_id, nonce), &fusion.sk);
+    format!("{}{}", base64::encode(&signature), ED25519_SIGNATURE_SUFFIX)
+}
+
+/// Checks a `proof_of_key` made with the key named by `fusion_id`.
+pub fn verify_proof_of_key(
+    fusion_id: &str,
+    consent_id: &str,
+    nonce: &[u8],
+    proof: &str,
+) -> Result<()> {
+    let signature = proof.to_ed25519_signature()?;
+    if verify_detached(
+        &signature,
+        &proof_bytes(consent_id, nonce),
+        &fusion_pk(fusion_id)?,
+    ) {
+        Ok(())
+    } else {
+        Err(Error::InvalidProofOfKey)
+    }
+}
+
+/// The content of a `fusion/proof-of-key` message, with a fresh nonce.
+pub fn proof_of_key_content(
+    fusion: &OwnedIdentity,
+    consent_id: &str,
+    root: &str,
+    previous: &[&str],
+) -> Value {
+    let nonce = super::backend::randombytes::randombytes(32);
+    json!({
+        "type": TYPE_PROOF_OF_KEY,
+        "id": fusion_id(&fusion.pk),
+        "consentId": consent_id,
+        "nonce": base64::encode(&nonce),
+        "proofOfKey": proof_of_key(fusion, consent_id, &nonce),
+        "tangles": {"fusion": {"root": root, "previous": previous}},
+    })
+}
+
+/// Stance of an attestation on a redirect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Confirm,
+    Reject,
+    /// Withdraws an earlier attestation.
+    Null,
+}
+
+impl Position {
+    fn to_value(self) -> Value {
+        match self {
+            Position::Confirm => json!("confirm"),
+            Position::Reject => json!("reject"),
+            Position::Null => Value::Null,
+        }
+    }
+
+    fn from_value(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(s) if s == "confirm" => Ok(Position::Confirm),
+            Value::String(s) if s == "reject" => Ok(Position::Reject),
+            Value::Null => Ok(Position::Null),
+            _ => Err(Error::InvalidAttestation),
+        }
+    }
+}
+
+/// An attestation by `author` on the redirect message `target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+    pub author: String,
+    pub target: String,
+    pub position: Position,
+    pub reason: Option<String>,
+}
+
+/// The attestation of `identity` on `target`, signed as by ssb-keys
+/// `signObj` so it can be checked on its own.
+pub fn sign_attestation(
+    identity: &OwnedIdentity,
+    target: &str,
+    position: Position,
+    reason: Option<&str>,
+) -> Result<Value> {
+    let mut payload = json!({
+        "type": TYPE_ATTESTATION,
+        "author": identity.id,
+        "target": target,
+        "position": position.to_value(),
+    });
+    if let Some(reason) = reason {
+        payload["reason"] = json!(reason);
+    }
+    sign_obj(identity, &payload).map_err(|_| Error::CannotCreateSignature)
+}
+
+/// Checks the fields and the signature of an attestation by its author.
+pub fn verify_attestation(signed: &Value) -> Result<Attestation> {
+    let field = |name: &str| signed.get(name).and_then(Value::as_str);
+    if field("type") != Some(TYPE_ATTESTATION) {
+        return Err(Error::InvalidAttestation);
+    }
+    let author = field("author").ok_or(Error::InvalidAttestation)?;
+    verify_obj(author, signed).map_err(|_| Error::InvalidAttestation)?;
+    Ok(Attestation {
+        author: author.to_string(),
+        target: field("target")
+            .ok_or(Error::InvalidAttestation)?
+            .to_string(),
+        position: Position::from_value(signed.get("position").unwrap_or(&Value::Null))?,
+        reason: field("reason").map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fusion_keys() -> Result<()> {
+        let fusion = OwnedIdentity::create();
+        let id = fusion_id(&fusion.pk);
+        assert!(!id[FUSION_ID_PREFIX.len()..].contains(['+', '/']));
+        assert_eq!(fusion_pk(&id)?, fusion.pk);
+
+        let consent = "%Cg0ZpZ8cV85G8UIIropgBOvM8+Srlv9LSGDNGnpdK44=.sha256";
+        let content = proof_of_key_content(&fusion, consent, consent, &[consent]);
+        let nonce = base64::decode(content["nonce"].as_str().unwrap())?;
+        let proof = content["proofOfKey"].as_str().unwrap();
+        verify_proof_of_key(&id, consent, &nonce, proof)?;
+        assert!(verify_proof_of_key(&id, consent, b"other", proof).is_err());
+
+        let member = OwnedIdentity::create();
+        let signed = sign_attestation(&member, consent, Position::Confirm, Some("it me"))?;
+        let attestation = verify_attestation(&signed)?;
+        assert_eq!(attestation.author, member.id);
+        assert_eq!(attestation.position, Position::Confirm);
+
+        let mut forged = signed;
+        forged["position"] = json!("reject");
+        assert!(verify_attestation(&forged).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,365 @@
+//! Private groups on top of box2: the symmetric key of each group, the
+//! keys shared by two feeds for direct messages and the messages adding
+//! members, with a store to look up the keys to try on a message.
+
+use std::{collections::HashMap, fmt};
+
+use serde_json::{json, Value};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::backend::{
+    auth::hmacsha256, hash::sha256, scalarmult::curve25519, secretbox, sign::ed25519,
+};
+use super::{
+    box2::{self, hkdf_expand, slp_encode, RecipientKey, MAX_SLOTS, SCHEME_DM, SCHEME_GROUP},
+    ed25519_pk_to_curve25519, ed25519_sk_to_curve25519,
+    error::{Error, Result},
+    ToSodiumObject,
+};
+use crate::{
+    feed::{bfe, Message},
+    keystore::OwnedIdentity,
+    uri::normalize_id,
+};
+
+pub const GROUP_SUFFIX: &str = ".cloaked";
+pub const TYPE_GROUP_INIT: &str = "group/init";
+pub const TYPE_ADD_MEMBER: &str = "group/add-member";
+
+const DM_INFO: &[u8] = b"envelope-ssb-dm-v1/shared-key";
+const DM_SALT: &[u8] = b"envelope-dm-v1-extract-salt";
+
+/// A private group: its `%...cloaked` id, key and the id of the
+/// `group/init` message that started it. The key is zeroized on drop and
+/// hidden from `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Group {
+    pub id: String,
+    pub key: [u8; 32],
+    pub root: String,
+}
+
+impl Group {
+    /// The group encrypted with `key` started by the `root` message, whose
+    /// envelope has the read key `root_read_key`.
+    pub fn new(key: [u8; 32], root: &str, root_read_key: &[u8; 32]) -> Self {
+        Group {
+            id: cloaked_msg_id(root, root_read_key),
+            key,
+            root: root.to_string(),
+        }
+    }
+
+    /// The group encrypted with `key` started by the `group/init` message
+    /// `root`, which must be readable with the key.
+    pub fn from_root(key: [u8; 32], root: &Message) -> Result<Self> {
+        let envelope = root
+            .content()
+            .as_str()
+            .and_then(|content| content.strip_suffix(box2::SUFFIX))
+            .ok_or(Error::InvalidSuffix)?;
+        let read_key = box2::read_key(
+            &base64::decode(envelope)?,
+            root.author(),
+            root.previous().map(String::as_str),
+            &[RecipientKey::new(key, SCHEME_GROUP)],
+        )?
+        .ok_or(Error::FailedToDecipher)?;
+        Ok(Group::new(key, &root.id().to_string(), &read_key))
+    }
+
+    pub fn recipient_key(&self) -> RecipientKey {
+        RecipientKey::new(self.key, SCHEME_GROUP)
+    }
+}
+
+impl fmt::Debug for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Group")
+            .field("id", &self.id)
+            .field("key", &"****")
+            .field("root", &self.root)
+            .finish()
+    }
+}
+
+impl Drop for Group {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+/// The `%...cloaked` id of the message `msg_id`, derived from the read key
+/// of its envelope, as private groups name the group started by a
+/// `group/init` message: it reveals neither the message nor its key.
+pub fn cloaked_msg_id(msg_id: &str, read_key: &[u8; 32]) -> String {
+    let info = slp_encode(&[b"cloaked_msg_id", &bfe::encode_str(msg_id)]);
+    let cloaked = hkdf_expand(read_key, &info);
+    format!("%{}{}", base64::encode(&cloaked), GROUP_SUFFIX)
+}
+
+/// A new random group key.
+pub fn generate_group_key() -> [u8; 32] {
+    secretbox::gen_key().0
+}
+
+/// The content of the message starting a group, to be encrypted with the
+/// new group key only.
+pub fn group_init_content() -> Value {
+    json!({
+        "type": TYPE_GROUP_INIT,
+        "tangles": {"group": {"root": null, "previous": null}},
+    })
+}
+
+/// The content of the message giving the key of `group` to `members`, to
+/// be encrypted with the group key and the dm keys of the members.
+pub fn add_member_content(group: &Group, members: &[&str], previous: &[&str]) -> Value {
+    let mut recps = vec![group.id.as_str()];
+    recps.extend_from_slice(members);
+    json!({
+        "type": TYPE_ADD_MEMBER,
+        "version": "v1",
+        "groupKey": base64::encode(&group.key),
+        "root": group.root,
+        "recps": recps,
+        "tangles": {
+            "group": {"root": group.root, "previous": previous},
+            "members": {"root": group.root, "previous": previous},
+        },
+    })
+}
+
+fn hkdf_extract(salt: &[u8], input: &[u8]) -> [u8; 32] {
+    let mut state = hmacsha256::State::init(salt);
+    state.update(input);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(state.finalize().as_ref());
+    out
+}
+
+/// The key shared by `identity` and `other` for direct messages, the same
+/// computed from either side.
+pub fn dm_key(identity: &OwnedIdentity, other: &str) -> Result<RecipientKey> {
+    let other = normalize_id(other);
+    let other = other.as_ref();
+    let other_pk = other.strip_prefix('@').unwrap_or(other).to_ed25519_pk()?;
+    let shared = curve25519::scalarmult(
+        &ed25519_sk_to_curve25519(&identity.sk)?,
+        &ed25519_pk_to_curve25519(&other_pk)?,
+    )
+    .map_err(|_| Error::CryptoScalarMultFailed)?;
+
+    let info_key = |pk: &ed25519::PublicKey, id: &str| -> Vec<u8> {
+        [pk.to_curve25519().as_ref(), &bfe::encode_str(id)].concat()
+    };
+    let mut keys = [
+        info_key(&identity.pk, &identity.id),
+        info_key(&other_pk, other),
+    ];
+    keys.sort();
+
+    let mut info = Vec::new();
+    for part in [DM_INFO, &keys[0], &keys[1]] {
+        info.extend_from_slice(&(part.len() as u16).to_le_bytes());
+        info.extend_from_slice(part);
+    }
+    let shared = Zeroizing::new(shared.0);
+    let prk = Zeroizing::new(hkdf_extract(sha256::hash(DM_SALT).as_ref(), &*shared));
+    Ok(RecipientKey::new(hkdf_expand(&*prk, &info), SCHEME_DM))
+}
+
+/// The groups known to an identity, to find the keys to encrypt to
+/// recipients and to try on received messages.
+#[derive(Debug)]
+pub struct GroupKeyStore {
+    identity: OwnedIdentity,
+    groups: HashMap<String, Group>,
+}
+
+impl GroupKeyStore {
+    pub fn new(identity: OwnedIdentity) -> Self {
+        GroupKeyStore {
+            identity,
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn add_group(&mut self, group: Group) {
+        self.groups.insert(group.id.clone(), group);
+    }
+
+    pub fn group(&self, id: &str) -> Option<&Group> {
+        self.groups.get(id)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.groups.values()
+    }
+
+    /// Registers the group of a decrypted `group/add-member` content
+    /// naming this identity, returning it. `root` is the `group/init`
+    /// message the content refers to, its id is derived from it.
+    pub fn process_add_member(
+        &mut self,
+        content: &Value,
+        root: &Message,
+    ) -> Result<Option<&Group>> {
+        if content.get("type").and_then(Value::as_str) != Some(TYPE_ADD_MEMBER) {
+            return Ok(None);
+        }
+        let names_me = content
+            .get("recps")
+            .and_then(Value::as_array)
+            .is_some_and(|recps| recps.iter().any(|r| r.as_str() == Some(&self.identity.id)));
+        if !names_me {
+            return Ok(None);
+        }
+        let key = content
+            .get("groupKey")
+            .and_then(Value::as_str)
+            .ok_or(Error::CannotCreateKey)?;
+        let key: [u8; 32] = base64::decode(key)?
+            .try_into()
+            .map_err(|_| Error::CannotCreateKey)?;
+        if content.get("root").and_then(Value::as_str) != Some(&root.id().to_string()) {
+            return Err(Error::CannotCreateKey);
+        }
+        let group = Group::from_root(key, root)?;
+        let id = group.id.clone();
+        self.groups.insert(id.clone(), group);
+        Ok(self.groups.get(&id))
+    }
+
+    /// The keys to encrypt to `recps`, group ids or feed ids.
+    pub fn recipient_keys(&self, recps: &[&str]) -> Result<Vec<RecipientKey>> {
+        if recps.is_empty() || recps.len() > MAX_SLOTS {
+            return Err(Error::BadRecipientCount);
+        }
+        recps
+            .iter()
+            .map(|recp| {
+                if recp.ends_with(GROUP_SUFFIX) {
+                    self.groups
+                        .get(*recp)
+                        .map(Group::recipient_key)
+                        .ok_or_else(|| Error::UnknownGroup(recp.to_string()))
+                } else {
+                    dm_key(&self.identity, recp)
+                }
+            })
+            .collect()
+    }
+
+    /// The keys to try on a message of `author`: those of all the groups,
+    /// and the dm key shared with the author.
+    pub fn keys_for(&self, author: &str) -> Vec<RecipientKey> {
+        let mut keys: Vec<_> = self.groups.values().map(Group::recipient_key).collect();
+        if let Ok(key) = dm_key(&self.identity, author) {
+            keys.push(key);
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::box2;
+
+    #[test]
+    fn test_group_keys() -> Result<()> {
+        let (alice, bob) = (OwnedIdentity::create(), OwnedIdentity::create());
+        assert_eq!(dm_key(&alice, &bob.id)?, dm_key(&bob, &alice.id)?);
+
+        // alice starts a group with an init message to its key only
+        let key = generate_group_key();
+        let init = box2::box2_cipher(
+            &group_init_content().to_string(),
+            &alice.id,
+            None,
+            &[RecipientKey::new(key, SCHEME_GROUP)],
+        )?;
+        let init = Message::sign(None, &alice, json!(init)).unwrap();
+        let root = init.id().to_string();
+        let group = Group::from_root(key, &init)?;
+        assert!(group.id.ends_with(GROUP_SUFFIX));
+        let mut alice_keys = GroupKeyStore::new(alice.clone());
+        alice_keys.add_group(group.clone());
+
+        // alice adds bob, who learns the key from the add-member message
+        let content = add_member_content(&group, &[&bob.id], &[&root]);
+        let recps = alice_keys.recipient_keys(&[&group.id, &bob.id])?;
+        let plaintext = content.to_string();
+        let envelope = box2::encrypt(plaintext.as_bytes(), &alice.id, Some(&root), &recps)?;
+
+        let mut bob_keys = GroupKeyStore::new(bob);
+        let decrypted = box2::decrypt(
+            &envelope,
+            &alice.id,
+            Some(&root),
+            &bob_keys.keys_for(&alice.id),
+        )?
+        .expect("bob can read the message");
+        let decrypted: Value = serde_json::from_slice(&decrypted).unwrap();
+        assert_eq!(
+            bob_keys.process_add_member(&decrypted, &init)?,
+            Some(&group)
+        );
+        assert_eq!(bob_keys.group(&group.id), Some(&group));
+
+        assert!(matches!(
+            GroupKeyStore::new(OwnedIdentity::create()).recipient_keys(&[&group.id]),
+            Err(Error::UnknownGroup(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cloaked_group_id() -> Result<()> {
+        let alice = OwnedIdentity::create();
+        let key = [1u8; 32];
+        let msg_key = [2u8; 32];
+        let envelope = box2::encrypt_with_key(
+            group_init_content().to_string().as_bytes(),
+            &alice.id,
+            None,
+            &msg_key,
+            &[RecipientKey::new(key, SCHEME_GROUP)],
+        )?;
+        let content = format!("{}{}", base64::encode(&envelope), box2::SUFFIX);
+        let init = Message::sign(None, &alice, json!(content)).unwrap();
+        let root = init.id().to_string();
+
+        // group_id = DeriveSecret(read_key, ["cloaked_msg_id", bfe(root)]),
+        // read_key = DeriveSecret(msg_key, ["envelope", bfe(author),
+        //   bfe(previous), "read_key"]), both with SLP encoded labels
+        let zero_prev = [&[1u8, 0][..], &[0u8; 32]].concat();
+        let read_key = hkdf_expand(
+            &msg_key,
+            &slp_encode(&[
+                b"envelope",
+                &bfe::encode_str(&alice.id),
+                &zero_prev,
+                b"read_key",
+            ]),
+        );
+        let group_id = hkdf_expand(
+            &read_key,
+            &slp_encode(&[b"cloaked_msg_id", &bfe::encode_str(&root)]),
+        );
+
+        let group = Group::from_root(key, &init)?;
+        assert_eq!(
+            group.id,
+            format!("%{}{}", base64::encode(&group_id), GROUP_SUFFIX)
+        );
+        // the group key names the group only through the init message
+        assert_ne!(group.id, cloaked_msg_id(&root, &key));
+        assert!(matches!(
+            Group::from_root([3u8; 32], &init),
+            Err(Error::FailedToDecipher)
+        ));
+        Ok(())
+    }
+}
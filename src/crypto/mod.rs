@@ -1,8 +1,21 @@
+pub mod backend;
+pub mod box2;
+mod curve;
 mod error;
+pub mod fusion;
+pub mod group;
+pub mod privatebox;
+pub mod secretblob;
+mod sign;
+mod signer;
 mod sodium;
+pub mod testvectors;
 
+pub use backend::{hash::sha256, sign::ed25519};
+pub use curve::{ed25519_pk_to_curve25519, ed25519_sk_to_curve25519, feed_id_to_curve25519};
 pub use error::{Error, Result};
-pub use kuska_sodiumoxide::crypto::{hash::sha256, sign::ed25519};
+pub use sign::{sign_detached, verify_detached};
+pub use signer::Signer;
 pub use sodium::{
     ToSodiumObject, ToSsbId, CURVE_ED25519_SUFFIX, ED25519_SIGNATURE_SUFFIX, SHA256_SUFFIX,
 };
@@ -1,12 +1,18 @@
-use crate::crypto::ToSodiumObject;
+//! The private-box envelope (box1) of classic private messages: the
+//! body is encrypted with a random key, which is encrypted for each of up to
+//! `MAX_RECIPIENTS` recipients with a key derived from an ephemeral
+//! curve25519 key. Compatible with js private-box.
 
-use kuska_sodiumoxide::crypto::{
+use super::backend::{
     scalarmult::curve25519,
     secretbox,
     sign::{ed25519, SecretKey},
 };
-
-use super::error::{Error, Result};
+use super::{
+    ed25519_pk_to_curve25519, ed25519_sk_to_curve25519,
+    error::{Error, Result},
+    ToSodiumObject,
+};
 
 pub const SUFFIX: &str = ".box";
 
@@ -19,31 +25,36 @@ pub fn is_privatebox(text: &str) -> bool {
     text.ends_with(SUFFIX)
 }
 
+/// Encrypts `plaintext` for the `@...ed25519` feeds in `recipients`, as a
+/// `<base64>.box` string.
 pub fn privatebox_cipher(plaintext: &str, recipients: &[&str]) -> Result<String> {
-    let recipients: crate::crypto::Result<Vec<_>> = recipients
+    let recipients = recipients
         .iter()
-        .map(|id| id[1..].to_ed25519_pk())
-        .collect();
-
-    let recipients = recipients?;
+        .map(|id| id.strip_prefix('@').unwrap_or(id).to_ed25519_pk())
+        .collect::<Result<Vec<_>>>()?;
 
     let recipients_ref: Vec<_> = recipients.iter().collect();
 
-    let ciphertext = cipher(plaintext.as_bytes(), &recipients_ref[..])?;
+    let ciphertext = encrypt(plaintext.as_bytes(), &recipients_ref[..])?;
 
     Ok(format!("{}{}", base64::encode(&ciphertext), SUFFIX))
 }
 
+/// Decrypts a `<base64>.box` string, `None` if `sk` is not one of the
+/// recipients.
 pub fn privatebox_decipher(ciphertext: &str, sk: &SecretKey) -> Result<Option<String>> {
-    let msg = &ciphertext.as_bytes()[..ciphertext.len() - SUFFIX.len()];
+    let msg = ciphertext
+        .strip_suffix(SUFFIX)
+        .ok_or(Error::InvalidSuffix)?;
     let msg = base64::decode(msg)?;
 
-    let plaintext = decipher(&msg, sk)?.map(|msg| String::from_utf8_lossy(&msg).to_string());
+    let plaintext = decrypt(&msg, sk)?.map(|msg| String::from_utf8_lossy(&msg).to_string());
 
     Ok(plaintext)
 }
 
-fn cipher(plaintext: &[u8], recipients: &[&ed25519::PublicKey]) -> Result<Box<[u8]>> {
+/// Encrypts `plaintext` for `recipients`, returning the raw envelope.
+pub fn encrypt(plaintext: &[u8], recipients: &[&ed25519::PublicKey]) -> Result<Box<[u8]>> {
     // Precondition checks
     if plaintext.is_empty() {
         return Err(Error::EmptyPlaintext);
@@ -57,7 +68,7 @@ fn cipher(plaintext: &[u8], recipients: &[&ed25519::PublicKey]) -> Result<Box<[u
     let (h_pk, h_sk) = ed25519::gen_keypair();
 
     // Generated random 32-byte secret key used to encrypt the message body
-    let y = kuska_sodiumoxide::crypto::secretbox::gen_key();
+    let y = super::backend::secretbox::gen_key();
 
     // Encrypt the plaintext with y, with a random nonce
     let nonce = secretbox::gen_nonce();
@@ -65,7 +76,7 @@ fn cipher(plaintext: &[u8], recipients: &[&ed25519::PublicKey]) -> Result<Box<[u
 
     // The sender uses scalar multiplication to derive a shared secret for each recipient,
     //   and encrypts (number_of_recipents || y) for each one
-    let h_sk_scalar = &h_sk.to_curve25519();
+    let h_sk_scalar = &ed25519_sk_to_curve25519(&h_sk)?;
     let mut plain_header = [0u8; RECIPIENT_COUNT_LEN + secretbox::KEYBYTES];
     plain_header[0] = recipients.len() as u8;
     plain_header[RECIPIENT_COUNT_LEN..].copy_from_slice(&y[..]);
@@ -82,7 +93,7 @@ fn cipher(plaintext: &[u8], recipients: &[&ed25519::PublicKey]) -> Result<Box<[u
     buffer.extend_from_slice(&h_pk.to_curve25519()[..]);
 
     for recipient in recipients {
-        let key = curve25519::scalarmult(h_sk_scalar, &recipient.to_curve25519())
+        let key = curve25519::scalarmult(h_sk_scalar, &ed25519_pk_to_curve25519(recipient)?)
             .map_err(|_| Error::CryptoScalarMultFailed)?;
 
         let key = secretbox::Key::from_slice(&key[..]).ok_or(Error::CryptoKeyFromGrupFailed)?;
@@ -94,7 +105,11 @@ fn cipher(plaintext: &[u8], recipients: &[&ed25519::PublicKey]) -> Result<Box<[u
     Ok(buffer.into_boxed_slice())
 }
 
-fn decipher(ciphertext: &[u8], sk: &SecretKey) -> Result<Option<Vec<u8>>> {
+/// Decrypts a raw envelope, `None` if `sk` is not one of the recipients.
+pub fn decrypt(ciphertext: &[u8], sk: &SecretKey) -> Result<Option<Vec<u8>>> {
+    if ciphertext.len() < secretbox::NONCEBYTES + ed25519::PUBLICKEYBYTES {
+        return Err(Error::CannotReadNonce);
+    }
     let mut cursor = ciphertext;
 
     let nonce = secretbox::Nonce::from_slice(&cursor[..secretbox::NONCEBYTES])
@@ -105,21 +120,25 @@ fn decipher(ciphertext: &[u8], sk: &SecretKey) -> Result<Option<Vec<u8>>> {
         .ok_or(Error::CannotReadNonce)?;
     cursor = &cursor[ed25519::PUBLICKEYBYTES..];
 
-    let key = curve25519::scalarmult(&sk.to_curve25519(), &h_pk)
+    let key = curve25519::scalarmult(&ed25519_sk_to_curve25519(sk)?, &h_pk)
         .and_then(|key| secretbox::Key::from_slice(&key[..]).ok_or(()))
         .map_err(|_| Error::CannotCreateKey)?;
 
     let mut header_no = 0;
     while header_no < MAX_RECIPIENTS && cursor.len() > ENCRYPTED_HEADER_LEN + secretbox::MACBYTES {
         if let Ok(header) = secretbox::open(&cursor[..ENCRYPTED_HEADER_LEN], &nonce, &key) {
-            let encrypted_message_offset = ENCRYPTED_HEADER_LEN * (header[0] - header_no) as usize;
+            let encrypted_message_offset =
+                ENCRYPTED_HEADER_LEN * header[0].saturating_sub(header_no) as usize;
             let y = secretbox::Key::from_slice(&header[1..]).ok_or(Error::CannotCreateKey)?;
-            let plaintext = secretbox::open(&cursor[encrypted_message_offset..], &nonce, &y)
+            let encrypted_message = cursor
+                .get(encrypted_message_offset..)
+                .ok_or(Error::FailedToDecipher)?;
+            let plaintext = secretbox::open(encrypted_message, &nonce, &y)
                 .map_err(|_| Error::FailedToDecipher)?;
             return Ok(Some(plaintext));
         }
         header_no += 1;
-        cursor = &cursor[49..];
+        cursor = &cursor[ENCRYPTED_HEADER_LEN..];
     }
 
     Ok(None)
@@ -134,8 +153,8 @@ mod test {
     fn test_msg_cipher_to_one() -> Result<()> {
         let (u1_pk, u1_sk) = ed25519::gen_keypair();
         let plaintext = "hola".as_bytes();
-        let ciphertext = cipher(plaintext, &[&u1_pk])?;
-        let plaintext_1 = decipher(&ciphertext, &u1_sk)?.unwrap();
+        let ciphertext = encrypt(plaintext, &[&u1_pk])?;
+        let plaintext_1 = decrypt(&ciphertext, &u1_sk)?.unwrap();
         assert_eq!(plaintext.to_vec(), plaintext_1);
         Ok(())
     }
@@ -148,6 +167,9 @@ mod test {
         assert_eq!(is_privatebox(&ciphertext), true);
         let plaintext_1 = privatebox_decipher(&ciphertext, &id.sk)?.unwrap();
         assert_eq!(plaintext, plaintext_1);
+
+        assert!(privatebox_decipher("holar", &id.sk).is_err());
+        assert!(decrypt(&[0u8; 10], &id.sk).is_err());
         Ok(())
     }
 
@@ -156,8 +178,8 @@ mod test {
         let (u1_pk, _) = ed25519::gen_keypair();
         let (_, u1_sk) = ed25519::gen_keypair();
         let plaintext = "hola".as_bytes();
-        let ciphertext = cipher(plaintext, &[&u1_pk])?;
-        let plaintext_1 = decipher(&ciphertext, &u1_sk)?;
+        let ciphertext = encrypt(plaintext, &[&u1_pk])?;
+        let plaintext_1 = decrypt(&ciphertext, &u1_sk)?;
         assert_eq!(None, plaintext_1);
         Ok(())
     }
@@ -168,10 +190,10 @@ mod test {
         let u_pk = u.iter().map(|(pk, _)| pk).collect::<Vec<_>>();
 
         let plaintext = "hola".as_bytes();
-        let ciphertext = cipher(plaintext, &u_pk)?;
+        let ciphertext = encrypt(plaintext, &u_pk)?;
 
         for (_, sk) in u.iter() {
-            let plaintext_1 = decipher(&ciphertext, sk)?.unwrap();
+            let plaintext_1 = decrypt(&ciphertext, sk)?.unwrap();
             assert_eq!(plaintext.to_vec(), plaintext_1);
         }
 
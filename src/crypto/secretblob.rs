@@ -0,0 +1,83 @@
+//! Secret blobs, for private attachments: the blob is encrypted with a
+//! random key before it is added, and messages link it as
+//! `&<hash>.sha256?unbox=<key>.boxs` so only their readers can open it.
+//! Peers store and replicate the encrypted blob like any other.
+
+use super::backend::{hash::sha256, secretbox};
+use super::error::{Error, Result};
+
+pub const UNBOX_PARAM: &str = "?unbox=";
+pub const KEY_SUFFIX: &str = ".boxs";
+
+/// The `&...sha256` id of the blob `data`.
+pub fn blob_id(data: &[u8]) -> String {
+    format!("&{}.sha256", base64::encode(&sha256::hash(data)))
+}
+
+fn nonce() -> secretbox::Nonce {
+    // every key encrypts a single blob
+    secretbox::Nonce([0u8; secretbox::NONCEBYTES])
+}
+
+/// Encrypts `plaintext` with a new key: the blob to add and the reference
+/// to link it with.
+pub fn encrypt(plaintext: &[u8]) -> (Vec<u8>, String) {
+    let key = secretbox::gen_key();
+    let blob = secretbox::seal(plaintext, &nonce(), &key);
+    let reference = format!(
+        "{}{}{}{}",
+        blob_id(&blob),
+        UNBOX_PARAM,
+        base64::encode(&key),
+        KEY_SUFFIX
+    );
+    (blob, reference)
+}
+
+/// The blob id and key of a secret blob reference.
+pub fn parse_ref(reference: &str) -> Result<(String, secretbox::Key)> {
+    let (id, key) = reference
+        .split_once(UNBOX_PARAM)
+        .ok_or(Error::InvalidSecretBlobRef)?;
+    let key = key
+        .strip_suffix(KEY_SUFFIX)
+        .ok_or(Error::InvalidSecretBlobRef)?;
+    let key = secretbox::Key::from_slice(&base64::decode(key)?).ok_or(Error::CannotCreateKey)?;
+    Ok((id.to_string(), key))
+}
+
+/// Whether `reference` links a secret blob.
+pub fn is_secret_ref(reference: &str) -> bool {
+    parse_ref(reference).is_ok()
+}
+
+/// Decrypts a secret blob downloaded for `reference`, checking that it is
+/// the blob the reference names.
+pub fn decrypt(blob: &[u8], reference: &str) -> Result<Vec<u8>> {
+    let (id, key) = parse_ref(reference)?;
+    if blob_id(blob) != id {
+        return Err(Error::InvalidDigest);
+    }
+    secretbox::open(blob, &nonce(), &key).map_err(|_| Error::FailedToDecipher)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_secret_blob() -> Result<()> {
+        let (blob, reference) = encrypt(b"a private picture");
+        assert!(reference.starts_with(&blob_id(&blob)));
+        assert!(is_secret_ref(&reference));
+        assert!(!is_secret_ref(&blob_id(&blob)));
+        assert_eq!(decrypt(&blob, &reference)?, b"a private picture");
+
+        let (other, _) = encrypt(b"another");
+        assert!(matches!(
+            decrypt(&other, &reference),
+            Err(Error::InvalidDigest)
+        ));
+        Ok(())
+    }
+}
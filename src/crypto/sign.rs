@@ -0,0 +1,76 @@
+//! ed25519 signatures of messages, computed by the crypto backend: libsodium
+//! or, with the `pure-rust` feature, the libsodium compatible dalek one.
+
+use super::backend::sign::ed25519;
+
+pub fn sign_detached(msg: &[u8], sk: &ed25519::SecretKey) -> ed25519::Signature {
+    ed25519::sign_detached(msg, sk)
+}
+
+pub fn verify_detached(
+    signature: &ed25519::Signature,
+    msg: &[u8],
+    pk: &ed25519::PublicKey,
+) -> bool {
+    ed25519::verify_detached(signature, msg, pk)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The order of the ed25519 base point, little endian.
+    const L: [u8; 32] = [
+        0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde,
+        0x14, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10,
+    ];
+
+    fn signature(hex: &str) -> ed25519::Signature {
+        ed25519::Signature::from_slice(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    fn public_key(hex: &str) -> ed25519::PublicKey {
+        ed25519::PublicKey::from_slice(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let (pk, sk) = ed25519::gen_keypair();
+        let signature = sign_detached(b"hello", &sk);
+        assert!(verify_detached(&signature, b"hello", &pk));
+        assert!(!verify_detached(&signature, b"hellO", &pk));
+    }
+
+    #[test]
+    fn test_libsodium_edge_cases() {
+        // RFC 8032 test 1, accepted by every backend
+        let pk = public_key("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+        let sig = signature(concat!(
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e06522490155",
+            "5fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+        ));
+        assert!(verify_detached(&sig, b"", &pk));
+
+        // the same signature with S + L, a non-canonical S
+        let mut high_s = sig;
+        let mut carry = 0u16;
+        for (s, l) in high_s.0[32..].iter_mut().zip(L) {
+            let sum = *s as u16 + l as u16 + carry;
+            *s = sum as u8;
+            carry = sum >> 8;
+        }
+        assert!(!verify_detached(&high_s, b"", &pk));
+
+        // the neutral element as A and R with S = 0 passes the cofactorless
+        // equation for any message, libsodium rejects the small order A and R
+        let identity = "0100000000000000000000000000000000000000000000000000000000000000";
+        let zero = "0000000000000000000000000000000000000000000000000000000000000000";
+        let sig = signature(&format!("{}{}", identity, zero));
+        assert!(!verify_detached(&sig, b"anything", &public_key(identity)));
+
+        // and so does its non-canonical encoding p + 1
+        let p_plus_one = "eeffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff7f";
+        let sig = signature(&format!("{}{}", p_plus_one, zero));
+        assert!(!verify_detached(&sig, b"anything", &public_key(p_plus_one)));
+    }
+}
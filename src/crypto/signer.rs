@@ -0,0 +1,61 @@
+//! Signing behind a trait, so identities whose secret key is held outside
+//! this process (hardware tokens, remote key services) can sign messages.
+
+use futures::future::{self, BoxFuture};
+
+use super::backend::sign::ed25519;
+use super::{error::Result, sign_detached, ToSsbId};
+use crate::keystore::OwnedIdentity;
+
+/// An ed25519 keypair that signs on request; the secret key may never be
+/// exposed.
+pub trait Signer: Send + Sync {
+    fn public_key(&self) -> ed25519::PublicKey;
+
+    /// Signs `msg`, failing with `Error::Signer` if the device or service
+    /// refuses or is unreachable.
+    fn sign<'a>(&'a self, msg: &'a [u8]) -> BoxFuture<'a, Result<ed25519::Signature>>;
+
+    /// The `@...ed25519` id of the key.
+    fn id(&self) -> String {
+        format!("@{}", self.public_key().to_ssb_id())
+    }
+}
+
+impl Signer for OwnedIdentity {
+    fn public_key(&self) -> ed25519::PublicKey {
+        self.pk
+    }
+
+    fn sign<'a>(&'a self, msg: &'a [u8]) -> BoxFuture<'a, Result<ed25519::Signature>> {
+        Box::pin(future::ready(Ok(sign_detached(msg, &self.sk))))
+    }
+
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{crypto::verify_detached, feed::Message};
+
+    #[async_std::test]
+    async fn test_identity_signer() -> Result<()> {
+        let identity = OwnedIdentity::create();
+        let signer: &dyn Signer = &identity;
+        assert_eq!(signer.id(), identity.id);
+
+        let signature = signer.sign(b"hello").await?;
+        assert!(verify_detached(&signature, b"hello", &signer.public_key()));
+
+        let content = serde_json::json!({"type": "post", "text": "hi"});
+        let msg = Message::sign_with(None, signer, content.clone(), 1000.0, None)
+            .await
+            .unwrap();
+        let expected = Message::sign_at(None, &identity, content, 1000.0).unwrap();
+        assert_eq!(msg.value, expected.value);
+        Ok(())
+    }
+}
@@ -1,5 +1,4 @@
-use kuska_sodiumoxide::crypto::{hash::sha256, sign::ed25519};
-
+use super::backend::{hash::sha256, sign::ed25519};
 use super::error::{Error, Result};
 
 pub const CURVE_ED25519_SUFFIX: &str = ".ed25519";
@@ -0,0 +1,325 @@
+//! Secret handshake and private-box vectors from other implementations, to
+//! check that crates embedding this one stay compatible; see
+//! `testvectors/README.md`.
+//!
+//! Private-box vectors use the json layout of the envelope-spec `vectors/`
+//! files (`{"description", "input": {...}, "output": {...}}` with base64
+//! bytes), so more of them can be loaded with `from_json` and checked with
+//! `assert_privatebox`.
+
+use serde::Deserialize;
+
+use super::backend::{secretbox, sign::ed25519};
+use super::{privatebox, ToSodiumObject};
+use crate::{
+    connection::{
+        boxstream::BoxStreamKeys,
+        shs::{transcript, EphemeralKeys},
+    },
+    discovery::NetworkKey,
+    keystore::OwnedIdentity,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrivateBoxVector {
+    pub description: String,
+    pub input: PrivateBoxInput,
+    pub output: PrivateBoxOutput,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrivateBoxInput {
+    pub ciphertext: String,
+    /// `...ed25519` secret key of a recipient, as in ssb-keys secrets.
+    pub secret_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrivateBoxOutput {
+    pub plain_text: String,
+}
+
+/// A secret handshake with fixed keys, bytes in hex.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShsVector {
+    pub description: String,
+    pub input: ShsInput,
+    pub output: ShsOutput,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShsInput {
+    pub network_key: String,
+    pub client_secret_key: String,
+    pub client_ephemeral_secret_key: String,
+    pub server_secret_key: String,
+    pub server_ephemeral_secret_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShsOutput {
+    pub client_public_key: String,
+    pub client_ephemeral_public_key: String,
+    pub server_public_key: String,
+    pub server_ephemeral_public_key: String,
+    pub client_hello: String,
+    pub server_hello: String,
+    pub client_auth: String,
+    pub server_accept: String,
+    pub client_encryption_key: String,
+    pub client_encryption_nonce: String,
+    pub server_encryption_key: String,
+    pub server_encryption_nonce: String,
+}
+
+fn hex(name: &str, field: &str, data: &str) -> Vec<u8> {
+    hex::decode(data).unwrap_or_else(|err| panic!("{}: {}: {}", name, field, err))
+}
+
+fn array<const N: usize>(name: &str, field: &str, data: &str) -> [u8; N] {
+    hex(name, field, data)
+        .try_into()
+        .unwrap_or_else(|_| panic!("{}: {}: not {} bytes", name, field, N))
+}
+
+impl ShsVector {
+    pub fn network_key(&self) -> NetworkKey {
+        let key = hex(&self.description, "network_key", &self.input.network_key);
+        NetworkKey::from_slice(&key).unwrap()
+    }
+
+    fn identity(&self, field: &str, sk: &str) -> OwnedIdentity {
+        let sk = hex(&self.description, field, sk);
+        OwnedIdentity::from_seed(&sk[..32].try_into().unwrap())
+    }
+
+    pub fn client(&self) -> OwnedIdentity {
+        self.identity("client_secret_key", &self.input.client_secret_key)
+    }
+
+    pub fn server(&self) -> OwnedIdentity {
+        self.identity("server_secret_key", &self.input.server_secret_key)
+    }
+
+    pub fn client_ephemeral(&self) -> EphemeralKeys {
+        let field = "client_ephemeral_secret_key";
+        EphemeralKeys::from_secret(array(
+            &self.description,
+            field,
+            &self.input.client_ephemeral_secret_key,
+        ))
+    }
+
+    pub fn server_ephemeral(&self) -> EphemeralKeys {
+        let field = "server_ephemeral_secret_key";
+        EphemeralKeys::from_secret(array(
+            &self.description,
+            field,
+            &self.input.server_ephemeral_secret_key,
+        ))
+    }
+
+    fn keys(&self, key: &str, nonce: &str) -> BoxStreamKeys {
+        BoxStreamKeys {
+            key: secretbox::Key(array(&self.description, "encryption_key", key)),
+            nonce: secretbox::Nonce(array(&self.description, "encryption_nonce", nonce)),
+        }
+    }
+
+    /// Keys of the box stream from the client to the server.
+    pub fn client_keys(&self) -> BoxStreamKeys {
+        self.keys(
+            &self.output.client_encryption_key,
+            &self.output.client_encryption_nonce,
+        )
+    }
+
+    /// Keys of the box stream from the server to the client.
+    pub fn server_keys(&self) -> BoxStreamKeys {
+        self.keys(
+            &self.output.server_encryption_key,
+            &self.output.server_encryption_nonce,
+        )
+    }
+
+    /// The bytes the client sends, hello and auth.
+    pub fn client_sent(&self) -> Vec<u8> {
+        let name = &self.description;
+        [
+            hex(name, "client_hello", &self.output.client_hello),
+            hex(name, "client_auth", &self.output.client_auth),
+        ]
+        .concat()
+    }
+
+    /// The bytes the server sends, hello and accept.
+    pub fn server_sent(&self) -> Vec<u8> {
+        let name = &self.description;
+        [
+            hex(name, "server_hello", &self.output.server_hello),
+            hex(name, "server_accept", &self.output.server_accept),
+        ]
+        .concat()
+    }
+}
+
+/// A private-box message and its recipients, as in the private-box-rs
+/// tests: base64 keys without suffix.
+#[derive(Deserialize)]
+struct UpstreamPrivateBox {
+    cypher_text: String,
+    msg: String,
+    keys: Vec<UpstreamKeys>,
+}
+
+#[derive(Deserialize)]
+struct UpstreamKeys {
+    secret: String,
+}
+
+const SHS_VECTORS: &str = include_str!("../../testvectors/shs1.json");
+const PRIVATEBOX_VECTORS: &str = include_str!("../../testvectors/private-box-simple.json");
+
+/// Parses a json array of vectors, e.g. a file of private-box vectors.
+pub fn from_json<'a, T: Deserialize<'a>>(json: &'a str) -> serde_json::Result<Vec<T>> {
+    serde_json::from_str(json)
+}
+
+/// The private-box vectors included in this crate, one per recipient.
+pub fn privatebox_vectors() -> Vec<PrivateBoxVector> {
+    let upstream: UpstreamPrivateBox = serde_json::from_str(PRIVATEBOX_VECTORS).unwrap();
+    upstream
+        .keys
+        .iter()
+        .enumerate()
+        .map(|(n, keys)| PrivateBoxVector {
+            description: format!("private-box-rs simple, recipient {}", n),
+            input: PrivateBoxInput {
+                ciphertext: upstream.cypher_text.clone(),
+                secret_key: format!("{}.ed25519", keys.secret),
+            },
+            output: PrivateBoxOutput {
+                plain_text: base64::encode(&upstream.msg),
+            },
+        })
+        .collect()
+}
+
+/// The secret handshake vectors included in this crate.
+pub fn shs_vectors() -> Vec<ShsVector> {
+    vec![serde_json::from_str(SHS_VECTORS).unwrap()]
+}
+
+fn decode(name: &str, field: &str, data: &str) -> Vec<u8> {
+    base64::decode(data).unwrap_or_else(|err| panic!("{}: {}: {}", name, field, err))
+}
+
+/// Panics if the ciphertext of `vector` does not decrypt to its plain
+/// text with its secret key.
+pub fn assert_privatebox(vector: &PrivateBoxVector) {
+    let name = &vector.description;
+    let ciphertext = decode(name, "ciphertext", &vector.input.ciphertext);
+    let sk = vector
+        .input
+        .secret_key
+        .to_ed25519_sk()
+        .unwrap_or_else(|err| panic!("{}: secret_key: {}", name, err));
+    let decrypted = privatebox::decrypt(&ciphertext, &sk)
+        .unwrap_or_else(|err| panic!("{}: cannot decrypt: {}", name, err));
+    assert_eq!(
+        decrypted,
+        Some(decode(name, "plain_text", &vector.output.plain_text)),
+        "{}: plain text",
+        name
+    );
+}
+
+/// Panics if the handshake of `vector` does not give its messages and box
+/// stream keys.
+pub fn assert_shs(vector: &ShsVector) {
+    let name = &vector.description;
+    let (client, server) = (vector.client(), vector.server());
+    let public = |field: &str, pk: &ed25519::PublicKey, expected: &str| {
+        assert_eq!(pk.0, array(name, field, expected), "{}: {}", name, field)
+    };
+    public(
+        "client_public_key",
+        &client.pk,
+        &vector.output.client_public_key,
+    );
+    public(
+        "server_public_key",
+        &server.pk,
+        &vector.output.server_public_key,
+    );
+    let (client_ephemeral, server_ephemeral) =
+        (vector.client_ephemeral(), vector.server_ephemeral());
+    assert_eq!(
+        client_ephemeral.pk.0,
+        array(
+            name,
+            "client_ephemeral_public_key",
+            &vector.output.client_ephemeral_public_key
+        ),
+        "{}: client_ephemeral_public_key",
+        name
+    );
+    assert_eq!(
+        server_ephemeral.pk.0,
+        array(
+            name,
+            "server_ephemeral_public_key",
+            &vector.output.server_ephemeral_public_key
+        ),
+        "{}: server_ephemeral_public_key",
+        name
+    );
+
+    let messages = transcript(
+        &vector.network_key(),
+        &client,
+        &client_ephemeral,
+        &server,
+        &server_ephemeral,
+    )
+    .unwrap_or_else(|err| panic!("{}: cannot run the handshake: {}", name, err));
+    assert_eq!(
+        [messages.client_hello, messages.client_auth].concat(),
+        vector.client_sent(),
+        "{}: client messages",
+        name
+    );
+    assert_eq!(
+        [messages.server_hello, messages.server_accept].concat(),
+        vector.server_sent(),
+        "{}: server messages",
+        name
+    );
+    assert_eq!(
+        messages.client_keys,
+        vector.client_keys(),
+        "{}: client keys",
+        name
+    );
+    assert_eq!(
+        messages.server_keys,
+        vector.server_keys(),
+        "{}: server keys",
+        name
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        for vector in privatebox_vectors() {
+            assert_privatebox(&vector);
+        }
+        for vector in shs_vectors() {
+            assert_shs(&vector);
+        }
+    }
+}
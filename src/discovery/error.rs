@@ -8,6 +8,12 @@ pub enum Error {
     InvalidInviteCode,
     #[error("invalid broadcast message")]
     InvalidBroadcastMessage,
+    #[error("invalid network key")]
+    InvalidNetworkKey,
+    #[error("invalid room invite")]
+    InvalidRoomInvite,
+    #[error("invalid room alias")]
+    InvalidAlias,
     #[error("invalid crypto format")]
     CryptoFormat(#[from] crate::crypto::Error),
     #[error("i/o")]
@@ -10,21 +10,37 @@ use async_std::net::{IpAddr, SocketAddr, UdpSocket};
 use once_cell::sync::Lazy;
 use regex::Regex;
 
+use crate::crypto::backend::secretbox;
 use crate::crypto::{ed25519, ToSodiumObject};
 
-use super::error::{Error, Result};
+use super::{
+    error::{Error, Result},
+    NetworkKey,
+};
 
 pub static BROADCAST_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"net:([0-9]+\.[0-9]+\.[0-9]+\.[0-9]+):([0-9]+)~shs:([0-9a-zA-Z=/]+)").unwrap()
+    Regex::new(r"net:([0-9]+\.[0-9]+\.[0-9]+\.[0-9]+):([0-9]+)~shs:([0-9a-zA-Z+=/]+)").unwrap()
 });
 
 pub struct LanBroadcast {
     destination: String,
     packets: Vec<(SocketAddr, SocketAddr, String)>,
+    network_key: NetworkKey,
 }
 
 impl LanBroadcast {
     pub async fn new(id: &ed25519::PublicKey, rpc_port: u16) -> Result<Self> {
+        Self::with_network_key(id, rpc_port, NetworkKey::default()).await
+    }
+
+    /// Broadcasts for the network of `network_key`. Outside of the main
+    /// network the messages are encrypted with the key, so only peers of
+    /// the same network see them.
+    pub async fn with_network_key(
+        id: &ed25519::PublicKey,
+        rpc_port: u16,
+        network_key: NetworkKey,
+    ) -> Result<Self> {
         let server_pk = base64::encode(&id);
 
         let mut packets = Vec::new();
@@ -54,13 +70,26 @@ impl LanBroadcast {
         Ok(LanBroadcast {
             packets,
             destination,
+            network_key,
         })
     }
+
+    fn seal(&self, msg: &str) -> Vec<u8> {
+        if self.network_key.is_main_net() {
+            return msg.as_bytes().to_vec();
+        }
+        let key = secretbox::Key::from_slice(self.network_key.as_bytes()).unwrap();
+        let nonce = secretbox::gen_nonce();
+        let mut packet = nonce.as_ref().to_vec();
+        packet.extend_from_slice(&secretbox::seal(msg.as_bytes(), &nonce, &key));
+        packet
+    }
+
     pub async fn send(&self) {
         for msg in &self.packets {
             if let Ok(socket) = UdpSocket::bind(msg.0).await {
                 let _ = socket.set_broadcast(true);
-                match socket.send_to(msg.2.as_bytes(), &self.destination).await {
+                match socket.send_to(&self.seal(&msg.2), &self.destination).await {
                     Err(err) => warn!(target:"solar", "Error broadcasting {}",err),
                     _ => {}
                 }
@@ -68,6 +97,20 @@ impl LanBroadcast {
         }
     }
 
+    /// Parses a received packet of the network of `network_key`.
+    pub fn parse_packet(
+        packet: &[u8],
+        network_key: &NetworkKey,
+    ) -> Option<(String, u32, ed25519::PublicKey)> {
+        if network_key.is_main_net() {
+            return Self::parse(std::str::from_utf8(packet).ok()?);
+        }
+        let key = secretbox::Key::from_slice(network_key.as_bytes())?;
+        let nonce = secretbox::Nonce::from_slice(packet.get(..secretbox::NONCEBYTES)?)?;
+        let msg = secretbox::open(&packet[secretbox::NONCEBYTES..], &nonce, &key).ok()?;
+        Self::parse(std::str::from_utf8(&msg).ok()?)
+    }
+
     pub fn parse(msg: &str) -> Option<(String, u32, ed25519::PublicKey)> {
         let parse_shs = |addr: &str| -> Result<_> {
             let captures = BROADCAST_REGEX
@@ -90,3 +133,32 @@ impl LanBroadcast {
         None
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_packet() {
+        let (pk, _) = ed25519::gen_keypair();
+        let msg = format!("net:192.168.1.2:8008~shs:{}", base64::encode(&pk));
+        let private_net = LanBroadcast {
+            destination: String::new(),
+            packets: Vec::new(),
+            network_key: NetworkKey::random(),
+        };
+
+        let packet = private_net.seal(&msg);
+        assert_ne!(packet, msg.as_bytes());
+        let parsed = LanBroadcast::parse_packet(&packet, &private_net.network_key);
+        assert_eq!(parsed, Some(("192.168.1.2".to_string(), 8008, pk)));
+        assert_eq!(
+            LanBroadcast::parse_packet(&packet, &NetworkKey::default()),
+            None
+        );
+        assert_eq!(
+            LanBroadcast::parse_packet(msg.as_bytes(), &NetworkKey::default()),
+            Some(("192.168.1.2".to_string(), 8008, pk))
+        );
+    }
+}
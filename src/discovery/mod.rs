@@ -2,7 +2,9 @@ mod error;
 mod lan;
 mod network;
 mod pubs;
+mod room;
 
 pub use lan::LanBroadcast;
-pub use network::ssb_net_id;
+pub use network::{ssb_net_id, NetworkConfig, NetworkKey, SSB_NET_ID};
 pub use pubs::Invite;
+pub use room::{is_valid_alias, AliasUrl, ConsumeAlias, RoomInvite, CLAIM_PATH};
@@ -1,6 +1,239 @@
-use kuska_sodiumoxide::crypto::auth;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::error::{Error, Result};
+use crate::crypto::backend::auth;
+use crate::{
+    connection::boxstream::MAX_SEGMENT_LEN,
+    feed::{hmac_key_from_base64, HmacKey},
+};
 
 pub const SSB_NET_ID: &str = "d4a1cb88a66f02f8db635ce26441cc5dac1b08420ceaac230839b755845a9ffb";
+
+/// The capability key (`caps.shs`) of an ssb network. It is mixed into the
+/// handshake, so peers only connect to peers of the same network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkKey(auth::Key);
+
+impl NetworkKey {
+    /// The key of the main ssb network.
+    pub fn main_net() -> Self {
+        Self::from_hex(SSB_NET_ID).unwrap()
+    }
+
+    pub fn from_hex(key: &str) -> Result<Self> {
+        let key = hex::decode(key).map_err(|_| Error::InvalidNetworkKey)?;
+        Self::from_slice(&key)
+    }
+
+    /// Parses the base64 form used in the `caps.shs` entry of ssb configs.
+    pub fn from_base64(key: &str) -> Result<Self> {
+        let key = base64::decode(key).map_err(|_| Error::InvalidNetworkKey)?;
+        Self::from_slice(&key)
+    }
+
+    pub fn from_slice(key: &[u8]) -> Result<Self> {
+        auth::Key::from_slice(key)
+            .map(NetworkKey)
+            .ok_or(Error::InvalidNetworkKey)
+    }
+
+    /// A new key, for a private network.
+    pub fn random() -> Self {
+        NetworkKey(auth::gen_key())
+    }
+
+    pub fn is_main_net(&self) -> bool {
+        *self == Self::main_net()
+    }
+
+    /// The key as expected by the handshake.
+    pub fn key(&self) -> auth::Key {
+        self.0.clone()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..]
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(&self.0[..])
+    }
+}
+
+impl Default for NetworkKey {
+    fn default() -> Self {
+        Self::main_net()
+    }
+}
+
+impl From<NetworkKey> for auth::Key {
+    fn from(key: NetworkKey) -> Self {
+        key.0
+    }
+}
+
 pub fn ssb_net_id() -> auth::Key {
-    auth::Key::from_slice(&hex::decode(SSB_NET_ID).unwrap()).unwrap()
+    NetworkKey::main_net().key()
+}
+
+/// The capabilities of an ssb network, grouped as in the `caps` of
+/// secret-stack configs, so another network is set up in one place.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NetworkConfig {
+    /// `caps.shs`, the handshake key.
+    pub shs: NetworkKey,
+    /// `caps.invite`, the handshake key invites are redeemed with, `shs`
+    /// when unset.
+    pub invite: Option<NetworkKey>,
+    /// `caps.sign`, the hmac key messages are signed with, if any.
+    pub sign: Option<HmacKey>,
+    /// Largest box stream segment to send, `MAX_SEGMENT_LEN` when unset.
+    pub segment_len: Option<usize>,
+    /// Quiet time after which the os probes tcp peers, if any.
+    pub tcp_keepalive: Option<Duration>,
+    /// How often to send box stream keepalives, if at all.
+    pub keepalive_interval: Option<Duration>,
+    /// Quiet time after which connections are taken for dead, if any.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl NetworkConfig {
+    /// The main ssb network.
+    pub fn main_net() -> Self {
+        Self::default()
+    }
+
+    pub fn new(shs: NetworkKey) -> Self {
+        NetworkConfig {
+            shs,
+            invite: None,
+            sign: None,
+            segment_len: None,
+            tcp_keepalive: None,
+            keepalive_interval: None,
+            idle_timeout: None,
+        }
+    }
+
+    pub fn invite(self, invite: NetworkKey) -> Self {
+        Self {
+            invite: Some(invite),
+            ..self
+        }
+    }
+
+    pub fn sign(self, sign: HmacKey) -> Self {
+        Self {
+            sign: Some(sign),
+            ..self
+        }
+    }
+
+    /// Sends box stream segments of up to `len` bytes, at most
+    /// `MAX_SEGMENT_LEN`.
+    pub fn segment_len(self, len: usize) -> Self {
+        Self {
+            segment_len: Some(len.clamp(1, MAX_SEGMENT_LEN)),
+            ..self
+        }
+    }
+
+    /// Turns on tcp keepalive, probing after `time` without traffic.
+    pub fn tcp_keepalive(self, time: Duration) -> Self {
+        Self {
+            tcp_keepalive: Some(time),
+            ..self
+        }
+    }
+
+    /// Sends a box stream keepalive every `interval` on connections whose
+    /// writer is shared with `Connection::into_shared`. Peers running
+    /// kuska-handshake readers take keepalives for the end of the stream.
+    pub fn keepalive_interval(self, interval: Duration) -> Self {
+        Self {
+            keepalive_interval: Some(interval),
+            ..self
+        }
+    }
+
+    /// Drops connections after `timeout` without anything coming in.
+    pub fn idle_timeout(self, timeout: Duration) -> Self {
+        Self {
+            idle_timeout: Some(timeout),
+            ..self
+        }
+    }
+
+    /// The largest box stream segment to send.
+    pub fn max_segment_len(&self) -> usize {
+        self.segment_len.unwrap_or(MAX_SEGMENT_LEN)
+    }
+
+    /// The handshake key to redeem invites with.
+    pub fn invite_key(&self) -> &NetworkKey {
+        self.invite.as_ref().unwrap_or(&self.shs)
+    }
+
+    /// Parses the `caps` object of an ssb config, with base64 keys; a
+    /// missing `shs` is the main network.
+    pub fn from_caps(caps: &Value) -> Result<Self> {
+        let key = |name: &str| caps.get(name).and_then(Value::as_str);
+        let mut config = match key("shs") {
+            Some(shs) => Self::new(NetworkKey::from_base64(shs)?),
+            None => Self::main_net(),
+        };
+        if let Some(invite) = key("invite") {
+            config = config.invite(NetworkKey::from_base64(invite)?);
+        }
+        if let Some(sign) = key("sign") {
+            config = config.sign(hmac_key_from_base64(sign).map_err(|_| Error::InvalidNetworkKey)?);
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_network_key() -> Result<()> {
+        let main_net = NetworkKey::default();
+        assert!(main_net.is_main_net());
+        assert_eq!(main_net.to_hex(), SSB_NET_ID);
+        assert_eq!(
+            NetworkKey::from_base64("1KHLiKZvAvjbY1ziZEHMXawbCEIM6qwjCDm3VYRan/s=")?,
+            main_net
+        );
+
+        let other = NetworkKey::random();
+        assert!(!other.is_main_net());
+        assert_eq!(NetworkKey::from_hex(&other.to_hex())?, other);
+        assert!(NetworkKey::from_hex("d4a1").is_err());
+
+        assert_eq!(
+            NetworkConfig::from_caps(&json!({}))?,
+            NetworkConfig::main_net()
+        );
+        let sign = "bW9ua2V5IGF0dGFjayBhdCBkYXduIGZyb20gdGhlIGxlZnQ=";
+        let config = NetworkConfig::from_caps(&json!({
+            "shs": base64::encode(other.as_bytes()),
+            "sign": base64::encode(&[7u8; 32]),
+        }))?;
+        assert_eq!(config.shs, other);
+        assert_eq!(config.invite_key(), &other);
+        assert!(config.sign.is_some());
+        assert!(NetworkConfig::from_caps(&json!({"sign": sign})).is_err());
+
+        assert_eq!(config.max_segment_len(), MAX_SEGMENT_LEN);
+        assert_eq!(config.clone().segment_len(1024).max_segment_len(), 1024);
+        assert_eq!(
+            config.segment_len(1 << 16).max_segment_len(),
+            MAX_SEGMENT_LEN
+        );
+        Ok(())
+    }
 }
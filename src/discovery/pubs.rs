@@ -1,42 +1,159 @@
-use crate::crypto::ToSodiumObject;
-use kuska_sodiumoxide::crypto::sign::ed25519;
+use std::fmt;
 
-use super::error::{Error, Result};
+use crate::crypto::backend::sign::ed25519;
+use crate::{
+    crypto::{ToSodiumObject, ToSsbId},
+    keystore::OwnedIdentity,
+    multiserver,
+};
 
+use super::{
+    error::{Error, Result},
+    NetworkKey,
+};
+
+/// A pub invite code, either in the legacy form
+/// `host:port:@key.ed25519~seed` or in the multiserver form
+/// `net:host:port~shs:key~seed`.
+#[derive(Clone, PartialEq)]
 pub struct Invite {
     pub domain: String,
     pub port: u16,
     pub pub_pk: ed25519::PublicKey,
-    pub invite_sk: ed25519::SecretKey,
+    /// Seed of the keypair used in the handshake to redeem the invite.
+    pub seed: [u8; 32],
+    /// Network of the pub, the main network unless set with
+    /// `network_key`; invite codes do not carry it.
+    pub network_key: NetworkKey,
 }
 
 impl Invite {
+    pub fn new(domain: &str, port: u16, pub_pk: ed25519::PublicKey, seed: [u8; 32]) -> Self {
+        Invite {
+            domain: domain.to_string(),
+            port,
+            pub_pk,
+            seed,
+            network_key: NetworkKey::default(),
+        }
+    }
+
     pub fn from_code(code: &str) -> Result<Self> {
-        let domain_port_keys: Vec<_> = code.split(':').collect();
-        if domain_port_keys.len() != 3 {
+        let code = code.trim();
+        let (address, seed) = code.rsplit_once('~').ok_or(Error::InvalidInviteCode)?;
+        let seed: [u8; 32] = base64::decode(seed)
+            .map_err(|_| Error::InvalidInviteCode)?
+            .try_into()
+            .map_err(|_| Error::InvalidInviteCode)?;
+
+        if address.starts_with("net:") {
+            let address: multiserver::Address =
+                address.parse().map_err(|_| Error::InvalidInviteCode)?;
+            return match (&address.protocol, address.shs_key()) {
+                (multiserver::Protocol::Net { host, port }, Some(pub_pk)) => {
+                    Ok(Invite::new(host, *port, *pub_pk, seed))
+                }
+                _ => Err(Error::InvalidInviteCode),
+            };
+        }
+
+        let (domain, port, pub_pk) = {
+            let mut parts = address.splitn(3, ':');
+            let (domain, port, key) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(domain), Some(port), Some(key)) => (domain, port, key),
+                _ => return Err(Error::InvalidInviteCode),
+            };
+            let key = key.strip_prefix('@').ok_or(Error::InvalidInviteCode)?;
+            (domain, port, key.to_ed25519_pk()?)
+        };
+        if domain.is_empty() {
             return Err(Error::InvalidInviteCode);
         }
 
-        let domain = domain_port_keys[0].to_string();
-        let port = domain_port_keys[1].parse::<u16>()?;
-        let pk_sk: Vec<_> = domain_port_keys[2].split('~').collect();
+        Ok(Invite::new(domain, port.parse::<u16>()?, pub_pk, seed))
+    }
 
-        if pk_sk.len() != 2 {
-            return Err(Error::InvalidInviteCode);
+    pub fn network_key(self, network_key: NetworkKey) -> Self {
+        Self {
+            network_key,
+            ..self
         }
-        let pub_pk = pk_sk[0][1..].to_ed25519_pk()?;
-        let invite_sk = pk_sk[1][..].to_ed25519_sk_no_suffix()?;
+    }
 
-        Ok(Invite {
-            domain,
-            port,
-            pub_pk,
-            invite_sk,
-        })
+    /// The ephemeral identity that redeems the invite.
+    pub fn identity(&self) -> OwnedIdentity {
+        OwnedIdentity::from_seed(&self.seed)
+    }
+
+    /// The legacy form of the code, `host:port:@key.ed25519~seed`.
+    pub fn to_code(&self) -> String {
+        format!(
+            "{}:{}:@{}~{}",
+            self.domain,
+            self.port,
+            self.pub_pk.to_ssb_id(),
+            base64::encode(&self.seed)
+        )
+    }
+
+    /// The multiserver form of the code, `net:host:port~shs:key~seed`.
+    pub fn to_multiserver_code(&self) -> String {
+        format!(
+            "net:{}:{}~shs:{}~{}",
+            self.domain,
+            self.port,
+            base64::encode(&self.pub_pk),
+            base64::encode(&self.seed)
+        )
+    }
+}
+
+impl fmt::Debug for Invite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Invite")
+            .field("domain", &self.domain)
+            .field("port", &self.port)
+            .field("pub_pk", &self.pub_pk)
+            .field("seed", &"****")
+            .field("network_key", &self.network_key)
+            .finish()
+    }
+}
+
+impl fmt::Display for Invite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_code())
     }
 }
 
 #[cfg(test)]
 mod test {
-    // const picopub : &str = "ssb-pub.picodevelopment.nl:8008:@UFDjYpDN89OTdow4sqZP5eEGGcy+1eN/HNc5DMdMI0M=.ed25519~ibtGafFt7myC9yEyJ6Oq7gWuS2+2ue9XI3iyE9QXSwI=";
+    use super::*;
+
+    const PICOPUB: &str = "ssb-pub.picodevelopment.nl:8008:@UFDjYpDN89OTdow4sqZP5eEGGcy+1eN/HNc5DMdMI0M=.ed25519~ibtGafFt7myC9yEyJ6Oq7gWuS2+2ue9XI3iyE9QXSwI=";
+
+    #[test]
+    fn test_invite_code() -> Result<()> {
+        let invite = Invite::from_code(PICOPUB)?;
+        assert_eq!(invite.domain, "ssb-pub.picodevelopment.nl");
+        assert_eq!(invite.port, 8008);
+        assert_eq!(invite.to_code(), PICOPUB);
+        assert_eq!(invite.to_string(), PICOPUB);
+        assert!(format!("{:?}", invite).contains("seed: \"****\""));
+
+        let multiserver = invite.to_multiserver_code();
+        assert_eq!(
+            multiserver,
+            "net:ssb-pub.picodevelopment.nl:8008~shs:UFDjYpDN89OTdow4sqZP5eEGGcy+1eN/HNc5DMdMI0M=~ibtGafFt7myC9yEyJ6Oq7gWuS2+2ue9XI3iyE9QXSwI="
+        );
+        assert_eq!(Invite::from_code(&multiserver)?, invite);
+
+        let identity = invite.identity();
+        assert_eq!(identity, OwnedIdentity::from_seed(&invite.seed));
+
+        assert!(Invite::from_code("ssb-pub.picodevelopment.nl:8008").is_err());
+        // truncated seed
+        assert!(Invite::from_code(&PICOPUB[..PICOPUB.len() - 4]).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,257 @@
+//! Invites and aliases of rooms (rooms2): invite links such as
+//! `https://room.example/join?token=...`, their
+//! `ssb:experimental?action=claim-http-invite&...` form, alias urls and the
+//! `consume-alias` uris alias pages link to.
+
+use std::fmt;
+
+use super::error::{Error, Result};
+use crate::crypto::backend::sign::ed25519;
+use crate::{
+    crypto::{verify_detached, ToSodiumObject},
+    multiserver::MultiAddress,
+    uri::{self, FeedRef, SsbUri},
+};
+
+/// Path of the page where invites are claimed over http.
+pub const CLAIM_PATH: &str = "/invite/consume";
+
+/// An invite to a room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RoomInvite {
+    /// `https://<room>/join?token=<token>`, the link shared with the invitee.
+    Link { room: String, token: String },
+    /// `ssb:experimental?action=claim-http-invite&invite=<token>&postTo=<url>`,
+    /// what the join page hands to the app.
+    Claim { token: String, post_to: String },
+}
+
+/// Splits an http(s) url into host, path and query.
+fn split_url(url: &str) -> Option<(&str, &str, &str)> {
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?;
+    let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let (host, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    (!host.is_empty()).then_some((host, path, query))
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    uri::parse_query(query)
+        .ok()?
+        .into_iter()
+        .find(|(key, value)| key == name && !value.is_empty())
+        .map(|(_, value)| value)
+}
+
+impl RoomInvite {
+    pub fn parse(invite: &str) -> Result<Self> {
+        let invite = invite.trim();
+        if invite.starts_with("ssb:") {
+            let uri: SsbUri = invite.parse().map_err(|_| Error::InvalidRoomInvite)?;
+            return match &uri {
+                SsbUri::Experimental { action, .. } if action == uri::ACTION_CLAIM_HTTP_INVITE => {
+                    Ok(RoomInvite::Claim {
+                        token: uri
+                            .param("invite")
+                            .ok_or(Error::InvalidRoomInvite)?
+                            .to_string(),
+                        post_to: uri
+                            .param("postTo")
+                            .ok_or(Error::InvalidRoomInvite)?
+                            .to_string(),
+                    })
+                }
+                _ => Err(Error::InvalidRoomInvite),
+            };
+        }
+        match split_url(invite) {
+            Some((room, "/join" | "/invite", query)) => Ok(RoomInvite::Link {
+                room: room.to_string(),
+                token: query_param(query, "token").ok_or(Error::InvalidRoomInvite)?,
+            }),
+            _ => Err(Error::InvalidRoomInvite),
+        }
+    }
+
+    pub fn token(&self) -> &str {
+        match self {
+            RoomInvite::Link { token, .. } | RoomInvite::Claim { token, .. } => token,
+        }
+    }
+
+    /// The url the invite is claimed at, posting the token and the id of
+    /// the invitee.
+    pub fn claim_url(&self) -> String {
+        match self {
+            RoomInvite::Link { room, .. } => format!("https://{}{}", room, CLAIM_PATH),
+            RoomInvite::Claim { post_to, .. } => post_to.clone(),
+        }
+    }
+}
+
+impl fmt::Display for RoomInvite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoomInvite::Link { room, token } => {
+                write!(
+                    f,
+                    "https://{}/join?token={}",
+                    room,
+                    uri::percent_encode(token)
+                )
+            }
+            RoomInvite::Claim { token, post_to } => write!(
+                f,
+                "ssb:experimental?action={}&invite={}&postTo={}",
+                uri::ACTION_CLAIM_HTTP_INVITE,
+                uri::percent_encode(token),
+                uri::percent_encode(post_to)
+            ),
+        }
+    }
+}
+
+/// Whether `alias` is a valid alias: a lowercase dns label.
+pub fn is_valid_alias(alias: &str) -> bool {
+    !alias.is_empty()
+        && alias.len() <= 63
+        && !alias.starts_with('-')
+        && !alias.ends_with('-')
+        && alias
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+}
+
+/// The url of an alias, `https://<alias>.<room>` or
+/// `https://<room>/alias/<alias>` for rooms without subdomains.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasUrl {
+    pub alias: String,
+    pub room: String,
+}
+
+impl AliasUrl {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (host, path, _) = split_url(url.trim()).ok_or(Error::InvalidAlias)?;
+        let (alias, room) = match path.trim_end_matches('/') {
+            "" => host.split_once('.').ok_or(Error::InvalidAlias)?,
+            path => (
+                path.strip_prefix("/alias/").ok_or(Error::InvalidAlias)?,
+                host,
+            ),
+        };
+        if !is_valid_alias(alias) || !room.contains('.') {
+            return Err(Error::InvalidAlias);
+        }
+        Ok(AliasUrl {
+            alias: alias.to_string(),
+            room: room.to_string(),
+        })
+    }
+}
+
+/// `ssb:experimental?action=consume-alias&...`, connecting to the user
+/// behind an alias of a room.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsumeAlias {
+    pub alias: String,
+    pub room_id: FeedRef,
+    pub user_id: FeedRef,
+    /// Signature of the user over the registration, see `verify`.
+    pub signature: String,
+    pub multiserver_address: MultiAddress,
+}
+
+impl ConsumeAlias {
+    pub fn parse(uri: &str) -> Result<Self> {
+        let uri: SsbUri = uri.trim().parse().map_err(|_| Error::InvalidAlias)?;
+        match &uri {
+            SsbUri::Experimental { action, .. } if action == uri::ACTION_CONSUME_ALIAS => {}
+            _ => return Err(Error::InvalidAlias),
+        }
+        let param = |name| uri.param(name).ok_or(Error::InvalidAlias);
+        let alias = param("alias")?.to_string();
+        if !is_valid_alias(&alias) {
+            return Err(Error::InvalidAlias);
+        }
+        Ok(ConsumeAlias {
+            alias,
+            room_id: param("roomId")?.parse().map_err(|_| Error::InvalidAlias)?,
+            user_id: param("userId")?.parse().map_err(|_| Error::InvalidAlias)?,
+            signature: param("signature")?.to_string(),
+            multiserver_address: param("multiserverAddress")?
+                .parse()
+                .map_err(|_| Error::InvalidAlias)?,
+        })
+    }
+
+    /// What the user signed when registering the alias.
+    pub fn registration(&self) -> String {
+        format!(
+            "=room-alias-registration:{}:{}:{}",
+            self.room_id, self.user_id, self.alias
+        )
+    }
+
+    /// Checks that the user registered the alias in the room.
+    pub fn verify(&self) -> Result<()> {
+        let user: ed25519::PublicKey = self.user_id.as_str()[1..].to_ed25519_pk()?;
+        let signature = self.signature.to_ed25519_signature()?;
+        if verify_detached(&signature, self.registration().as_bytes(), &user) {
+            Ok(())
+        } else {
+            Err(Error::InvalidAlias)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{crypto::sign_detached, keystore::OwnedIdentity};
+
+    #[test]
+    fn test_room_codes() -> Result<()> {
+        let link = RoomInvite::parse("https://room.example.org/join?token=abc%2B1")?;
+        assert_eq!(link.token(), "abc+1");
+        assert_eq!(link.claim_url(), "https://room.example.org/invite/consume");
+        assert_eq!(RoomInvite::parse(&link.to_string())?, link);
+
+        let claim = RoomInvite::Claim {
+            token: "abc".to_string(),
+            post_to: "https://room.example.org/invite/consume".to_string(),
+        };
+        assert_eq!(RoomInvite::parse(&claim.to_string())?, claim);
+        assert!(RoomInvite::parse("https://room.example.org/join").is_err());
+
+        let alias = AliasUrl::parse("https://alice.room.example.org")?;
+        assert_eq!(
+            (alias.alias.as_str(), alias.room.as_str()),
+            ("alice", "room.example.org")
+        );
+        assert_eq!(
+            AliasUrl::parse("https://room.example.org/alias/alice")?.room,
+            "room.example.org"
+        );
+        assert!(AliasUrl::parse("https://Alice.room.example.org").is_err());
+
+        let (room, user) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let registration = format!("=room-alias-registration:{}:{}:alice", room.id, user.id);
+        let signature = sign_detached(registration.as_bytes(), &user.sk);
+        let consume = format!(
+            "ssb:experimental?action=consume-alias&alias=alice&roomId={}&userId={}&signature={}&multiserverAddress={}",
+            uri::percent_encode(&room.id),
+            uri::percent_encode(&user.id),
+            uri::percent_encode(&format!("{}.sig.ed25519", base64::encode(&signature))),
+            uri::percent_encode(&format!("net:room.example.org:8008~shs:{}", &room.id[1..45])),
+        );
+        let consume = ConsumeAlias::parse(&consume)?;
+        assert_eq!(consume.user_id.as_str(), user.id);
+        consume.verify()?;
+        Ok(())
+    }
+}
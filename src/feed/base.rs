@@ -4,6 +4,7 @@ use super::{
     error::{Error, Result},
     message::Message,
     ssb_sha256,
+    unbox::UnboxerRegistry,
 };
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -50,6 +51,68 @@ impl Feed {
     }
 }
 
+/// A `{key, value, timestamp}` envelope, as emitted by sbot streams, with
+/// its value verified as a `Message`.
+#[derive(Debug, Clone)]
+pub struct KvtMessage {
+    pub key: String,
+    pub value: Message,
+    /// When the message was received by the sbot.
+    pub timestamp: f64,
+    pub rts: Option<f64>,
+}
+
+impl KvtMessage {
+    /// Parses the envelope, checking the key and signature of the message.
+    pub fn from_slice(s: &[u8]) -> Result<Self> {
+        Self::from_feed(Feed::from_slice(s)?)
+    }
+
+    /// Like `from_slice`, decrypting private content with `registry`, see
+    /// `Message::unbox`.
+    pub fn from_slice_unboxed(s: &[u8], registry: &UnboxerRegistry) -> Result<Self> {
+        let mut kvt = Self::from_slice(s)?;
+        kvt.value.unbox(registry);
+        Ok(kvt)
+    }
+
+    pub fn from_feed(feed: Feed) -> Result<Self> {
+        Ok(KvtMessage {
+            key: feed.key,
+            value: Message::from_value(feed.value)?,
+            timestamp: feed.timestamp,
+            rts: feed.rts,
+        })
+    }
+
+    /// When the author says the message was published.
+    pub fn asserted_timestamp(&self) -> f64 {
+        self.value.timestamp()
+    }
+
+    /// The time to show the message at: the asserted timestamp, unless it
+    /// is after the message was received, which only a wrong clock or a
+    /// lying author can produce.
+    pub fn display_timestamp(&self) -> f64 {
+        self.asserted_timestamp().min(self.timestamp)
+    }
+
+    /// How far ahead of the receive time the asserted timestamp is, in
+    /// milliseconds; negative when it is behind, as usual.
+    pub fn clock_skew(&self) -> f64 {
+        self.asserted_timestamp() - self.timestamp
+    }
+
+    pub fn into_feed(self) -> Feed {
+        Feed {
+            key: self.key,
+            value: self.value.value,
+            timestamp: self.timestamp,
+            rts: self.rts,
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -58,6 +121,19 @@ mod test {
     fn test_verify_feed_integrity() -> Result<()> {
         let feed = r#"{"key":"%Cg0ZpZ8cV85G8UIIropgBOvM8+Srlv9LSGDNGnpdK44=.sha256","value":{"previous":"%seUEAo7PTyA7vNwnOrmGIsUFfpyRzOvzGVv1QCb/Fz8=.sha256","author":"@BIbVppzlrNiRJogxDYz3glUS7G4s4D4NiXiPEAEzxdE=.ed25519","sequence":37,"timestamp":1439392020612,"hash":"sha256","content":{"type":"post","text":"@paul real time replies didn't work.","repliesTo":"%xWKunF6nXD7XMC+D4cjwDMZWmBnmRu69w9T25iLNa1Q=.sha256","mentions":["%7UKRfZb2u8al4tYWHqM55R9xpE/KKVh9U0M6BdugGt4=.sha256"],"recps":[{"link":"@hxGxqPrplLjRG2vtjQL87abX4QKqeLgCwQpS730nNwE=.ed25519","name":"paul"}]},"signature":"gGxSPdBJZxp6x5f3HzQGoQSeSdh/C5AtymIn+miWa+lcC6DdqpRSgaeH9KHeLf+/CKhU6REYIpWaLr4CKDMfCg==.sig.ed25519"},"timestamp":1573574678194,"rts":1439392020612}"#;
         Feed::from_slice(feed.as_bytes())?;
+        let kvt = KvtMessage::from_slice(feed.as_bytes())?;
+        assert_eq!(kvt.key, kvt.value.id().to_string());
+        assert_eq!(kvt.timestamp, 1573574678194.0);
+        assert_eq!(kvt.display_timestamp(), 1439392020612.0);
+        assert!(kvt.clock_skew() < 0.0);
+
+        let skewed = KvtMessage {
+            timestamp: 1439392020000.0,
+            ..kvt.clone()
+        };
+        assert_eq!(skewed.display_timestamp(), 1439392020000.0);
+        assert_eq!(skewed.clock_skew(), 612.0);
+        assert_eq!(kvt.value.sequence(), 37);
         Ok(())
     }
 }
@@ -0,0 +1,141 @@
+//! Minimal bencode, as used by the bendy butt feed format.
+
+use std::collections::BTreeMap;
+
+use super::error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    /// Keys are kept sorted, as required by the encoding.
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.encode_into(&mut buffer);
+        buffer
+    }
+
+    fn encode_into(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(n) => {
+                buffer.extend_from_slice(format!("i{}e", n).as_bytes());
+            }
+            Bencode::Bytes(bytes) => {
+                buffer.extend_from_slice(format!("{}:", bytes.len()).as_bytes());
+                buffer.extend_from_slice(bytes);
+            }
+            Bencode::List(items) => {
+                buffer.push(b'l');
+                for item in items {
+                    item.encode_into(buffer);
+                }
+                buffer.push(b'e');
+            }
+            Bencode::Dict(entries) => {
+                buffer.push(b'd');
+                for (key, value) in entries {
+                    buffer.extend_from_slice(format!("{}:", key.len()).as_bytes());
+                    buffer.extend_from_slice(key);
+                    value.encode_into(buffer);
+                }
+                buffer.push(b'e');
+            }
+        }
+    }
+
+    /// Decodes `bytes`, which must contain exactly one value.
+    pub fn decode(bytes: &[u8]) -> Result<Bencode> {
+        let (value, rest) = Self::decode_prefix(bytes)?;
+        if !rest.is_empty() {
+            return Err(Error::InvalidBencode);
+        }
+        Ok(value)
+    }
+
+    fn decode_prefix(bytes: &[u8]) -> Result<(Bencode, &[u8])> {
+        match bytes.first() {
+            Some(b'i') => {
+                let end = find(bytes, b'e')?;
+                let n = parse_int(&bytes[1..end])?;
+                Ok((Bencode::Int(n), &bytes[end + 1..]))
+            }
+            Some(b'l') => {
+                let mut items = Vec::new();
+                let mut rest = &bytes[1..];
+                while rest.first() != Some(&b'e') {
+                    let (item, next) = Self::decode_prefix(rest)?;
+                    items.push(item);
+                    rest = next;
+                }
+                Ok((Bencode::List(items), &rest[1..]))
+            }
+            Some(b'd') => {
+                let mut entries = BTreeMap::new();
+                let mut rest = &bytes[1..];
+                while rest.first() != Some(&b'e') {
+                    let (key, next) = decode_bytes(rest)?;
+                    let (value, next) = Self::decode_prefix(next)?;
+                    entries.insert(key.to_vec(), value);
+                    rest = next;
+                }
+                Ok((Bencode::Dict(entries), &rest[1..]))
+            }
+            Some(b'0'..=b'9') => {
+                let (data, rest) = decode_bytes(bytes)?;
+                Ok((Bencode::Bytes(data.to_vec()), rest))
+            }
+            _ => Err(Error::InvalidBencode),
+        }
+    }
+}
+
+fn find(bytes: &[u8], byte: u8) -> Result<usize> {
+    bytes
+        .iter()
+        .position(|b| *b == byte)
+        .ok_or(Error::InvalidBencode)
+}
+
+fn parse_int(bytes: &[u8]) -> Result<i64> {
+    std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or(Error::InvalidBencode)
+}
+
+fn decode_bytes(bytes: &[u8]) -> Result<(&[u8], &[u8])> {
+    let colon = find(bytes, b':')?;
+    let len = parse_int(&bytes[..colon])? as usize;
+    let data = bytes
+        .get(colon + 1..colon + 1 + len)
+        .ok_or(Error::InvalidBencode)?;
+    Ok((data, &bytes[colon + 1 + len..]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bencode_round_trip() -> Result<()> {
+        let encoded = b"d3:bari-3e3:fool4:spami0eee";
+        let value = Bencode::decode(encoded)?;
+        let mut dict = BTreeMap::new();
+        dict.insert(b"bar".to_vec(), Bencode::Int(-3));
+        dict.insert(
+            b"foo".to_vec(),
+            Bencode::List(vec![Bencode::Bytes(b"spam".to_vec()), Bencode::Int(0)]),
+        );
+        assert_eq!(value, Bencode::Dict(dict));
+        assert_eq!(value.encode(), encoded);
+
+        assert!(Bencode::decode(b"4:spa").is_err());
+        assert!(Bencode::decode(b"i1ei2e").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,251 @@
+//! The bendy butt feed format (`ssb-bendy-butt`), used by metafeeds.
+//!
+//! A message is the bencoded list `[payload, signature]`, where the payload
+//! is `[author, sequence, previous, timestamp, [content, contentSignature]]`
+//! with its fields encoded as BFE.
+
+use serde_json::Value;
+
+use super::{
+    bencode::Bencode,
+    bfe,
+    error::{Error, Result},
+};
+use crate::crypto::backend::{hash::sha256, sign::ed25519};
+use crate::{
+    crypto::{self, ToSodiumObject},
+    keystore::OwnedIdentity,
+};
+
+pub const BENDYBUTT_FEED_SUFFIX: &str = ".bbfeed-v1";
+pub const BENDYBUTT_MSG_SUFFIX: &str = ".bbmsg-v1";
+
+/// Prefix of the bytes signed by the content signature.
+const CONTENT_SIGNATURE_PREFIX: &[u8] = b"bendybutt";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BendyButtContent {
+    /// The content and its signature by the key of the subfeed.
+    Plain { content: Value, signature: String },
+    /// A `.box` or `.box2` string.
+    Encrypted(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BendyButtMessage {
+    /// `@...bbfeed-v1` id of the metafeed.
+    pub author: String,
+    pub sequence: u64,
+    /// `%...bbmsg-v1` id of the previous message.
+    pub previous: Option<String>,
+    pub timestamp: u64,
+    pub content: BendyButtContent,
+    pub signature: String,
+}
+
+/// The bendy butt feed id of `identity`.
+pub fn bendybutt_feed_id(identity: &OwnedIdentity) -> String {
+    format!("@{}{}", base64::encode(&identity.pk), BENDYBUTT_FEED_SUFFIX)
+}
+
+fn sign_bytes(identity: &OwnedIdentity, bytes: &[u8]) -> String {
+    let signature = crypto::sign_detached(bytes, &identity.sk);
+    format!("{}.sig.ed25519", base64::encode(&signature))
+}
+
+fn verify_bytes(signature: &str, bytes: &[u8], pk: &ed25519::PublicKey) -> Result<()> {
+    let signature = signature.to_ed25519_signature()?;
+    if crypto::verify_detached(&signature, bytes, pk) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+fn bytes(value: &Bencode) -> Result<&[u8]> {
+    match value {
+        Bencode::Bytes(bytes) => Ok(bytes),
+        _ => Err(Error::InvalidBencode),
+    }
+}
+
+fn string(value: &Bencode) -> Result<String> {
+    match bfe::decode(bytes(value)?)? {
+        Value::String(s) => Ok(s),
+        _ => Err(Error::InvalidBfe),
+    }
+}
+
+fn uint(value: &Bencode) -> Result<u64> {
+    match value {
+        Bencode::Int(n) if *n >= 0 => Ok(*n as u64),
+        _ => Err(Error::InvalidBencode),
+    }
+}
+
+fn content_signing_bytes(content: &Value) -> Result<Vec<u8>> {
+    let mut to_sign = CONTENT_SIGNATURE_PREFIX.to_vec();
+    to_sign.extend_from_slice(&bfe::encode_value(content)?.encode());
+    Ok(to_sign)
+}
+
+impl BendyButtMessage {
+    /// Create the message following `prev` in the metafeed of `metafeed`,
+    /// with `content` signed by `subfeed`.
+    pub fn sign(
+        prev: Option<&BendyButtMessage>,
+        metafeed: &OwnedIdentity,
+        subfeed: &OwnedIdentity,
+        content: Value,
+        timestamp: u64,
+    ) -> Result<Self> {
+        let content_signature = sign_bytes(subfeed, &content_signing_bytes(&content)?);
+        let mut msg = BendyButtMessage {
+            author: bendybutt_feed_id(metafeed),
+            sequence: prev.map_or(1, |prev| prev.sequence + 1),
+            previous: prev.map(|prev| prev.id()).transpose()?,
+            timestamp,
+            content: BendyButtContent::Plain {
+                content,
+                signature: content_signature,
+            },
+            signature: String::new(),
+        };
+        msg.signature = sign_bytes(metafeed, &msg.payload()?.encode());
+        Ok(msg)
+    }
+
+    fn payload(&self) -> Result<Bencode> {
+        let previous = match &self.previous {
+            Some(previous) => bfe::encode_str(previous),
+            None => bfe::encode_nil(),
+        };
+        let content = match &self.content {
+            BendyButtContent::Plain { content, signature } => Bencode::List(vec![
+                bfe::encode_value(content)?,
+                Bencode::Bytes(bfe::encode_str(signature)),
+            ]),
+            BendyButtContent::Encrypted(boxed) => Bencode::Bytes(bfe::encode_str(boxed)),
+        };
+        Ok(Bencode::List(vec![
+            Bencode::Bytes(bfe::encode_str(&self.author)),
+            Bencode::Int(self.sequence as i64),
+            Bencode::Bytes(previous),
+            Bencode::Int(self.timestamp as i64),
+            content,
+        ]))
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        Ok(Bencode::List(vec![
+            self.payload()?,
+            Bencode::Bytes(bfe::encode_str(&self.signature)),
+        ])
+        .encode())
+    }
+
+    pub fn decode(encoded: &[u8]) -> Result<Self> {
+        let (payload, signature) = match Bencode::decode(encoded)? {
+            Bencode::List(items) if items.len() == 2 => (items[0].clone(), string(&items[1])?),
+            _ => return Err(Error::InvalidBencode),
+        };
+        let fields = match payload {
+            Bencode::List(fields) if fields.len() == 5 => fields,
+            _ => return Err(Error::InvalidBencode),
+        };
+        let previous = match bfe::decode(bytes(&fields[2])?)? {
+            Value::Null => None,
+            Value::String(previous) => Some(previous),
+            _ => return Err(Error::InvalidBfe),
+        };
+        let content = match &fields[4] {
+            Bencode::List(section) if section.len() == 2 => BendyButtContent::Plain {
+                content: bfe::decode_value(&section[0])?,
+                signature: string(&section[1])?,
+            },
+            boxed => BendyButtContent::Encrypted(string(boxed)?),
+        };
+        Ok(BendyButtMessage {
+            author: string(&fields[0])?,
+            sequence: uint(&fields[1])?,
+            previous,
+            timestamp: uint(&fields[3])?,
+            content,
+            signature,
+        })
+    }
+
+    /// The `%...bbmsg-v1` id, the hash of the encoded message.
+    pub fn id(&self) -> Result<String> {
+        let hash = sha256::hash(&self.encode()?);
+        Ok(format!(
+            "%{}{}",
+            base64::encode(&hash),
+            BENDYBUTT_MSG_SUFFIX
+        ))
+    }
+
+    /// Check the signature of the author and, for plain content, the
+    /// content signature by the key in `subfeed` (the field of the content
+    /// naming the subfeed the message is about).
+    pub fn verify(&self) -> Result<()> {
+        let author = self
+            .author
+            .strip_prefix('@')
+            .and_then(|author| author.strip_suffix(BENDYBUTT_FEED_SUFFIX))
+            .ok_or(Error::InvalidJson)?;
+        let author = author.to_ed25519_pk_no_suffix()?;
+        verify_bytes(&self.signature, &self.payload()?.encode(), &author)?;
+
+        if let BendyButtContent::Plain { content, signature } = &self.content {
+            let subfeed = match content.get("subfeed") {
+                Some(Value::String(subfeed)) => subfeed,
+                _ => return Err(Error::InvalidContent),
+            };
+            let subfeed = subfeed
+                .strip_prefix('@')
+                .and_then(|subfeed| subfeed.rsplit_once('.'))
+                .ok_or(Error::InvalidContent)?
+                .0
+                .to_ed25519_pk_no_suffix()?;
+            verify_bytes(signature, &content_signing_bytes(content)?, &subfeed)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bendybutt_sign_verify() -> Result<()> {
+        let metafeed = OwnedIdentity::create();
+        let subfeed = OwnedIdentity::create();
+        let content = json!({
+            "type": "metafeed/add/existing",
+            "feedpurpose": "main",
+            "subfeed": subfeed.id,
+            "metafeed": bendybutt_feed_id(&metafeed),
+            "tangles": {"metafeed": {"root": null, "previous": null}},
+        });
+
+        let msg1 = BendyButtMessage::sign(None, &metafeed, &subfeed, content.clone(), 1000)?;
+        let msg2 = BendyButtMessage::sign(Some(&msg1), &metafeed, &subfeed, content, 1001)?;
+        assert_eq!(msg2.sequence, 2);
+        assert_eq!(msg2.previous, Some(msg1.id()?));
+
+        let decoded = BendyButtMessage::decode(&msg2.encode()?)?;
+        assert_eq!(decoded, msg2);
+        decoded.verify()?;
+
+        let other = OwnedIdentity::create();
+        let forged = BendyButtMessage {
+            signature: BendyButtMessage::sign(None, &other, &subfeed, json!(null), 0)?.signature,
+            ..decoded
+        };
+        assert!(matches!(forged.verify(), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+//! Binary field encoding (`ssb-bfe`): sigil strings such as feed ids, message
+//! ids and signatures are encoded as a type byte, a format byte and the
+//! decoded key or hash.
+
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use super::{
+    bencode::Bencode,
+    error::{Error, Result},
+};
+
+const TYPE_VALUE: u8 = 6;
+const VALUE_STRING: u8 = 0;
+const VALUE_BOOLEAN: u8 = 1;
+const VALUE_NIL: u8 = 2;
+
+/// (type, format, sigil, suffix) of the sigil strings with a binary form.
+const SIGIL_FORMATS: &[(u8, u8, &str, &str)] = &[
+    (0, 0, "@", ".ed25519"),
+    (0, 1, "@", ".ggfeed-v1"),
+    (0, 2, "@", ".bbfeed-v1"),
+    (0, 3, "@", ".buttwoo-v1"),
+    (1, 0, "%", ".sha256"),
+    (1, 1, "%", ".ggmsg-v1"),
+    (1, 3, "%", ".bbmsg-v1"),
+    (1, 4, "%", ".buttwoo-v1"),
+    (2, 0, "&", ".sha256"),
+    (4, 0, "", ".sig.ed25519"),
+    (5, 0, "", ".box"),
+    (5, 1, "", ".box2"),
+];
+
+/// Encodes a string, using the binary form of sigil strings.
+pub fn encode_str(s: &str) -> Vec<u8> {
+    for (bfe_type, format, sigil, suffix) in SIGIL_FORMATS {
+        if s.starts_with(sigil) && s.ends_with(suffix) && s.len() >= sigil.len() + suffix.len() {
+            if let Ok(data) = base64::decode(&s[sigil.len()..s.len() - suffix.len()]) {
+                let mut encoded = vec![*bfe_type, *format];
+                encoded.extend_from_slice(&data);
+                return encoded;
+            }
+        }
+    }
+    let mut encoded = vec![TYPE_VALUE, VALUE_STRING];
+    encoded.extend_from_slice(s.as_bytes());
+    encoded
+}
+
+/// Encodes a null value, e.g. the `previous` of the first message.
+pub fn encode_nil() -> Vec<u8> {
+    vec![TYPE_VALUE, VALUE_NIL]
+}
+
+/// Decodes a BFE value to its json form.
+pub fn decode(bytes: &[u8]) -> Result<Value> {
+    if bytes.len() < 2 {
+        return Err(Error::InvalidBfe);
+    }
+    let (bfe_type, format, data) = (bytes[0], bytes[1], &bytes[2..]);
+    if bfe_type == TYPE_VALUE {
+        return match (format, data) {
+            (VALUE_STRING, data) => std::str::from_utf8(data)
+                .map(|s| Value::String(s.to_string()))
+                .map_err(|_| Error::InvalidBfe),
+            (VALUE_BOOLEAN, [b]) => Ok(Value::Bool(*b == 1)),
+            (VALUE_NIL, []) => Ok(Value::Null),
+            _ => Err(Error::InvalidBfe),
+        };
+    }
+    SIGIL_FORMATS
+        .iter()
+        .find(|(t, f, _, _)| *t == bfe_type && *f == format)
+        .map(|(_, _, sigil, suffix)| {
+            Value::String(format!("{}{}{}", sigil, base64::encode(data), suffix))
+        })
+        .ok_or(Error::InvalidBfe)
+}
+
+/// Encodes a json value as bencode with its strings, booleans and nulls
+/// as BFE.
+pub fn encode_value(value: &Value) -> Result<Bencode> {
+    Ok(match value {
+        Value::Null => Bencode::Bytes(encode_nil()),
+        Value::Bool(b) => Bencode::Bytes(vec![TYPE_VALUE, VALUE_BOOLEAN, *b as u8]),
+        Value::Number(n) => Bencode::Int(n.as_i64().ok_or(Error::InvalidBfe)?),
+        Value::String(s) => Bencode::Bytes(encode_str(s)),
+        Value::Array(items) => {
+            Bencode::List(items.iter().map(encode_value).collect::<Result<Vec<_>>>()?)
+        }
+        Value::Object(entries) => Bencode::Dict(
+            entries
+                .iter()
+                .map(|(k, v)| Ok((k.as_bytes().to_vec(), encode_value(v)?)))
+                .collect::<Result<BTreeMap<_, _>>>()?,
+        ),
+    })
+}
+
+/// Reverse of `encode_value`.
+pub fn decode_value(value: &Bencode) -> Result<Value> {
+    Ok(match value {
+        Bencode::Int(n) => Value::from(*n),
+        Bencode::Bytes(bytes) => decode(bytes)?,
+        Bencode::List(items) => {
+            Value::Array(items.iter().map(decode_value).collect::<Result<Vec<_>>>()?)
+        }
+        Bencode::Dict(entries) => Value::Object(
+            entries
+                .iter()
+                .map(|(k, v)| {
+                    let key = String::from_utf8(k.clone()).map_err(|_| Error::InvalidBfe)?;
+                    Ok((key, decode_value(v)?))
+                })
+                .collect::<Result<serde_json::Map<_, _>>>()?,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bfe_round_trip() -> Result<()> {
+        let feed = "@FCX/tsDLpubCPKKfIrw4gc+SQkHcaD17s7GI6i/ziWY=.ed25519";
+        let encoded = encode_str(feed);
+        assert_eq!(&encoded[..2], &[0, 0]);
+        assert_eq!(encoded.len(), 34);
+        assert_eq!(decode(&encoded)?, json!(feed));
+
+        let content = json!({
+            "type": "metafeed/add/existing",
+            "subfeed": feed,
+            "tangles": {"metafeed": {"root": null, "previous": null}},
+            "nonce": 3,
+            "ok": true,
+        });
+        let encoded = encode_value(&content)?;
+        let decoded = decode_value(&Bencode::decode(&encoded.encode())?)?;
+        assert_eq!(decoded, content);
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! Binary in-place format (`bipf`), the encoding used by ssb-db2 and the
+//! buttwoo feed format.
+//!
+//! Every value is a varint of `length << 3 | type` followed by `length`
+//! bytes of payload.
+
+use serde_json::Value;
+
+use super::error::{Error, Result};
+
+const STRING: u8 = 0;
+const BUFFER: u8 = 1;
+const INT: u8 = 2;
+const DOUBLE: u8 = 3;
+const ARRAY: u8 = 4;
+const OBJECT: u8 = 5;
+const BOOLNULL: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bipf {
+    String(String),
+    Buffer(Vec<u8>),
+    Int(i32),
+    Double(f64),
+    Array(Vec<Bipf>),
+    /// Entries in their original order.
+    Object(Vec<(String, Bipf)>),
+    Bool(bool),
+    Null,
+}
+
+fn write_varint(buffer: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buffer.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buffer.push(n as u8);
+}
+
+fn read_varint(bytes: &[u8]) -> Result<(u64, &[u8])> {
+    let mut n = 0u64;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        n |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((n, &bytes[i + 1..]));
+        }
+    }
+    Err(Error::InvalidBipf)
+}
+
+impl Bipf {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        self.encode_into(&mut buffer);
+        buffer
+    }
+
+    fn encode_into(&self, buffer: &mut Vec<u8>) {
+        let (value_type, payload) = match self {
+            Bipf::String(s) => (STRING, s.as_bytes().to_vec()),
+            Bipf::Buffer(bytes) => (BUFFER, bytes.clone()),
+            Bipf::Int(n) => (INT, n.to_le_bytes().to_vec()),
+            Bipf::Double(n) => (DOUBLE, n.to_le_bytes().to_vec()),
+            Bipf::Array(items) => {
+                let mut payload = Vec::new();
+                for item in items {
+                    item.encode_into(&mut payload);
+                }
+                (ARRAY, payload)
+            }
+            Bipf::Object(entries) => {
+                let mut payload = Vec::new();
+                for (key, value) in entries {
+                    Bipf::String(key.clone()).encode_into(&mut payload);
+                    value.encode_into(&mut payload);
+                }
+                (OBJECT, payload)
+            }
+            Bipf::Bool(b) => (BOOLNULL, vec![*b as u8]),
+            Bipf::Null => (BOOLNULL, vec![]),
+        };
+        write_varint(buffer, (payload.len() as u64) << 3 | value_type as u64);
+        buffer.extend_from_slice(&payload);
+    }
+
+    /// Decodes `bytes`, which must contain exactly one value.
+    pub fn decode(bytes: &[u8]) -> Result<Bipf> {
+        let (value, rest) = Self::decode_prefix(bytes)?;
+        if !rest.is_empty() {
+            return Err(Error::InvalidBipf);
+        }
+        Ok(value)
+    }
+
+    fn decode_prefix(bytes: &[u8]) -> Result<(Bipf, &[u8])> {
+        let (tag, rest) = read_varint(bytes)?;
+        let len = (tag >> 3) as usize;
+        if rest.len() < len {
+            return Err(Error::InvalidBipf);
+        }
+        let (payload, rest) = rest.split_at(len);
+        let value = match (tag & 7) as u8 {
+            STRING => {
+                Bipf::String(String::from_utf8(payload.to_vec()).map_err(|_| Error::InvalidBipf)?)
+            }
+            BUFFER => Bipf::Buffer(payload.to_vec()),
+            INT if len == 4 => {
+                let mut n = [0u8; 4];
+                n.copy_from_slice(payload);
+                Bipf::Int(i32::from_le_bytes(n))
+            }
+            DOUBLE if len == 8 => {
+                let mut n = [0u8; 8];
+                n.copy_from_slice(payload);
+                Bipf::Double(f64::from_le_bytes(n))
+            }
+            ARRAY => {
+                let mut items = Vec::new();
+                let mut payload = payload;
+                while !payload.is_empty() {
+                    let (item, next) = Self::decode_prefix(payload)?;
+                    items.push(item);
+                    payload = next;
+                }
+                Bipf::Array(items)
+            }
+            OBJECT => {
+                let mut entries = Vec::new();
+                let mut payload = payload;
+                while !payload.is_empty() {
+                    let (key, next) = Self::decode_prefix(payload)?;
+                    let (value, next) = Self::decode_prefix(next)?;
+                    match key {
+                        Bipf::String(key) => entries.push((key, value)),
+                        _ => return Err(Error::InvalidBipf),
+                    }
+                    payload = next;
+                }
+                Bipf::Object(entries)
+            }
+            BOOLNULL => match payload {
+                [] => Bipf::Null,
+                [b] => Bipf::Bool(*b == 1),
+                _ => return Err(Error::InvalidBipf),
+            },
+            _ => return Err(Error::InvalidBipf),
+        };
+        Ok((value, rest))
+    }
+
+    /// The bipf form of a json value; integers that fit in 32 bits are
+    /// encoded as `Int`, other numbers as `Double`.
+    pub fn from_json(value: &Value) -> Bipf {
+        match value {
+            Value::Null => Bipf::Null,
+            Value::Bool(b) => Bipf::Bool(*b),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) if n >= i32::MIN as i64 && n <= i32::MAX as i64 => Bipf::Int(n as i32),
+                _ => Bipf::Double(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => Bipf::String(s.clone()),
+            Value::Array(items) => Bipf::Array(items.iter().map(Bipf::from_json).collect()),
+            Value::Object(entries) => Bipf::Object(
+                entries
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Bipf::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// The json form of the value; buffers are encoded as base64 strings.
+    pub fn to_json(&self) -> Result<Value> {
+        Ok(match self {
+            Bipf::String(s) => Value::String(s.clone()),
+            Bipf::Buffer(bytes) => Value::String(base64::encode(bytes)),
+            Bipf::Int(n) => Value::from(*n),
+            Bipf::Double(n) => {
+                if n.fract() == 0.0 && n.abs() < 9007199254740992.0 {
+                    Value::from(*n as i64)
+                } else {
+                    serde_json::Number::from_f64(*n)
+                        .map(Value::Number)
+                        .ok_or(Error::InvalidBipf)?
+                }
+            }
+            Bipf::Array(items) => Value::Array(
+                items
+                    .iter()
+                    .map(Bipf::to_json)
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Bipf::Object(entries) => Value::Object(
+                entries
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), v.to_json()?)))
+                    .collect::<Result<serde_json::Map<_, _>>>()?,
+            ),
+            Bipf::Bool(b) => Value::Bool(*b),
+            Bipf::Null => Value::Null,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_bipf_round_trip() -> Result<()> {
+        assert_eq!(
+            Bipf::String("hi".to_string()).encode(),
+            vec![0x10, b'h', b'i']
+        );
+        assert_eq!(Bipf::Int(1).encode(), vec![0x22, 1, 0, 0, 0]);
+        assert_eq!(Bipf::Null.encode(), vec![0x06]);
+
+        let value =
+            json!({"type": "post", "n": 1, "big": 1650000000000u64, "x": 0.5, "ok": [true, null]});
+        let encoded = Bipf::from_json(&value).encode();
+        assert_eq!(Bipf::decode(&encoded)?.to_json()?, value);
+        assert!(Bipf::decode(&encoded[..encoded.len() - 1]).is_err());
+        Ok(())
+    }
+}
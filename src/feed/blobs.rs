@@ -0,0 +1,59 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+use super::message::Message;
+
+static BLOB_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"&[0-9A-Za-z+/]{43}=\.sha256").unwrap());
+
+fn collect(value: &Value, refs: &mut Vec<String>) {
+    match value {
+        Value::String(s) => {
+            for found in BLOB_REGEX.find_iter(s) {
+                if !refs.iter().any(|r| r == found.as_str()) {
+                    refs.push(found.as_str().to_string());
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect(item, refs)),
+        Value::Object(entries) => entries.values().for_each(|v| collect(v, refs)),
+        _ => {}
+    }
+}
+
+/// Every blob id (`&...sha256`) referenced in `content`: links in
+/// `mentions`, images of abouts and gatherings, and ids embedded in text
+/// such as markdown images. Each id appears once, in order of appearance.
+pub fn blob_refs(content: &Value) -> Vec<String> {
+    let mut refs = Vec::new();
+    collect(content, &mut refs);
+    refs
+}
+
+impl Message {
+    /// The blobs referenced by the content, see `blob_refs`.
+    pub fn blob_refs(&self) -> Vec<String> {
+        blob_refs(self.content())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_blob_refs() {
+        let a = format!("&{}=.sha256", "a".repeat(43));
+        let b = format!("&{}=.sha256", "B".repeat(43));
+        let c = format!("&{}=.sha256", "c".repeat(43));
+        let content = json!({
+            "type": "post",
+            "text": format!("look ![cat]({}) and [file]({})", a, b),
+            "mentions": [{"link": a, "type": "image/png"}, {"link": "%notablob.sha256"}],
+            "image": {"link": c},
+        });
+        assert_eq!(blob_refs(&content), vec![a, b, c]);
+        assert!(blob_refs(&json!("YWJj.box")).is_empty());
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::SystemTime;
+
+use serde_json::Value;
+
+use super::{error::Result, message::Message, signobj::HmacKey};
+use crate::keystore::OwnedIdentity;
+
+/// Builds a signed message with every input fixed, so the same inputs
+/// always give the same bytes; useful for golden tests.
+pub struct MessageBuilder {
+    identity: OwnedIdentity,
+    content: Value,
+    /// Key and sequence of the previous message.
+    previous: Option<(String, u64)>,
+    timestamp: Option<f64>,
+    hmac_key: Option<HmacKey>,
+}
+
+impl MessageBuilder {
+    pub fn new(identity: OwnedIdentity, content: Value) -> Self {
+        Self {
+            identity,
+            content,
+            previous: None,
+            timestamp: None,
+            hmac_key: None,
+        }
+    }
+    pub fn previous(self, key: String, sequence: u64) -> Self {
+        Self {
+            previous: Some((key, sequence)),
+            ..self
+        }
+    }
+    pub fn previous_msg(self, prev: &Message) -> Self {
+        self.previous(prev.id().to_string(), prev.sequence())
+    }
+    /// Milliseconds since the epoch; the current time if not set.
+    pub fn timestamp(self, timestamp: f64) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+    /// Signs the hmac of the message with `hmac_key`, see `sign_obj_hmac`.
+    pub fn hmac_key(self, hmac_key: HmacKey) -> Self {
+        Self {
+            hmac_key: Some(hmac_key),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<Message> {
+        let timestamp = match self.timestamp {
+            Some(timestamp) => timestamp,
+            None => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)?
+                .as_millis() as f64,
+        };
+        Message::sign_after(
+            self.previous,
+            &self.identity,
+            self.content,
+            timestamp,
+            self.hmac_key.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reproducible_message() -> Result<()> {
+        let build = || {
+            MessageBuilder::new(OwnedIdentity::from_seed(&[7; 32]), json!({"type": "post"}))
+                .previous(
+                    "%AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=.sha256".to_string(),
+                    4,
+                )
+                .timestamp(1600000000000.0)
+                .build()
+        };
+        let msg = build()?;
+        assert_eq!(msg.sequence(), 5);
+        assert_eq!(msg.timestamp(), 1600000000000.0);
+        assert_eq!(msg.to_string(), build()?.to_string());
+        Message::from_value(msg.value)?;
+        Ok(())
+    }
+}
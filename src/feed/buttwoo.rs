@@ -0,0 +1,224 @@
+//! The buttwoo feed format (`ssb-buttwoo`), produced by newer ssb-db2
+//! deployments.
+//!
+//! A message is the bipf array `[value, signature, content]`, where `value`
+//! is the bipf array `[author, parent, sequence, timestamp, previous, tag,
+//! contentLength, contentHash]` and the signature covers its encoding.
+
+use serde_json::Value;
+
+use super::{
+    bfe,
+    bipf::Bipf,
+    error::{Error, Result},
+};
+use crate::crypto::backend::{hash::sha256, sign::ed25519};
+use crate::{crypto, keystore::OwnedIdentity};
+
+pub const BUTTWOO_SUFFIX: &str = ".buttwoo-v1";
+
+/// Tag of a regular message.
+pub const TAG_STANDARD: u8 = 0;
+/// Tag of the last message of a feed.
+pub const TAG_END_OF_FEED: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ButtwooMessage {
+    /// `@...buttwoo-v1` id of the author.
+    pub author: String,
+    /// Id of the message this feed is a subfeed of, if any.
+    pub parent: Option<String>,
+    pub sequence: u64,
+    pub timestamp: u64,
+    /// `%...buttwoo-v1` id of the previous message.
+    pub previous: Option<String>,
+    pub tag: u8,
+    pub content: Value,
+    pub signature: Vec<u8>,
+}
+
+/// The buttwoo feed id of `identity`.
+pub fn buttwoo_feed_id(identity: &OwnedIdentity) -> String {
+    format!("@{}{}", base64::encode(&identity.pk), BUTTWOO_SUFFIX)
+}
+
+fn encode_number(n: u64) -> Bipf {
+    if n <= i32::MAX as u64 {
+        Bipf::Int(n as i32)
+    } else {
+        Bipf::Double(n as f64)
+    }
+}
+
+fn decode_number(value: &Bipf) -> Result<u64> {
+    match value {
+        Bipf::Int(n) if *n >= 0 => Ok(*n as u64),
+        Bipf::Double(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as u64),
+        _ => Err(Error::InvalidBipf),
+    }
+}
+
+fn encode_id(id: &Option<String>) -> Bipf {
+    Bipf::Buffer(match id {
+        Some(id) => bfe::encode_str(id),
+        None => bfe::encode_nil(),
+    })
+}
+
+fn decode_id(value: &Bipf) -> Result<Option<String>> {
+    match value {
+        Bipf::Buffer(bytes) => match bfe::decode(bytes)? {
+            Value::Null => Ok(None),
+            Value::String(id) => Ok(Some(id)),
+            _ => Err(Error::InvalidBfe),
+        },
+        _ => Err(Error::InvalidBipf),
+    }
+}
+
+impl ButtwooMessage {
+    /// Create the message following `prev` in the feed of `identity`.
+    pub fn sign(
+        prev: Option<&ButtwooMessage>,
+        identity: &OwnedIdentity,
+        parent: Option<String>,
+        content: Value,
+        timestamp: u64,
+    ) -> Result<Self> {
+        let mut msg = ButtwooMessage {
+            author: buttwoo_feed_id(identity),
+            parent,
+            sequence: prev.map_or(1, |prev| prev.sequence + 1),
+            timestamp,
+            previous: prev.map(|prev| prev.id()).transpose()?,
+            tag: TAG_STANDARD,
+            content,
+            signature: Vec::new(),
+        };
+        let signature = crypto::sign_detached(&msg.encode_value(), &identity.sk);
+        msg.signature = signature.as_ref().to_vec();
+        Ok(msg)
+    }
+
+    fn encode_content(&self) -> Vec<u8> {
+        Bipf::from_json(&self.content).encode()
+    }
+
+    fn encode_value(&self) -> Vec<u8> {
+        let content = self.encode_content();
+        let content_hash = sha256::hash(&content);
+        Bipf::Array(vec![
+            Bipf::Buffer(bfe::encode_str(&self.author)),
+            encode_id(&self.parent),
+            encode_number(self.sequence),
+            encode_number(self.timestamp),
+            encode_id(&self.previous),
+            Bipf::Buffer(vec![self.tag]),
+            encode_number(content.len() as u64),
+            Bipf::Buffer(content_hash.as_ref().to_vec()),
+        ])
+        .encode()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        Bipf::Array(vec![
+            Bipf::Buffer(self.encode_value()),
+            Bipf::Buffer(self.signature.clone()),
+            Bipf::Buffer(self.encode_content()),
+        ])
+        .encode()
+    }
+
+    /// Decodes and checks the content against the length and hash in the
+    /// signed value; the signature is checked by `verify`.
+    pub fn decode(encoded: &[u8]) -> Result<Self> {
+        let (value, signature, content) = match Bipf::decode(encoded)? {
+            Bipf::Array(items) => match items.as_slice() {
+                [Bipf::Buffer(value), Bipf::Buffer(signature), Bipf::Buffer(content)] => {
+                    (value.clone(), signature.clone(), content.clone())
+                }
+                _ => return Err(Error::InvalidBipf),
+            },
+            _ => return Err(Error::InvalidBipf),
+        };
+        let fields = match Bipf::decode(&value)? {
+            Bipf::Array(fields) if fields.len() == 8 => fields,
+            _ => return Err(Error::InvalidBipf),
+        };
+
+        if decode_number(&fields[6])? != content.len() as u64
+            || fields[7] != Bipf::Buffer(sha256::hash(&content).as_ref().to_vec())
+        {
+            return Err(Error::InvalidContent);
+        }
+
+        let msg = ButtwooMessage {
+            author: decode_id(&fields[0])?.ok_or(Error::InvalidBfe)?,
+            parent: decode_id(&fields[1])?,
+            sequence: decode_number(&fields[2])?,
+            timestamp: decode_number(&fields[3])?,
+            previous: decode_id(&fields[4])?,
+            tag: match &fields[5] {
+                Bipf::Buffer(tag) if tag.len() == 1 => tag[0],
+                _ => return Err(Error::InvalidBipf),
+            },
+            content: Bipf::decode(&content)?.to_json()?,
+            signature,
+        };
+        // the signature is checked on the re-encoded value, so only accept
+        // contents in the encoding produced by `Bipf::from_json`
+        if msg.encode_value() != value {
+            return Err(Error::InvalidContent);
+        }
+        Ok(msg)
+    }
+
+    /// The `%...buttwoo-v1` id, the hash of the encoded message.
+    pub fn id(&self) -> Result<String> {
+        let hash = sha256::hash(&self.encode());
+        Ok(format!("%{}{}", base64::encode(&hash), BUTTWOO_SUFFIX))
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        let author = self
+            .author
+            .strip_prefix('@')
+            .and_then(|author| author.strip_suffix(BUTTWOO_SUFFIX))
+            .ok_or(Error::InvalidJson)?;
+        let author = base64::decode(author)?;
+        let author = ed25519::PublicKey::from_slice(&author).ok_or(Error::InvalidSignature)?;
+        let signature =
+            ed25519::Signature::from_slice(&self.signature).ok_or(Error::InvalidSignature)?;
+        if crypto::verify_detached(&signature, &self.encode_value(), &author) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_buttwoo_sign_verify() -> Result<()> {
+        let identity = OwnedIdentity::create();
+        let content = json!({"type": "post", "text": "hello", "n": 1.5});
+        let msg1 = ButtwooMessage::sign(None, &identity, None, content.clone(), 1650000000000)?;
+        let msg2 = ButtwooMessage::sign(Some(&msg1), &identity, None, content, 1650000000001)?;
+
+        let decoded = ButtwooMessage::decode(&msg2.encode())?;
+        assert_eq!(decoded, msg2);
+        assert_eq!(decoded.previous, Some(msg1.id()?));
+        decoded.verify()?;
+
+        let tampered = ButtwooMessage {
+            sequence: 3,
+            ..decoded
+        };
+        assert!(matches!(tampered.verify(), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use serde_json::Value;
+
+/// Algorithm of encrypted content, named after its suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoxAlgo {
+    /// `.box`, the private-box format.
+    Box,
+    /// `.box2`, the envelope format of private groups.
+    Box2,
+}
+
+impl BoxAlgo {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            BoxAlgo::Box => ".box",
+            BoxAlgo::Box2 => ".box2",
+        }
+    }
+}
+
+/// The content of a message, told apart from encrypted content that needs
+/// to go through an unboxer first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    Plain(Value),
+    Encrypted {
+        algo: BoxAlgo,
+        /// The whole string, suffix included, as expected by
+        /// `privatebox_decipher`.
+        ciphertext: String,
+    },
+}
+
+impl Content {
+    pub fn from_value(content: &Value) -> Self {
+        if let Value::String(text) = content {
+            for algo in [BoxAlgo::Box2, BoxAlgo::Box] {
+                if text.ends_with(algo.suffix()) {
+                    return Content::Encrypted {
+                        algo,
+                        ciphertext: text.clone(),
+                    };
+                }
+            }
+        }
+        Content::Plain(content.clone())
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, Content::Encrypted { .. })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_content_from_value() {
+        assert_eq!(
+            Content::from_value(&json!("YWJj.box2")),
+            Content::Encrypted {
+                algo: BoxAlgo::Box2,
+                ciphertext: "YWJj.box2".to_string()
+            }
+        );
+        assert!(Content::from_value(&json!("YWJj.box")).is_encrypted());
+        assert!(!Content::from_value(&json!("just text")).is_encrypted());
+        let post = json!({"type": "post"});
+        assert_eq!(Content::from_value(&post), Content::Plain(post));
+    }
+}
@@ -1,7 +1,8 @@
-use super::error::Result;
-use kuska_sodiumoxide::crypto::hash::sha256;
 use serde_json::Value;
 
+use super::error::{Error, Result};
+use crate::crypto::backend::hash::sha256;
+
 pub fn ssb_sha256(v: &Value) -> Result<sha256::Digest> {
     let v8encoding = stringify_json(v)?
         .encode_utf16()
@@ -11,6 +12,76 @@ pub fn ssb_sha256(v: &Value) -> Result<sha256::Digest> {
     Ok(sha256::hash(&v8encoding[..]))
 }
 
+/// The bytes signed by `ssb-keys`: the UTF-8 of `JSON.stringify(v, null, 2)`.
+pub fn signing_bytes(v: &Value) -> Result<Vec<u8>> {
+    Ok(stringify_json(v)?.into_bytes())
+}
+
+/// Formats `n` like the ECMAScript `Number::toString`, e.g. `1e21`,
+/// `0.000001` and `1e-7`.
+pub fn ecmascript_number(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    }
+    if n == 0.0 {
+        return "0".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let sign = if n < 0.0 { "-" } else { "" };
+
+    // the shortest digits that round trip, e.g. "1.2345e-7"
+    let sci = format!("{:e}", n.abs());
+    let (mantissa, exponent) = sci.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let k = digits.len() as i32;
+    let n = exponent.parse::<i32>().unwrap() + 1;
+
+    let formatted = if k <= n && n <= 21 {
+        format!("{}{}", digits, "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        format!("{}.{}", &digits[..n as usize], &digits[n as usize..])
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{}", "0".repeat(-n as usize), digits)
+    } else {
+        let exponent_sign = if n - 1 < 0 { '-' } else { '+' };
+        if k == 1 {
+            format!("{}e{}{}", digits, exponent_sign, (n - 1).abs())
+        } else {
+            format!(
+                "{}.{}e{}{}",
+                &digits[..1],
+                &digits[1..],
+                exponent_sign,
+                (n - 1).abs()
+            )
+        }
+    };
+    format!("{}{}", sign, formatted)
+}
+
+/// Quotes `s` like `JSON.stringify`.
+fn quote_json_string(buffer: &mut String, s: &str) {
+    buffer.push('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => buffer.push_str("\\\""),
+            '\\' => buffer.push_str("\\\\"),
+            '\u{08}' => buffer.push_str("\\b"),
+            '\u{0c}' => buffer.push_str("\\f"),
+            '\n' => buffer.push_str("\\n"),
+            '\r' => buffer.push_str("\\r"),
+            '\t' => buffer.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => buffer.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => buffer.push(ch),
+        }
+    }
+    buffer.push('"');
+}
+
+/// Serializes `v` like `JSON.stringify(v, null, 2)` in V8, the encoding
+/// used to sign and hash classic messages.
 pub fn stringify_json(v: &Value) -> Result<String> {
     fn spaces(n: usize) -> &'static str {
         &"                                         "[..2 * n]
@@ -25,7 +96,7 @@ pub fn stringify_json(v: &Value) -> Result<String> {
                     buffer.push_str("{\n");
                     for (i, (key, value)) in values.iter().enumerate() {
                         buffer.push_str(spaces(level + 1));
-                        buffer.push_str(&serde_json::to_string(&key)?);
+                        quote_json_string(buffer, key);
                         buffer.push_str(": ");
                         append_json(buffer, level + 1, value)?;
                         if i < values.len() - 1 {
@@ -55,14 +126,11 @@ pub fn stringify_json(v: &Value) -> Result<String> {
                 }
             }
             Value::String(value) => {
-                buffer.push_str(&serde_json::to_string(&value)?);
+                quote_json_string(buffer, value);
             }
             Value::Number(value) => {
-                let mut as_str = value.to_string();
-                if as_str.contains('e') && !as_str.contains("e-") && !as_str.contains("e+") {
-                    as_str = as_str.replace('e', "e+")
-                }
-                buffer.push_str(&as_str);
+                let value = value.as_f64().ok_or(Error::InvalidJson)?;
+                buffer.push_str(&ecmascript_number(value));
             }
             Value::Bool(value) => {
                 buffer.push_str(if *value { "true" } else { "false" });
@@ -110,6 +178,40 @@ mod test {
         assert_eq!(expected, json);
         Ok(())
     }
+    #[test]
+    fn test_ecmascript_number() {
+        let cases = [
+            (0.0, "0"),
+            (-0.0, "0"),
+            (1.0, "1"),
+            (-1.5, "-1.5"),
+            (1439392020612.0, "1439392020612"),
+            (1567190273951.0159, "1567190273951.0159"),
+            (0.1, "0.1"),
+            (0.000001, "0.000001"),
+            (0.0000001, "1e-7"),
+            (1.5e-10, "1.5e-10"),
+            (1e21, "1e+21"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (9.691449834862513e76, "9.691449834862513e+76"),
+        ];
+        for (n, expected) in cases.iter() {
+            assert_eq!(ecmascript_number(*n), *expected);
+        }
+    }
+
+    #[test]
+    fn test_stringify_escapes() -> Result<()> {
+        let v: Value =
+            serde_json::from_str(r#"{"a\"b":"\u0001\n\u001f\u007f\u2028é","n":1.0,"e":1e3}"#)?;
+        assert_eq!(
+            stringify_json(&v)?,
+            "{\n  \"a\\\"b\": \"\\u0001\\n\\u001f\u{7f}\u{2028}é\",\n  \"n\": 1,\n  \"e\": 1000\n}"
+        );
+        assert_eq!(signing_bytes(&v)?, stringify_json(&v)?.into_bytes());
+        Ok(())
+    }
+
     #[test]
     fn test_verify_known_msg_integrity() -> Result<()> {
         let expected = "Cg0ZpZ8cV85G8UIIropgBOvM8+Srlv9LSGDNGnpdK44=";
@@ -12,6 +12,44 @@ pub enum Error {
     InvalidJson,
     #[error("invalid signature")]
     InvalidSignature,
+    #[error("invalid hmac key")]
+    InvalidHmacKey,
+    #[error("message fields missing, unexpected or out of order")]
+    InvalidFieldOrder,
+    #[error("unsupported hash algorithm: {0}")]
+    InvalidHashAlgorithm(String),
+    #[error("invalid content")]
+    InvalidContent,
+    #[error("invalid content type")]
+    InvalidContentType,
+    #[error("author differs from the previous message")]
+    AuthorMismatch,
+    #[error("sequence is not a positive integer")]
+    InvalidSequence,
+    #[error("timestamp is not a finite number")]
+    InvalidTimestamp,
+    #[error("expected sequence {expected}, found {actual}")]
+    SequenceMismatch { expected: u64, actual: u64 },
+    #[error("previous does not match the last message")]
+    PreviousMismatch,
+    #[error("message {index} of the chain is invalid: {reason}")]
+    InvalidChainEntry { index: usize, reason: Box<Error> },
+    #[error("index message does not point to the indexed message")]
+    IndexMismatch,
+    #[error("content does not match the schema of its type: {0:?}")]
+    InvalidSchema(Vec<crate::feed::SchemaViolation>),
+    #[error("invalid bencode")]
+    InvalidBencode,
+    #[error("invalid binary field encoding")]
+    InvalidBfe,
+    #[error("invalid bipf")]
+    InvalidBipf,
+    #[error("mention {0} is not a valid link")]
+    InvalidMention(usize),
+    #[error("recipient {0} is not a feed id")]
+    InvalidRecipient(usize),
+    #[error("message is {0} code units long, over the limit")]
+    MessageTooLarge(usize),
     #[error("failed to decipher")]
     FailedToDecipher,
     #[error("cannot create key")]
@@ -27,9 +65,27 @@ pub enum Error {
     #[error("invalid key from group")]
     CryptoKeyFromGrupFailed,
     #[error("invalid key format")]
-    CryptoFormat(#[from] crate::crypto::Error),
+    CryptoFormat(#[source] crate::crypto::Error),
     #[error("invalid json")]
     Json(#[from] serde_json::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+/// Private-box failures keep the variants they had before private-box
+/// moved to `crypto`.
+impl From<crate::crypto::Error> for Error {
+    fn from(err: crate::crypto::Error) -> Self {
+        use crate::crypto::Error as Crypto;
+        match err {
+            Crypto::FailedToDecipher => Error::FailedToDecipher,
+            Crypto::CannotCreateKey => Error::CannotCreateKey,
+            Crypto::CannotReadNonce => Error::CannotReadNonce,
+            Crypto::CryptoScalarMultFailed => Error::CryptoScalarMultFailed,
+            Crypto::EmptyPlaintext => Error::EmptyPlaintext,
+            Crypto::BadRecipientCount => Error::BadRecipientCount,
+            Crypto::CryptoKeyFromGrupFailed => Error::CryptoKeyFromGrupFailed,
+            err => Error::CryptoFormat(err),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Dumping a `FeedStore` to a file and loading it back, for backups and
+//! migrations.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use super::{
+    base::{Feed, KvtMessage},
+    validate::{validate_kvt, FeedState},
+};
+use crate::store::{FeedStore, OffsetLog, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One `{key, value, timestamp}` json object per line.
+    JsonLines,
+    /// The flume `log.offset` format of ssb-server.
+    OffsetLog,
+}
+
+/// Writes every message of `store` to `path`, feed after feed, returning
+/// how many were written.
+pub fn export<S: FeedStore, P: AsRef<Path>>(
+    store: &S,
+    path: P,
+    format: ExportFormat,
+) -> Result<usize> {
+    let mut count = 0;
+    match format {
+        ExportFormat::JsonLines => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            for author in store.authors()? {
+                for msg in store.iter_feed(&author, 1)? {
+                    writeln!(writer, "{}", msg.into_feed().to_string())?;
+                    count += 1;
+                }
+            }
+            writer.flush()?;
+        }
+        ExportFormat::OffsetLog => {
+            let mut log = OffsetLog::open(path)?;
+            for author in store.authors()? {
+                for msg in store.iter_feed(&author, 1)? {
+                    log.append(msg.into_feed().to_string().as_bytes())?;
+                    count += 1;
+                }
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Validates the messages in `path` and appends them to `store`, skipping
+/// the ones already there. Returns how many were added.
+pub fn import<S: FeedStore, P: AsRef<Path>>(
+    store: &mut S,
+    path: P,
+    format: ExportFormat,
+) -> Result<usize> {
+    let mut count = 0;
+    let mut add = |data: &[u8]| -> Result<()> {
+        let feed: Feed = serde_json::from_slice(data).map_err(crate::feed::Error::from)?;
+        if store.get_by_key(&feed.key)?.is_some() {
+            return Ok(());
+        }
+        let author = feed.value.get("author").and_then(|author| author.as_str());
+        let state = match author {
+            Some(author) => store.latest(author)?,
+            None => None,
+        }
+        .map(|latest| FeedState::from_message(&latest.value));
+        let (timestamp, rts) = (feed.timestamp, feed.rts);
+        let key = feed.key.clone();
+        let value = validate_kvt(state.as_ref(), feed)?;
+        store.append(KvtMessage {
+            key,
+            value,
+            timestamp,
+            rts,
+        })?;
+        count += 1;
+        Ok(())
+    };
+    match format {
+        ExportFormat::JsonLines => {
+            for line in BufReader::new(File::open(path)?).lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    add(line.as_bytes())?;
+                }
+            }
+        }
+        ExportFormat::OffsetLog => {
+            // opening the log would create it
+            std::fs::metadata(&path)?;
+            let log = OffsetLog::open(path)?;
+            for record in log.iter() {
+                add(&record?.1)?;
+            }
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Message, keystore::OwnedIdentity, store::MemoryStore};
+    use serde_json::json;
+
+    #[test]
+    fn test_export_import() -> Result<()> {
+        let (alice, bob) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let mut store = MemoryStore::new();
+        let mut prev = None;
+        for n in 0..3 {
+            let msg = Message::sign(prev.as_ref(), &alice, json!({"type": "post", "n": n}))?;
+            store.append(KvtMessage::from_feed(Feed::new(msg.clone()))?)?;
+            prev = Some(msg);
+        }
+        let msg = Message::sign(None, &bob, json!({"type": "post"}))?;
+        store.append(KvtMessage::from_feed(Feed::new(msg))?)?;
+
+        for (format, ext) in [
+            (ExportFormat::JsonLines, "jsonl"),
+            (ExportFormat::OffsetLog, "offset"),
+        ] {
+            let path =
+                std::env::temp_dir().join(format!("kuska-export-{}.{}", std::process::id(), ext));
+            let _ = std::fs::remove_file(&path);
+            assert_eq!(export(&store, &path, format)?, 4);
+
+            let mut imported = MemoryStore::new();
+            assert_eq!(import(&mut imported, &path, format)?, 4);
+            assert_eq!(import(&mut imported, &path, format)?, 0);
+            assert_eq!(imported.latest(&alice.id)?.unwrap().value.sequence(), 3);
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,59 @@
+use super::message::Message;
+
+/// Two different messages with the same author and sequence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fork {
+    pub author: String,
+    pub sequence: u64,
+    /// Keys of both messages.
+    pub keys: (String, String),
+}
+
+impl std::fmt::Display for Fork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "feed {} forked at sequence {}: {} and {}",
+            self.author, self.sequence, self.keys.0, self.keys.1
+        )
+    }
+}
+
+/// Returns the fork if `a` and `b` are different messages of the same
+/// author claiming the same sequence.
+pub fn detect_fork(a: &Message, b: &Message) -> Option<Fork> {
+    if a.author() != b.author() || a.sequence() != b.sequence() {
+        return None;
+    }
+    let keys = (a.id().to_string(), b.id().to_string());
+    if keys.0 == keys.1 {
+        return None;
+    }
+    Some(Fork {
+        author: a.author().clone(),
+        sequence: a.sequence(),
+        keys,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Result, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_detect_fork() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg1 = Message::sign(None, &id, json!({"type": "post", "text": "a"}))?;
+        let forked = Message::sign(None, &id, json!({"type": "post", "text": "b"}))?;
+        let msg2 = Message::sign(Some(&msg1), &id, json!({"type": "post"}))?;
+
+        let fork = detect_fork(&msg1, &forked).unwrap();
+        assert_eq!(fork.sequence, 1);
+        assert_eq!(fork.keys, (msg1.id().to_string(), forked.id().to_string()));
+        assert!(detect_fork(&msg1, &msg1).is_none());
+        assert!(detect_fork(&msg1, &msg2).is_none());
+        Ok(())
+    }
+}
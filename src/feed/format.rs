@@ -0,0 +1,228 @@
+//! The operations validation, storage and replication need from a feed
+//! format, implemented by the classic, bendy butt and buttwoo formats.
+
+use super::{
+    bendybutt::{bendybutt_feed_id, BendyButtMessage, BENDYBUTT_FEED_SUFFIX},
+    bipf::Bipf,
+    buttwoo::{buttwoo_feed_id, ButtwooMessage, BUTTWOO_SUFFIX},
+    error::{Error, Result},
+    message::{check_size, Message},
+};
+use crate::{crypto::CURVE_ED25519_SUFFIX, keystore::OwnedIdentity, uri::normalize_id};
+
+pub trait FeedFormat {
+    type Message;
+
+    /// Suffix of the feed ids of the format.
+    const FEED_SUFFIX: &'static str;
+
+    fn feed_id(identity: &OwnedIdentity) -> String;
+
+    fn encode(msg: &Self::Message) -> Result<Vec<u8>>;
+
+    /// Decodes a message, checking its signature.
+    fn decode(bytes: &[u8]) -> Result<Self::Message>;
+
+    fn message_id(msg: &Self::Message) -> Result<String>;
+
+    fn verify(msg: &Self::Message) -> Result<()>;
+
+    /// Fails with `Error::MessageTooLarge` if over the size limit.
+    fn check_size(msg: &Self::Message) -> Result<()>;
+
+    fn author(msg: &Self::Message) -> &str;
+
+    fn sequence(msg: &Self::Message) -> u64;
+
+    fn previous(msg: &Self::Message) -> Option<&str>;
+}
+
+/// Maximum length of an encoded bendy butt message, in bytes.
+pub const BENDYBUTT_MAX_LEN: usize = 8192;
+/// Maximum length of the content of a buttwoo message, in bytes.
+pub const BUTTWOO_MAX_CONTENT_LEN: usize = 16384;
+
+pub struct Classic;
+pub struct BendyButt;
+pub struct Buttwoo;
+
+/// The formats, to pick the one of a feed at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormatKind {
+    Classic,
+    BendyButt,
+    Buttwoo,
+}
+
+impl FeedFormatKind {
+    /// The format of the feed `feed_id`, from its suffix.
+    pub fn from_feed_id(feed_id: &str) -> Option<Self> {
+        let feed_id = normalize_id(feed_id);
+        let feed_id = feed_id.as_ref();
+        if feed_id.ends_with(CURVE_ED25519_SUFFIX) {
+            Some(FeedFormatKind::Classic)
+        } else if feed_id.ends_with(BENDYBUTT_FEED_SUFFIX) {
+            Some(FeedFormatKind::BendyButt)
+        } else if feed_id.ends_with(BUTTWOO_SUFFIX) {
+            Some(FeedFormatKind::Buttwoo)
+        } else {
+            None
+        }
+    }
+}
+
+impl FeedFormat for Classic {
+    type Message = Message;
+
+    const FEED_SUFFIX: &'static str = CURVE_ED25519_SUFFIX;
+
+    fn feed_id(identity: &OwnedIdentity) -> String {
+        identity.id.clone()
+    }
+    fn encode(msg: &Message) -> Result<Vec<u8>> {
+        Ok(msg.to_string().into_bytes())
+    }
+    fn decode(bytes: &[u8]) -> Result<Message> {
+        Message::from_slice(bytes)
+    }
+    fn message_id(msg: &Message) -> Result<String> {
+        msg.compute_id()
+    }
+    fn verify(msg: &Message) -> Result<()> {
+        Message::from_value(msg.value.clone()).map(|_| ())
+    }
+    fn check_size(msg: &Message) -> Result<()> {
+        check_size(&msg.value)
+    }
+    fn author(msg: &Message) -> &str {
+        msg.author()
+    }
+    fn sequence(msg: &Message) -> u64 {
+        msg.sequence()
+    }
+    fn previous(msg: &Message) -> Option<&str> {
+        msg.previous().map(String::as_str)
+    }
+}
+
+impl FeedFormat for BendyButt {
+    type Message = BendyButtMessage;
+
+    const FEED_SUFFIX: &'static str = BENDYBUTT_FEED_SUFFIX;
+
+    fn feed_id(identity: &OwnedIdentity) -> String {
+        bendybutt_feed_id(identity)
+    }
+    fn encode(msg: &BendyButtMessage) -> Result<Vec<u8>> {
+        msg.encode()
+    }
+    fn decode(bytes: &[u8]) -> Result<BendyButtMessage> {
+        let msg = BendyButtMessage::decode(bytes)?;
+        msg.verify()?;
+        Ok(msg)
+    }
+    fn message_id(msg: &BendyButtMessage) -> Result<String> {
+        msg.id()
+    }
+    fn verify(msg: &BendyButtMessage) -> Result<()> {
+        msg.verify()
+    }
+    fn check_size(msg: &BendyButtMessage) -> Result<()> {
+        let len = msg.encode()?.len();
+        if len > BENDYBUTT_MAX_LEN {
+            return Err(Error::MessageTooLarge(len));
+        }
+        Ok(())
+    }
+    fn author(msg: &BendyButtMessage) -> &str {
+        &msg.author
+    }
+    fn sequence(msg: &BendyButtMessage) -> u64 {
+        msg.sequence
+    }
+    fn previous(msg: &BendyButtMessage) -> Option<&str> {
+        msg.previous.as_deref()
+    }
+}
+
+impl FeedFormat for Buttwoo {
+    type Message = ButtwooMessage;
+
+    const FEED_SUFFIX: &'static str = BUTTWOO_SUFFIX;
+
+    fn feed_id(identity: &OwnedIdentity) -> String {
+        buttwoo_feed_id(identity)
+    }
+    fn encode(msg: &ButtwooMessage) -> Result<Vec<u8>> {
+        Ok(msg.encode())
+    }
+    fn decode(bytes: &[u8]) -> Result<ButtwooMessage> {
+        let msg = ButtwooMessage::decode(bytes)?;
+        msg.verify()?;
+        Ok(msg)
+    }
+    fn message_id(msg: &ButtwooMessage) -> Result<String> {
+        msg.id()
+    }
+    fn verify(msg: &ButtwooMessage) -> Result<()> {
+        msg.verify()
+    }
+    fn check_size(msg: &ButtwooMessage) -> Result<()> {
+        let len = Bipf::from_json(&msg.content).encode().len();
+        if len > BUTTWOO_MAX_CONTENT_LEN {
+            return Err(Error::MessageTooLarge(len));
+        }
+        Ok(())
+    }
+    fn author(msg: &ButtwooMessage) -> &str {
+        &msg.author
+    }
+    fn sequence(msg: &ButtwooMessage) -> u64 {
+        msg.sequence
+    }
+    fn previous(msg: &ButtwooMessage) -> Option<&str> {
+        msg.previous.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    /// Decodes the encoding of `msg` and checks it is the same message.
+    fn round_trip<F: FeedFormat>(msg: &F::Message) -> Result<()> {
+        let decoded = F::decode(&F::encode(msg)?)?;
+        assert_eq!(F::message_id(&decoded)?, F::message_id(msg)?);
+        assert_eq!(F::sequence(&decoded), F::sequence(msg));
+        F::check_size(&decoded)?;
+        assert!(F::author(&decoded).ends_with(F::FEED_SUFFIX));
+        Ok(())
+    }
+
+    #[test]
+    fn test_feed_formats() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let content = json!({"type": "post", "text": "hi"});
+
+        let classic = Message::sign(None, &id, content.clone())?;
+        round_trip::<Classic>(&classic)?;
+        let bendybutt = BendyButtMessage::sign(
+            None,
+            &id,
+            &id,
+            json!({"type": "metafeed/add/existing", "subfeed": id.id}),
+            1,
+        )?;
+        round_trip::<BendyButt>(&bendybutt)?;
+        let buttwoo = ButtwooMessage::sign(None, &id, None, content, 1)?;
+        round_trip::<Buttwoo>(&buttwoo)?;
+
+        assert_eq!(
+            FeedFormatKind::from_feed_id(&Buttwoo::feed_id(&id)),
+            Some(FeedFormatKind::Buttwoo)
+        );
+        assert_eq!(FeedFormatKind::from_feed_id("@abc"), None);
+        Ok(())
+    }
+}
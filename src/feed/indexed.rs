@@ -0,0 +1,124 @@
+//! Index feeds, whose messages each point to a message of another feed
+//! (`{"type": "metafeed/index", "indexed": "%..."}`). They are replicated as
+//! `[index, indexed]` pairs, so the indexed messages come along.
+
+use serde_json::Value;
+
+use super::{
+    error::{Error, Result},
+    message::Message,
+    validate::{validate, validate_ooo, FeedState},
+};
+
+pub const TYPE_INDEX: &str = "metafeed/index";
+
+/// A message of an index feed with the message it points to.
+#[derive(Debug, Clone)]
+pub struct IndexedMessage {
+    pub index: Message,
+    pub indexed: Message,
+}
+
+/// Fails with `Error::IndexMismatch` unless `index` is an index message
+/// pointing to `indexed`.
+pub fn check_index_entry(index: &Message, indexed: &Message) -> Result<()> {
+    if index.content_type() != Some(TYPE_INDEX) {
+        return Err(Error::InvalidContentType);
+    }
+    match index.content().get("indexed").and_then(Value::as_str) {
+        Some(key) if key == indexed.id().to_string() => Ok(()),
+        _ => Err(Error::IndexMismatch),
+    }
+}
+
+fn split_pair(value: Value) -> Result<(Value, Value)> {
+    match value {
+        Value::Array(mut pair) if pair.len() == 2 => {
+            let indexed = pair.pop().unwrap();
+            Ok((pair.pop().unwrap(), indexed))
+        }
+        _ => Err(Error::InvalidJson),
+    }
+}
+
+impl IndexedMessage {
+    /// Validates an `[index, indexed]` pair, the index message as following
+    /// `state` in the index feed.
+    pub fn validate(state: Option<&FeedState>, value: Value) -> Result<Self> {
+        let (index, indexed) = split_pair(value)?;
+        let index = validate(state, index)?;
+        let indexed = validate_ooo(indexed)?;
+        check_index_entry(&index, &indexed)?;
+        Ok(IndexedMessage { index, indexed })
+    }
+}
+
+/// Like `validate_chain`, for a batch of `[index, indexed]` pairs.
+pub fn validate_index_chain<I>(state: Option<&FeedState>, values: I) -> Result<Vec<IndexedMessage>>
+where
+    I: IntoIterator<Item = Value>,
+{
+    let mut state = state.cloned();
+    let mut entries = Vec::new();
+    for (index, value) in values.into_iter().enumerate() {
+        let entry = IndexedMessage::validate(state.as_ref(), value).map_err(|reason| {
+            Error::InvalidChainEntry {
+                index,
+                reason: Box::new(reason),
+            }
+        })?;
+        state = Some(FeedState::from_message(&entry.index));
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keystore::OwnedIdentity;
+    use serde_json::json;
+
+    #[test]
+    fn test_index_feed() -> Result<()> {
+        let main = OwnedIdentity::create();
+        let index = OwnedIdentity::create();
+        let post = Message::sign(None, &main, json!({"type": "post"}))?;
+        let vote = Message::sign(Some(&post), &main, json!({"type": "vote"}))?;
+
+        let entry1 = Message::sign(
+            None,
+            &index,
+            json!({"type": TYPE_INDEX, "indexed": post.id().to_string()}),
+        )?;
+        let entry2 = Message::sign(
+            Some(&entry1),
+            &index,
+            json!({"type": TYPE_INDEX, "indexed": vote.id().to_string()}),
+        )?;
+
+        let entries = validate_index_chain(
+            None,
+            vec![
+                json!([entry1.value, post.value]),
+                json!([entry2.value, vote.value]),
+            ],
+        )?;
+        assert_eq!(entries[1].indexed.id().to_string(), vote.id().to_string());
+
+        let wrong = validate_index_chain(
+            None,
+            vec![
+                json!([entry1.value, post.value]),
+                json!([entry2.value, post.value]),
+            ],
+        );
+        match wrong {
+            Err(Error::InvalidChainEntry { index: 1, reason }) => {
+                assert!(matches!(*reason, Error::IndexMismatch))
+            }
+            other => panic!("{:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,21 @@
-use std::{str::FromStr, time::SystemTime};
+use std::{borrow::Cow, str::FromStr, time::SystemTime};
 
-use kuska_sodiumoxide::crypto::sign::ed25519;
 use serde_json::Value;
 
+use super::content::Content;
 use super::{
+    encoding::stringify_json,
     error::{Error, Result},
-    ssb_sha256, stringify_json,
+    signobj::{sign_obj_hmac, sign_obj_with, verify_obj_hmac, HmacKey},
+    ssb_sha256,
+    unbox::UnboxerRegistry,
+};
+use crate::crypto::backend::hash::sha256;
+use crate::{
+    api::dto::content::{Contact, Post, Tombstone, TypedMessage},
+    crypto::Signer,
+    keystore::OwnedIdentity,
 };
-use crate::{crypto::ToSodiumObject, keystore::OwnedIdentity};
-use kuska_sodiumoxide::crypto::hash::sha256;
 
 const MSG_PREVIOUS: &str = "previous";
 const MSG_AUTHOR: &str = "author";
@@ -18,6 +25,19 @@ const MSG_HASH: &str = "hash";
 const MSG_CONTENT: &str = "content";
 const MSG_SIGNATURE: &str = "signature";
 
+/// Maximum length of a message, in UTF-16 code units of its signing
+/// encoding (what js `ssb-validate` counts).
+pub const MAX_MESSAGE_LEN: usize = 8192;
+
+/// Fails with `Error::MessageTooLarge` if `value` is over `MAX_MESSAGE_LEN`.
+pub fn check_size(value: &Value) -> Result<()> {
+    let len = stringify_json(value)?.encode_utf16().count();
+    if len > MAX_MESSAGE_LEN {
+        return Err(Error::MessageTooLarge(len));
+    }
+    Ok(())
+}
+
 macro_rules! cast {
     ($input:expr,$pth:path) => {
         match $input {
@@ -55,94 +75,201 @@ impl AsRef<[u8]> for MessageId {
 #[derive(Debug, Deserialize, Clone)]
 pub struct Message {
     pub value: serde_json::Value,
+    /// The bytes the message was parsed from, if any.
+    #[serde(skip)]
+    received: Option<Vec<u8>>,
+    /// The decrypted content of a private message, set by `unbox`.
+    #[serde(skip)]
+    unboxed: Option<Value>,
 }
 
 impl Message {
+    /// Create and sign the message that follows `prev` in the feed of
+    /// `identity`, with the fields in the order expected by `ssb-validate`.
     pub fn sign(prev: Option<&Message>, identity: &OwnedIdentity, content: Value) -> Result<Self> {
-        let mut value: serde_json::Map<String, Value> = serde_json::Map::new();
-        if let Some(prev) = prev {
-            value.insert(
-                MSG_PREVIOUS.to_string(),
-                Value::String(prev.id().to_string()),
-            );
-            value.insert(
-                MSG_SEQUENCE.to_string(),
-                Value::Number(serde_json::Number::from(prev.sequence() + 1)),
-            );
-        } else {
-            value.insert(MSG_PREVIOUS.to_string(), Value::Null);
-            value.insert(
-                MSG_SEQUENCE.to_string(),
-                Value::Number(serde_json::Number::from(1)),
-            );
-        }
-
         let timestamp = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_millis() as u64;
+            .as_millis() as f64;
+        Self::sign_at(prev, identity, content, timestamp)
+    }
 
-        let timestamp = Value::Number(serde_json::Number::from(timestamp));
+    /// Like `sign`, with the given timestamp in milliseconds, e.g. for
+    /// messages queued while offline. Timestamps are kept increasing along
+    /// the feed.
+    pub fn sign_at(
+        prev: Option<&Message>,
+        identity: &OwnedIdentity,
+        content: Value,
+        timestamp: f64,
+    ) -> Result<Self> {
+        let (previous, timestamp) = Self::follow(prev, timestamp);
+        Self::sign_after(previous, identity, content, timestamp, None)
+    }
 
-        value.insert(MSG_AUTHOR.to_string(), Value::String(identity.id.clone()));
-        value.insert(MSG_TIMESTAMP.to_string(), timestamp);
-        value.insert(MSG_HASH.to_string(), Value::String("sha256".to_string()));
-        value.insert(MSG_CONTENT.to_string(), content);
+    /// Like `sign_at`, signing with `signer`, e.g. a hardware token or a
+    /// remote key service that does not expose the secret key.
+    pub async fn sign_with(
+        prev: Option<&Message>,
+        signer: &dyn Signer,
+        content: Value,
+        timestamp: f64,
+        hmac_key: Option<&HmacKey>,
+    ) -> Result<Self> {
+        let (previous, timestamp) = Self::follow(prev, timestamp);
+        let value = Self::unsigned_value(previous, signer.id(), content, timestamp)?;
+        Self::signed(sign_obj_with(signer, hmac_key, &value).await?)
+    }
 
-        let value = Value::Object(value);
-        let to_sign_text = stringify_json(&value)?;
-        let mut value = cast!(Some(value), Value::Object)?;
+    /// The key and sequence of `prev` and a timestamp after its own.
+    fn follow(prev: Option<&Message>, timestamp: f64) -> (Option<(String, u64)>, f64) {
+        match prev {
+            Some(prev) => (
+                Some((prev.id().to_string(), prev.sequence())),
+                timestamp.max(prev.timestamp() + 1.0),
+            ),
+            None => (None, timestamp),
+        }
+    }
 
-        let signature = ed25519::sign_detached(to_sign_text.as_bytes(), &identity.sk);
+    /// Signs the message following the message with the given key and
+    /// sequence, using `timestamp` as is.
+    pub(crate) fn sign_after(
+        previous: Option<(String, u64)>,
+        identity: &OwnedIdentity,
+        content: Value,
+        timestamp: f64,
+        hmac_key: Option<&HmacKey>,
+    ) -> Result<Self> {
+        let value = Self::unsigned_value(previous, identity.id.clone(), content, timestamp)?;
+        Self::signed(sign_obj_hmac(identity, hmac_key, &value)?)
+    }
+
+    fn unsigned_value(
+        previous: Option<(String, u64)>,
+        author: String,
+        content: Value,
+        timestamp: f64,
+    ) -> Result<Value> {
+        let (previous, sequence) = match previous {
+            Some((key, sequence)) => (Value::String(key), sequence + 1),
+            None => (Value::Null, 1),
+        };
+        let timestamp = if timestamp.fract() == 0.0 {
+            Value::Number(serde_json::Number::from(timestamp as u64))
+        } else {
+            serde_json::Number::from_f64(timestamp)
+                .map(Value::Number)
+                .ok_or(Error::InvalidJson)?
+        };
+
+        let mut value: serde_json::Map<String, Value> = serde_json::Map::new();
+        value.insert(MSG_PREVIOUS.to_string(), previous);
+        value.insert(MSG_AUTHOR.to_string(), Value::String(author));
         value.insert(
-            MSG_SIGNATURE.to_string(),
-            Value::String(format!("{}.sig.ed25519", base64::encode(&signature))),
+            MSG_SEQUENCE.to_string(),
+            Value::Number(serde_json::Number::from(sequence)),
         );
+        value.insert(MSG_TIMESTAMP.to_string(), timestamp);
+        value.insert(MSG_HASH.to_string(), Value::String("sha256".to_string()));
+        value.insert(MSG_CONTENT.to_string(), content);
+        Ok(Value::Object(value))
+    }
 
+    fn signed(value: Value) -> Result<Self> {
+        check_size(&value)?;
         Ok(Message {
-            value: Value::Object(value),
+            value,
+            received: None,
+            unboxed: None,
         })
     }
 
+    /// Parses and checks a message, keeping `s` for `as_received_bytes`.
     pub fn from_slice(s: &[u8]) -> Result<Self> {
-        Self::from_value(serde_json::from_slice(s)?)
+        let msg = Self::from_value(serde_json::from_slice(s)?)?;
+        Ok(Message {
+            received: Some(s.to_vec()),
+            ..msg
+        })
     }
 
-    pub fn from_value(v: Value) -> Result<Self> {
-        let mut v = cast!(Some(v), Value::Object)?;
-
-        // check if ok
-        cast_opt!(v.get(MSG_PREVIOUS), Value::String)?;
-        cast!(v.get(MSG_SEQUENCE), Value::Number)?;
-        cast!(v.get(MSG_SEQUENCE), Value::Number)?;
-        cast!(v.get(MSG_TIMESTAMP), Value::Number)?;
-        cast!(v.get(MSG_HASH), Value::String)?;
-        v.get(MSG_CONTENT).ok_or(Error::InvalidJson)?;
-
-        // verify signature
-        let signature = cast!(v.remove(MSG_SIGNATURE), Value::String)?;
-        let author = cast!(v.get(MSG_AUTHOR), Value::String)?;
-        let sig = signature.to_ed25519_signature()?;
-        let signer = author[1..].to_ed25519_pk()?;
-
-        let value = Value::Object(v);
-        let signed_text = stringify_json(&value)?;
-        if !ed25519::verify_detached(&sig, signed_text.as_ref(), &signer) {
-            return Err(Error::InvalidSignature);
+    /// The exact bytes the message was parsed from by `from_slice` or
+    /// `from_str`, whitespace and escapes included, to forward it as is;
+    /// otherwise its json serialization.
+    pub fn as_received_bytes(&self) -> Cow<'_, [u8]> {
+        match &self.received {
+            Some(received) => Cow::Borrowed(received),
+            None => Cow::Owned(self.to_string().into_bytes()),
         }
+    }
 
-        // put signature back
-        let mut v = cast!(Some(value), Value::Object)?;
-        v.insert(MSG_SIGNATURE.to_string(), Value::String(signature));
+    /// Checks and wraps a message value. The value is kept as received, in
+    /// particular the order of its keys (serde_json is built with
+    /// `preserve_order`), so it can be hashed and forwarded unchanged.
+    pub fn from_value(v: Value) -> Result<Self> {
+        Self::from_value_hmac(v, None)
+    }
+
+    /// Like `from_value`, for messages of a network signing with
+    /// `hmac_key`.
+    pub fn from_value_hmac(v: Value, hmac_key: Option<&HmacKey>) -> Result<Self> {
+        // verify signature, signed is the value without it
+        let author = Self::check_fields(&v)?;
+        verify_obj_hmac(author, hmac_key, &v)?;
 
         Ok(Message {
-            value: Value::Object(v),
+            value: v,
+            received: None,
+            unboxed: None,
         })
     }
 
+    /// Parses a message whose signature by `author` was already checked
+    /// over `s`, checking only its fields.
+    pub(crate) fn from_slice_preverified(s: &[u8], author: &str) -> Result<Self> {
+        let value = serde_json::from_slice(s)?;
+        // a duplicated author field must not swap the author that signed
+        if Self::check_fields(&value)? != author {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(Message {
+            value,
+            received: Some(s.to_vec()),
+            unboxed: None,
+        })
+    }
+
+    /// Checks the fields of a message value, returning its author.
+    fn check_fields(v: &Value) -> Result<&str> {
+        let fields = cast!(Some(v), Value::Object)?;
+
+        cast_opt!(fields.get(MSG_PREVIOUS), Value::String)?;
+        fields
+            .get(MSG_SEQUENCE)
+            .and_then(Value::as_u64)
+            .filter(|sequence| *sequence > 0)
+            .ok_or(Error::InvalidSequence)?;
+        fields
+            .get(MSG_TIMESTAMP)
+            .and_then(Value::as_f64)
+            .filter(|timestamp| timestamp.is_finite())
+            .ok_or(Error::InvalidTimestamp)?;
+        cast!(fields.get(MSG_HASH), Value::String)?;
+        fields.get(MSG_CONTENT).ok_or(Error::InvalidJson)?;
+        cast!(fields.get(MSG_SIGNATURE), Value::String)?;
+        Ok(cast!(fields.get(MSG_AUTHOR), Value::String)?)
+    }
+
     pub fn id(&self) -> MessageId {
         MessageId(ssb_sha256(&self.value).unwrap())
     }
 
+    /// The key of the message (`%...sha256`), the hash of its signing
+    /// encoding.
+    pub fn compute_id(&self) -> Result<String> {
+        Ok(MessageId(ssb_sha256(&self.value)?).to_string())
+    }
+
     pub fn previous(&self) -> Option<&String> {
         cast_opt!(self.value.get(MSG_PREVIOUS), Value::String).unwrap()
     }
@@ -175,13 +302,83 @@ impl Message {
     pub fn signature(&self) -> &String {
         cast!(self.value.get(MSG_SIGNATURE), Value::String).unwrap()
     }
+
+    /// The `type` of the content, `None` if it is encrypted and was not
+    /// unboxed.
+    pub fn content_type(&self) -> Option<&str> {
+        self.readable_content().get("type").and_then(Value::as_str)
+    }
+
+    /// Decrypts the content with the first unboxer of `registry` that
+    /// opens it; the typed accessors then read the decrypted content.
+    /// Returns whether the message was unboxed.
+    pub fn unbox(&mut self, registry: &UnboxerRegistry) -> bool {
+        if self.unboxed.is_none() {
+            self.unboxed = registry.unbox(self);
+        }
+        self.unboxed.is_some()
+    }
+
+    /// The decrypted content, if the message was unboxed.
+    pub fn unboxed(&self) -> Option<&Value> {
+        self.unboxed.as_ref()
+    }
+
+    /// Whether the message is private and was unboxed.
+    pub fn is_private(&self) -> bool {
+        self.unboxed.is_some()
+    }
+
+    /// The decrypted content of unboxed messages, the content otherwise.
+    fn readable_content(&self) -> &Value {
+        self.unboxed.as_ref().unwrap_or_else(|| self.content())
+    }
+
+    /// The content, telling apart `.box` and `.box2` encrypted content.
+    pub fn parse_content(&self) -> Content {
+        Content::from_value(self.content())
+    }
+
+    /// Whether the content is a `.box` or `.box2` private message.
+    pub fn is_encrypted(&self) -> bool {
+        self.parse_content().is_encrypted()
+    }
+
+    /// The content as one of the known message types.
+    pub fn typed_content(&self) -> Option<TypedMessage> {
+        serde_json::from_value(self.readable_content().clone()).ok()
+    }
+
+    pub fn as_post(&self) -> Option<Post> {
+        self.typed_content_of("post")
+    }
+
+    pub fn as_contact(&self) -> Option<Contact> {
+        self.typed_content_of("contact")
+    }
+
+    /// The content as a tombstone; `delete` messages are read as such.
+    pub fn as_tombstone(&self) -> Option<Tombstone> {
+        let mut tombstone: Tombstone = self
+            .typed_content_of("tombstone")
+            .or_else(|| self.typed_content_of("delete"))?;
+        tombstone.xtype = String::from("tombstone");
+        Some(tombstone)
+    }
+
+    fn typed_content_of<T: serde::de::DeserializeOwned>(&self, content_type: &str) -> Option<T> {
+        if self.content_type() != Some(content_type) {
+            return None;
+        }
+        serde_json::from_value(self.readable_content().clone()).ok()
+    }
 }
 
 impl FromStr for Message {
     type Err = Error;
 
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        Message::from_value(serde_json::from_str::<Value>(s)?)
+        Message::from_slice(s.as_bytes())
     }
 }
 
@@ -194,6 +391,7 @@ impl ToString for Message {
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_verify_known_msg_integrity() -> Result<()> {
@@ -201,6 +399,7 @@ mod test {
         let message = r#"{"previous":"%seUEAo7PTyA7vNwnOrmGIsUFfpyRzOvzGVv1QCb/Fz8=.sha256","author":"@BIbVppzlrNiRJogxDYz3glUS7G4s4D4NiXiPEAEzxdE=.ed25519","sequence":37,"timestamp":1439392020612,"hash":"sha256","content":{"type":"post","text":"@paul real time replies didn't work.","repliesTo":"%xWKunF6nXD7XMC+D4cjwDMZWmBnmRu69w9T25iLNa1Q=.sha256","mentions":["%7UKRfZb2u8al4tYWHqM55R9xpE/KKVh9U0M6BdugGt4=.sha256"],"recps":[{"link":"@hxGxqPrplLjRG2vtjQL87abX4QKqeLgCwQpS730nNwE=.ed25519","name":"paul"}]},"signature":"gGxSPdBJZxp6x5f3HzQGoQSeSdh/C5AtymIn+miWa+lcC6DdqpRSgaeH9KHeLf+/CKhU6REYIpWaLr4CKDMfCg==.sig.ed25519"}"#;
         let msg = Message::from_str(&message)?;
         assert_eq!(msg.id().to_string(), message_id);
+        assert_eq!(msg.compute_id()?, message_id);
         Ok(())
     }
 
@@ -214,4 +413,184 @@ mod test {
         Message::from_str(&msg2)?;
         Ok(())
     }
+
+    #[test]
+    fn test_preserve_key_order() -> Result<()> {
+        let content = serde_json::json!({"type": "post", "zeta": 1, "alpha": {"z": 1.50, "a": 2}});
+        let id = OwnedIdentity::create();
+        let text = Message::sign(None, &id, content)?.to_string();
+        assert!(text.contains(r#"{"type":"post","zeta":1,"alpha":{"z":1.5,"a":2}}"#));
+        assert_eq!(Message::from_str(&text)?.to_string(), text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_size() -> Result<()> {
+        let id = OwnedIdentity::create();
+        // 4 bytes but 2 code units in UTF-16
+        let text = "\u{1F600}".repeat(3900);
+        Message::sign(None, &id, serde_json::json!({"type": "post", "text": text}))?;
+
+        let text = "\u{1F600}".repeat(4100);
+        assert!(matches!(
+            Message::sign(None, &id, serde_json::json!({"type": "post", "text": text})),
+            Err(Error::MessageTooLarge(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_offline() -> Result<()> {
+        let content = serde_json::json!({"type": "post", "text": "offline"});
+        let id = OwnedIdentity::create();
+        let msg1 = Message::sign_at(None, &id, content.clone(), 1000.0)?;
+        let msg2 = Message::sign_at(Some(&msg1), &id, content, 500.0)?;
+        assert_eq!(msg2.timestamp(), 1001.0);
+
+        let keys: Vec<&str> = msg2
+            .value
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "previous",
+                "author",
+                "sequence",
+                "timestamp",
+                "hash",
+                "content",
+                "signature"
+            ]
+        );
+
+        let msg1 = crate::feed::validate(None, msg1.value)?;
+        let state = crate::feed::FeedState::from_message(&msg1);
+        crate::feed::validate(Some(&state), msg2.value)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_content() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let post = Message::sign(None, &id, serde_json::json!({"type": "post", "text": "hi"}))?;
+        assert_eq!(post.content_type(), Some("post"));
+        assert_eq!(post.as_post().unwrap().text, "hi");
+        assert!(post.as_contact().is_none());
+        assert!(matches!(
+            post.typed_content(),
+            Some(TypedMessage::Post { .. })
+        ));
+
+        let contact = Message::sign(
+            Some(&post),
+            &id,
+            serde_json::json!({"type": "contact", "contact": id.id, "following": true}),
+        )?;
+        assert_eq!(contact.as_contact().unwrap().following, Some(true));
+        assert!(!contact.is_encrypted());
+
+        let boxed = Message::sign(Some(&contact), &id, serde_json::json!("c2VjcmV0.box"))?;
+        assert!(boxed.is_encrypted());
+        assert_eq!(boxed.content_type(), None);
+        assert!(boxed.typed_content().is_none());
+        Ok(())
+    }
+
+    /// Writes `value` as json with random whitespace and unicode escapes,
+    /// which do not change what is signed.
+    fn render_randomly(rng: &mut StdRng, value: &Value, out: &mut String) {
+        let space = |rng: &mut StdRng, out: &mut String| {
+            for _ in 0..rng.gen_range(0, 3) {
+                out.push([' ', '\n', '\t'][rng.gen_range(0, 3)]);
+            }
+        };
+        space(rng, out);
+        match value {
+            Value::String(s) => {
+                out.push('"');
+                for c in s.chars() {
+                    match c {
+                        '"' | '\\' => out.extend(['\\', c]),
+                        c if (c as u32) < 0x20 || (rng.gen() && (c as u32) < 0x10000) => {
+                            out.push_str(&format!("\\u{:04x}", c as u32))
+                        }
+                        c => out.push(c),
+                    }
+                }
+                out.push('"');
+            }
+            Value::Array(items) => {
+                out.push('[');
+                for (n, item) in items.iter().enumerate() {
+                    if n > 0 {
+                        out.push(',');
+                    }
+                    render_randomly(rng, item, out);
+                }
+                out.push(']');
+            }
+            Value::Object(entries) => {
+                out.push('{');
+                for (n, (key, item)) in entries.iter().enumerate() {
+                    if n > 0 {
+                        out.push(',');
+                    }
+                    render_randomly(rng, &Value::String(key.clone()), out);
+                    out.push(':');
+                    render_randomly(rng, item, out);
+                }
+                out.push('}');
+            }
+            other => out.push_str(&other.to_string()),
+        }
+        space(rng, out);
+    }
+
+    #[test]
+    fn test_received_bytes_round_trip() -> Result<()> {
+        // SSB_TEST_SEED reruns a failing case
+        let seed = std::env::var("SSB_TEST_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random::<u64>);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let id = OwnedIdentity::from_seed(&rng.gen());
+        for n in 0..50 {
+            let text: String = (0..20)
+                .map(|_| ['a', 'é', '"', '\n', '/', '😀', '\\'][rng.gen_range(0, 7)])
+                .collect();
+            let content = serde_json::json!({"type": "post", "text": text, "n": n, "list": [1.5, null, true]});
+            let msg = Message::sign(None, &id, content)?;
+
+            let mut received = String::new();
+            render_randomly(&mut rng, &msg.value, &mut received);
+            let failed = |err| panic!("seed {}: {}", seed, err);
+            let parsed = Message::from_slice(received.as_bytes()).unwrap_or_else(failed);
+            assert_eq!(
+                parsed.as_received_bytes(),
+                received.as_bytes(),
+                "seed {}",
+                seed
+            );
+            assert_eq!(
+                parsed.id().to_string(),
+                msg.id().to_string(),
+                "seed {}",
+                seed
+            );
+            assert_eq!(
+                Message::from_str(&received)
+                    .unwrap_or_else(failed)
+                    .as_received_bytes(),
+                received.as_bytes(),
+                "seed {}",
+                seed
+            );
+        }
+        Ok(())
+    }
 }
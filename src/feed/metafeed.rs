@@ -0,0 +1,229 @@
+//! The tree of feeds described by metafeeds: a root metafeed announced from
+//! the main feed, with subfeeds that can be metafeeds themselves.
+
+use serde_json::Value;
+
+use super::{
+    bendybutt::{BendyButtContent, BendyButtMessage, BENDYBUTT_FEED_SUFFIX},
+    error::{Error, Result},
+    message::Message,
+};
+
+pub const TYPE_ADD_EXISTING: &str = "metafeed/add/existing";
+pub const TYPE_ADD_DERIVED: &str = "metafeed/add/derived";
+pub const TYPE_TOMBSTONE: &str = "metafeed/tombstone";
+pub const TYPE_ANNOUNCE: &str = "metafeed/announce";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subfeed {
+    pub id: String,
+    /// `feedpurpose`, e.g. `main`, `v1` or `index`.
+    pub purpose: String,
+    pub tombstoned: bool,
+    /// `nonce` of a `metafeed/add/derived` subfeed, to derive its key again
+    /// from the metafeed seed.
+    pub nonce: Option<String>,
+    /// The subfeeds of this feed, when it is a metafeed.
+    pub metafeed: Option<Metafeed>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metafeed {
+    /// `@...bbfeed-v1` id.
+    pub id: String,
+    pub subfeeds: Vec<Subfeed>,
+}
+
+/// A `metafeed/announce` message, linking a main feed to its root metafeed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub metafeed: String,
+    pub subfeed: String,
+}
+
+impl Announcement {
+    /// The announcement in `msg`, if it is one.
+    pub fn from_message(msg: &Message) -> Option<Self> {
+        let content = msg.content();
+        if content.get("type")?.as_str()? != TYPE_ANNOUNCE {
+            return None;
+        }
+        Some(Announcement {
+            metafeed: content.get("metafeed")?.as_str()?.to_string(),
+            subfeed: content.get("subfeed")?.as_str()?.to_string(),
+        })
+    }
+}
+
+fn str_field<'a>(content: &'a Value, field: &str) -> Result<&'a str> {
+    content
+        .get(field)
+        .and_then(Value::as_str)
+        .ok_or(Error::InvalidContent)
+}
+
+impl Metafeed {
+    pub fn new(id: String) -> Self {
+        Metafeed {
+            id,
+            subfeeds: Vec::new(),
+        }
+    }
+
+    /// Builds the tree from the messages of the metafeed `id` and of its
+    /// nested metafeeds, in the order they were published.
+    pub fn from_messages<'a, I>(id: String, msgs: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = &'a BendyButtMessage>,
+    {
+        let mut metafeed = Self::new(id);
+        for msg in msgs {
+            metafeed.apply(msg)?;
+        }
+        Ok(metafeed)
+    }
+
+    /// Updates the tree with a message of this metafeed or of one of its
+    /// nested metafeeds. Encrypted messages are ignored.
+    pub fn apply(&mut self, msg: &BendyButtMessage) -> Result<()> {
+        let node = self.find_mut(&msg.author).ok_or(Error::AuthorMismatch)?;
+        let content = match &msg.content {
+            BendyButtContent::Plain { content, .. } => content,
+            BendyButtContent::Encrypted(_) => return Ok(()),
+        };
+        let subfeed = str_field(content, "subfeed")?;
+        match str_field(content, "type")? {
+            TYPE_ADD_EXISTING | TYPE_ADD_DERIVED => {
+                let metafeed = if subfeed.ends_with(BENDYBUTT_FEED_SUFFIX) {
+                    Some(Metafeed::new(subfeed.to_string()))
+                } else {
+                    None
+                };
+                node.subfeeds.push(Subfeed {
+                    id: subfeed.to_string(),
+                    purpose: str_field(content, "feedpurpose")?.to_string(),
+                    tombstoned: false,
+                    nonce: content
+                        .get("nonce")
+                        .and_then(Value::as_str)
+                        .map(str::to_string),
+                    metafeed,
+                });
+            }
+            TYPE_TOMBSTONE => {
+                for entry in node.subfeeds.iter_mut().filter(|entry| entry.id == subfeed) {
+                    entry.tombstoned = true;
+                }
+            }
+            _ => return Err(Error::InvalidContentType),
+        }
+        Ok(())
+    }
+
+    fn find_mut(&mut self, id: &str) -> Option<&mut Metafeed> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.subfeeds
+            .iter_mut()
+            .filter_map(|entry| entry.metafeed.as_mut())
+            .find_map(|metafeed| metafeed.find_mut(id))
+    }
+
+    /// All the subfeeds in the tree, nested ones included.
+    pub fn all_subfeeds(&self) -> Vec<&Subfeed> {
+        let mut all = Vec::new();
+        for entry in &self.subfeeds {
+            all.push(entry);
+            if let Some(metafeed) = &entry.metafeed {
+                all.extend(metafeed.all_subfeeds());
+            }
+        }
+        all
+    }
+
+    /// The live subfeeds in the tree with the given purpose, what a partial
+    /// replication client would fetch.
+    pub fn find_by_purpose(&self, purpose: &str) -> Vec<&Subfeed> {
+        self.all_subfeeds()
+            .into_iter()
+            .filter(|entry| !entry.tombstoned && entry.purpose == purpose)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::bendybutt_feed_id, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_metafeed_tree() -> Result<()> {
+        let root = OwnedIdentity::create();
+        let v1 = OwnedIdentity::create();
+        let main = OwnedIdentity::create();
+        let index = OwnedIdentity::create();
+        let (root_id, v1_id) = (bendybutt_feed_id(&root), bendybutt_feed_id(&v1));
+
+        let add = |kind: &str, metafeed: &str, subfeed: &str, purpose: &str| json!({"type": kind, "feedpurpose": purpose, "subfeed": subfeed, "metafeed": metafeed});
+        let msg1 = BendyButtMessage::sign(
+            None,
+            &root,
+            &main,
+            add(TYPE_ADD_EXISTING, &root_id, &main.id, "main"),
+            1,
+        )?;
+        let msg2 = BendyButtMessage::sign(
+            Some(&msg1),
+            &root,
+            &v1,
+            {
+                let mut content = add(TYPE_ADD_DERIVED, &root_id, &v1_id, "v1");
+                content["nonce"] = json!("bm9uY2U=");
+                content
+            },
+            2,
+        )?;
+        let msg3 = BendyButtMessage::sign(
+            None,
+            &v1,
+            &index,
+            add(TYPE_ADD_DERIVED, &v1_id, &index.id, "index"),
+            3,
+        )?;
+        let msg4 = BendyButtMessage::sign(
+            Some(&msg3),
+            &v1,
+            &index,
+            json!({"type": TYPE_TOMBSTONE, "subfeed": index.id, "metafeed": v1_id}),
+            4,
+        )?;
+
+        let tree = Metafeed::from_messages(root_id.clone(), [&msg1, &msg2, &msg3])?;
+        assert_eq!(tree.subfeeds.len(), 2);
+        assert_eq!(tree.find_by_purpose("main")[0].id, main.id);
+        assert_eq!(tree.find_by_purpose("index")[0].id, index.id);
+        assert_eq!(tree.all_subfeeds().len(), 3);
+        assert_eq!(
+            tree.find_by_purpose("v1")[0].nonce.as_deref(),
+            Some("bm9uY2U=")
+        );
+
+        let mut tree = tree;
+        tree.apply(&msg4)?;
+        assert!(tree.find_by_purpose("index").is_empty());
+
+        let other = BendyButtMessage::sign(None, &main, &main, json!({}), 5)?;
+        assert!(matches!(tree.apply(&other), Err(Error::AuthorMismatch)));
+
+        let announce = Message::sign(
+            None,
+            &main,
+            json!({"type": TYPE_ANNOUNCE, "metafeed": root_id, "subfeed": main.id}),
+        )?;
+        let announcement = Announcement::from_message(&announce).unwrap();
+        assert_eq!(announcement.metafeed, root_id);
+        Ok(())
+    }
+}
@@ -1,11 +1,64 @@
 mod base;
-mod encoding;
+mod bencode;
+mod bendybutt;
+pub mod bfe;
+mod bipf;
+mod blobs;
+mod builder;
+mod buttwoo;
+mod content;
+pub mod encoding;
 mod error;
+mod export;
+mod fork;
+mod format;
+mod indexed;
 mod message;
-mod privatebox;
+pub mod metafeed;
+mod preverify;
+mod schema;
+mod signobj;
+mod thread;
+mod unbox;
+mod validate;
+mod verify;
 
-pub use base::Feed;
+pub use crate::crypto::privatebox::is_privatebox;
+pub use base::{Feed, KvtMessage};
+pub use bencode::Bencode;
+pub use bendybutt::{
+    bendybutt_feed_id, BendyButtContent, BendyButtMessage, BENDYBUTT_FEED_SUFFIX,
+    BENDYBUTT_MSG_SUFFIX,
+};
+pub use bipf::Bipf;
+pub use blobs::blob_refs;
+pub use builder::MessageBuilder;
+pub use buttwoo::{buttwoo_feed_id, ButtwooMessage, BUTTWOO_SUFFIX, TAG_END_OF_FEED, TAG_STANDARD};
+pub use content::{BoxAlgo, Content};
 pub use encoding::{ssb_sha256, stringify_json};
 pub use error::{Error, Result};
-pub use message::Message;
-pub use privatebox::{is_privatebox, privatebox_cipher, privatebox_decipher};
+pub use export::{export, import, ExportFormat};
+pub use fork::{detect_fork, Fork};
+pub use format::{
+    BendyButt, Buttwoo, Classic, FeedFormat, FeedFormatKind, BENDYBUTT_MAX_LEN,
+    BUTTWOO_MAX_CONTENT_LEN,
+};
+pub use indexed::{check_index_entry, validate_index_chain, IndexedMessage, TYPE_INDEX};
+pub use message::{check_size, Message, MAX_MESSAGE_LEN};
+pub use metafeed::{Announcement, Metafeed, Subfeed};
+pub use preverify::{preverify, preverify_hmac, RawMessage};
+pub use schema::{validate_schema, SchemaViolation};
+pub use signobj::{
+    hmac_key_from_base64, sign_obj, sign_obj_hmac, sign_obj_with, verify_obj, verify_obj_hmac,
+    HmacKey,
+};
+pub use thread::{branches, causal_sort, heads, root_of, Thread};
+pub use unbox::{
+    privatebox_cipher, privatebox_decipher, Box2Unboxer, PrivateBoxUnboxer, Unboxer,
+    UnboxerRegistry,
+};
+pub use validate::{
+    validate, validate_chain, validate_content_for_publish, validate_hmac, validate_kvt,
+    validate_ooo, FeedState,
+};
+pub use verify::{verify_all, verify_all_async};
@@ -0,0 +1,224 @@
+//! Signature checks of messages in their signing encoding straight from
+//! the received bytes, for bulk replication: the author, signature and
+//! signed bytes are found by scanning, and `RawMessage` is only decoded
+//! when it is used.
+//!
+//! Messages not received in the `JSON.stringify(v, null, 2)` encoding
+//! fall back to the full decoding and check of `Message::from_slice`.
+//! Bytes that only look like that encoding, e.g. with `\u00e9` escapes or
+//! `1.0` numbers, are rejected when decoded, as the full check would:
+//! their signature is not over the encoding of the message.
+
+use serde_json::Value;
+
+use super::{
+    encoding,
+    error::{Error, Result},
+    message::Message,
+    signobj::HmacKey,
+};
+use crate::crypto::backend::auth;
+use crate::crypto::{self, ToSodiumObject};
+
+/// Top level fields of the signing encoding start at a new line with an
+/// indent of two, which nested fields and strings never do.
+const AUTHOR_FIELD: &[u8] = b"\n  \"author\": \"";
+const SIGNATURE_FIELD: &[u8] = b",\n  \"signature\": \"";
+const END: &[u8] = b"\"\n}";
+
+/// A message in its signing encoding, not decoded yet.
+#[derive(Debug, Clone, Copy)]
+pub struct RawMessage<'a> {
+    bytes: &'a [u8],
+    author: &'a str,
+    signature: &'a str,
+    /// Length of the bytes before the signature field.
+    signed_len: usize,
+}
+
+fn find(bytes: &[u8], pattern: &[u8]) -> Option<usize> {
+    bytes
+        .windows(pattern.len())
+        .position(|window| window == pattern)
+}
+
+/// The json string starting at `bytes`, up to its closing quote, if it
+/// has no escapes.
+fn plain_string(bytes: &[u8]) -> Option<&str> {
+    let len = bytes.iter().position(|b| *b == b'"')?;
+    let s = std::str::from_utf8(&bytes[..len]).ok()?;
+    (!s.contains('\\')).then_some(s)
+}
+
+impl<'a> RawMessage<'a> {
+    /// Finds the author and signature in `bytes`, `None` if they are not
+    /// in the signing encoding.
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let trimmed = bytes.trim_ascii_end();
+        if !trimmed.starts_with(b"{") || !trimmed.ends_with(END) {
+            return None;
+        }
+        let author_at = find(trimmed, AUTHOR_FIELD)? + AUTHOR_FIELD.len();
+        let author = plain_string(&trimmed[author_at..])?;
+
+        // the signature is the last field, a string without quotes
+        let before_end = &trimmed[..trimmed.len() - END.len()];
+        let signature_at = before_end.iter().rposition(|b| *b == b'"')? + 1;
+        if !before_end[..signature_at].ends_with(SIGNATURE_FIELD) {
+            return None;
+        }
+        let signature = std::str::from_utf8(&before_end[signature_at..]).ok()?;
+        if signature.contains('\\') {
+            return None;
+        }
+        let signed_len = signature_at - SIGNATURE_FIELD.len();
+        Some(RawMessage {
+            bytes,
+            author,
+            signature,
+            signed_len,
+        })
+    }
+
+    pub fn author(&self) -> &'a str {
+        self.author
+    }
+
+    pub fn signature(&self) -> &'a str {
+        self.signature
+    }
+
+    /// The bytes signed by the author: the message without its signature.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut signed = self.bytes[..self.signed_len].to_vec();
+        signed.extend_from_slice(b"\n}");
+        signed
+    }
+
+    /// Checks the signature, without decoding the message.
+    pub fn verify(&self) -> Result<()> {
+        self.verify_hmac(None)
+    }
+
+    /// Like `verify`, for messages of a network signing with `hmac_key`.
+    pub fn verify_hmac(&self, hmac_key: Option<&HmacKey>) -> Result<()> {
+        let public = self.author.strip_prefix('@').unwrap_or(self.author);
+        let public = public.to_ed25519_pk()?;
+        let signature = self.signature.to_ed25519_signature()?;
+        let signed = self.signing_bytes();
+        let signed = match hmac_key {
+            Some(key) => auth::authenticate(&signed, key).as_ref().to_vec(),
+            None => signed,
+        };
+        if crypto::verify_detached(&signature, &signed, &public) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignature)
+        }
+    }
+
+    /// Decodes a message checked with `verify`, without checking its
+    /// signature again. Fails if the signed bytes are not the signing
+    /// encoding of the decoded message.
+    pub fn decode(&self) -> Result<Message> {
+        let msg = Message::from_slice_preverified(self.bytes, self.author)?;
+        let unsigned: serde_json::Map<String, Value> = msg
+            .value
+            .as_object()
+            .ok_or(Error::InvalidJson)?
+            .iter()
+            .filter(|(key, _)| key.as_str() != "signature")
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        if encoding::signing_bytes(&Value::Object(unsigned))? != self.signing_bytes() {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(msg)
+    }
+}
+
+/// Checks the signature of the message in `bytes`, the fast way when it is
+/// in its signing encoding, returning its author.
+pub fn preverify(bytes: &[u8]) -> Result<String> {
+    preverify_hmac(bytes, None)
+}
+
+/// Like `preverify`, for messages of a network signing with `hmac_key`.
+pub fn preverify_hmac(bytes: &[u8], hmac_key: Option<&HmacKey>) -> Result<String> {
+    if let Some(raw) = RawMessage::parse(bytes) {
+        if raw.verify_hmac(hmac_key).is_ok() {
+            raw.decode()?;
+            return Ok(raw.author().to_string());
+        }
+    }
+    let msg = Message::from_value_hmac(serde_json::from_slice(bytes)?, hmac_key)?;
+    Ok(msg.author().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::stringify_json, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_preverify() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg = Message::sign(
+            None,
+            &id,
+            json!({"type": "post", "text": "\n  \"author\": \"x"}),
+        )?;
+        let encoded = stringify_json(&msg.value)?;
+
+        let raw = RawMessage::parse(encoded.as_bytes()).unwrap();
+        assert_eq!(raw.author(), id.id);
+        raw.verify()?;
+        assert_eq!(raw.decode()?.value, msg.value);
+        assert_eq!(preverify(encoded.as_bytes())?, id.id);
+
+        // compact json is not in the signing encoding, but still checks
+        let compact = serde_json::to_vec(&msg.value)?;
+        assert!(RawMessage::parse(&compact).is_none());
+        assert_eq!(preverify(&compact)?, id.id);
+
+        let tampered = encoded.replace("post", "p0st");
+        assert!(RawMessage::parse(tampered.as_bytes())
+            .unwrap()
+            .verify()
+            .is_err());
+        assert!(matches!(
+            preverify(tampered.as_bytes()),
+            Err(Error::InvalidSignature)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed_non_canonical_bytes() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg = Message::sign(None, &id, json!({"type": "post", "text": "é", "n": 1}))?;
+        let mut unsigned = msg.value.clone();
+        unsigned.as_object_mut().unwrap().remove("signature");
+        let canonical = stringify_json(&unsigned)?;
+
+        for (from, to) in [("é", "\\u00e9"), ("\"n\": 1\n", "\"n\": 1.0\n")] {
+            // validly signed by the author, but not the message encoding
+            let signed = canonical.replace(from, to);
+            assert_ne!(signed, canonical);
+            let signature = crypto::sign_detached(signed.as_bytes(), &id.sk);
+            let bytes = format!(
+                "{},\n  \"signature\": \"{}.sig.ed25519\"\n}}",
+                &signed[..signed.len() - 2],
+                base64::encode(&signature)
+            );
+
+            let raw = RawMessage::parse(bytes.as_bytes()).unwrap();
+            raw.verify()?;
+            assert!(matches!(raw.decode(), Err(Error::InvalidSignature)));
+            assert!(preverify(bytes.as_bytes()).is_err());
+            assert!(Message::from_slice(bytes.as_bytes()).is_err());
+        }
+        Ok(())
+    }
+}
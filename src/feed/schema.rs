@@ -0,0 +1,247 @@
+//! Strict checks of the shape of the well-known content types, for pubs
+//! and clients that want to refuse malformed messages when publishing.
+
+use serde_json::Value;
+
+use super::{
+    error::{Error, Result},
+    validate::is_link,
+};
+
+/// One problem found in a content, e.g. `vote.link: not a message id`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// Path of the field, dot separated.
+    pub field: String,
+    pub problem: &'static str,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.problem)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    String,
+    Bool,
+    Number,
+    FeedId,
+    MsgId,
+    BlobId,
+    /// A feed or message id.
+    Link,
+    /// A feed, message or blob id.
+    AnyLink,
+}
+
+impl Kind {
+    fn matches(self, value: &Value) -> bool {
+        let link = |sigil: char| {
+            value
+                .as_str()
+                .is_some_and(|s| s.starts_with(sigil) && is_link(s))
+        };
+        match self {
+            Kind::String => value.is_string(),
+            Kind::Bool => value.is_boolean(),
+            Kind::Number => value.is_number(),
+            Kind::FeedId => link('@'),
+            Kind::MsgId => link('%'),
+            Kind::BlobId => link('&'),
+            Kind::Link => link('@') || link('%'),
+            Kind::AnyLink => value.as_str().is_some_and(is_link),
+        }
+    }
+
+    fn problem(self) -> &'static str {
+        match self {
+            Kind::String => "not a string",
+            Kind::Bool => "not a boolean",
+            Kind::Number => "not a number",
+            Kind::FeedId => "not a feed id",
+            Kind::MsgId => "not a message id",
+            Kind::BlobId => "not a blob id",
+            Kind::Link => "not a feed or message id",
+            Kind::AnyLink => "not a link",
+        }
+    }
+}
+
+struct Checker<'a> {
+    content: &'a Value,
+    violations: Vec<SchemaViolation>,
+}
+
+impl<'a> Checker<'a> {
+    fn report(&mut self, field: &str, problem: &'static str) {
+        self.violations.push(SchemaViolation {
+            field: field.to_string(),
+            problem,
+        });
+    }
+
+    fn check(&mut self, field: &str, value: Option<&Value>, kind: Kind, required: bool) {
+        match value {
+            None if required => self.report(field, "missing"),
+            None => {}
+            Some(value) if !kind.matches(value) => self.report(field, kind.problem()),
+            Some(_) => {}
+        }
+    }
+
+    fn required(&mut self, field: &str, kind: Kind) {
+        self.check(field, self.content.get(field), kind, true);
+    }
+
+    fn optional(&mut self, field: &str, kind: Kind) {
+        self.check(field, self.content.get(field), kind, false);
+    }
+
+    /// A single id or an array of them.
+    fn links(&mut self, field: &str, kind: Kind) {
+        match self.content.get(field) {
+            Some(Value::Array(items)) => {
+                for (n, item) in items.iter().enumerate() {
+                    self.check(&format!("{}.{}", field, n), Some(item), kind, true);
+                }
+            }
+            value => self.check(field, value, kind, false),
+        }
+    }
+
+    fn mentions(&mut self) {
+        if let Some(mentions) = self.content.get("mentions") {
+            match mentions {
+                Value::Array(items) => {
+                    for (n, item) in items.iter().enumerate() {
+                        let field = format!("mentions.{}.link", n);
+                        let link = item.get("link").or(Some(item).filter(|i| i.is_string()));
+                        self.check(&field, link, Kind::AnyLink, true);
+                    }
+                }
+                _ => self.report("mentions", "not an array"),
+            }
+        }
+    }
+}
+
+/// Checks `content` against the schema of its type. Types other than
+/// post, contact, about, vote and channel, and encrypted content, pass.
+pub fn validate_schema(content: &Value) -> Result<()> {
+    let content_type = match content.get("type").and_then(Value::as_str) {
+        Some(content_type) => content_type,
+        None => return Ok(()),
+    };
+    let mut checker = Checker {
+        content,
+        violations: Vec::new(),
+    };
+    match content_type {
+        "post" => {
+            checker.required("text", Kind::String);
+            checker.optional("root", Kind::MsgId);
+            checker.links("branch", Kind::MsgId);
+            checker.optional("channel", Kind::String);
+            checker.mentions();
+            checker.links("recps", Kind::FeedId);
+        }
+        "contact" => {
+            checker.required("contact", Kind::FeedId);
+            checker.optional("following", Kind::Bool);
+            checker.optional("blocking", Kind::Bool);
+            checker.optional("autofollow", Kind::Bool);
+        }
+        "about" => {
+            checker.required("about", Kind::Link);
+            checker.optional("name", Kind::String);
+            checker.optional("description", Kind::String);
+            match content.get("image") {
+                Some(image @ Value::String(_)) => {
+                    checker.check("image", Some(image), Kind::BlobId, true)
+                }
+                Some(image) => checker.check("image.link", image.get("link"), Kind::BlobId, true),
+                None => {}
+            }
+        }
+        "vote" => match content.get("vote") {
+            Some(vote @ Value::Object(_)) => {
+                checker.check("vote.link", vote.get("link"), Kind::MsgId, true);
+                checker.check("vote.value", vote.get("value"), Kind::Number, true);
+                checker.check(
+                    "vote.expression",
+                    vote.get("expression"),
+                    Kind::String,
+                    false,
+                );
+            }
+            Some(_) => checker.report("vote", "not an object"),
+            None => checker.report("vote", "missing"),
+        },
+        "channel" => {
+            checker.required("channel", Kind::String);
+            checker.required("subscribed", Kind::Bool);
+        }
+        _ => {}
+    }
+    if checker.violations.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidSchema(checker.violations))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    fn violations(content: Value) -> Vec<String> {
+        match validate_schema(&content) {
+            Ok(()) => Vec::new(),
+            Err(Error::InvalidSchema(violations)) => {
+                violations.iter().map(ToString::to_string).collect()
+            }
+            Err(err) => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let feed = format!("@{}=.ed25519", "A".repeat(43));
+        let msg = format!("%{}=.sha256", "E".repeat(43));
+        let blob = format!("&{}=.sha256", "I".repeat(43));
+
+        assert!(violations(json!({
+            "type": "post", "text": "hi", "root": msg, "branch": [msg],
+            "mentions": [{"link": feed}, {"link": blob}],
+        }))
+        .is_empty());
+        assert!(violations(json!({"type": "custom", "anything": 1})).is_empty());
+        assert_eq!(
+            violations(json!({"type": "post", "branch": [msg, "x"], "mentions": [{"name": "x"}]})),
+            vec![
+                "text: missing",
+                "branch.1: not a message id",
+                "mentions.0.link: missing"
+            ]
+        );
+        assert_eq!(
+            violations(json!({"type": "contact", "contact": msg, "following": "yes"})),
+            vec!["contact: not a feed id", "following: not a boolean"]
+        );
+        assert_eq!(
+            violations(json!({"type": "about", "about": feed, "image": {"link": msg}})),
+            vec!["image.link: not a blob id"]
+        );
+        assert_eq!(
+            violations(json!({"type": "vote", "vote": {"link": msg, "value": true}})),
+            vec!["vote.value: not a number"]
+        );
+        assert_eq!(
+            violations(json!({"type": "channel", "channel": "rust"})),
+            vec!["subscribed: missing"]
+        );
+    }
+}
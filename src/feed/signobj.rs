@@ -0,0 +1,153 @@
+//! Signatures of arbitrary json objects as done by ssb-keys `signObj` and
+//! `verifyObj`, e.g. for room aliases and http authentication.
+//!
+//! With an hmac key (`caps.sign` in ssb configs) the hmac of the encoding
+//! is signed instead of the encoding itself, so signatures are only valid
+//! within the network sharing the key.
+
+use serde_json::Value;
+
+use super::{
+    encoding::signing_bytes,
+    error::{Error, Result},
+};
+use crate::crypto::backend::auth;
+use crate::{
+    crypto::{self, Signer, ToSodiumObject, ED25519_SIGNATURE_SUFFIX},
+    keystore::OwnedIdentity,
+};
+
+const SIGNATURE: &str = "signature";
+
+/// Key of the hmac (HMAC-SHA-512-256) signed instead of the encoding.
+pub type HmacKey = auth::Key;
+
+/// Parses the base64 form of an hmac key, as in `caps.sign`.
+pub fn hmac_key_from_base64(key: &str) -> Result<HmacKey> {
+    auth::Key::from_slice(&base64::decode(key)?).ok_or(Error::InvalidHmacKey)
+}
+
+fn signed_bytes(obj: &Value, hmac_key: Option<&HmacKey>) -> Result<Vec<u8>> {
+    let bytes = signing_bytes(obj)?;
+    Ok(match hmac_key {
+        Some(key) => auth::authenticate(&bytes, key).as_ref().to_vec(),
+        None => bytes,
+    })
+}
+
+/// `obj` with the signature of its legacy encoding appended as
+/// `signature`.
+pub fn sign_obj(identity: &OwnedIdentity, obj: &Value) -> Result<Value> {
+    sign_obj_hmac(identity, None, obj)
+}
+
+/// Like `sign_obj`, signing the hmac of the encoding with `hmac_key`.
+pub fn sign_obj_hmac(
+    identity: &OwnedIdentity,
+    hmac_key: Option<&HmacKey>,
+    obj: &Value,
+) -> Result<Value> {
+    let to_sign = signed_bytes(obj, hmac_key)?;
+    with_signature(obj, &crypto::sign_detached(&to_sign, &identity.sk))
+}
+
+/// Like `sign_obj_hmac`, signing with `signer`.
+pub async fn sign_obj_with(
+    signer: &dyn Signer,
+    hmac_key: Option<&HmacKey>,
+    obj: &Value,
+) -> Result<Value> {
+    let to_sign = signed_bytes(obj, hmac_key)?;
+    with_signature(obj, &signer.sign(&to_sign).await?)
+}
+
+fn with_signature(obj: &Value, signature: &crypto::ed25519::Signature) -> Result<Value> {
+    let mut obj = obj.as_object().ok_or(Error::InvalidJson)?.clone();
+    obj.insert(
+        SIGNATURE.to_string(),
+        Value::String(format!(
+            "{}{}",
+            base64::encode(signature),
+            ED25519_SIGNATURE_SUFFIX
+        )),
+    );
+    Ok(Value::Object(obj))
+}
+
+/// Checks the `signature` of `obj`, made by `public` (`@...ed25519` or
+/// the bare `...ed25519` key), over the rest of the object.
+pub fn verify_obj(public: &str, obj: &Value) -> Result<()> {
+    verify_obj_hmac(public, None, obj)
+}
+
+/// Like `verify_obj`, for objects signed with `hmac_key`.
+pub fn verify_obj_hmac(public: &str, hmac_key: Option<&HmacKey>, obj: &Value) -> Result<()> {
+    let fields = obj.as_object().ok_or(Error::InvalidJson)?;
+    let signature = fields
+        .get(SIGNATURE)
+        .and_then(Value::as_str)
+        .ok_or(Error::InvalidJson)?
+        .to_ed25519_signature()?;
+    let public = public.strip_prefix('@').unwrap_or(public).to_ed25519_pk()?;
+
+    let unsigned = fields
+        .iter()
+        .filter(|(key, _)| key.as_str() != SIGNATURE)
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let signed = signed_bytes(&Value::Object(unsigned), hmac_key)?;
+    if crypto::verify_detached(&signature, &signed, &public) {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sign_obj() -> Result<()> {
+        let identity = OwnedIdentity::create();
+        let obj = json!({
+            "type": "confirm",
+            "alias": "alice",
+            "room": "@RoomRoomRoomRoomRoomRoomRoomRoomRoomRoomRoo=.ed25519",
+        });
+        let signed = sign_obj(&identity, &obj)?;
+        assert_eq!(
+            signed.as_object().unwrap().keys().last().unwrap(),
+            SIGNATURE
+        );
+        verify_obj(&identity.id, &signed)?;
+        verify_obj(&identity.id[1..], &signed)?;
+
+        let mut tampered = signed.clone();
+        tampered["alias"] = json!("mallory");
+        assert!(matches!(
+            verify_obj(&identity.id, &tampered),
+            Err(Error::InvalidSignature)
+        ));
+        let other = OwnedIdentity::create();
+        assert!(verify_obj(&other.id, &signed).is_err());
+        assert!(verify_obj(&identity.id, &obj).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_obj_hmac() -> Result<()> {
+        let identity = OwnedIdentity::create();
+        let hmac_key = hmac_key_from_base64(&base64::encode(&[3u8; 32]))?;
+        let obj = json!({"type": "post"});
+
+        let signed = sign_obj_hmac(&identity, Some(&hmac_key), &obj)?;
+        verify_obj_hmac(&identity.id, Some(&hmac_key), &signed)?;
+        assert!(verify_obj(&identity.id, &signed).is_err());
+        let other_key = auth::gen_key();
+        assert!(verify_obj_hmac(&identity.id, Some(&other_key), &signed).is_err());
+        assert!(hmac_key_from_base64("AAAA").is_err());
+        Ok(())
+    }
+}
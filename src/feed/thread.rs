@@ -0,0 +1,160 @@
+//! Reconstruction of threads from their messages: the root, the `branch`
+//! links to the messages replied to, a causal order and the current heads.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use super::message::Message;
+
+/// The root of the thread `msg` is part of, from `root` or, in newer
+/// messages, `tangles.<name>.root`.
+pub fn root_of<'a>(msg: &'a Message, tangle: &str) -> Option<&'a str> {
+    let content = msg.content();
+    content
+        .get("tangles")
+        .and_then(|tangles| tangles.get(tangle)?.get("root")?.as_str())
+        .or_else(|| content.get("root")?.as_str())
+}
+
+/// The messages `msg` replies to, from `branch` or `tangles.<name>.previous`.
+pub fn branches<'a>(msg: &'a Message, tangle: &str) -> Vec<&'a str> {
+    let content = msg.content();
+    let links = content
+        .get("tangles")
+        .and_then(|tangles| tangles.get(tangle)?.get("previous"))
+        .or_else(|| content.get("branch"));
+    match links {
+        Some(Value::String(link)) => vec![link.as_str()],
+        Some(Value::Array(links)) => links.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// A thread sorted so every message comes after the ones it replies to,
+/// ties broken by timestamp.
+#[derive(Debug)]
+pub struct Thread<'a> {
+    pub root: &'a Message,
+    /// The replies, in causal order.
+    pub replies: Vec<&'a Message>,
+    /// Keys of the messages nothing replies to yet, the `branch` of a new
+    /// reply.
+    pub heads: Vec<String>,
+}
+
+/// Sorts `msgs` causally by their `branch` links among themselves.
+pub fn causal_sort<'a>(msgs: &[&'a Message], tangle: &str) -> Vec<&'a Message> {
+    let keys: HashMap<String, usize> = msgs
+        .iter()
+        .enumerate()
+        .map(|(n, msg)| (msg.id().to_string(), n))
+        .collect();
+    let mut pending = vec![0; msgs.len()];
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); msgs.len()];
+    for (n, msg) in msgs.iter().enumerate() {
+        let parents: HashSet<_> = branches(msg, tangle)
+            .into_iter()
+            .filter_map(|link| keys.get(link))
+            .collect();
+        pending[n] = parents.len();
+        for parent in parents {
+            children[*parent].push(n);
+        }
+    }
+
+    let mut ready: Vec<usize> = (0..msgs.len()).filter(|n| pending[*n] == 0).collect();
+    let mut sorted = Vec::with_capacity(msgs.len());
+    while !ready.is_empty() {
+        let (pos, _) = ready
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| msgs[**a].timestamp().total_cmp(&msgs[**b].timestamp()))
+            .unwrap();
+        let n = ready.swap_remove(pos);
+        sorted.push(msgs[n]);
+        for child in &children[n] {
+            pending[*child] -= 1;
+            if pending[*child] == 0 {
+                ready.push(*child);
+            }
+        }
+    }
+    sorted
+}
+
+/// Keys of the messages of `msgs` no other one links to.
+pub fn heads(msgs: &[&Message], tangle: &str) -> Vec<String> {
+    let linked: HashSet<&str> = msgs.iter().flat_map(|msg| branches(msg, tangle)).collect();
+    msgs.iter()
+        .map(|msg| msg.id().to_string())
+        .filter(|key| !linked.contains(key.as_str()))
+        .collect()
+}
+
+impl<'a> Thread<'a> {
+    /// The thread of `root` among `msgs`; messages of other threads are
+    /// ignored. Use `"post"` as `tangle` for the usual reply threads.
+    pub fn new(root: &'a Message, msgs: &[&'a Message], tangle: &str) -> Self {
+        let root_key = root.id().to_string();
+        let replies: Vec<_> = msgs
+            .iter()
+            .copied()
+            .filter(|msg| root_of(msg, tangle) == Some(root_key.as_str()))
+            .collect();
+        let mut all = vec![root];
+        all.extend(&replies);
+        Thread {
+            root,
+            replies: causal_sort(&replies, tangle),
+            heads: heads(&all, tangle),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Result, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_thread() -> Result<()> {
+        let (alice, bob) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let root = Message::sign_at(None, &alice, json!({"type": "post", "text": "q"}), 10.0)?;
+        let key = root.id().to_string();
+        let reply =
+            |branch: Value, ts| json!({"type": "post", "root": key, "branch": branch, "ts": ts});
+        let r1 = Message::sign_at(None, &bob, reply(json!(key), 1), 30.0)?;
+        // clock behind, but replies to r1
+        let r2 = Message::sign_at(
+            Some(&root),
+            &alice,
+            reply(json!([r1.id().to_string()]), 2),
+            20.0,
+        )?;
+        let r3 = Message::sign_at(Some(&r1), &bob, reply(json!(key), 3), 40.0)?;
+        let other = Message::sign_at(Some(&r3), &bob, json!({"type": "post"}), 50.0)?;
+
+        let thread = Thread::new(&root, &[&r3, &other, &r2, &r1], "post");
+        let order: Vec<_> = thread
+            .replies
+            .iter()
+            .map(|msg| msg.id().to_string())
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                r1.id().to_string(),
+                r2.id().to_string(),
+                r3.id().to_string()
+            ]
+        );
+        let mut expected = vec![r2.id().to_string(), r3.id().to_string()];
+        let mut heads = thread.heads.clone();
+        expected.sort();
+        heads.sort();
+        assert_eq!(heads, expected);
+        Ok(())
+    }
+}
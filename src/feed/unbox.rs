@@ -0,0 +1,163 @@
+//! Decryption of `.box` and `.box2` content with the keys at hand, so
+//! private messages read like any other once unboxed.
+
+use serde_json::Value;
+
+use super::{
+    content::{BoxAlgo, Content},
+    error::Result,
+    message::Message,
+};
+use crate::crypto::backend::sign::ed25519::SecretKey;
+use crate::{
+    crypto::{box2, group::GroupKeyStore, privatebox},
+    keystore::OwnedIdentity,
+};
+
+/// `crypto::privatebox::privatebox_cipher`, with the feed errors.
+pub fn privatebox_cipher(plaintext: &str, recipients: &[&str]) -> Result<String> {
+    Ok(privatebox::privatebox_cipher(plaintext, recipients)?)
+}
+
+/// `crypto::privatebox::privatebox_decipher`, with the feed errors.
+pub fn privatebox_decipher(ciphertext: &str, sk: &SecretKey) -> Result<Option<String>> {
+    Ok(privatebox::privatebox_decipher(ciphertext, sk)?)
+}
+
+/// Something that can open encrypted content of one algorithm.
+pub trait Unboxer: Send + Sync {
+    fn algo(&self) -> BoxAlgo;
+
+    /// The content inside `ciphertext`, the content of `msg`; `None` if
+    /// none of the keys opens it.
+    fn unbox(&self, ciphertext: &str, msg: &Message) -> Option<Value>;
+}
+
+/// Opens private-box messages sent to `identity`.
+pub struct PrivateBoxUnboxer {
+    identity: OwnedIdentity,
+}
+
+impl PrivateBoxUnboxer {
+    pub fn new(identity: OwnedIdentity) -> Self {
+        PrivateBoxUnboxer { identity }
+    }
+}
+
+impl Unboxer for PrivateBoxUnboxer {
+    fn algo(&self) -> BoxAlgo {
+        BoxAlgo::Box
+    }
+
+    fn unbox(&self, ciphertext: &str, _msg: &Message) -> Option<Value> {
+        let plaintext = privatebox::privatebox_decipher(ciphertext, &self.identity.sk).ok()??;
+        serde_json::from_str(&plaintext).ok()
+    }
+}
+
+/// Opens box2 messages of the groups in `keys` and direct messages.
+pub struct Box2Unboxer {
+    keys: GroupKeyStore,
+}
+
+impl Box2Unboxer {
+    pub fn new(keys: GroupKeyStore) -> Self {
+        Box2Unboxer { keys }
+    }
+
+    pub fn keys(&self) -> &GroupKeyStore {
+        &self.keys
+    }
+
+    pub fn keys_mut(&mut self) -> &mut GroupKeyStore {
+        &mut self.keys
+    }
+}
+
+impl Unboxer for Box2Unboxer {
+    fn algo(&self) -> BoxAlgo {
+        BoxAlgo::Box2
+    }
+
+    fn unbox(&self, ciphertext: &str, msg: &Message) -> Option<Value> {
+        let keys = self.keys.keys_for(msg.author());
+        let plaintext = box2::box2_decipher(
+            ciphertext,
+            msg.author(),
+            msg.previous().map(String::as_str),
+            &keys,
+        )
+        .ok()??;
+        serde_json::from_str(&plaintext).ok()
+    }
+}
+
+/// The unboxers consulted when parsing messages, tried in the order they
+/// were registered.
+#[derive(Default)]
+pub struct UnboxerRegistry {
+    unboxers: Vec<Box<dyn Unboxer>>,
+}
+
+impl UnboxerRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<U: Unboxer + 'static>(&mut self, unboxer: U) {
+        self.unboxers.push(Box::new(unboxer));
+    }
+
+    /// The decrypted content of `msg`, `None` if it is not encrypted or no
+    /// unboxer opens it.
+    pub fn unbox(&self, msg: &Message) -> Option<Value> {
+        let (algo, ciphertext) = match msg.parse_content() {
+            Content::Encrypted { algo, ciphertext } => (algo, ciphertext),
+            Content::Plain(_) => return None,
+        };
+        self.unboxers
+            .iter()
+            .filter(|unboxer| unboxer.algo() == algo)
+            .find_map(|unboxer| unboxer.unbox(&ciphertext, msg))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{api::dto::content::TypedMessage, feed::KvtMessage};
+    use serde_json::json;
+
+    #[test]
+    fn test_unbox_registry() -> crate::feed::Result<()> {
+        let (alice, bob) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let content = json!({"type": "post", "text": "psst"});
+        let boxed = privatebox_cipher(&content.to_string(), &[bob.id.as_str()])?;
+        assert!(matches!(
+            privatebox_decipher("c2hvcnQ=.box", &bob.sk),
+            Err(crate::feed::Error::CannotReadNonce)
+        ));
+        let msg = Message::sign(None, &alice, json!(boxed))?;
+        let kvt = serde_json::to_vec(&json!({
+            "key": msg.id().to_string(),
+            "value": msg.value,
+            "timestamp": 1.0,
+        }))?;
+
+        let mut registry = UnboxerRegistry::new();
+        registry.register(PrivateBoxUnboxer::new(bob));
+        let kvt = KvtMessage::from_slice_unboxed(&kvt, &registry)?;
+        assert!(kvt.value.is_private());
+        assert!(matches!(
+            kvt.value.typed_content(),
+            Some(TypedMessage::Post { .. })
+        ));
+        // the signed value is left as is
+        assert_eq!(kvt.value.value, msg.value);
+
+        let mut other = UnboxerRegistry::new();
+        other.register(PrivateBoxUnboxer::new(alice));
+        assert!(other.unbox(&msg).is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,512 @@
+//! Validation of classic messages, following the rules of js `ssb-validate`.
+
+use serde_json::Value;
+
+use super::{
+    base::Feed,
+    error::{Error, Result},
+    message::{check_size, Message},
+    signobj::HmacKey,
+};
+use crate::crypto::{box2::MAX_SLOTS, group::GROUP_SUFFIX, privatebox::MAX_RECIPIENTS};
+
+/// Fields of a classic message, in the order they must appear. Legacy
+/// messages may also have `sequence` before `author`.
+const FIELD_ORDER: [&str; 7] = [
+    "previous",
+    "author",
+    "sequence",
+    "timestamp",
+    "hash",
+    "content",
+    "signature",
+];
+const LEGACY_FIELD_ORDER: [&str; 7] = [
+    "previous",
+    "sequence",
+    "author",
+    "timestamp",
+    "hash",
+    "content",
+    "signature",
+];
+
+const MIN_TYPE_LEN: usize = 3;
+const MAX_TYPE_LEN: usize = 52;
+
+/// The last valid message of a feed, what the next one is checked against.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedState {
+    pub author: String,
+    pub sequence: u64,
+    /// Key (`%...sha256`) of the last message.
+    pub key: String,
+}
+
+impl FeedState {
+    pub fn from_message(msg: &Message) -> Self {
+        FeedState {
+            author: msg.author().clone(),
+            sequence: msg.sequence(),
+            key: msg.id().to_string(),
+        }
+    }
+}
+
+/// Validate `value` as the message that follows `state` in its feed, or as
+/// the first message of the feed if `state` is `None`.
+pub fn validate(state: Option<&FeedState>, value: Value) -> Result<Message> {
+    validate_hmac(state, value, None)
+}
+
+/// Like `validate`, for a network signing messages with `hmac_key`.
+pub fn validate_hmac(
+    state: Option<&FeedState>,
+    value: Value,
+    hmac_key: Option<&HmacKey>,
+) -> Result<Message> {
+    check_shape(&value)?;
+    let msg = Message::from_value_hmac(value, hmac_key)?;
+
+    match state {
+        Some(state) => {
+            if msg.author() != &state.author {
+                return Err(Error::AuthorMismatch);
+            }
+            if msg.sequence() != state.sequence + 1 {
+                return Err(Error::SequenceMismatch {
+                    expected: state.sequence + 1,
+                    actual: msg.sequence(),
+                });
+            }
+            if msg.previous() != Some(&state.key) {
+                return Err(Error::PreviousMismatch);
+            }
+        }
+        None => {
+            if msg.sequence() != 1 {
+                return Err(Error::SequenceMismatch {
+                    expected: 1,
+                    actual: msg.sequence(),
+                });
+            }
+            if msg.previous().is_some() {
+                return Err(Error::PreviousMismatch);
+            }
+        }
+    }
+
+    Ok(msg)
+}
+
+/// Validate a message without its feed context, e.g. one fetched with
+/// `ooo.get` or `getSubset`: the shape and signature are checked, but not
+/// `previous` and `sequence` against earlier messages.
+pub fn validate_ooo(value: Value) -> Result<Message> {
+    check_shape(&value)?;
+    Message::from_value(value)
+}
+
+/// Like `validate`, for a message received with its key (e.g. from
+/// createHistoryStream with `keys`), also checking the key is its id.
+pub fn validate_kvt(state: Option<&FeedState>, feed: Feed) -> Result<Message> {
+    let msg = validate(state, feed.value)?;
+    if msg.compute_id()? != feed.key {
+        return Err(Error::FeedDigestMismatch);
+    }
+    Ok(msg)
+}
+
+/// Validate a batch of consecutive messages of a feed, e.g. the result of a
+/// createHistoryStream, starting after `state`. On failure, the error is
+/// `Error::InvalidChainEntry` with the index of the first invalid message.
+pub fn validate_chain<I>(state: Option<&FeedState>, values: I) -> Result<Vec<Message>>
+where
+    I: IntoIterator<Item = Value>,
+{
+    let mut state = state.cloned();
+    let mut messages = Vec::new();
+    for (index, value) in values.into_iter().enumerate() {
+        let msg = validate(state.as_ref(), value).map_err(|reason| Error::InvalidChainEntry {
+            index,
+            reason: Box::new(reason),
+        })?;
+        state = Some(FeedState::from_message(&msg));
+        messages.push(msg);
+    }
+    Ok(messages)
+}
+
+/// Checks `content` before publishing it: its type, the shape of its
+/// `mentions` and `recps`, and that the message holding it would not go
+/// over `MAX_MESSAGE_LEN`.
+///
+/// Recipients that include a `%...cloaked` group can only be encrypted
+/// with box2, and may be up to `MAX_SLOTS`; others are held to the
+/// `MAX_RECIPIENTS` of box1.
+pub fn validate_content_for_publish(content: &Value) -> Result<()> {
+    check_content(content)?;
+
+    if let Some(mentions) = content.get("mentions") {
+        let mentions: Vec<&Value> = match mentions {
+            Value::Array(mentions) => mentions.iter().collect(),
+            Value::Object(mentions) => mentions.values().collect(),
+            _ => return Err(Error::InvalidMention(0)),
+        };
+        for (index, mention) in mentions.into_iter().enumerate() {
+            let link = mention.as_str().or_else(|| mention.get("link")?.as_str());
+            if !link.is_some_and(is_mention) {
+                return Err(Error::InvalidMention(index));
+            }
+        }
+    }
+
+    if let Some(recps) = content.get("recps") {
+        let recps = match recps {
+            Value::Array(recps) => recps,
+            _ => return Err(Error::InvalidRecipient(0)),
+        };
+        let mut boxed_with_box2 = false;
+        for (index, recp) in recps.iter().enumerate() {
+            let id = recp.as_str().or_else(|| recp.get("link")?.as_str());
+            match id {
+                Some(id) if id.starts_with('@') && is_link(id) => {}
+                Some(id) if is_group_id(id) => boxed_with_box2 = true,
+                _ => return Err(Error::InvalidRecipient(index)),
+            }
+        }
+        let max_recps = if boxed_with_box2 {
+            MAX_SLOTS
+        } else {
+            MAX_RECIPIENTS as usize
+        };
+        if recps.is_empty() || recps.len() > max_recps {
+            return Err(Error::BadRecipientCount);
+        }
+    }
+
+    // the largest envelope the content can end up in
+    let key = "A".repeat(43) + "=";
+    let envelope = serde_json::json!({
+        "previous": format!("%{}.sha256", key),
+        "author": format!("@{}.ed25519", key),
+        "sequence": 9007199254740991u64,
+        "timestamp": 9007199254740991u64,
+        "hash": "sha256",
+        "content": content,
+        "signature": format!("{}{}.sig.ed25519", key, key),
+    });
+    check_size(&envelope)
+}
+
+/// Whether `s` is a feed, message or blob id.
+pub(super) fn is_link(s: &str) -> bool {
+    let key = match s.strip_prefix('@') {
+        Some(key) => key.strip_suffix(".ed25519"),
+        None => s
+            .strip_prefix(['%', '&'])
+            .and_then(|key| key.strip_suffix(".sha256")),
+    };
+    key.and_then(|key| base64::decode(key).ok())
+        .is_some_and(|key| key.len() == 32)
+}
+
+/// Whether `s` can be mentioned: a `#channel`, or a link, possibly with a
+/// query such as the `?unbox=` key of an encrypted blob.
+fn is_mention(s: &str) -> bool {
+    match s.strip_prefix('#') {
+        Some(channel) => !channel.is_empty() && !channel.contains(char::is_whitespace),
+        None => is_link(s.split_once('?').map_or(s, |(link, _)| link)),
+    }
+}
+
+/// Whether `s` is the `%...cloaked` id of a private group.
+fn is_group_id(s: &str) -> bool {
+    s.strip_prefix('%')
+        .and_then(|key| key.strip_suffix(GROUP_SUFFIX))
+        .and_then(|key| base64::decode(key).ok())
+        .is_some_and(|key| key.len() == 32)
+}
+
+/// Checks that do not need the signature or the previous message: fields,
+/// their order, the hash algorithm, the content and the size.
+fn check_shape(value: &Value) -> Result<()> {
+    let fields = match value {
+        Value::Object(fields) => fields,
+        _ => return Err(Error::InvalidJson),
+    };
+    let keys = fields.keys().map(|key| key.as_str());
+    if !keys.clone().eq(FIELD_ORDER.iter().copied()) && !keys.eq(LEGACY_FIELD_ORDER.iter().copied())
+    {
+        return Err(Error::InvalidFieldOrder);
+    }
+
+    match &fields["hash"] {
+        Value::String(hash) if hash == "sha256" => {}
+        other => return Err(Error::InvalidHashAlgorithm(other.to_string())),
+    }
+
+    match &fields["signature"] {
+        Value::String(signature) if signature.ends_with(".sig.ed25519") => {}
+        _ => return Err(Error::InvalidSignature),
+    }
+
+    check_content(&fields["content"])?;
+    check_size(value)
+}
+
+fn check_content(content: &Value) -> Result<()> {
+    match content {
+        Value::String(boxed) if boxed.ends_with(".box") || boxed.ends_with(".box2") => Ok(()),
+        Value::Object(fields) => match fields.get("type") {
+            Some(Value::String(msg_type))
+                if (MIN_TYPE_LEN..=MAX_TYPE_LEN).contains(&msg_type.chars().count()) =>
+            {
+                Ok(())
+            }
+            _ => Err(Error::InvalidContentType),
+        },
+        _ => Err(Error::InvalidContent),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::sign_obj, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_chain() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let content = json!({"type": "post", "text": "hello"});
+        let msg1 = Message::sign(None, &id, content.clone())?;
+        let msg2 = Message::sign(Some(&msg1), &id, content.clone())?;
+
+        let msg1 = validate(None, msg1.value)?;
+        let state = FeedState::from_message(&msg1);
+        validate(Some(&state), msg2.value.clone())?;
+
+        assert!(matches!(
+            validate(None, msg2.value.clone()),
+            Err(Error::SequenceMismatch {
+                expected: 1,
+                actual: 2
+            })
+        ));
+        let other = FeedState {
+            key: "%other".to_string(),
+            ..state.clone()
+        };
+        assert!(matches!(
+            validate(Some(&other), msg2.value.clone()),
+            Err(Error::PreviousMismatch)
+        ));
+        let other = FeedState {
+            author: OwnedIdentity::create().id,
+            ..state
+        };
+        assert!(matches!(
+            validate(Some(&other), msg2.value),
+            Err(Error::AuthorMismatch)
+        ));
+
+        // signed, but with a sequence out of range
+        for bad in [json!(1.5), json!(-1), json!(0)] {
+            let mut value = msg1.value.clone();
+            value.as_object_mut().unwrap().remove("signature");
+            value["sequence"] = bad;
+            let value = sign_obj(&id, &value)?;
+            assert!(matches!(validate(None, value), Err(Error::InvalidSequence)));
+        }
+        let mut value = msg1.value.clone();
+        value["timestamp"] = serde_json::from_str("1e400")?;
+        assert!(matches!(
+            Message::from_value(value),
+            Err(Error::InvalidTimestamp)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_chain_batch() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let content = json!({"type": "post"});
+        let msg1 = Message::sign(None, &id, content.clone())?;
+        let msg2 = Message::sign(Some(&msg1), &id, content.clone())?;
+        let msg3 = Message::sign(Some(&msg2), &id, content.clone())?;
+
+        let values = vec![msg1.value.clone(), msg2.value.clone(), msg3.value.clone()];
+        assert_eq!(validate_chain(None, values)?.len(), 3);
+
+        let state = FeedState::from_message(&msg1);
+        let values = vec![msg2.value.clone(), msg2.value.clone()];
+        match validate_chain(Some(&state), values) {
+            Err(Error::InvalidChainEntry { index, reason }) => {
+                assert_eq!(index, 1);
+                assert!(matches!(*reason, Error::SequenceMismatch { .. }));
+            }
+            _ => panic!("chain should be invalid"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_kvt() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg = Message::sign(None, &id, json!({"type": "post"}))?;
+        let feed = Feed::new(msg.clone());
+        validate_kvt(None, feed)?;
+
+        let mut feed = Feed::new(msg);
+        feed.key = "%Cg0ZpZ8cV85G8UIIropgBOvM8+Srlv9LSGDNGnpdK44=.sha256".to_string();
+        assert!(matches!(
+            validate_kvt(None, feed),
+            Err(Error::FeedDigestMismatch)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_ooo() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg1 = Message::sign(None, &id, json!({"type": "post"}))?;
+        let msg2 = Message::sign(Some(&msg1), &id, json!({"type": "post"}))?;
+        validate_ooo(msg2.value.clone())?;
+
+        let mut value = msg2.value;
+        value["sequence"] = json!(5);
+        assert!(matches!(validate_ooo(value), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_shape() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg = Message::sign(None, &id, json!({"type": "po"}))?;
+        assert!(matches!(
+            validate(None, msg.value),
+            Err(Error::InvalidContentType)
+        ));
+
+        let msg = Message::sign(None, &id, json!({"type": "post"}))?;
+        let mut value = msg.value.clone();
+        value["hash"] = json!("sha512");
+        assert!(matches!(
+            validate(None, value),
+            Err(Error::InvalidHashAlgorithm(_))
+        ));
+
+        let mut fields = msg.value.as_object().unwrap().clone();
+        let author = fields.remove("author").unwrap();
+        fields.insert("author".to_string(), author);
+        assert!(matches!(
+            validate(None, Value::Object(fields)),
+            Err(Error::InvalidFieldOrder)
+        ));
+
+        for boxed in ["c2VjcmV0.box", "c2VjcmV0.box2"] {
+            validate(None, Message::sign(None, &id, json!(boxed))?.value)?;
+        }
+        let msg = Message::sign(None, &id, json!("c2VjcmV0.box3"))?;
+        assert!(matches!(
+            validate(None, msg.value),
+            Err(Error::InvalidContent)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_content_for_publish() -> Result<()> {
+        let id = OwnedIdentity::create();
+        validate_content_for_publish(&json!({
+            "type": "post",
+            "text": "hi",
+            "mentions": [{"link": id.id, "name": "me"}],
+            "recps": [id.id],
+        }))?;
+        validate_content_for_publish(&json!("c2VjcmV0.box"))?;
+
+        assert!(matches!(
+            validate_content_for_publish(&json!({"text": "hi"})),
+            Err(Error::InvalidContentType)
+        ));
+        assert!(matches!(
+            validate_content_for_publish(&json!({"type": "post", "mentions": [id.id, "x"]})),
+            Err(Error::InvalidMention(1))
+        ));
+        assert!(matches!(
+            validate_content_for_publish(&json!({"type": "post", "recps": []})),
+            Err(Error::BadRecipientCount)
+        ));
+        assert!(matches!(
+            validate_content_for_publish(&json!({"type": "post", "mentions": ["#"]})),
+            Err(Error::InvalidMention(0))
+        ));
+        assert!(matches!(
+            validate_content_for_publish(&json!({"type": "post", "recps": ["%x"]})),
+            Err(Error::InvalidRecipient(0))
+        ));
+        assert!(matches!(
+            validate_content_for_publish(&json!({"type": "post", "text": "a".repeat(8000)})),
+            Err(Error::MessageTooLarge(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_publish_mentions() -> Result<()> {
+        let blob = format!("&{}.sha256", base64::encode(&[1u8; 32]));
+        validate_content_for_publish(&json!({
+            "type": "post",
+            "mentions": [
+                {"link": "#ssb"},
+                {"link": format!("{}?unbox={}.boxs", blob, base64::encode(&[2u8; 32]))},
+            ],
+        }))?;
+        assert!(matches!(
+            validate_content_for_publish(&json!({"type": "post", "mentions": ["#two words"]})),
+            Err(Error::InvalidMention(0))
+        ));
+        assert!(matches!(
+            validate_content_for_publish(
+                &json!({"type": "post", "mentions": ["&x.sha256?unbox="]})
+            ),
+            Err(Error::InvalidMention(0))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_publish_recipient_limits() -> Result<()> {
+        let feeds: Vec<String> = (0..MAX_SLOTS + 1)
+            .map(|_| OwnedIdentity::create().id)
+            .collect();
+        let group = format!("%{}{}", base64::encode(&[3u8; 32]), GROUP_SUFFIX);
+        let content = |recps: &[String]| json!({"type": "post", "recps": recps});
+
+        // box1, to feeds only
+        validate_content_for_publish(&content(&feeds[..MAX_RECIPIENTS as usize]))?;
+        assert!(matches!(
+            validate_content_for_publish(&content(&feeds[..MAX_RECIPIENTS as usize + 1])),
+            Err(Error::BadRecipientCount)
+        ));
+
+        // box2, to a group and feeds
+        let mut recps = vec![group.clone()];
+        recps.extend_from_slice(&feeds[..MAX_SLOTS - 1]);
+        validate_content_for_publish(&content(&recps))?;
+        recps.push(feeds[MAX_SLOTS].clone());
+        assert!(matches!(
+            validate_content_for_publish(&content(&recps)),
+            Err(Error::BadRecipientCount)
+        ));
+
+        let bad_group = format!("%{}{}", base64::encode(&[3u8; 16]), GROUP_SUFFIX);
+        assert!(matches!(
+            validate_content_for_publish(&content(&[feeds[0].clone(), bad_group])),
+            Err(Error::InvalidRecipient(1))
+        ));
+        Ok(())
+    }
+}
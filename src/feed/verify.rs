@@ -0,0 +1,77 @@
+use std::thread;
+
+use serde_json::Value;
+
+use super::{error::Result, message::Message};
+
+/// Verifies the signatures of `values` spread over the available cores,
+/// returning the results in the order of `values`.
+pub fn verify_all(values: Vec<Value>) -> Vec<Result<Message>> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk_len = values.len().div_ceil(threads).max(1);
+
+    let mut chunks = Vec::new();
+    let mut values = values.into_iter();
+    loop {
+        let chunk: Vec<Value> = values.by_ref().take(chunk_len).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push(chunk);
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                scope.spawn(|| {
+                    chunk
+                        .into_iter()
+                        .map(Message::from_value)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Like `verify_all`, without blocking the executor.
+pub async fn verify_all_async(values: Vec<Value>) -> Vec<Result<Message>> {
+    async_std::task::spawn_blocking(move || verify_all(values)).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Error, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_verify_all() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let mut values = Vec::new();
+        let mut prev: Option<Message> = None;
+        for n in 0..20 {
+            let msg = Message::sign(prev.as_ref(), &id, json!({"type": "post", "n": n}))?;
+            values.push(msg.value.clone());
+            prev = Some(msg);
+        }
+        values[7]["content"]["n"] = json!(100);
+
+        let results = verify_all(values);
+        assert_eq!(results.len(), 20);
+        for (n, result) in results.iter().enumerate() {
+            match result {
+                Ok(msg) => assert_eq!(msg.sequence(), n as u64 + 1),
+                Err(Error::InvalidSignature) => assert_eq!(n, 7),
+                Err(err) => panic!("{:?}", err),
+            }
+        }
+        assert!(verify_all(Vec::new()).is_empty());
+        Ok(())
+    }
+}
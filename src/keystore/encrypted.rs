@@ -0,0 +1,175 @@
+//! Secret files protected by a passphrase: the ssb-keys secret file is
+//! encrypted with secretbox, keyed by argon2id of the passphrase, and
+//! stored as json with the parameters needed to derive the key again.
+
+use std::path::Path;
+
+use async_std::fs;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use super::{
+    error::{Error, Result},
+    ssbkeys::{decode_secret, encode_secret, write_secret_file},
+    OwnedIdentity,
+};
+use crate::crypto::backend::{pwhash::argon2id13, secretbox};
+
+const KDF_ARGON2ID: &str = "argon2id13";
+
+/// Cost of deriving the key from the passphrase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub opslimit: usize,
+    pub memlimit: usize,
+}
+
+impl KdfParams {
+    /// Fast enough to unlock at every start, the default.
+    pub fn interactive() -> Self {
+        KdfParams {
+            opslimit: argon2id13::OPSLIMIT_INTERACTIVE.0,
+            memlimit: argon2id13::MEMLIMIT_INTERACTIVE.0,
+        }
+    }
+
+    /// Slower and more memory hungry.
+    pub fn moderate() -> Self {
+        KdfParams {
+            opslimit: argon2id13::OPSLIMIT_MODERATE.0,
+            memlimit: argon2id13::MEMLIMIT_MODERATE.0,
+        }
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::interactive()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    kdf: String,
+    opslimit: usize,
+    memlimit: usize,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+fn derive_key(
+    passphrase: &str,
+    salt: &argon2id13::Salt,
+    params: KdfParams,
+) -> Result<secretbox::Key> {
+    let mut key = secretbox::Key([0u8; secretbox::KEYBYTES]);
+    argon2id13::derive_key(
+        &mut key.0,
+        passphrase.as_bytes(),
+        salt,
+        argon2id13::OpsLimit(params.opslimit),
+        argon2id13::MemLimit(params.memlimit),
+    )
+    .map_err(|_| Error::KeyDerivation)?;
+    Ok(key)
+}
+
+/// Whether the contents of a secret file are passphrase protected.
+pub fn is_encrypted_secret(contents: &str) -> bool {
+    serde_json::from_str::<Envelope>(contents).is_ok()
+}
+
+/// The secret file of `id`, encrypted with `passphrase`.
+pub fn encrypt_secret(id: &OwnedIdentity, passphrase: &str, params: KdfParams) -> Result<String> {
+    let salt = argon2id13::gen_salt();
+    let key = derive_key(passphrase, &salt, params)?;
+    let nonce = secretbox::gen_nonce();
+    let plaintext = Zeroizing::new(encode_secret(id)?);
+    let envelope = Envelope {
+        kdf: KDF_ARGON2ID.to_string(),
+        opslimit: params.opslimit,
+        memlimit: params.memlimit,
+        salt: base64::encode(&salt),
+        nonce: base64::encode(&nonce),
+        ciphertext: base64::encode(&secretbox::seal(plaintext.as_bytes(), &nonce, &key)),
+    };
+    Ok(serde_json::to_string_pretty(&envelope)?)
+}
+
+/// Reverse of `encrypt_secret`.
+pub fn decrypt_secret(contents: &str, passphrase: &str) -> Result<OwnedIdentity> {
+    let envelope: Envelope = serde_json::from_str(contents)?;
+    if envelope.kdf != KDF_ARGON2ID {
+        return Err(Error::InvalidConfig);
+    }
+    let decode = |data: &str| base64::decode(data).map_err(|_| Error::InvalidConfig);
+    let salt =
+        argon2id13::Salt::from_slice(&decode(&envelope.salt)?).ok_or(Error::InvalidConfig)?;
+    let nonce =
+        secretbox::Nonce::from_slice(&decode(&envelope.nonce)?).ok_or(Error::InvalidConfig)?;
+    let params = KdfParams {
+        opslimit: envelope.opslimit,
+        memlimit: envelope.memlimit,
+    };
+    let key = derive_key(passphrase, &salt, params)?;
+    let plaintext = secretbox::open(&decode(&envelope.ciphertext)?, &nonce, &key)
+        .map_err(|_| Error::WrongPassphrase)?;
+    let plaintext = Zeroizing::new(String::from_utf8(plaintext).map_err(|_| Error::InvalidConfig)?);
+    decode_secret(&plaintext)
+}
+
+/// Writes the secret file of `id` encrypted with `passphrase`, like
+/// `save_secret`.
+pub async fn save_encrypted_secret<P: AsRef<Path>>(
+    id: &OwnedIdentity,
+    passphrase: &str,
+    params: KdfParams,
+    path: P,
+) -> Result<()> {
+    write_secret_file(path.as_ref(), &encrypt_secret(id, passphrase, params)?).await
+}
+
+/// Loads the secret file at `path` at startup, decrypting it with
+/// `passphrase` if it is protected; fails with `Error::Locked` if it is and
+/// no passphrase was given.
+pub async fn unlock_secret<P: AsRef<Path>>(
+    path: P,
+    passphrase: Option<&str>,
+) -> Result<OwnedIdentity> {
+    let contents = Zeroizing::new(fs::read_to_string(path.as_ref()).await?);
+    if !is_encrypted_secret(&contents) {
+        return decode_secret(&contents);
+    }
+    decrypt_secret(&contents, passphrase.ok_or(Error::Locked)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_encrypted_secret() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("kuska-locked-{}", std::process::id()));
+        let path = dir.join("secret");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let id = OwnedIdentity::create();
+        save_encrypted_secret(&id, "hunter2", KdfParams::default(), &path).await?;
+        let contents = std::fs::read_to_string(&path)?;
+        assert!(is_encrypted_secret(&contents));
+        assert!(!contents.contains(&base64::encode(&id.sk)));
+
+        assert_eq!(unlock_secret(&path, Some("hunter2")).await?, id);
+        assert!(matches!(
+            unlock_secret(&path, None).await,
+            Err(Error::Locked)
+        ));
+        assert!(matches!(
+            unlock_secret(&path, Some("hunter3")).await,
+            Err(Error::WrongPassphrase)
+        ));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -10,8 +10,20 @@ pub enum Error {
     Serde(#[from] serde_json::Error),
     #[error("crypto format")]
     CryptoFormat(#[from] crate::crypto::Error),
+    #[error("invalid mnemonic")]
+    Mnemonic(#[from] bip39::Error),
     #[error("i/o")]
     SyncIo(#[from] std::io::Error),
+    #[error("secret file is locked, a passphrase is needed")]
+    Locked,
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    #[error("cannot derive key from passphrase")]
+    KeyDerivation,
+    #[error("unknown identity {0}")]
+    UnknownIdentity(String),
+    #[error("cannot sign message")]
+    Feed(#[from] crate::feed::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -2,16 +2,14 @@
 
 #![warn(missing_docs)]
 
-use async_std::{
-    io::{Read, Write},
-    prelude::*,
-};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use super::{
     error::{Error, Result},
     util, JsonSSBSecret, OwnedIdentity, CURVE_ED25519,
 };
 use crate::crypto::{ToSodiumObject, ToSsbId};
+use zeroize::Zeroizing;
 
 /// Return an `OwnedIdentity` from the local go-sbot secret file with a custom path
 pub async fn from_custom_gosbot_keypath(local_key_file: String) -> Result<OwnedIdentity> {
@@ -28,12 +26,12 @@ pub async fn from_gosbot_local() -> Result<OwnedIdentity> {
 
 /// Read the contents of the go-sbot secret file, deserialize into a
 /// `JsonSSBSecret` and return an `OwnedIdentity`.
-pub async fn read_gosbot_config<R: Read + Unpin>(reader: &mut R) -> Result<OwnedIdentity> {
-    let mut buf = String::new();
+pub async fn read_gosbot_config<R: AsyncRead + Unpin>(reader: &mut R) -> Result<OwnedIdentity> {
+    let mut buf = Zeroizing::new(String::new());
     reader.read_to_string(&mut buf).await?;
 
     // parse json
-    let secret: JsonSSBSecret = serde_json::from_str(buf.as_ref()).map_err(util::to_io_error)?;
+    let secret: JsonSSBSecret = serde_json::from_str(&buf).map_err(util::to_io_error)?;
 
     if secret.curve != CURVE_ED25519 {
         return Err(Error::InvalidConfig);
@@ -47,17 +45,17 @@ pub async fn read_gosbot_config<R: Read + Unpin>(reader: &mut R) -> Result<Owned
 }
 
 /// Write an `OwnedIdentity`.
-pub async fn write_gosbot_config<W: Write + Unpin>(
+pub async fn write_gosbot_config<W: AsyncWrite + Unpin>(
     id: &OwnedIdentity,
     writer: &mut W,
 ) -> Result<()> {
     let json = JsonSSBSecret {
         curve: CURVE_ED25519.to_owned(),
         id: id.id.clone(),
-        private: id.sk.to_ssb_id(),
+        private: Zeroizing::new(id.sk.to_ssb_id()),
         public: id.pk.to_ssb_id(),
     };
-    let encoded = serde_json::to_vec(&json)?;
+    let encoded = Zeroizing::new(serde_json::to_vec(&json)?);
     Ok(writer.write_all(&encoded).await?)
 }
 
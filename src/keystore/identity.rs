@@ -1,5 +1,6 @@
+use crate::crypto::backend::sign::ed25519;
 use crate::crypto::CURVE_ED25519_SUFFIX;
-use kuska_sodiumoxide::crypto::sign::ed25519;
+use zeroize::Zeroizing;
 
 /// Ed25519 signature scheme identifier.
 pub const CURVE_ED25519: &str = "ed25519";
@@ -8,7 +9,8 @@ pub const CURVE_ED25519: &str = "ed25519";
 pub struct JsonSSBSecret {
     pub curve: String,
     pub id: String,
-    pub private: String,
+    /// Zeroized on drop.
+    pub private: Zeroizing<String>,
     pub public: String,
 }
 
@@ -28,4 +30,43 @@ impl OwnedIdentity {
             id: format!("@{}{}", base64::encode(&pk), CURVE_ED25519_SUFFIX),
         }
     }
+
+    /// The identity derived from `seed`, always the same for a given seed.
+    pub fn from_seed(seed: &[u8; 32]) -> OwnedIdentity {
+        let (pk, sk) = ed25519::keypair_from_seed(&ed25519::Seed(*seed));
+        OwnedIdentity {
+            pk,
+            sk,
+            id: format!("@{}{}", base64::encode(&pk), CURVE_ED25519_SUFFIX),
+        }
+    }
+}
+
+/// A new random identity: an ed25519 keypair and its `@...ed25519` id.
+pub fn generate() -> OwnedIdentity {
+    OwnedIdentity::create()
+}
+
+/// The identity derived from a 32 byte `seed`, see `OwnedIdentity::from_seed`.
+pub fn from_seed(seed: &[u8; 32]) -> OwnedIdentity {
+    OwnedIdentity::from_seed(seed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto::ToSsbId;
+
+    #[test]
+    fn test_generate() {
+        let identity = generate();
+        assert_eq!(identity.id, format!("@{}", identity.pk.to_ssb_id()));
+        assert_eq!(identity.sk.public_key(), identity.pk);
+        assert_ne!(generate(), identity);
+
+        let seed = [7u8; 32];
+        assert_eq!(from_seed(&seed), from_seed(&seed));
+        assert_ne!(from_seed(&seed), from_seed(&[8u8; 32]));
+        assert_eq!(from_seed(&seed).sk.public_key(), from_seed(&seed).pk);
+    }
 }
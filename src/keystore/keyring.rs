@@ -0,0 +1,191 @@
+//! Several identities held together: the main one, the keys of metafeeds
+//! and their subfeeds, and app identities, each under a label.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{
+    error::{Error, Result},
+    metafeed::{derive_root_metafeed, derive_subfeed},
+    OwnedIdentity,
+};
+use crate::{
+    feed::{Message, Metafeed},
+    uri::{FeedRef, SsbUri},
+};
+
+/// Label of the main identity.
+pub const MAIN: &str = "main";
+/// Label of the root metafeed identity.
+pub const ROOT_METAFEED: &str = "metafeed";
+
+pub struct Keyring {
+    /// (label, identity), the main identity first.
+    identities: Vec<(String, OwnedIdentity)>,
+    /// Label of the identity to use with each peer, by peer id.
+    peers: HashMap<String, String>,
+}
+
+/// The base64 key of a feed id in any feed format.
+fn key_of(feed: &str) -> Option<String> {
+    match feed.parse::<FeedRef>().ok()?.to_uri() {
+        SsbUri::Feed { data, .. } => Some(data),
+        _ => None,
+    }
+}
+
+impl Keyring {
+    pub fn new(main: OwnedIdentity) -> Self {
+        Keyring {
+            identities: vec![(MAIN.to_string(), main)],
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Adds `identity` under `label`, replacing the identity that had it.
+    pub fn add(&mut self, label: &str, identity: OwnedIdentity) {
+        match self.identities.iter_mut().find(|(l, _)| l == label) {
+            Some(entry) => entry.1 = identity,
+            None => self.identities.push((label.to_string(), identity)),
+        }
+    }
+
+    /// Removes the identity under `label`; the main identity stays.
+    pub fn remove(&mut self, label: &str) -> Option<OwnedIdentity> {
+        if label == MAIN {
+            return None;
+        }
+        let index = self.identities.iter().position(|(l, _)| l == label)?;
+        self.peers.retain(|_, l| l != label);
+        Some(self.identities.remove(index).1)
+    }
+
+    pub fn main(&self) -> &OwnedIdentity {
+        &self.identities[0].1
+    }
+
+    pub fn get(&self, label: &str) -> Option<&OwnedIdentity> {
+        self.identities
+            .iter()
+            .find(|(l, _)| l == label)
+            .map(|(_, identity)| identity)
+    }
+
+    /// The identity of `feed`, a sigil or uri id in any feed format, so
+    /// the `@...bbfeed-v1` id of a metafeed finds its key too.
+    pub fn by_feed_id(&self, feed: &str) -> Option<&OwnedIdentity> {
+        let key = key_of(feed)?;
+        self.identities
+            .iter()
+            .map(|(_, identity)| identity)
+            .find(|identity| base64::encode(&identity.pk) == key)
+    }
+
+    /// Adds the root metafeed key derived from `seed` and the keys of the
+    /// derived subfeeds found in `tree`, live or not, each labelled with
+    /// its feed id. Returns how many subfeed keys were added.
+    pub fn restore_metafeed(&mut self, seed: &[u8; 32], tree: &Metafeed) -> usize {
+        self.add(ROOT_METAFEED, derive_root_metafeed(seed));
+        let mut restored = 0;
+        for subfeed in tree.all_subfeeds() {
+            if let Some(nonce) = &subfeed.nonce {
+                self.add(&subfeed.id, derive_subfeed(seed, nonce));
+                restored += 1;
+            }
+        }
+        restored
+    }
+
+    /// (label, identity) pairs, the main identity first.
+    pub fn identities(&self) -> impl Iterator<Item = (&str, &OwnedIdentity)> {
+        self.identities
+            .iter()
+            .map(|(label, identity)| (label.as_str(), identity))
+    }
+
+    /// Connect to `peer` as the identity under `label`.
+    pub fn use_for_peer(&mut self, peer: &str, label: &str) -> Result<()> {
+        if self.get(label).is_none() {
+            return Err(Error::UnknownIdentity(label.to_string()));
+        }
+        let peer = peer.parse::<FeedRef>().map_err(|_| Error::InvalidConfig)?;
+        self.peers.insert(peer.into(), label.to_string());
+        Ok(())
+    }
+
+    /// The identity to connect to `peer` with, the main one unless set
+    /// with `use_for_peer`.
+    pub fn for_peer(&self, peer: &str) -> &OwnedIdentity {
+        peer.parse::<FeedRef>()
+            .ok()
+            .and_then(|peer| self.peers.get(peer.as_str()))
+            .and_then(|label| self.get(label))
+            .unwrap_or_else(|| self.main())
+    }
+
+    /// Signs the message following `prev` in the feed of `author`.
+    pub fn publish(&self, author: &str, prev: Option<&Message>, content: Value) -> Result<Message> {
+        let identity = self
+            .by_feed_id(author)
+            .ok_or_else(|| Error::UnknownIdentity(author.to_string()))?;
+        Ok(Message::sign(prev, identity, content)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::feed::bendybutt_feed_id;
+    use serde_json::json;
+
+    #[test]
+    fn test_keyring() -> Result<()> {
+        let (main, metafeed, app) = (
+            OwnedIdentity::create(),
+            OwnedIdentity::create(),
+            OwnedIdentity::create(),
+        );
+        let mut keyring = Keyring::new(main.clone());
+        keyring.add("metafeed", metafeed.clone());
+        keyring.add("app", app.clone());
+
+        assert_eq!(
+            keyring.by_feed_id(&bendybutt_feed_id(&metafeed)),
+            Some(&metafeed)
+        );
+        assert_eq!(keyring.by_feed_id(&app.id), Some(&app));
+
+        let peer = OwnedIdentity::create().id;
+        assert_eq!(keyring.for_peer(&peer), &main);
+        keyring.use_for_peer(&peer, "app")?;
+        assert_eq!(keyring.for_peer(&peer), &app);
+        assert!(keyring.use_for_peer(&peer, "nope").is_err());
+
+        let msg = keyring.publish(&app.id, None, json!({"type": "post", "text": "hi"}))?;
+        assert_eq!(msg.author(), &app.id);
+        assert!(keyring
+            .publish(&peer, None, json!({"type": "post"}))
+            .is_err());
+
+        let seed = crate::keystore::generate_metafeed_seed();
+        let nonce = crate::keystore::generate_subfeed_nonce();
+        let derived = derive_subfeed(&seed, &nonce);
+        let mut tree = Metafeed::new(bendybutt_feed_id(&derive_root_metafeed(&seed)));
+        tree.subfeeds.push(crate::feed::Subfeed {
+            id: derived.id.clone(),
+            purpose: "index".to_string(),
+            tombstoned: false,
+            nonce: Some(nonce),
+            metafeed: None,
+        });
+        assert_eq!(keyring.restore_metafeed(&seed, &tree), 1);
+        assert!(keyring.by_feed_id(&tree.id).is_some());
+        assert_eq!(keyring.by_feed_id(&derived.id), Some(&derived));
+
+        keyring.remove("app");
+        assert_eq!(keyring.for_peer(&peer), &main);
+        assert!(keyring.remove(MAIN).is_none());
+        Ok(())
+    }
+}
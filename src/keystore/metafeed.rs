@@ -0,0 +1,65 @@
+//! Keys of metafeeds and their subfeeds derived from a single seed, as in
+//! the metafeed spec: the root metafeed key is derived from the seed, and
+//! each `metafeed/add/derived` subfeed key from the seed and the nonce
+//! published with it, so all of them can be recreated from the seed.
+
+use zeroize::Zeroizing;
+
+use super::OwnedIdentity;
+use crate::crypto::backend::randombytes::randombytes_into;
+use crate::crypto::box2::hkdf_expand;
+
+/// Prefix of the HKDF info of every derived key.
+pub const SEED_INFO_PREFIX: &str = "ssb-meta-feed-seed-v1:";
+/// HKDF info, after the prefix, of the root metafeed key.
+pub const ROOT_METAFEED_INFO: &str = "metafeed";
+
+fn derive(seed: &[u8; 32], info: &str) -> OwnedIdentity {
+    let info = format!("{}{}", SEED_INFO_PREFIX, info);
+    let derived = Zeroizing::new(hkdf_expand(seed, info.as_bytes()));
+    OwnedIdentity::from_seed(&derived)
+}
+
+/// A new random metafeed seed.
+pub fn generate_metafeed_seed() -> Zeroizing<[u8; 32]> {
+    let mut seed = Zeroizing::new([0u8; 32]);
+    randombytes_into(&mut *seed);
+    seed
+}
+
+/// A new random base64 nonce to derive a subfeed with.
+pub fn generate_subfeed_nonce() -> String {
+    let mut nonce = [0u8; 32];
+    randombytes_into(&mut nonce);
+    base64::encode(&nonce)
+}
+
+/// The key of the root metafeed of `seed`.
+pub fn derive_root_metafeed(seed: &[u8; 32]) -> OwnedIdentity {
+    derive(seed, ROOT_METAFEED_INFO)
+}
+
+/// The key of the subfeed derived from `seed` with the base64 `nonce` of
+/// its `metafeed/add/derived` message.
+pub fn derive_subfeed(seed: &[u8; 32], nonce: &str) -> OwnedIdentity {
+    derive(seed, nonce)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_derive_metafeed_keys() {
+        let seed = generate_metafeed_seed();
+        let root = derive_root_metafeed(&seed);
+        assert_eq!(derive_root_metafeed(&seed), root);
+        assert_ne!(derive_root_metafeed(&generate_metafeed_seed()), root);
+
+        let (nonce1, nonce2) = (generate_subfeed_nonce(), generate_subfeed_nonce());
+        let subfeed = derive_subfeed(&seed, &nonce1);
+        assert_eq!(derive_subfeed(&seed, &nonce1), subfeed);
+        assert_ne!(derive_subfeed(&seed, &nonce2), subfeed);
+        assert_ne!(subfeed, root);
+    }
+}
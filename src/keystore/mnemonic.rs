@@ -0,0 +1,43 @@
+//! Backup of identities as 24 word BIP39 phrases, compatible with
+//! ssb-keys-mnemonic: the words encode the 32 byte seed of the keypair.
+
+use bip39::Mnemonic;
+use zeroize::Zeroizing;
+
+use super::{error::Result, OwnedIdentity};
+
+/// The 24 words encoding the seed of `identity`.
+pub fn to_mnemonic(identity: &OwnedIdentity) -> Result<String> {
+    Ok(Mnemonic::from_entropy(&identity.sk.0[..32])?.to_string())
+}
+
+/// The identity whose seed is encoded by `words`.
+pub fn from_mnemonic(words: &str) -> Result<OwnedIdentity> {
+    let entropy = Zeroizing::new(Mnemonic::parse_normalized(words.trim())?.to_entropy());
+    let mut seed = Zeroizing::new([0u8; 32]);
+    if entropy.len() != seed.len() {
+        return Err(bip39::Error::BadWordCount(entropy.len() * 3 / 4).into());
+    }
+    seed.copy_from_slice(&entropy);
+    Ok(OwnedIdentity::from_seed(&seed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mnemonic() -> Result<()> {
+        let identity = OwnedIdentity::from_seed(&[0u8; 32]);
+        let words = to_mnemonic(&identity)?;
+        assert_eq!(words, format!("{}art", "abandon ".repeat(23)));
+        assert_eq!(from_mnemonic(&words)?, identity);
+
+        let identity = OwnedIdentity::create();
+        assert_eq!(from_mnemonic(&to_mnemonic(&identity)?)?, identity);
+
+        assert!(from_mnemonic("abandon abandon abandon").is_err());
+        assert!(from_mnemonic(&format!("{}abandon", "abandon ".repeat(23))).is_err());
+        Ok(())
+    }
+}
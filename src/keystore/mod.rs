@@ -1,14 +1,33 @@
+mod encrypted;
 mod error;
 pub mod gosbot;
 mod identity;
+pub mod keyring;
+mod metafeed;
+mod mnemonic;
 pub mod patchwork;
+pub mod ssbkeys;
 mod util;
 
+pub use encrypted::{
+    decrypt_secret, encrypt_secret, is_encrypted_secret, save_encrypted_secret, unlock_secret,
+    KdfParams,
+};
 pub use gosbot::{
     from_custom_gosbot_keypath, from_gosbot_local, read_gosbot_config, write_gosbot_config,
 };
-pub use identity::{JsonSSBSecret, OwnedIdentity, CURVE_ED25519};
+pub use identity::{from_seed, generate, JsonSSBSecret, OwnedIdentity, CURVE_ED25519};
+pub use keyring::Keyring;
+pub use metafeed::{
+    derive_root_metafeed, derive_subfeed, generate_metafeed_seed, generate_subfeed_nonce,
+    ROOT_METAFEED_INFO, SEED_INFO_PREFIX,
+};
+pub use mnemonic::{from_mnemonic, to_mnemonic};
 pub use patchwork::{
     from_custom_patchwork_keypath, from_patchwork_local, read_patchwork_config,
     write_patchwork_config,
 };
+pub use ssbkeys::{
+    decode_secret, default_secret_path, encode_secret, load_or_create_secret, load_secret,
+    save_secret,
+};
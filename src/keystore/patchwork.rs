@@ -1,7 +1,4 @@
-use async_std::{
-    io::{Read, Write},
-    prelude::*,
-};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
 use super::{
     error::{Error, Result},
@@ -9,6 +6,7 @@ use super::{
 };
 use crate::crypto::{ToSodiumObject, ToSsbId};
 use serde_json::to_vec_pretty;
+use zeroize::Zeroizing;
 
 pub async fn from_custom_patchwork_keypath(local_key_file: String) -> Result<OwnedIdentity> {
     let mut file = async_std::fs::File::open(local_key_file).await?;
@@ -21,18 +19,19 @@ pub async fn from_patchwork_local() -> Result<OwnedIdentity> {
     from_custom_patchwork_keypath(local_key_file).await
 }
 
-pub async fn read_patchwork_config<R: Read + Unpin>(reader: &mut R) -> Result<OwnedIdentity> {
-    let mut buf = String::new();
+pub async fn read_patchwork_config<R: AsyncRead + Unpin>(reader: &mut R) -> Result<OwnedIdentity> {
+    let mut buf = Zeroizing::new(String::new());
     reader.read_to_string(&mut buf).await?;
 
-    let json = buf
-        .lines()
-        .filter(|line| !line.starts_with('#'))
-        .collect::<Vec<_>>()
-        .join("");
+    let json = Zeroizing::new(
+        buf.lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join(""),
+    );
 
     // parse json
-    let secret: JsonSSBSecret = serde_json::from_str(json.as_ref()).map_err(util::to_io_error)?;
+    let secret: JsonSSBSecret = serde_json::from_str(&json).map_err(util::to_io_error)?;
 
     if secret.curve != CURVE_ED25519 {
         return Err(Error::InvalidConfig);
@@ -45,7 +44,7 @@ pub async fn read_patchwork_config<R: Read + Unpin>(reader: &mut R) -> Result<Ow
     })
 }
 
-pub async fn write_patchwork_config<W: Write + Unpin>(
+pub async fn write_patchwork_config<W: AsyncWrite + Unpin>(
     id: &OwnedIdentity,
     writer: &mut W,
 ) -> Result<()> {
@@ -53,8 +52,8 @@ pub async fn write_patchwork_config<W: Write + Unpin>(
         id: id.id.clone(),
         curve: CURVE_ED25519.to_owned(),
         public: id.pk.to_ssb_id(),
-        private: id.sk.to_ssb_id(),
+        private: Zeroizing::new(id.sk.to_ssb_id()),
     };
-    let encoded = to_vec_pretty(&json)?;
+    let encoded = Zeroizing::new(to_vec_pretty(&json)?);
     Ok(writer.write_all(&encoded).await?)
 }
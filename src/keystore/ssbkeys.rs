@@ -0,0 +1,161 @@
+//! Read and write secret files in the exact format of ssb-keys, the one
+//! used by the js implementation in `~/.ssb/secret`.
+
+use std::path::{Path, PathBuf};
+
+use async_std::{fs, prelude::*};
+use serde_json::json;
+use zeroize::Zeroizing;
+
+use super::{
+    error::{Error, Result},
+    JsonSSBSecret, OwnedIdentity, CURVE_ED25519,
+};
+use crate::crypto::{ToSodiumObject, ToSsbId};
+
+const HEADER: &str = "# this is your SECRET name.
+# this name gives you magical powers.
+# with it you can mark your messages so that your friends can verify
+# that they really did come from you.
+#
+# if any one learns this name, they can use it to destroy your identity
+# NEVER show this to anyone!!!
+";
+
+const FOOTER: &str = "# WARNING! It's vital that you DO NOT edit OR share your secret name
+# instead, share your public name
+# your public name: ";
+
+/// `~/.ssb/secret`
+pub fn default_secret_path() -> Result<PathBuf> {
+    let home_dir = dirs::home_dir().ok_or(Error::HomeNotFound)?;
+    Ok(home_dir.join(".ssb").join("secret"))
+}
+
+/// The contents of the secret file of `id`, comments included; callers
+/// should zeroize it once written.
+pub fn encode_secret(id: &OwnedIdentity) -> Result<String> {
+    // in the key order of ssb-keys
+    let json = json!({
+        "curve": CURVE_ED25519,
+        "public": id.pk.to_ssb_id(),
+        "private": id.sk.to_ssb_id(),
+        "id": id.id,
+    });
+    Ok(format!(
+        "{}\n{}\n\n{}{}\n",
+        HEADER,
+        serde_json::to_string_pretty(&json)?,
+        FOOTER,
+        id.id
+    ))
+}
+
+/// Parses a secret file, ignoring comments as ssb-keys does, and checks
+/// that the keys and the id match.
+pub fn decode_secret(contents: &str) -> Result<OwnedIdentity> {
+    let json = Zeroizing::new(
+        contents
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    );
+    let secret: JsonSSBSecret = serde_json::from_str(&json)?;
+    if secret.curve != CURVE_ED25519 {
+        return Err(Error::InvalidConfig);
+    }
+
+    let identity = OwnedIdentity {
+        id: secret.id,
+        pk: secret.public.to_ed25519_pk()?,
+        sk: secret.private.to_ed25519_sk()?,
+    };
+    if identity.id != format!("@{}", identity.pk.to_ssb_id())
+        || identity.sk.public_key() != identity.pk
+    {
+        return Err(Error::InvalidConfig);
+    }
+    Ok(identity)
+}
+
+pub async fn load_secret<P: AsRef<Path>>(path: P) -> Result<OwnedIdentity> {
+    let contents = Zeroizing::new(fs::read_to_string(path.as_ref()).await?);
+    decode_secret(&contents)
+}
+
+/// Writes the secret file of `id`, read-only to its owner in a directory
+/// only its owner can access. Like ssb-keys, an existing file is never
+/// overwritten.
+pub async fn save_secret<P: AsRef<Path>>(id: &OwnedIdentity, path: P) -> Result<()> {
+    let contents = Zeroizing::new(encode_secret(id)?);
+    write_secret_file(path.as_ref(), &contents).await
+}
+
+/// Writes `contents` as `save_secret` does.
+pub(super) async fn write_secret_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        async_std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir).await?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    async_std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o400);
+    let mut file = options.open(path).await?;
+    file.write_all(contents.as_bytes()).await?;
+    Ok(file.sync_all().await?)
+}
+
+/// Loads the secret file at `path`, creating a new identity if there is
+/// none.
+pub async fn load_or_create_secret<P: AsRef<Path>>(path: P) -> Result<OwnedIdentity> {
+    let path = path.as_ref();
+    if fs::metadata(path).await.is_ok() {
+        return load_secret(path).await;
+    }
+    let id = OwnedIdentity::create();
+    save_secret(&id, path).await?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn test_secret_file() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("kuska-secret-{}", std::process::id()));
+        let path = dir.join("secret");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let id = load_or_create_secret(&path).await?;
+        assert_eq!(load_or_create_secret(&path).await?, id);
+        assert!(save_secret(&id, &path).await.is_err());
+
+        let contents = std::fs::read_to_string(&path)?;
+        assert!(contents.starts_with("# this is your SECRET name."));
+        assert!(contents.contains("{\n  \"curve\": \"ed25519\",\n  \"public\": "));
+        assert!(contents.ends_with(&format!("# your public name: {}\n", id.id)));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)?.permissions().mode();
+            assert_eq!(mode & 0o777, 0o400);
+        }
+
+        let other = OwnedIdentity::create();
+        let mismatched = contents.replace(&id.id, &other.id);
+        assert!(matches!(
+            decode_secret(&mismatched),
+            Err(Error::InvalidConfig)
+        ));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
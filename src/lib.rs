@@ -1,3 +1,4 @@
+#[cfg(feature = "sodium")]
 pub extern crate kuska_handshake as handshake;
 
 #[macro_use]
@@ -7,8 +8,22 @@ extern crate serde_json;
 extern crate thiserror;
 
 pub mod api;
+pub mod connection;
 pub mod crypto;
 pub mod discovery;
 pub mod feed;
 pub mod keystore;
+pub mod multiserver;
+pub mod replication;
 pub mod rpc;
+pub mod social_graph;
+pub mod store;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod uri;
+
+/// Used by the exported macros.
+#[doc(hidden)]
+pub mod __private {
+    pub use futures::io::AsyncWrite;
+}
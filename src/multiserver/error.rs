@@ -0,0 +1,13 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid multiserver address: {0}")]
+    InvalidAddress(String),
+    #[error("invalid port")]
+    ParseInt(#[from] std::num::ParseIntError),
+    #[error("invalid key")]
+    CryptoFormat(#[from] crate::crypto::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
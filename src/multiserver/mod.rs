@@ -0,0 +1,285 @@
+//! Multiserver addresses, e.g.
+//! `net:192.168.1.2:8008~shs:<key>;ws://example.com:80~shs:<key>`: a list of
+//! alternative addresses separated by `;`, each a protocol followed by
+//! `~`-separated transforms.
+
+mod error;
+
+use std::{fmt, str::FromStr};
+
+use crate::crypto::backend::sign::ed25519;
+use crate::crypto::{ToSodiumObject, ToSsbId};
+pub use error::{Error, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Protocol {
+    /// `net:host:port`, tcp.
+    Net { host: String, port: u16 },
+    /// `ws://host:port/path` or `wss://host:port/path`, websockets.
+    Ws {
+        secure: bool,
+        host: String,
+        port: u16,
+        path: String,
+    },
+    /// `onion:host.onion:port`, through tor.
+    Onion { host: String, port: u16 },
+    /// `tunnel:@portal.ed25519:@target.ed25519`, through a room.
+    Tunnel { portal: String, target: String },
+    /// Any other protocol, as is.
+    Other(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+    /// `shs:<base64 key>`, secret handshake with the given server key.
+    Shs(ed25519::PublicKey),
+    /// `shs2:<base64 key>`, the second version of the secret handshake.
+    Shs2(ed25519::PublicKey),
+    /// `noauth`, no authentication.
+    Noauth,
+    /// Any other transform, as is.
+    Other(String),
+}
+
+/// One address: a protocol and its transforms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub protocol: Protocol,
+    pub transforms: Vec<Transform>,
+}
+
+/// Alternative addresses of the same peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiAddress(pub Vec<Address>);
+
+fn host_port(data: &str) -> Result<(String, u16)> {
+    let (host, port) = data
+        .rsplit_once(':')
+        .ok_or_else(|| Error::InvalidAddress(data.to_string()))?;
+    if host.is_empty() {
+        return Err(Error::InvalidAddress(data.to_string()));
+    }
+    Ok((host.to_string(), port.parse()?))
+}
+
+impl FromStr for Protocol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(data) = s.strip_prefix("net:") {
+            let (host, port) = host_port(data)?;
+            return Ok(Protocol::Net { host, port });
+        }
+        if let Some(data) = s.strip_prefix("onion:") {
+            let (host, port) = host_port(data)?;
+            return Ok(Protocol::Onion { host, port });
+        }
+        if let Some(data) = s.strip_prefix("tunnel:") {
+            let (portal, target) = data
+                .split_once(':')
+                .ok_or_else(|| Error::InvalidAddress(s.to_string()))?;
+            return Ok(Protocol::Tunnel {
+                portal: portal.to_string(),
+                target: target.to_string(),
+            });
+        }
+        let ws = match (s.strip_prefix("ws://"), s.strip_prefix("wss://")) {
+            (Some(data), _) => Some((false, data)),
+            (_, Some(data)) => Some((true, data)),
+            _ => None,
+        };
+        if let Some((secure, data)) = ws {
+            let (authority, path) = match data.find('/') {
+                Some(index) => data.split_at(index),
+                None => (data, ""),
+            };
+            let (host, port) = match authority.rsplit_once(':') {
+                Some(_) => host_port(authority)?,
+                None => (authority.to_string(), if secure { 443 } else { 80 }),
+            };
+            return Ok(Protocol::Ws {
+                secure,
+                host,
+                port,
+                path: path.to_string(),
+            });
+        }
+        Ok(Protocol::Other(s.to_string()))
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Protocol::Net { host, port } => write!(f, "net:{}:{}", host, port),
+            Protocol::Ws {
+                secure,
+                host,
+                port,
+                path,
+            } => {
+                let scheme = if *secure { "wss" } else { "ws" };
+                write!(f, "{}://{}:{}{}", scheme, host, port, path)
+            }
+            Protocol::Onion { host, port } => write!(f, "onion:{}:{}", host, port),
+            Protocol::Tunnel { portal, target } => write!(f, "tunnel:{}:{}", portal, target),
+            Protocol::Other(protocol) => write!(f, "{}", protocol),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, data) = s.split_once(':').unwrap_or((s, ""));
+        match name {
+            "shs" => Ok(Transform::Shs(data.to_ed25519_pk_no_suffix()?)),
+            "shs2" => Ok(Transform::Shs2(data.to_ed25519_pk_no_suffix()?)),
+            "noauth" => Ok(Transform::Noauth),
+            _ => Ok(Transform::Other(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Transform::Shs(key) => write!(f, "shs:{}", base64::encode(key)),
+            Transform::Shs2(key) => write!(f, "shs2:{}", base64::encode(key)),
+            Transform::Noauth => write!(f, "noauth"),
+            Transform::Other(transform) => write!(f, "{}", transform),
+        }
+    }
+}
+
+impl Address {
+    /// The server key of the first `shs` or `shs2` transform.
+    pub fn shs_key(&self) -> Option<&ed25519::PublicKey> {
+        self.transforms
+            .iter()
+            .find_map(|transform| match transform {
+                Transform::Shs(key) | Transform::Shs2(key) => Some(key),
+                _ => None,
+            })
+    }
+
+    /// The `@...ed25519` id of the server, from its `shs` key.
+    pub fn feed_id(&self) -> Option<String> {
+        self.shs_key().map(|key| format!("@{}", key.to_ssb_id()))
+    }
+
+    /// `host:port` to connect to for `net` addresses.
+    pub fn net_socket_addr(&self) -> Option<String> {
+        match &self.protocol {
+            Protocol::Net { host, port } => Some(format!("{}:{}", host, port)),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split('~');
+        let protocol = parts
+            .next()
+            .filter(|protocol| !protocol.is_empty())
+            .ok_or_else(|| Error::InvalidAddress(s.to_string()))?
+            .parse()?;
+        let transforms = parts.map(str::parse).collect::<Result<_>>()?;
+        Ok(Address {
+            protocol,
+            transforms,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.protocol)?;
+        for transform in &self.transforms {
+            write!(f, "~{}", transform)?;
+        }
+        Ok(())
+    }
+}
+
+impl MultiAddress {
+    /// The first `net` address with a secret handshake key, what the tcp
+    /// connect helpers need.
+    pub fn net_shs(&self) -> Option<(String, ed25519::PublicKey)> {
+        self.0
+            .iter()
+            .find_map(|address| Some((address.net_socket_addr()?, *address.shs_key()?)))
+    }
+}
+
+impl FromStr for MultiAddress {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let addresses = s
+            .split(';')
+            .filter(|address| !address.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if addresses.is_empty() {
+            return Err(Error::InvalidAddress(s.to_string()));
+        }
+        Ok(MultiAddress(addresses))
+    }
+}
+
+impl fmt::Display for MultiAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let addresses: Vec<_> = self.0.iter().map(Address::to_string).collect();
+        write!(f, "{}", addresses.join(";"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY: &str = "+oaWWDs8g73EZFUMfW37R/ULtFEjwKN/DczvdYlwI2E=";
+
+    #[test]
+    fn test_multiserver() -> Result<()> {
+        let s = format!(
+            "net:192.168.1.2:8008~shs:{key};wss://example.com:443/path~shs:{key};onion:abc.onion:8008~noauth",
+            key = KEY
+        );
+        let multi: MultiAddress = s.parse()?;
+        assert_eq!(multi.0.len(), 3);
+        assert_eq!(multi.to_string(), s);
+
+        let key = KEY.to_ed25519_pk_no_suffix()?;
+        assert_eq!(multi.net_shs(), Some(("192.168.1.2:8008".to_string(), key)));
+        assert_eq!(
+            multi.0[1].protocol,
+            Protocol::Ws {
+                secure: true,
+                host: "example.com".to_string(),
+                port: 443,
+                path: "/path".to_string(),
+            }
+        );
+        assert_eq!(multi.0[0].feed_id(), Some(format!("@{}.ed25519", KEY)));
+        assert_eq!(multi.0[2].transforms, vec![Transform::Noauth]);
+
+        let tunnel: Address =
+            format!("tunnel:@{key}.ed25519:@{key}.ed25519~shs:{key}", key = KEY).parse()?;
+        assert!(matches!(tunnel.protocol, Protocol::Tunnel { .. }));
+
+        // ipv6 hosts keep their colons
+        let ipv6: Address = "net:::1:8008".parse()?;
+        assert_eq!(ipv6.net_socket_addr().as_deref(), Some("::1:8008"));
+
+        assert!("net:host~shs:AAAA".parse::<Address>().is_err());
+        assert!("".parse::<MultiAddress>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,15 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("feed")]
+    Feed(#[from] crate::feed::Error),
+    #[error("api")]
+    Api(#[from] crate::api::Error),
+    #[error("i/o")]
+    Io(#[from] std::io::Error),
+    #[error("json")]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,9 @@
+//! Pieces to replicate feeds from peers.
+
+mod error;
+mod scheduler;
+mod state;
+
+pub use error::{Error, Result};
+pub use scheduler::{Request, Scheduler, SchedulerConfig};
+pub use state::{CursorPersistence, JsonFilePersistence, NoPersistence, ReplicationState};
@@ -0,0 +1,284 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use super::state::{CursorPersistence, ReplicationState};
+use crate::{api::dto::CreateHistoryStreamIn, social_graph::SocialGraph, uri::normalize_id};
+
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Feeds farther than this in the follow graph are not replicated.
+    pub max_hops: u32,
+    /// Maximum number of streams open with a single peer.
+    pub max_per_peer: usize,
+    /// Maximum number of streams open over all peers.
+    pub max_total: usize,
+    /// Delay before retrying a feed after its first failure, doubled on
+    /// every further failure up to `max_backoff`.
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Request live streams, left open once the peer has sent everything.
+    pub live: bool,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        SchedulerConfig {
+            max_hops: 2,
+            max_per_peer: 16,
+            max_total: 64,
+            backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(300),
+            live: true,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    pub fn max_hops(self, max_hops: u32) -> Self {
+        Self { max_hops, ..self }
+    }
+    pub fn max_per_peer(self, max_per_peer: usize) -> Self {
+        Self {
+            max_per_peer,
+            ..self
+        }
+    }
+    pub fn max_total(self, max_total: usize) -> Self {
+        Self { max_total, ..self }
+    }
+    pub fn backoff(self, backoff: Duration, max_backoff: Duration) -> Self {
+        Self {
+            backoff,
+            max_backoff,
+            ..self
+        }
+    }
+    pub fn live(self, live: bool) -> Self {
+        Self { live, ..self }
+    }
+}
+
+/// A createHistoryStream to send to `peer`.
+#[derive(Debug)]
+pub struct Request {
+    pub peer: String,
+    pub feed: String,
+    pub args: CreateHistoryStreamIn,
+}
+
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    retry_at: Instant,
+}
+
+/// Decides which feeds to request from which connected peers.
+///
+/// The scheduler does no i/o: the caller sends the returned requests and
+/// reports back with `completed`, `failed` and the peer (dis)connections.
+#[derive(Debug)]
+pub struct Scheduler {
+    config: SchedulerConfig,
+    peers: Vec<String>,
+    /// feed -> peer its stream is open with
+    in_flight: HashMap<String, String>,
+    backoffs: HashMap<String, Backoff>,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Scheduler {
+            config,
+            peers: Vec::new(),
+            in_flight: HashMap::new(),
+            backoffs: HashMap::new(),
+        }
+    }
+
+    pub fn peer_connected(&mut self, peer: &str) {
+        if !self.peers.iter().any(|p| p == peer) {
+            self.peers.push(peer.to_string());
+        }
+    }
+
+    /// Forgets `peer`, returning the feeds whose streams were open with it;
+    /// they are requested again from other peers.
+    pub fn peer_disconnected(&mut self, peer: &str) -> Vec<String> {
+        self.peers.retain(|p| p != peer);
+        let feeds: Vec<_> = self
+            .in_flight
+            .iter()
+            .filter(|(_, p)| *p == peer)
+            .map(|(feed, _)| feed.clone())
+            .collect();
+        for feed in &feeds {
+            self.in_flight.remove(feed);
+        }
+        feeds
+    }
+
+    pub fn in_flight(&self, feed: &str) -> Option<&str> {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        self.in_flight.get(feed).map(String::as_str)
+    }
+
+    /// The stream of `feed` ended without errors.
+    pub fn completed(&mut self, feed: &str) {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        self.in_flight.remove(feed);
+        self.backoffs.remove(feed);
+    }
+
+    /// The stream of `feed` failed, it is retried after a backoff.
+    pub fn failed(&mut self, feed: &str, now: Instant) {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        self.in_flight.remove(feed);
+        let failures = self.backoffs.get(feed).map_or(0, |b| b.failures) + 1;
+        let delay = self
+            .config
+            .backoff
+            .saturating_mul(1 << (failures - 1).min(16))
+            .min(self.config.max_backoff);
+        self.backoffs.insert(
+            feed.to_string(),
+            Backoff {
+                failures,
+                retry_at: now + delay,
+            },
+        );
+    }
+
+    fn load(&self, peer: &str) -> usize {
+        self.in_flight.values().filter(|p| *p == peer).count()
+    }
+
+    /// The requests to send now for the feeds within `max_hops` of `me`,
+    /// nearest first. A feed is requested from its own author when
+    /// connected to it, else from the least busy peer.
+    pub fn next_requests<P: CursorPersistence>(
+        &mut self,
+        me: &str,
+        graph: &SocialGraph,
+        state: &ReplicationState<P>,
+        now: Instant,
+    ) -> Vec<Request> {
+        let mut wanted: Vec<_> = graph.hops(me, self.config.max_hops).into_iter().collect();
+        wanted.sort_by(|(a, a_hops), (b, b_hops)| a_hops.cmp(b_hops).then(a.cmp(b)));
+
+        let mut requests = Vec::new();
+        let mut full = HashSet::new();
+        for (feed, _) in wanted {
+            if self.in_flight.len() >= self.config.max_total {
+                break;
+            }
+            if feed == me || self.in_flight.contains_key(&feed) {
+                continue;
+            }
+            if self.backoffs.get(&feed).is_some_and(|b| b.retry_at > now) {
+                continue;
+            }
+
+            let peer = if self.peers.contains(&feed) && !full.contains(&feed) {
+                Some(feed.clone())
+            } else {
+                self.peers
+                    .iter()
+                    .filter(|peer| !full.contains(*peer))
+                    .min_by_key(|peer| self.load(peer))
+                    .cloned()
+            };
+            let peer = match peer {
+                Some(peer) => peer,
+                None => break,
+            };
+            if self.load(&peer) >= self.config.max_per_peer {
+                full.insert(peer);
+                continue;
+            }
+
+            let args = state.history_stream_args(&feed).live(self.config.live);
+            self.in_flight.insert(feed.clone(), peer.clone());
+            requests.push(Request { peer, feed, args });
+        }
+        requests
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        feed::Message,
+        keystore::OwnedIdentity,
+        replication::{NoPersistence, Result},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_scheduler() -> Result<()> {
+        let ids: Vec<_> = (0..4).map(|_| OwnedIdentity::create()).collect();
+        let (me, a, b, c) = (&ids[0], &ids[1], &ids[2], &ids[3]);
+
+        let mut graph = SocialGraph::new();
+        let follow_a = json!({"type": "contact", "contact": a.id, "following": true});
+        let follow_b = json!({"type": "contact", "contact": b.id, "following": true});
+        let follow_c = json!({"type": "contact", "contact": c.id, "following": true});
+        let me1 = Message::sign(None, me, follow_a)?;
+        graph.apply(&me1);
+        graph.apply(&Message::sign(Some(&me1), me, follow_b)?);
+        graph.apply(&Message::sign(None, a, follow_c)?);
+
+        let mut state = ReplicationState::open(NoPersistence)?;
+        state.validate_next(Message::sign(None, b, json!({"type": "post"}))?.value)?;
+
+        let config = SchedulerConfig::default().max_per_peer(2);
+        let mut scheduler = Scheduler::new(config);
+        let now = Instant::now();
+        assert!(scheduler
+            .next_requests(&me.id, &graph, &state, now)
+            .is_empty());
+
+        scheduler.peer_connected(&a.id);
+        let requests = scheduler.next_requests(&me.id, &graph, &state, now);
+        let feeds: Vec<_> = requests.iter().map(|r| r.feed.as_str()).collect();
+        // nearest first, and no more than two streams with `a`
+        let mut hop1 = vec![a.id.as_str(), b.id.as_str()];
+        hop1.sort();
+        assert_eq!(feeds, hop1);
+        assert!(requests.iter().all(|r| r.peer == a.id));
+        let b_request = requests.iter().find(|r| r.feed == b.id).unwrap();
+        assert_eq!(b_request.args.seq, Some(2));
+
+        scheduler.peer_connected(&c.id);
+        let requests = scheduler.next_requests(&me.id, &graph, &state, now);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            (requests[0].feed.as_str(), requests[0].peer.as_str()),
+            (c.id.as_str(), c.id.as_str())
+        );
+
+        // the streams with `a` are moved to `c`, `b` after its backoff
+        let mut lost = scheduler.peer_disconnected(&a.id);
+        lost.sort();
+        assert_eq!(lost, hop1);
+        scheduler.failed(&b.id, now);
+        let requests = scheduler.next_requests(&me.id, &graph, &state, now);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].feed, a.id);
+        assert_eq!(scheduler.in_flight(&a.id), Some(c.id.as_str()));
+
+        let later = now + Duration::from_secs(6);
+        let requests = scheduler.next_requests(&me.id, &graph, &state, later);
+        assert!(requests.is_empty());
+        scheduler.completed(&c.id);
+        let requests = scheduler.next_requests(&me.id, &graph, &state, later);
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].feed, b.id);
+        Ok(())
+    }
+}
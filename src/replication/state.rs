@@ -0,0 +1,168 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use futures::io::AsyncWrite;
+use serde_json::Value;
+
+use super::error::Result;
+use crate::{
+    api::{dto::CreateHistoryStreamIn, ApiCaller},
+    feed::{validate, FeedState, Message},
+    rpc::RequestNo,
+    uri::normalize_id,
+};
+
+/// Where the cursors of a `ReplicationState` are kept.
+pub trait CursorPersistence {
+    fn load(&mut self) -> Result<Vec<FeedState>>;
+    fn save(&mut self, cursor: &FeedState) -> Result<()>;
+}
+
+/// Keeps nothing, cursors are lost with the `ReplicationState`.
+#[derive(Debug, Default)]
+pub struct NoPersistence;
+
+impl CursorPersistence for NoPersistence {
+    fn load(&mut self) -> Result<Vec<FeedState>> {
+        Ok(Vec::new())
+    }
+    fn save(&mut self, _cursor: &FeedState) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps the cursors as a json object in a file, rewritten on every save.
+#[derive(Debug)]
+pub struct JsonFilePersistence {
+    path: PathBuf,
+    cursors: HashMap<String, FeedState>,
+}
+
+impl JsonFilePersistence {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        JsonFilePersistence {
+            path: path.into(),
+            cursors: HashMap::new(),
+        }
+    }
+}
+
+impl CursorPersistence for JsonFilePersistence {
+    fn load(&mut self) -> Result<Vec<FeedState>> {
+        if self.path.exists() {
+            self.cursors = serde_json::from_reader(BufReader::new(File::open(&self.path)?))?;
+        }
+        Ok(self.cursors.values().cloned().collect())
+    }
+    fn save(&mut self, cursor: &FeedState) -> Result<()> {
+        self.cursors.insert(cursor.author.clone(), cursor.clone());
+        let tmp = self.path.with_extension("tmp");
+        serde_json::to_writer(BufWriter::new(File::create(&tmp)?), &self.cursors)?;
+        std::fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// The last validated message of every replicated feed, to validate the
+/// next ones and resume replication where it stopped.
+pub struct ReplicationState<P> {
+    persistence: P,
+    cursors: HashMap<String, FeedState>,
+}
+
+impl<P: CursorPersistence> ReplicationState<P> {
+    pub fn open(mut persistence: P) -> Result<Self> {
+        let cursors = persistence
+            .load()?
+            .into_iter()
+            .map(|cursor| (cursor.author.clone(), cursor))
+            .collect();
+        Ok(ReplicationState {
+            persistence,
+            cursors,
+        })
+    }
+
+    pub fn cursor(&self, feed: &str) -> Option<&FeedState> {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        self.cursors.get(feed)
+    }
+
+    pub fn feeds(&self) -> impl Iterator<Item = &str> {
+        self.cursors.keys().map(String::as_str)
+    }
+
+    /// Arguments of the createHistoryStream that continues `feed` after its
+    /// last validated message.
+    pub fn history_stream_args(&self, feed: &str) -> CreateHistoryStreamIn {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        let next = self.cursor(feed).map_or(1, |cursor| cursor.sequence + 1);
+        CreateHistoryStreamIn::new(feed.to_string()).after_seq(next)
+    }
+
+    /// Sends the createHistoryStream resuming `feed`.
+    pub async fn resume<W: AsyncWrite + Unpin>(
+        &self,
+        api: &mut ApiCaller<W>,
+        feed: &str,
+        live: bool,
+    ) -> Result<RequestNo> {
+        let args = self.history_stream_args(feed).live(live);
+        Ok(api.create_history_stream_req_send(&args).await?)
+    }
+
+    /// Validates `value` as the next message of its feed and moves the
+    /// cursor of the feed to it.
+    pub fn validate_next(&mut self, value: Value) -> Result<Message> {
+        let author = value.get("author").and_then(Value::as_str).unwrap_or("");
+        let msg = validate(self.cursors.get(author), value)?;
+        self.advance(FeedState::from_message(&msg))?;
+        Ok(msg)
+    }
+
+    /// Moves the cursor of `cursor.author`, e.g. to a message validated
+    /// elsewhere.
+    pub fn advance(&mut self, cursor: FeedState) -> Result<()> {
+        self.persistence.save(&cursor)?;
+        self.cursors.insert(cursor.author.clone(), cursor);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keystore::OwnedIdentity;
+    use serde_json::json;
+
+    #[test]
+    fn test_replication_state() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("kuska-cursors-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let id = OwnedIdentity::create();
+        let msg1 = Message::sign(None, &id, json!({"type": "post"}))?;
+        let msg2 = Message::sign(Some(&msg1), &id, json!({"type": "post"}))?;
+        {
+            let mut state = ReplicationState::open(JsonFilePersistence::new(&path))?;
+            assert_eq!(state.history_stream_args(&id.id).seq, Some(1));
+            state.validate_next(msg1.value.clone())?;
+            assert!(state.validate_next(msg1.value).is_err());
+        }
+
+        let mut state = ReplicationState::open(JsonFilePersistence::new(&path))?;
+        assert_eq!(state.cursor(&id.id).unwrap().sequence, 1);
+        assert_eq!(state.history_stream_args(&id.id).seq, Some(2));
+        state.validate_next(msg2.value)?;
+        assert_eq!(state.history_stream_args(&id.id).seq, Some(3));
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,156 @@
+//! Wire capture of muxrpc frames, used to diagnose interop problems with
+//! other implementations.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::stream::{BodyType, Header};
+
+/// Direction of a captured frame, relative to this peer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// Receives every frame that goes through a `RpcReader` or `RpcWriter`
+/// with capture enabled.
+pub trait FrameSink: Send {
+    fn frame(&mut self, direction: Direction, header: &Header, body: &[u8]);
+}
+
+impl<F> FrameSink for F
+where
+    F: FnMut(Direction, &Header, &[u8]) + Send,
+{
+    fn frame(&mut self, direction: Direction, header: &Header, body: &[u8]) {
+        self(direction, header, body)
+    }
+}
+
+/// Writes frames to a file in a simple pcap-like record format:
+///
+/// `direction (1 byte) | unix time in ms (8 bytes, be) | rpc header (9 bytes) | body`
+///
+/// The body length is the one found in the header, so records can be read
+/// back sequentially.
+pub struct CaptureFile<W: std::io::Write + Send> {
+    writer: W,
+}
+
+impl<W: std::io::Write + Send> CaptureFile<W> {
+    pub fn new(writer: W) -> Self {
+        CaptureFile { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: std::io::Write + Send> FrameSink for CaptureFile<W> {
+    fn frame(&mut self, direction: Direction, header: &Header, body: &[u8]) {
+        let direction = match direction {
+            Direction::Inbound => 0u8,
+            Direction::Outbound => 1u8,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let header = Header {
+            body_len: body.len() as u32,
+            ..*header
+        };
+
+        let result = self
+            .writer
+            .write_all(&[direction])
+            .and_then(|_| self.writer.write_all(&now.to_be_bytes()))
+            .and_then(|_| self.writer.write_all(&header.to_array()))
+            .and_then(|_| self.writer.write_all(body))
+            .and_then(|_| self.writer.flush());
+        if let Err(err) = result {
+            log::warn!(target: "ssb-rpc", "cannot write capture: {}", err);
+        }
+    }
+}
+
+/// Capture configuration attached to a reader or writer.
+pub struct Capture {
+    sink: Box<dyn FrameSink>,
+    redact_binary: bool,
+}
+
+impl Capture {
+    pub fn new<S: FrameSink + 'static>(sink: S) -> Self {
+        Capture {
+            sink: Box::new(sink),
+            redact_binary: false,
+        }
+    }
+
+    /// Replace binary bodies (e.g. blobs) with an empty body in the capture.
+    pub fn redact_binary(self, redact_binary: bool) -> Self {
+        Capture {
+            redact_binary,
+            ..self
+        }
+    }
+
+    pub(crate) fn record(&mut self, direction: Direction, header: &Header, body: &[u8]) {
+        if self.redact_binary && header.body_type == BodyType::Binary {
+            self.sink.frame(direction, header, &[]);
+        } else {
+            self.sink.frame(direction, header, body);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_capture_redact_binary() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let sink_frames = frames.clone();
+        let mut capture = Capture::new(move |direction: Direction, _: &Header, body: &[u8]| {
+            sink_frames.lock().unwrap().push((direction, body.to_vec()));
+        })
+        .redact_binary(true);
+
+        let mut header = Header {
+            req_no: 1,
+            is_stream: true,
+            is_end_or_error: false,
+            body_type: BodyType::Binary,
+            body_len: 3,
+        };
+        capture.record(Direction::Outbound, &header, &[1, 2, 3]);
+        header.body_type = BodyType::UTF8;
+        capture.record(Direction::Inbound, &header, b"abc");
+
+        let frames = frames.lock().unwrap();
+        assert_eq!(frames[0], (Direction::Outbound, vec![]));
+        assert_eq!(frames[1], (Direction::Inbound, b"abc".to_vec()));
+    }
+
+    #[test]
+    fn test_capture_file_format() {
+        let mut file = CaptureFile::new(Vec::new());
+        let header = Header {
+            req_no: -2,
+            is_stream: false,
+            is_end_or_error: false,
+            body_type: BodyType::JSON,
+            body_len: 4,
+        };
+        file.frame(Direction::Inbound, &header, b"true");
+        let bytes = file.into_inner();
+        assert_eq!(bytes.len(), 1 + 8 + 9 + 4);
+        assert_eq!(bytes[0], 0);
+        assert_eq!(Header::from_slice(&bytes[9..18]).unwrap(), header);
+        assert_eq!(&bytes[18..], b"true");
+    }
+}
@@ -6,8 +6,16 @@ pub enum Error {
     HeaderSizeTooSmall,
     #[error("invalid body type: {0}")]
     InvalidBodyType(u8),
+    #[error("request number {0} is already in flight")]
+    DuplicateRequestNo(i32),
+    #[error("remote error: {0}")]
+    Remote(String),
+    #[error("body exceeds the {0} bytes limit")]
+    TooLarge(usize),
+    #[error("connection is closing")]
+    Closing,
     #[error("i/o")]
-    Io(#[from] async_std::io::Error),
+    Io(#[from] std::io::Error),
     #[error("json decoding")]
     Json(#[from] serde_json::Error),
 }
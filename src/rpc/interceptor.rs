@@ -0,0 +1,39 @@
+//! Hooks to observe or modify the requests and responses going through a
+//! `RpcReader` or `RpcWriter`.
+
+use super::{
+    error::Result,
+    stream::{Body, RecvMsg, RequestNo, RpcErrorBody},
+};
+
+/// What to do with an incoming request after the interceptors ran.
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    Accept,
+    /// The request is not handed to the application as a `RpcRequest`;
+    /// `RecvMsg::RejectedRequest` is returned instead so the error can be
+    /// sent back.
+    Reject(RpcErrorBody),
+}
+
+/// Cross-cutting concerns (logging, argument rewriting, authorization) that
+/// apply to every call. All methods have a no-op default, implement only
+/// the ones needed.
+///
+/// Interceptors are registered per reader or writer; to share state between
+/// both sides, implement the trait on a shared handle.
+pub trait Interceptor: Send {
+    /// Called before a request is sent by the `RpcWriter`. The body can be
+    /// rewritten, returning an error aborts the send.
+    fn outgoing_request(&mut self, _req_no: RequestNo, _body: &mut Body) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called by the `RpcReader` for each request of the remote peer.
+    fn incoming_request(&mut self, _req_no: RequestNo, _body: &mut Body) -> Verdict {
+        Verdict::Accept
+    }
+
+    /// Called by the `RpcReader` for each response to one of our requests.
+    fn incoming_response(&mut self, _req_no: RequestNo, _msg: &RecvMsg) {}
+}
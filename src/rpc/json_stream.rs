@@ -0,0 +1,82 @@
+//! Incremental parsing of json items arriving in arbitrary chunks, e.g. the
+//! bodies of a history stream, keeping a single buffer for all of them.
+
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+
+use super::error::Result;
+
+pub struct JsonStream<T> {
+    buffer: Vec<u8>,
+    _item: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Default for JsonStream<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: DeserializeOwned> JsonStream<T> {
+    pub fn new() -> Self {
+        JsonStream {
+            buffer: Vec::new(),
+            _item: PhantomData,
+        }
+    }
+
+    /// Add received bytes; items can span several calls.
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Parse the next complete item, or `None` if more bytes are needed.
+    pub fn next_item(&mut self) -> Option<Result<T>> {
+        let mut items = serde_json::Deserializer::from_slice(&self.buffer).into_iter::<T>();
+        let item = match items.next()? {
+            Ok(item) => Ok(item),
+            Err(err) if err.is_eof() => return None,
+            Err(err) => {
+                self.buffer.clear();
+                return Some(Err(err.into()));
+            }
+        };
+        let consumed = items.byte_offset();
+        self.buffer.drain(..consumed);
+        Some(item)
+    }
+
+    /// Parse all the complete items, leaving the rest in the buffer.
+    pub fn drain_items(&mut self) -> impl Iterator<Item = Result<T>> + '_ {
+        std::iter::from_fn(move || self.next_item())
+    }
+
+    /// Bytes received but not parsed yet.
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn test_json_stream() -> Result<()> {
+        let mut stream = JsonStream::<Value>::new();
+        stream.push(br#"{"seq":1}{"se"#);
+        assert_eq!(stream.next_item().unwrap()?, json!({"seq": 1}));
+        assert!(stream.next_item().is_none());
+
+        stream.push(br#"q":2} {"seq":3}"#);
+        let items = stream.drain_items().collect::<Result<Vec<_>>>()?;
+        assert_eq!(items, vec![json!({"seq": 2}), json!({"seq": 3})]);
+        assert_eq!(stream.pending(), 0);
+
+        stream.push(b"}");
+        assert!(stream.next_item().unwrap().is_err());
+        Ok(())
+    }
+}
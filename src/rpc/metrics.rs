@@ -0,0 +1,139 @@
+//! Per-connection counters, e.g. to be exported to a Prometheus dashboard.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use super::tracker::RequestTracker;
+
+#[derive(Default)]
+struct Counters {
+    requests_sent: AtomicU64,
+    requests_received: AtomicU64,
+    errors_sent: AtomicU64,
+    errors_received: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+/// Counters updated by the `RpcReader` and `RpcWriter` they are given to.
+/// Clones share the same counters, so give the same value to both sides of
+/// the connection and keep one to read them.
+#[derive(Clone, Default)]
+pub struct RpcMetrics {
+    counters: Arc<Counters>,
+    tracker: Option<RequestTracker>,
+}
+
+/// The values of a `RpcMetrics` at some point in time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub requests_sent: u64,
+    pub requests_received: u64,
+    /// Error responses sent to the remote.
+    pub errors_sent: u64,
+    /// Error responses received for our requests.
+    pub errors_received: u64,
+    /// Bytes of muxrpc frames (header and body) received.
+    pub bytes_in: u64,
+    /// Bytes of muxrpc frames (header and body) sent.
+    pub bytes_out: u64,
+    /// Source and duplex requests open in both directions; only known
+    /// with `with_tracker`.
+    pub active_streams: Option<usize>,
+}
+
+impl RpcMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report the active streams of the connection using `tracker`.
+    pub fn with_tracker(self, tracker: RequestTracker) -> Self {
+        Self {
+            tracker: Some(tracker),
+            ..self
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let get = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        MetricsSnapshot {
+            requests_sent: get(&self.counters.requests_sent),
+            requests_received: get(&self.counters.requests_received),
+            errors_sent: get(&self.counters.errors_sent),
+            errors_received: get(&self.counters.errors_received),
+            bytes_in: get(&self.counters.bytes_in),
+            bytes_out: get(&self.counters.bytes_out),
+            active_streams: self
+                .tracker
+                .as_ref()
+                .map(|tracker| tracker.outgoing_streams_len() + tracker.incoming_streams_len()),
+        }
+    }
+
+    pub(crate) fn request_sent(&self) {
+        self.counters.requests_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn request_received(&self) {
+        self.counters
+            .requests_received
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn error_sent(&self) {
+        self.counters.errors_sent.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn error_received(&self) {
+        self.counters
+            .errors_received
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn frame_received(&self, len: usize) {
+        self.counters
+            .bytes_in
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn frame_sent(&self, len: usize) {
+        self.counters
+            .bytes_out
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let tracker = RequestTracker::new();
+        let metrics = RpcMetrics::new().with_tracker(tracker.clone());
+        let reader_side = metrics.clone();
+        reader_side.request_received();
+        reader_side.frame_received(20);
+        metrics.request_sent();
+        metrics.frame_sent(9);
+        metrics.frame_sent(11);
+        tracker.next_outgoing(0, true);
+        tracker.incoming_started(1, true).unwrap();
+
+        assert_eq!(
+            metrics.snapshot(),
+            MetricsSnapshot {
+                requests_sent: 1,
+                requests_received: 1,
+                errors_sent: 0,
+                errors_received: 0,
+                bytes_in: 20,
+                bytes_out: 20,
+                active_streams: Some(2),
+            }
+        );
+    }
+}
@@ -1,5 +1,25 @@
+mod capture;
 mod error;
+mod interceptor;
+mod json_stream;
+mod metrics;
+mod ratelimit;
+mod reassembly;
+mod shared;
 mod stream;
+pub mod testvectors;
+mod tracker;
 
+pub use capture::{Capture, CaptureFile, Direction, FrameSink};
 pub use error::{Error, Result};
-pub use stream::{ArgType, Body, BodyType, RecvMsg, RequestNo, RpcReader, RpcType, RpcWriter};
+pub use interceptor::{Interceptor, Verdict};
+pub use json_stream::JsonStream;
+pub use metrics::{MetricsSnapshot, RpcMetrics};
+pub use ratelimit::RateLimit;
+pub use reassembly::Reassembler;
+pub use shared::SharedRpcWriter;
+pub use stream::{
+    ArgType, Body, BodyType, Header, RecvMsg, RequestNo, RpcErrorBody, RpcReader, RpcType,
+    RpcWriter,
+};
+pub use tracker::{RequestTracker, SourceGuard};
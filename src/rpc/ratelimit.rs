@@ -0,0 +1,169 @@
+//! Per-connection limits on what the remote peer may ask us to do.
+
+use std::time::{Duration, Instant};
+
+use super::stream::RpcErrorBody;
+
+/// Limits applied by a `RpcReader` to the remote peer. Unset limits are
+/// not enforced.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimit {
+    /// Incoming requests accepted per second, bursts up to the same amount.
+    pub requests_per_sec: Option<u32>,
+    /// Source and duplex requests of the remote that can be open at the same
+    /// time. Needs a `RequestTracker` on the reader.
+    pub max_concurrent_streams: Option<usize>,
+    /// Incoming bytes per second; reading is delayed when exceeded.
+    pub bytes_per_sec: Option<u64>,
+}
+
+impl RateLimit {
+    pub fn requests_per_sec(self, requests_per_sec: u32) -> Self {
+        Self {
+            requests_per_sec: Some(requests_per_sec),
+            ..self
+        }
+    }
+    pub fn max_concurrent_streams(self, max_concurrent_streams: usize) -> Self {
+        Self {
+            max_concurrent_streams: Some(max_concurrent_streams),
+            ..self
+        }
+    }
+    pub fn bytes_per_sec(self, bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: Some(bytes_per_sec),
+            ..self
+        }
+    }
+}
+
+/// Token bucket refilled continuously at `rate` tokens per second.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+    }
+
+    /// Take one token if available.
+    fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Take `amount` tokens, going into debt if needed; returns how long to
+    /// wait until the debt is paid.
+    fn take_with_delay(&mut self, amount: u64, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        self.tokens -= amount as f64;
+        if self.tokens >= 0.0 || self.rate <= 0.0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(-self.tokens / self.rate))
+        }
+    }
+}
+
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    requests: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit, now: Instant) -> Self {
+        RateLimiter {
+            requests: limit
+                .requests_per_sec
+                .map(|rate| TokenBucket::new(rate as u64, now)),
+            bytes: limit.bytes_per_sec.map(|rate| TokenBucket::new(rate, now)),
+            limit,
+        }
+    }
+
+    /// Account for a received frame, returning how long to pause reading.
+    pub(crate) fn frame_received(&mut self, len: usize, now: Instant) -> Option<Duration> {
+        self.bytes
+            .as_mut()
+            .and_then(|bucket| bucket.take_with_delay(len as u64, now))
+    }
+
+    /// Check if a new request of the remote can be served, given the number
+    /// of its streams already open (including this one).
+    pub(crate) fn check_request(
+        &mut self,
+        open_streams: Option<usize>,
+        now: Instant,
+    ) -> Option<RpcErrorBody> {
+        if let (Some(max), Some(open)) = (self.limit.max_concurrent_streams, open_streams) {
+            if open > max {
+                return Some(RpcErrorBody::new("too many concurrent streams"));
+            }
+        }
+        if let Some(bucket) = &mut self.requests {
+            if !bucket.try_take(now) {
+                return Some(RpcErrorBody::new("request rate limit exceeded"));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_request_rate() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimit::default().requests_per_sec(2), now);
+        assert!(limiter.check_request(None, now).is_none());
+        assert!(limiter.check_request(None, now).is_none());
+        assert!(limiter.check_request(None, now).is_some());
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check_request(None, later).is_none());
+        assert!(limiter.check_request(None, later).is_some());
+    }
+
+    #[test]
+    fn test_concurrent_streams() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimit::default().max_concurrent_streams(1), now);
+        assert!(limiter.check_request(Some(1), now).is_none());
+        assert!(limiter.check_request(Some(2), now).is_some());
+        assert!(limiter.check_request(None, now).is_none());
+    }
+
+    #[test]
+    fn test_bytes_delay() {
+        let now = Instant::now();
+        let mut limiter = RateLimiter::new(RateLimit::default().bytes_per_sec(1000), now);
+        assert_eq!(limiter.frame_received(1000, now), None);
+        assert_eq!(
+            limiter.frame_received(500, now),
+            Some(Duration::from_millis(500))
+        );
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.frame_received(0, later), None);
+    }
+}
@@ -0,0 +1,70 @@
+//! Reassembly of binary bodies that were split in several frames, like the
+//! ones sent by `ApiCaller::blobs_get_res_send`.
+
+use async_std::io::Cursor;
+
+use super::error::{Error, Result};
+
+/// Concatenates the binary frames of one source response into a contiguous
+/// buffer, failing as soon as more than `max_len` bytes are received.
+#[derive(Debug)]
+pub struct Reassembler {
+    max_len: usize,
+    buffer: Vec<u8>,
+}
+
+impl Reassembler {
+    pub fn new(max_len: usize) -> Self {
+        Reassembler {
+            max_len,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Appends the body of the next frame.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<()> {
+        if self.buffer.len() + chunk.len() > self.max_len {
+            return Err(Error::TooLarge(self.max_len));
+        }
+        self.buffer.extend_from_slice(chunk);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    /// The reassembled body as an `AsyncRead`.
+    pub fn into_reader(self) -> Cursor<Vec<u8>> {
+        Cursor::new(self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::io::ReadExt;
+
+    #[async_std::test]
+    async fn test_reassemble() -> Result<()> {
+        let mut reassembler = Reassembler::new(6);
+        reassembler.push(b"abc")?;
+        reassembler.push(b"")?;
+        reassembler.push(b"def")?;
+        assert!(matches!(reassembler.push(b"g"), Err(Error::TooLarge(6))));
+        assert_eq!(reassembler.len(), 6);
+
+        let mut out = String::new();
+        reassembler.into_reader().read_to_string(&mut out).await?;
+        assert_eq!(out, "abcdef");
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use async_std::{
+    sync::{Arc, Mutex, MutexGuard},
+    task,
+};
+use futures::io::AsyncWrite;
+use std::time::Duration;
+
+use super::{
+    error::Result,
+    stream::{ArgType, BodyType, RequestNo, RpcErrorBody, RpcType, RpcWriter},
+};
+
+/// A `RpcWriter` that can be cloned and used from several tasks at once.
+///
+/// Every frame is written while holding a lock, so frames from different
+/// tasks never interleave.
+pub struct SharedRpcWriter<W: AsyncWrite + Unpin> {
+    inner: Arc<Mutex<RpcWriter<W>>>,
+}
+
+impl<W: AsyncWrite + Unpin> Clone for SharedRpcWriter<W> {
+    fn clone(&self) -> Self {
+        SharedRpcWriter {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<W: AsyncWrite + Unpin> From<RpcWriter<W>> for SharedRpcWriter<W> {
+    fn from(writer: RpcWriter<W>) -> Self {
+        Self::new(writer)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> SharedRpcWriter<W> {
+    pub fn new(writer: RpcWriter<W>) -> Self {
+        SharedRpcWriter {
+            inner: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Sends a keepalive every `interval` in a background task, see
+    /// `RpcWriter::keepalive`. The task stops once the writer is closed or
+    /// every other clone of it has been dropped.
+    pub fn spawn_keepalive(&self, interval: Duration) -> task::JoinHandle<()>
+    where
+        W: Send + 'static,
+    {
+        let writer = self.clone();
+        task::spawn(async move {
+            while Arc::strong_count(&writer.inner) > 1 {
+                task::sleep(interval).await;
+                if writer.lock().await.keepalive().await.is_err() {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// Get exclusive access to the writer, e.g. to send several frames
+    /// without other tasks writing in between.
+    pub async fn lock(&self) -> MutexGuard<'_, RpcWriter<W>> {
+        self.inner.lock().await
+    }
+
+    pub async fn send_request<T: serde::Serialize, U: serde::Serialize>(
+        &self,
+        name: &[&str],
+        rpc_type: RpcType,
+        arg_type: ArgType,
+        args: &T,
+        opts: &Option<U>,
+    ) -> Result<RequestNo> {
+        self.lock()
+            .await
+            .send_request(name, rpc_type, arg_type, args, opts)
+            .await
+    }
+
+    pub async fn send_response(
+        &self,
+        req_no: RequestNo,
+        rpc_type: RpcType,
+        body_type: BodyType,
+        body: &[u8],
+    ) -> Result<()> {
+        self.lock()
+            .await
+            .send_response(req_no, rpc_type, body_type, body)
+            .await
+    }
+
+    pub async fn send_error(
+        &self,
+        req_no: RequestNo,
+        rpc_type: RpcType,
+        message: &str,
+    ) -> Result<()> {
+        self.lock()
+            .await
+            .send_error(req_no, rpc_type, message)
+            .await
+    }
+
+    pub async fn send_error_body(
+        &self,
+        req_no: RequestNo,
+        rpc_type: RpcType,
+        error: &RpcErrorBody,
+    ) -> Result<()> {
+        self.lock()
+            .await
+            .send_error_body(req_no, rpc_type, error)
+            .await
+    }
+
+    pub async fn send_stream_eof(&self, req_no: RequestNo) -> Result<()> {
+        self.lock().await.send_stream_eof(req_no).await
+    }
+
+    pub async fn send_stream_end(&self, req_no: RequestNo) -> Result<()> {
+        self.lock().await.send_stream_end(req_no).await
+    }
+
+    pub async fn flush(&self) -> Result<()> {
+        self.lock().await.flush().await
+    }
+
+    pub async fn shutdown(&self, timeout: Duration) -> Result<()> {
+        self.lock().await.shutdown(timeout).await
+    }
+
+    pub async fn close(&self) -> Result<()> {
+        self.lock().await.close().await
+    }
+}
@@ -1,14 +1,24 @@
-use super::error::{Error, Result};
-
-use async_std::{io, prelude::*};
+use super::{
+    capture::{Capture, Direction},
+    error::{Error, Result},
+    interceptor::{Interceptor, Verdict},
+    metrics::RpcMetrics,
+    ratelimit::{RateLimit, RateLimiter},
+    reassembly::Reassembler,
+    tracker::{next_req_no, RequestTracker},
+};
+
+use async_std::{prelude::*, task};
+use futures::io::{AsyncRead, AsyncWrite};
 use log::{trace, warn};
+use std::time::{Duration, Instant};
 
+use crate::connection::stream::{BoxStreamReader, BoxStreamWriter};
 use async_stream::stream;
-use kuska_handshake::async_std::{BoxStreamRead, BoxStreamWrite};
 
 pub type RequestNo = i32;
 
-const HEADER_SIZE: usize = 9;
+pub(crate) const HEADER_SIZE: usize = 9;
 
 const RPC_HEADER_STREAM_FLAG: u8 = 1 << 3;
 const RPC_HEADER_END_OR_ERROR_FLAG: u8 = 1 << 2;
@@ -28,7 +38,7 @@ pub enum BodyType {
     JSON,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Body {
     pub name: Vec<String>,
     #[serde(rename = "type")]
@@ -60,9 +70,20 @@ pub enum RpcType {
     Source,
     #[serde(rename = "duplex")]
     Duplex,
+    /// Used by some plugins (e.g. `manifest`); over the wire it behaves
+    /// like `Async`, a single response with no stream flag.
+    #[serde(rename = "sync")]
+    Sync,
+}
+
+impl RpcType {
+    /// Whether frames of this call type carry the stream flag.
+    pub fn is_stream(&self) -> bool {
+        matches!(self, RpcType::Source | RpcType::Duplex)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Header {
     pub req_no: RequestNo,
     pub is_stream: bool,
@@ -71,11 +92,42 @@ pub struct Header {
     pub body_len: u32,
 }
 
-#[derive(Serialize, Deserialize)]
-struct ErrorMessage<'a> {
-    name: &'a str,
-    stack: &'a str,
-    message: &'a str,
+/// Body of a muxrpc error response, shaped like a serialized js `Error`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RpcErrorBody {
+    #[serde(default = "RpcErrorBody::default_name")]
+    pub name: String,
+    pub message: String,
+    #[serde(default)]
+    pub stack: String,
+}
+
+impl RpcErrorBody {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        RpcErrorBody {
+            name: RpcErrorBody::default_name(),
+            message: message.into(),
+            stack: String::new(),
+        }
+    }
+    pub fn with_name<N: Into<String>, M: Into<String>>(name: N, message: M) -> Self {
+        RpcErrorBody {
+            name: name.into(),
+            ..RpcErrorBody::new(message)
+        }
+    }
+    pub fn stack<S: Into<String>>(self, stack: S) -> Self {
+        RpcErrorBody {
+            stack: stack.into(),
+            ..self
+        }
+    }
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+    fn default_name() -> String {
+        "Error".to_string()
+    }
 }
 
 impl Header {
@@ -140,27 +192,76 @@ impl Header {
     }
 }
 
-pub struct RpcReader<R: io::Read + Unpin> {
-    box_reader: BoxStreamRead<R>,
+pub struct RpcReader<R: AsyncRead + Unpin> {
+    box_reader: BoxStreamReader<R>,
+    capture: Option<Capture>,
+    tracker: Option<RequestTracker>,
+    interceptors: Vec<Box<dyn Interceptor>>,
+    rate_limiter: Option<RateLimiter>,
+    metrics: Option<RpcMetrics>,
 }
 
-pub struct RpcWriter<W: io::Write + Unpin> {
-    box_writer: BoxStreamWrite<W>,
+pub struct RpcWriter<W: AsyncWrite + Unpin> {
+    box_writer: BoxStreamWriter<W>,
     req_no: RequestNo,
+    capture: Option<Capture>,
+    tracker: Option<RequestTracker>,
+    interceptors: Vec<Box<dyn Interceptor>>,
+    metrics: Option<RpcMetrics>,
+    corked: bool,
+    closing: bool,
 }
 
 #[derive(Debug)]
 pub enum RecvMsg {
     RpcRequest(Body),
     RpcResponse(BodyType, Vec<u8>),
+    /// Anything else from the remote, like the end of one of its streams.
     OtherRequest(BodyType, Vec<u8>),
     ErrorResponse(String),
     CancelStreamRespose(),
+    /// A request refused by an interceptor; the error should be sent back.
+    RejectedRequest(Body, RpcErrorBody),
 }
 
-impl<R: io::Read + Unpin> RpcReader<R> {
-    pub fn new(box_reader: BoxStreamRead<R>) -> RpcReader<R> {
-        RpcReader { box_reader }
+impl<R: AsyncRead + Unpin> RpcReader<R> {
+    pub fn new(box_reader: BoxStreamReader<R>) -> RpcReader<R> {
+        RpcReader {
+            box_reader,
+            capture: None,
+            tracker: None,
+            interceptors: Vec::new(),
+            rate_limiter: None,
+            metrics: None,
+        }
+    }
+
+    /// Record every received frame into `capture`.
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    /// Track in-flight request numbers; must be the same tracker given to
+    /// the `RpcWriter` of this connection.
+    pub fn set_tracker(&mut self, tracker: RequestTracker) {
+        self.tracker = Some(tracker);
+    }
+
+    /// Limit what the remote peer can ask for; requests over the limits are
+    /// returned as `RecvMsg::RejectedRequest`.
+    pub fn set_rate_limit(&mut self, limit: RateLimit) {
+        self.rate_limiter = Some(RateLimiter::new(limit, Instant::now()));
+    }
+
+    /// Count received requests, errors and bytes into `metrics`.
+    pub fn set_metrics(&mut self, metrics: RpcMetrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Run `interceptor` on every incoming request and response, after the
+    /// ones already added.
+    pub fn add_interceptor<I: Interceptor + 'static>(&mut self, interceptor: I) {
+        self.interceptors.push(Box::new(interceptor));
     }
 
     pub async fn recv(&mut self) -> Result<(RequestNo, RecvMsg)> {
@@ -176,32 +277,88 @@ impl<R: io::Read + Unpin> RpcReader<R> {
             String::from_utf8_lossy(&body_raw[..])
         );
 
-        if rpc_header.req_no > 0 {
-            match serde_json::from_slice(&body_raw) {
-                Ok(rpc_body) => Ok((rpc_header.req_no, RecvMsg::RpcRequest(rpc_body))),
-                Err(_) => Ok((
-                    rpc_header.req_no,
-                    RecvMsg::OtherRequest(rpc_header.body_type, body_raw),
-                )),
+        if let Some(capture) = &mut self.capture {
+            capture.record(Direction::Inbound, &rpc_header, &body_raw);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.frame_received(HEADER_SIZE + body_raw.len());
+        }
+
+        if let Some(rate_limiter) = &mut self.rate_limiter {
+            let len = HEADER_SIZE + body_raw.len();
+            if let Some(delay) = rate_limiter.frame_received(len, Instant::now()) {
+                task::sleep(delay).await;
             }
-        } else if rpc_header.is_end_or_error {
-            if rpc_header.is_stream {
-                Ok((-rpc_header.req_no, RecvMsg::CancelStreamRespose()))
-            } else {
-                let err: ErrorMessage = serde_json::from_slice(&body_raw)?;
-                Ok((
-                    -rpc_header.req_no,
-                    RecvMsg::ErrorResponse(err.message.to_string()),
-                ))
+        }
+
+        if let Some(tracker) = &self.tracker {
+            if rpc_header.req_no > 0 && rpc_header.is_stream && rpc_header.is_end_or_error {
+                tracker.incoming_end_received(rpc_header.req_no);
+            } else if rpc_header.req_no < 0 {
+                if !rpc_header.is_stream {
+                    tracker.outgoing_finished(-rpc_header.req_no);
+                } else if rpc_header.is_end_or_error {
+                    tracker.outgoing_end_received(-rpc_header.req_no);
+                } else {
+                    tracker.outgoing_response(-rpc_header.req_no);
+                }
             }
+        }
+
+        if rpc_header.req_no > 0 {
+            let msg = match serde_json::from_slice::<Body>(&body_raw) {
+                Ok(rpc_body) if !rpc_header.is_end_or_error => {
+                    if let Some(tracker) = &self.tracker {
+                        tracker.incoming_started(rpc_header.req_no, rpc_header.is_stream)?;
+                    }
+                    if let Some(metrics) = &self.metrics {
+                        metrics.request_received();
+                    }
+                    self.accept_request(rpc_header.req_no, rpc_body)
+                }
+                _ => RecvMsg::OtherRequest(rpc_header.body_type, body_raw),
+            };
+            Ok((rpc_header.req_no, msg))
         } else {
-            Ok((
-                -rpc_header.req_no,
-                RecvMsg::RpcResponse(rpc_header.body_type, body_raw),
-            ))
+            let msg = if rpc_header.is_end_or_error {
+                if rpc_header.is_stream {
+                    RecvMsg::CancelStreamRespose()
+                } else {
+                    let err: RpcErrorBody = serde_json::from_slice(&body_raw)?;
+                    if let Some(metrics) = &self.metrics {
+                        metrics.error_received();
+                    }
+                    RecvMsg::ErrorResponse(err.message)
+                }
+            } else {
+                RecvMsg::RpcResponse(rpc_header.body_type, body_raw)
+            };
+            for interceptor in self.interceptors.iter_mut() {
+                interceptor.incoming_response(-rpc_header.req_no, &msg);
+            }
+            Ok((-rpc_header.req_no, msg))
         }
     }
 
+    fn accept_request(&mut self, req_no: RequestNo, mut body: Body) -> RecvMsg {
+        if let Some(rate_limiter) = &mut self.rate_limiter {
+            let open_streams = self.tracker.as_ref().map(|t| t.incoming_streams_len());
+            if let Some(err) = rate_limiter.check_request(open_streams, Instant::now()) {
+                warn!(target: "ssb-rpc", "rejecting request {}: {}", req_no, err.message);
+                return RecvMsg::RejectedRequest(body, err);
+            }
+        }
+        for interceptor in self.interceptors.iter_mut() {
+            if let Verdict::Reject(err) = interceptor.incoming_request(req_no, &mut body) {
+                return RecvMsg::RejectedRequest(body, err);
+            }
+        }
+        RecvMsg::RpcRequest(body)
+    }
+
+    /// Turn the reader into a stream of received messages, so the standard
+    /// `Stream` combinators can be used instead of a `recv` loop. The stream
+    /// ends on the first error, usually the connection being closed.
     pub fn into_stream(mut self) -> impl Stream<Item = (RequestNo, RecvMsg)> {
         stream! {
             while let Ok(v) = self.recv().await {
@@ -209,14 +366,219 @@ impl<R: io::Read + Unpin> RpcReader<R> {
             }
         }
     }
+
+    /// Read the responses to our source request `req_no` (e.g. a
+    /// `blobs.get`) as a stream of chunks, ending with the source.
+    ///
+    /// The next frame is only read when the stream is polled, so a slow
+    /// consumer applies backpressure to the remote. Frames that belong to
+    /// other requests are handed to `other`.
+    pub fn source_chunks<'a, F>(
+        &'a mut self,
+        req_no: RequestNo,
+        mut other: F,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + 'a
+    where
+        F: FnMut(RequestNo, RecvMsg) + 'a,
+    {
+        stream! {
+            loop {
+                match self.recv().await {
+                    Ok((id, RecvMsg::RpcResponse(_, body))) if id == req_no => yield Ok(body),
+                    Ok((id, RecvMsg::CancelStreamRespose())) if id == req_no => break,
+                    Ok((id, RecvMsg::ErrorResponse(message))) if id == req_no => {
+                        yield Err(Error::Remote(message));
+                        break;
+                    }
+                    Ok((id, msg)) => other(id, msg),
+                    Err(err) => {
+                        yield Err(err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read the whole binary body of our source request `req_no`, failing
+    /// if it is longer than `max_len`. See `source_chunks`.
+    pub async fn source_to_end<F>(
+        &mut self,
+        req_no: RequestNo,
+        max_len: usize,
+        other: F,
+    ) -> Result<Vec<u8>>
+    where
+        F: FnMut(RequestNo, RecvMsg),
+    {
+        let mut reassembler = Reassembler::new(max_len);
+        let chunks = self.source_chunks(req_no, other);
+        futures::pin_mut!(chunks);
+        while let Some(chunk) = chunks.next().await {
+            reassembler.push(&chunk?)?;
+        }
+        Ok(reassembler.into_inner())
+    }
+
+    /// Like `into_stream`, but the error that ended the stream is yielded
+    /// as the last item.
+    pub fn into_try_stream(mut self) -> impl Stream<Item = Result<(RequestNo, RecvMsg)>> {
+        stream! {
+            loop {
+                match self.recv().await {
+                    Ok(v) => yield Ok(v),
+                    Err(err) => {
+                        yield Err(err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
 }
 
-impl<W: io::Write + Unpin> RpcWriter<W> {
-    pub fn new(box_writer: BoxStreamWrite<W>) -> RpcWriter<W> {
+impl<W: AsyncWrite + Unpin> RpcWriter<W> {
+    pub fn new(box_writer: BoxStreamWriter<W>) -> RpcWriter<W> {
         RpcWriter {
             box_writer,
             req_no: 0,
+            capture: None,
+            tracker: None,
+            interceptors: Vec::new(),
+            metrics: None,
+            corked: false,
+            closing: false,
+        }
+    }
+
+    /// Track in-flight request numbers, so numbers still in use are not
+    /// reused after the counter wraps around.
+    pub fn set_tracker(&mut self, tracker: RequestTracker) {
+        self.tracker = Some(tracker);
+    }
+
+    /// Count sent requests, errors and bytes into `metrics`.
+    pub fn set_metrics(&mut self, metrics: RpcMetrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// Run `interceptor` on every outgoing request, after the ones already
+    /// added.
+    pub fn add_interceptor<I: Interceptor + 'static>(&mut self, interceptor: I) {
+        self.interceptors.push(Box::new(interceptor));
+    }
+
+    fn intercept_request(&mut self, req_no: RequestNo, body_str: String) -> Result<String> {
+        if self.interceptors.is_empty() {
+            return Ok(body_str);
         }
+        let mut body: Body = serde_json::from_str(&body_str)?;
+        for interceptor in self.interceptors.iter_mut() {
+            interceptor.outgoing_request(req_no, &mut body)?;
+        }
+        Ok(serde_json::to_string(&body)?)
+    }
+
+    /// Stop flushing after every frame; frames are buffered in the box
+    /// stream until `uncork` or `flush` is called, so bursts of small frames
+    /// are sent in fewer segments.
+    pub fn cork(&mut self) {
+        self.corked = true;
+    }
+
+    /// Go back to flushing every frame, flushing what was buffered.
+    pub async fn uncork(&mut self) -> Result<()> {
+        self.corked = false;
+        self.flush().await
+    }
+
+    pub fn is_corked(&self) -> bool {
+        self.corked
+    }
+
+    /// Send all buffered frames.
+    pub async fn flush(&mut self) -> Result<()> {
+        self.send_pending_ends().await?;
+        self.box_writer.flush().await?;
+        Ok(())
+    }
+
+    /// Send the end packets queued in the tracker, acknowledging sources
+    /// ended by the remote and ending sources the application dropped.
+    /// This is also done before sending any other frame.
+    pub async fn send_pending_ends(&mut self) -> Result<()> {
+        let pending = match &self.tracker {
+            Some(tracker) => tracker.take_pending_ends(),
+            None => return Ok(()),
+        };
+        for req_no in pending {
+            self.send_end(req_no).await?;
+        }
+        Ok(())
+    }
+
+    /// End one of our own source or duplex requests.
+    pub async fn send_stream_end(&mut self, req_no: RequestNo) -> Result<()> {
+        self.send_pending_ends().await?;
+        self.send_end(req_no).await
+    }
+
+    async fn send_end(&mut self, req_no: RequestNo) -> Result<()> {
+        let body_bytes = b"true";
+
+        let rpc_header = Header {
+            req_no,
+            is_stream: true,
+            is_end_or_error: true,
+            body_type: BodyType::JSON,
+            body_len: body_bytes.len() as u32,
+        };
+
+        trace!(target: "ssb-rpc", "send {:?} 'true'", rpc_header);
+
+        self.send_frame(&rpc_header, &body_bytes[..]).await?;
+        if let Some(tracker) = &self.tracker {
+            tracker.outgoing_end_sent(req_no);
+        }
+        Ok(())
+    }
+
+    fn next_req_no(&mut self, is_stream: bool) -> RequestNo {
+        self.req_no = match &self.tracker {
+            Some(tracker) => tracker.next_outgoing(self.req_no, is_stream),
+            None => next_req_no(self.req_no),
+        };
+        self.req_no
+    }
+
+    fn request_finished(&self, req_no: RequestNo) {
+        if let Some(tracker) = &self.tracker {
+            tracker.incoming_finished(req_no);
+        }
+    }
+
+    /// Record every sent frame into `capture`.
+    pub fn set_capture(&mut self, capture: Capture) {
+        self.capture = Some(capture);
+    }
+
+    async fn send_frame(&mut self, rpc_header: &Header, body: &[u8]) -> Result<()> {
+        if let Some(capture) = &mut self.capture {
+            capture.record(Direction::Outbound, rpc_header, body);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.frame_sent(HEADER_SIZE + body.len());
+        }
+
+        // the box stream writer buffers until flushed, so header and body,
+        // and the frames sent while corked, share segments
+        self.box_writer.write_all(&rpc_header.to_array()).await?;
+        self.box_writer.write_all(body).await?;
+        if !self.corked {
+            self.box_writer.flush().await?;
+        }
+
+        Ok(())
     }
 
     pub async fn send_request<T: serde::Serialize, U: serde::Serialize>(
@@ -227,7 +589,17 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
         args: &T,
         opts: &Option<U>,
     ) -> Result<RequestNo> {
-        self.req_no += 1;
+        if self.closing {
+            return Err(Error::Closing);
+        }
+        self.send_pending_ends().await?;
+
+        // sync methods are invoked as async ones by remote callers
+        let rpc_type = match rpc_type {
+            RpcType::Sync => RpcType::Async,
+            _ => rpc_type,
+        };
+        let req_no = self.next_req_no(rpc_type.is_stream());
 
         // the arg_type allows us to package the args in the correct form
         let body_str = match arg_type {
@@ -248,9 +620,19 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
             })?,
         };
 
+        let body_str = match self.intercept_request(req_no, body_str) {
+            Ok(body_str) => body_str,
+            Err(err) => {
+                if let Some(tracker) = &self.tracker {
+                    tracker.outgoing_finished(req_no);
+                }
+                return Err(err);
+            }
+        };
+
         let rpc_header = Header {
-            req_no: self.req_no,
-            is_stream: rpc_type == RpcType::Source,
+            req_no,
+            is_stream: rpc_type.is_stream(),
             is_end_or_error: false,
             body_type: BodyType::JSON,
             body_len: body_str.as_bytes().len() as u32,
@@ -258,13 +640,12 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
 
         trace!(target: "ssb-rpc", "send {:?} '{}'", rpc_header, body_str);
 
-        self.box_writer
-            .write_all(&rpc_header.to_array()[..])
-            .await?;
-        self.box_writer.write_all(body_str.as_bytes()).await?;
-        self.box_writer.flush().await?;
+        self.send_frame(&rpc_header, body_str.as_bytes()).await?;
+        if let Some(metrics) = &self.metrics {
+            metrics.request_sent();
+        }
 
-        Ok(self.req_no)
+        Ok(req_no)
     }
 
     pub async fn send_response(
@@ -274,9 +655,11 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
         body_type: BodyType,
         body: &[u8],
     ) -> Result<()> {
+        self.send_pending_ends().await?;
+
         let rpc_header = Header {
             req_no: -req_no,
-            is_stream: rpc_type == RpcType::Source,
+            is_stream: rpc_type.is_stream(),
             is_end_or_error: false,
             body_type,
             body_len: body.len() as u32,
@@ -288,11 +671,11 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
             String::from_utf8_lossy(body)
         );
 
-        self.box_writer
-            .write_all(&rpc_header.to_array()[..])
-            .await?;
-        self.box_writer.write_all(body).await?;
-        self.box_writer.flush().await?;
+        self.send_frame(&rpc_header, body).await?;
+
+        if !rpc_type.is_stream() {
+            self.request_finished(req_no);
+        }
 
         Ok(())
     }
@@ -303,34 +686,42 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
         rpc_type: RpcType,
         message: &str,
     ) -> Result<()> {
-        let body_bytes = serde_json::to_string(&ErrorMessage {
-            name: "Error",
-            stack: "",
-            message,
-        })?;
+        self.send_error_body(req_no, rpc_type, &RpcErrorBody::new(message))
+            .await
+    }
+
+    pub async fn send_error_body(
+        &mut self,
+        req_no: RequestNo,
+        rpc_type: RpcType,
+        error: &RpcErrorBody,
+    ) -> Result<()> {
+        self.send_pending_ends().await?;
 
-        let is_stream = !matches!(rpc_type, RpcType::Async);
+        let body_bytes = error.to_json()?;
 
         let rpc_header = Header {
             req_no: -req_no,
-            is_stream,
+            is_stream: rpc_type.is_stream(),
             is_end_or_error: true,
-            body_type: BodyType::UTF8,
+            body_type: BodyType::JSON,
             body_len: body_bytes.as_bytes().len() as u32,
         };
 
         trace!(target: "ssb-rpc", "send {:?} '{}'", rpc_header, body_bytes);
 
-        self.box_writer
-            .write_all(&rpc_header.to_array()[..])
-            .await?;
-        self.box_writer.write_all(body_bytes.as_bytes()).await?;
-        self.box_writer.flush().await?;
+        self.send_frame(&rpc_header, body_bytes.as_bytes()).await?;
+        self.request_finished(req_no);
+        if let Some(metrics) = &self.metrics {
+            metrics.error_sent();
+        }
 
         Ok(())
     }
 
     pub async fn send_stream_eof(&mut self, req_no: RequestNo) -> Result<()> {
+        self.send_pending_ends().await?;
+
         let body_bytes = b"true";
 
         let rpc_header = Header {
@@ -347,23 +738,48 @@ impl<W: io::Write + Unpin> RpcWriter<W> {
             String::from_utf8_lossy(body_bytes)
         );
 
-        self.box_writer
-            .write_all(&rpc_header.to_array()[..])
-            .await?;
-        self.box_writer.write_all(&body_bytes[..]).await?;
-        self.box_writer.flush().await?;
+        self.send_frame(&rpc_header, &body_bytes[..]).await?;
+        self.request_finished(req_no);
+        Ok(())
+    }
+
+    /// Close the connection gracefully: new requests are refused, pending
+    /// async requests get up to `timeout` to be answered, our open sources
+    /// are ended and finally the box stream goodbye is sent.
+    ///
+    /// Waiting for responses requires a tracker shared with a reader that
+    /// keeps receiving in another task.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<()> {
+        self.closing = true;
+
+        if let Some(tracker) = self.tracker.clone() {
+            let _ = async_std::future::timeout(timeout, tracker.pending_async_done()).await;
+            for req_no in tracker.open_streams() {
+                self.send_end(req_no).await?;
+            }
+        }
+
+        self.close().await
+    }
+
+    /// Sends an empty box stream segment, which keeps a quiet link and
+    /// the idle timeout of the peer busy. Frames held by `cork` stay
+    /// buffered.
+    pub async fn keepalive(&mut self) -> Result<()> {
+        self.box_writer.keepalive().await?;
         Ok(())
     }
 
     pub async fn close(&mut self) -> Result<()> {
-        self.box_writer.goodbye().await?;
+        self.flush().await?;
+        futures::io::AsyncWriteExt::close(&mut self.box_writer).await?;
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::{BodyType, Header};
+    use super::{BodyType, Header, RpcErrorBody, RpcType};
 
     #[test]
     fn test_header_encoding_1() {
@@ -404,4 +820,27 @@ mod test {
         assert_eq!(h.body_type, BodyType::Binary);
         assert_eq!(h.body_len, 2123);
     }
+
+    #[test]
+    fn test_error_body_encoding() {
+        let err = RpcErrorBody::with_name("TypeError", "no such \"method\"").stack("at x");
+        let json = err.to_json().unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"TypeError","message":"no such \"method\"","stack":"at x"}"#
+        );
+        let decoded: RpcErrorBody = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, err);
+
+        let decoded: RpcErrorBody = serde_json::from_str(r#"{"message":"oops"}"#).unwrap();
+        assert_eq!(decoded, RpcErrorBody::new("oops"));
+    }
+
+    #[test]
+    fn test_rpc_type_sync() {
+        let rpc_type: RpcType = serde_json::from_str(r#""sync""#).unwrap();
+        assert_eq!(rpc_type, RpcType::Sync);
+        assert_eq!(rpc_type.is_stream(), false);
+        assert_eq!(RpcType::Duplex.is_stream(), true);
+    }
 }
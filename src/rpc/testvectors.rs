@@ -0,0 +1,153 @@
+//! Muxrpc frames as written by js muxrpc, to check that other transports
+//! or reimplementations of the framing stay wire compatible.
+//!
+//! Each vector is a whole frame (header and body) with the values it
+//! decodes to; `assert_round_trip` checks both directions.
+
+use super::stream::{Body, BodyType, Header, RequestNo, RpcErrorBody, HEADER_SIZE};
+
+pub struct TestVector {
+    pub name: &'static str,
+    pub frame: &'static [u8],
+    pub req_no: RequestNo,
+    pub is_stream: bool,
+    pub is_end_or_error: bool,
+    pub body_type: BodyType,
+}
+
+impl TestVector {
+    pub fn header(&self) -> Header {
+        Header {
+            req_no: self.req_no,
+            is_stream: self.is_stream,
+            is_end_or_error: self.is_end_or_error,
+            body_type: self.body_type,
+            body_len: self.body().len() as u32,
+        }
+    }
+
+    pub fn body(&self) -> &'static [u8] {
+        &self.frame[HEADER_SIZE..]
+    }
+}
+
+pub const VECTORS: &[TestVector] = &[
+    TestVector {
+        name: "whoami request",
+        frame: b"\x02\x00\x00\x00\x2c\x00\x00\x00\x01\
+            {\"name\":[\"whoami\"],\"type\":\"async\",\"args\":[]}",
+        req_no: 1,
+        is_stream: false,
+        is_end_or_error: false,
+        body_type: BodyType::JSON,
+    },
+    TestVector {
+        name: "whoami response",
+        frame: b"\x02\x00\x00\x00\x3e\xff\xff\xff\xff\
+            {\"id\":\"@FCX/tsDLpubCPKKfIrw4gc+SQkHcaD17s7GI6i/ziWY=.ed25519\"}",
+        req_no: -1,
+        is_stream: false,
+        is_end_or_error: false,
+        body_type: BodyType::JSON,
+    },
+    TestVector {
+        name: "createHistoryStream request",
+        frame: b"\x0a\x00\x00\x00\x9a\x00\x00\x00\x02\
+            {\"name\":[\"createHistoryStream\"],\"type\":\"source\",\"args\":[{\
+            \"id\":\"@FCX/tsDLpubCPKKfIrw4gc+SQkHcaD17s7GI6i/ziWY=.ed25519\",\
+            \"seq\":1,\"live\":false,\"keys\":false}]}",
+        req_no: 2,
+        is_stream: true,
+        is_end_or_error: false,
+        body_type: BodyType::JSON,
+    },
+    TestVector {
+        name: "binary source chunk",
+        frame: b"\x08\x00\x00\x00\x04\xff\xff\xff\xfd\x00\x01\x02\xff",
+        req_no: -3,
+        is_stream: true,
+        is_end_or_error: false,
+        body_type: BodyType::Binary,
+    },
+    TestVector {
+        name: "utf8 async response",
+        frame: b"\x01\x00\x00\x00\x05\xff\xff\xff\xfchello",
+        req_no: -4,
+        is_stream: false,
+        is_end_or_error: false,
+        body_type: BodyType::UTF8,
+    },
+    TestVector {
+        name: "source end by the responder",
+        frame: b"\x0e\x00\x00\x00\x04\xff\xff\xff\xfetrue",
+        req_no: -2,
+        is_stream: true,
+        is_end_or_error: true,
+        body_type: BodyType::JSON,
+    },
+    TestVector {
+        name: "source end by the caller",
+        frame: b"\x0e\x00\x00\x00\x04\x00\x00\x00\x02true",
+        req_no: 2,
+        is_stream: true,
+        is_end_or_error: true,
+        body_type: BodyType::JSON,
+    },
+    TestVector {
+        name: "async error response",
+        frame: b"\x06\x00\x00\x00\x54\xff\xff\xff\xfb\
+            {\"name\":\"Error\",\"message\":\"method:foo is not in list of allowed methods\",\
+            \"stack\":\"\"}",
+        req_no: -5,
+        is_stream: false,
+        is_end_or_error: true,
+        body_type: BodyType::JSON,
+    },
+];
+
+/// Encodes `header` and `body` as a whole frame.
+pub fn encode_frame(header: &Header, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(HEADER_SIZE + body.len());
+    frame.extend_from_slice(&header.to_array()[..]);
+    frame.extend_from_slice(body);
+    frame
+}
+
+/// Panics if `vector` does not decode to its values, or if they do not
+/// encode back to the same bytes. JSON bodies of requests and errors are
+/// also parsed and serialized again.
+pub fn assert_round_trip(vector: &TestVector) {
+    let header = Header::from_slice(vector.frame)
+        .unwrap_or_else(|err| panic!("{}: cannot decode header: {}", vector.name, err));
+    assert_eq!(header, vector.header(), "{}: decoded header", vector.name);
+    assert_eq!(
+        encode_frame(&header, vector.body()),
+        vector.frame,
+        "{}: encoded frame",
+        vector.name
+    );
+
+    let body = vector.body();
+    let reencoded = if vector.req_no > 0 && !vector.is_end_or_error {
+        serde_json::from_slice::<Body>(body).and_then(|body| serde_json::to_vec(&body))
+    } else if vector.is_end_or_error && !vector.is_stream {
+        serde_json::from_slice::<RpcErrorBody>(body).and_then(|error| serde_json::to_vec(&error))
+    } else {
+        return;
+    };
+    let reencoded =
+        reencoded.unwrap_or_else(|err| panic!("{}: cannot decode body: {}", vector.name, err));
+    assert_eq!(reencoded, body, "{}: encoded body", vector.name);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vectors() {
+        for vector in VECTORS {
+            assert_round_trip(vector);
+        }
+    }
+}
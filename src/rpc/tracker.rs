@@ -0,0 +1,400 @@
+//! Bookkeeping of the request numbers that are in flight on a connection.
+
+use async_std::task;
+use futures::channel::oneshot;
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{
+    error::{Error, Result},
+    stream::RequestNo,
+};
+
+#[derive(Default)]
+struct Inner {
+    // our requests, with the time of the request or of its last response
+    outgoing: HashMap<RequestNo, Instant>,
+    // the subset of `outgoing` that are source/duplex requests
+    streams: HashSet<RequestNo>,
+    incoming: HashSet<RequestNo>,
+    // the subset of `incoming` that are source/duplex requests
+    incoming_streams: HashSet<RequestNo>,
+    // our source/duplex requests for which we already sent the end packet
+    end_sent: HashSet<RequestNo>,
+    // our source/duplex requests that the remote ended and we did not ack
+    end_received: HashSet<RequestNo>,
+    // end packets the writer has to send for our own requests
+    pending_ends: Vec<RequestNo>,
+    // woken when the last pending async request finishes
+    async_waiters: Vec<oneshot::Sender<()>>,
+}
+
+impl Inner {
+    fn finish_outgoing(&mut self, req_no: RequestNo) {
+        self.outgoing.remove(&req_no);
+        self.streams.remove(&req_no);
+        self.end_sent.remove(&req_no);
+        self.end_received.remove(&req_no);
+        if self.pending_async_len() == 0 {
+            for waiter in self.async_waiters.drain(..) {
+                let _ = waiter.send(());
+            }
+        }
+    }
+
+    fn pending_async_len(&self) -> usize {
+        self.outgoing.len() - self.streams.len()
+    }
+}
+
+/// Request numbers in flight, shared between the `RpcReader` and the
+/// `RpcWriter` of the same connection.
+///
+/// The writer registers its own requests and the reader releases them when
+/// the final response arrives; the reader registers remote requests and the
+/// writer releases them when the final response is sent.
+#[derive(Clone, Default)]
+pub struct RequestTracker {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl RequestTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the request number that follows `current`, wrapping around
+    /// before overflowing and skipping numbers that are still in flight.
+    pub(crate) fn next_outgoing(&self, current: RequestNo, is_stream: bool) -> RequestNo {
+        let mut inner = self.inner.lock().unwrap();
+        let mut req_no = current;
+        loop {
+            req_no = next_req_no(req_no);
+            if let Entry::Vacant(entry) = inner.outgoing.entry(req_no) {
+                entry.insert(Instant::now());
+                if is_stream {
+                    inner.streams.insert(req_no);
+                }
+                return req_no;
+            }
+        }
+    }
+
+    pub(crate) fn outgoing_finished(&self, req_no: RequestNo) {
+        self.inner.lock().unwrap().finish_outgoing(req_no);
+    }
+
+    /// A response arrived for one of our stream requests that keeps it open.
+    pub(crate) fn outgoing_response(&self, req_no: RequestNo) {
+        if let Some(last) = self.inner.lock().unwrap().outgoing.get_mut(&req_no) {
+            *last = Instant::now();
+        }
+    }
+
+    /// The remote ended one of our stream requests; like js muxrpc, an end
+    /// packet is queued to acknowledge it unless we already sent ours.
+    pub(crate) fn outgoing_end_received(&self, req_no: RequestNo) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.end_sent.remove(&req_no) {
+            inner.finish_outgoing(req_no);
+        } else if inner.outgoing.contains_key(&req_no) && inner.end_received.insert(req_no) {
+            inner.pending_ends.push(req_no);
+        }
+    }
+
+    pub(crate) fn outgoing_end_sent(&self, req_no: RequestNo) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.pending_ends.retain(|pending| *pending != req_no);
+        if inner.end_received.remove(&req_no) {
+            inner.finish_outgoing(req_no);
+        } else if inner.outgoing.contains_key(&req_no) {
+            inner.end_sent.insert(req_no);
+        }
+    }
+
+    /// Queue an end packet for one of our stream requests that the
+    /// application is no longer interested in.
+    pub fn abandon(&self, req_no: RequestNo) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.outgoing.contains_key(&req_no)
+            && !inner.end_sent.contains(&req_no)
+            && !inner.pending_ends.contains(&req_no)
+        {
+            inner.pending_ends.push(req_no);
+        }
+    }
+
+    pub(crate) fn take_pending_ends(&self) -> Vec<RequestNo> {
+        std::mem::take(&mut self.inner.lock().unwrap().pending_ends)
+    }
+
+    /// Returns a guard that abandons the stream request `req_no` when
+    /// dropped, so sources the application stops consuming are ended.
+    pub fn source_guard(&self, req_no: RequestNo) -> SourceGuard {
+        SourceGuard {
+            tracker: self.clone(),
+            req_no,
+        }
+    }
+
+    /// Forget our requests that got no response for longer than `timeout`,
+    /// returning their numbers so the application can fail whatever was
+    /// waiting for them. With `end_streams`, an end packet is queued for the
+    /// expired stream requests.
+    pub fn sweep(&self, timeout: Duration, end_streams: bool) -> Vec<RequestNo> {
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        let expired: Vec<RequestNo> = inner
+            .outgoing
+            .iter()
+            .filter(|(_, last)| now.saturating_duration_since(**last) > timeout)
+            .map(|(req_no, _)| *req_no)
+            .collect();
+        for req_no in expired.iter().copied() {
+            if end_streams
+                && inner.streams.contains(&req_no)
+                && !inner.end_sent.contains(&req_no)
+                && !inner.pending_ends.contains(&req_no)
+            {
+                inner.pending_ends.push(req_no);
+            }
+            inner.finish_outgoing(req_no);
+        }
+        expired
+    }
+
+    /// Run `sweep` every `interval` in a background task, calling
+    /// `on_expired` with the expired requests. The task stops when every
+    /// other clone of the tracker has been dropped.
+    pub fn spawn_sweeper<F>(
+        &self,
+        interval: Duration,
+        timeout: Duration,
+        end_streams: bool,
+        mut on_expired: F,
+    ) -> task::JoinHandle<()>
+    where
+        F: FnMut(Vec<RequestNo>) + Send + 'static,
+    {
+        let tracker = self.clone();
+        task::spawn(async move {
+            while Arc::strong_count(&tracker.inner) > 1 {
+                task::sleep(interval).await;
+                let expired = tracker.sweep(timeout, end_streams);
+                if !expired.is_empty() {
+                    on_expired(expired);
+                }
+            }
+        })
+    }
+
+    /// Registers a request from the remote peer, failing if the remote
+    /// replays a request number that has not been answered yet.
+    pub(crate) fn incoming_started(&self, req_no: RequestNo, is_stream: bool) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.incoming.insert(req_no) {
+            return Err(Error::DuplicateRequestNo(req_no));
+        }
+        if is_stream {
+            inner.incoming_streams.insert(req_no);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn incoming_finished(&self, req_no: RequestNo) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.incoming.remove(&req_no);
+        inner.incoming_streams.remove(&req_no);
+    }
+
+    /// The remote ended or cancelled one of its stream requests, which no
+    /// longer counts as open.
+    pub(crate) fn incoming_end_received(&self, req_no: RequestNo) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.incoming_streams.remove(&req_no) {
+            inner.incoming.remove(&req_no);
+        }
+    }
+
+    /// Number of source/duplex requests of the remote still open.
+    pub fn incoming_streams_len(&self) -> usize {
+        self.inner.lock().unwrap().incoming_streams.len()
+    }
+
+    /// Number of our source/duplex requests not yet completed.
+    pub fn outgoing_streams_len(&self) -> usize {
+        self.inner.lock().unwrap().streams.len()
+    }
+
+    /// Number of async requests sent and still waiting for a response.
+    pub fn pending_async_len(&self) -> usize {
+        self.inner.lock().unwrap().pending_async_len()
+    }
+
+    /// Resolves once none of our async requests is waiting for a response.
+    pub fn pending_async_done(&self) -> impl Future<Output = ()> {
+        let (sender, receiver) = oneshot::channel();
+        let mut inner = self.inner.lock().unwrap();
+        if inner.pending_async_len() == 0 {
+            let _ = sender.send(());
+        } else {
+            inner.async_waiters.push(sender);
+        }
+        async move {
+            let _ = receiver.await;
+        }
+    }
+
+    /// Our stream requests for which no end packet was sent yet.
+    pub fn open_streams(&self) -> Vec<RequestNo> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .streams
+            .iter()
+            .filter(|req_no| !inner.end_sent.contains(req_no))
+            .copied()
+            .collect()
+    }
+
+    /// Number of requests sent and not yet completed.
+    pub fn outgoing_len(&self) -> usize {
+        self.inner.lock().unwrap().outgoing.len()
+    }
+
+    /// Number of remote requests received and not yet completed.
+    pub fn incoming_len(&self) -> usize {
+        self.inner.lock().unwrap().incoming.len()
+    }
+}
+
+/// Ends a source request when dropped, see `RequestTracker::source_guard`.
+pub struct SourceGuard {
+    tracker: RequestTracker,
+    req_no: RequestNo,
+}
+
+impl SourceGuard {
+    pub fn req_no(&self) -> RequestNo {
+        self.req_no
+    }
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        self.tracker.abandon(self.req_no);
+    }
+}
+
+/// Request numbers are positive `i32`s; after `i32::MAX` start again at 1.
+pub(crate) fn next_req_no(current: RequestNo) -> RequestNo {
+    match current.checked_add(1) {
+        Some(next) if next > 0 => next,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+
+    #[test]
+    fn test_req_no_wraps() {
+        assert_eq!(next_req_no(0), 1);
+        assert_eq!(next_req_no(41), 42);
+        assert_eq!(next_req_no(RequestNo::MAX), 1);
+    }
+
+    #[test]
+    fn test_skip_in_flight() {
+        let tracker = RequestTracker::new();
+        assert_eq!(
+            tracker.next_outgoing(RequestNo::MAX - 1, false),
+            RequestNo::MAX
+        );
+        assert_eq!(tracker.next_outgoing(0, true), 1);
+        assert_eq!(tracker.next_outgoing(RequestNo::MAX - 1, false), 2);
+        tracker.outgoing_finished(1);
+        assert_eq!(tracker.next_outgoing(0, true), 1);
+        assert_eq!(tracker.outgoing_len(), 3);
+    }
+
+    #[test]
+    fn test_incoming_replay() {
+        let tracker = RequestTracker::new();
+        tracker.incoming_started(3, false).unwrap();
+        assert!(matches!(
+            tracker.incoming_started(3, true),
+            Err(Error::DuplicateRequestNo(3))
+        ));
+        tracker.incoming_finished(3);
+        tracker.incoming_started(3, false).unwrap();
+
+        // the remote ending its stream releases it
+        tracker.incoming_started(4, true).unwrap();
+        tracker.incoming_end_received(3);
+        assert_eq!(tracker.incoming_len(), 2);
+        tracker.incoming_end_received(4);
+        assert_eq!(tracker.incoming_streams_len(), 0);
+        tracker.incoming_started(4, true).unwrap();
+    }
+
+    #[test]
+    fn test_end_ack_when_remote_ends() {
+        let tracker = RequestTracker::new();
+        let req_no = tracker.next_outgoing(0, true);
+        tracker.outgoing_end_received(req_no);
+        tracker.outgoing_end_received(req_no);
+        assert_eq!(tracker.take_pending_ends(), vec![req_no]);
+        tracker.outgoing_end_sent(req_no);
+        assert_eq!(tracker.outgoing_len(), 0);
+        assert!(tracker.open_streams().is_empty());
+    }
+
+    #[test]
+    fn test_sweep() {
+        let tracker = RequestTracker::new();
+        let async_req = tracker.next_outgoing(0, false);
+        let source_req = tracker.next_outgoing(async_req, true);
+        assert!(tracker.sweep(Duration::from_secs(60), true).is_empty());
+        let async_done = tracker.pending_async_done();
+        assert!(tracker.pending_async_done().now_or_never().is_none());
+
+        std::thread::sleep(Duration::from_millis(5));
+        tracker.outgoing_response(source_req);
+        assert_eq!(
+            tracker.sweep(Duration::from_millis(3), true),
+            vec![async_req]
+        );
+        assert!(async_done.now_or_never().is_some());
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(
+            tracker.sweep(Duration::from_millis(3), true),
+            vec![source_req]
+        );
+        assert_eq!(tracker.take_pending_ends(), vec![source_req]);
+        tracker.outgoing_end_sent(source_req);
+        assert_eq!(tracker.outgoing_len(), 0);
+        assert!(tracker.open_streams().is_empty());
+    }
+
+    #[test]
+    fn test_end_sent_on_drop() {
+        let tracker = RequestTracker::new();
+        let req_no = tracker.next_outgoing(0, true);
+        drop(tracker.source_guard(req_no));
+        assert_eq!(tracker.take_pending_ends(), vec![req_no]);
+        tracker.outgoing_end_sent(req_no);
+        // a guard dropped after the end was sent does nothing
+        drop(tracker.source_guard(req_no));
+        assert!(tracker.take_pending_ends().is_empty());
+        // the remote end completes the request without a new ack
+        tracker.outgoing_end_received(req_no);
+        assert!(tracker.take_pending_ends().is_empty());
+        assert_eq!(tracker.outgoing_len(), 0);
+    }
+}
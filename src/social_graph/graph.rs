@@ -0,0 +1,139 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::feed::Message;
+
+/// What a feed says about another one in its latest contact message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Relation {
+    Follow,
+    Block,
+    /// Neither following nor blocking, e.g. after an unfollow.
+    Neutral,
+}
+
+#[derive(Debug, Default)]
+pub struct SocialGraph {
+    // author -> contact -> (relation, sequence of the contact message)
+    edges: HashMap<String, HashMap<String, (Relation, u64)>>,
+}
+
+impl SocialGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Folds a contact message into the graph, returning whether it was
+    /// one. A message only replaces the relation set by an earlier message
+    /// of the same author.
+    pub fn apply(&mut self, msg: &Message) -> bool {
+        let contact = match msg.as_contact() {
+            Some(contact) => contact,
+            None => return false,
+        };
+        let relation = if contact.blocking == Some(true) {
+            Relation::Block
+        } else if contact.following == Some(true) {
+            Relation::Follow
+        } else {
+            Relation::Neutral
+        };
+        let edges = self.edges.entry(msg.author().clone()).or_default();
+        match edges.get(&contact.contact) {
+            Some((_, sequence)) if *sequence > msg.sequence() => {}
+            _ => {
+                edges.insert(contact.contact, (relation, msg.sequence()));
+            }
+        }
+        true
+    }
+
+    pub fn relation(&self, from: &str, to: &str) -> Relation {
+        self.edges
+            .get(from)
+            .and_then(|edges| edges.get(to))
+            .map_or(Relation::Neutral, |(relation, _)| *relation)
+    }
+
+    fn with_relation(&self, from: &str, relation: Relation) -> Vec<&str> {
+        self.edges.get(from).map_or_else(Vec::new, |edges| {
+            edges
+                .iter()
+                .filter(|(_, (r, _))| *r == relation)
+                .map(|(to, _)| to.as_str())
+                .collect()
+        })
+    }
+
+    pub fn follows(&self, from: &str) -> Vec<&str> {
+        self.with_relation(from, Relation::Follow)
+    }
+
+    pub fn blocks(&self, from: &str) -> Vec<&str> {
+        self.with_relation(from, Relation::Block)
+    }
+
+    /// Distance in follows from `from` to every feed up to `max_hops`
+    /// away, `from` itself being at 0. Feeds blocked by `from` are left
+    /// out, as ssb-friends does.
+    pub fn hops(&self, from: &str, max_hops: u32) -> HashMap<String, u32> {
+        let mut hops = HashMap::new();
+        hops.insert(from.to_string(), 0);
+        let mut queue = VecDeque::from(vec![(from, 0)]);
+        while let Some((feed, distance)) = queue.pop_front() {
+            if distance == max_hops {
+                continue;
+            }
+            for followed in self.follows(feed) {
+                if hops.contains_key(followed) || self.relation(from, followed) == Relation::Block {
+                    continue;
+                }
+                hops.insert(followed.to_string(), distance + 1);
+                queue.push_back((followed, distance + 1));
+            }
+        }
+        hops
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Result, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    fn contact(prev: Option<&Message>, from: &OwnedIdentity, to: &str, following: bool) -> Message {
+        let content = json!({"type": "contact", "contact": to, "following": following});
+        Message::sign(prev, from, content).unwrap()
+    }
+
+    #[test]
+    fn test_hops() -> Result<()> {
+        let ids: Vec<_> = (0..4).map(|_| OwnedIdentity::create()).collect();
+        let (a, b, c, d) = (&ids[0], &ids[1], &ids[2], &ids[3]);
+
+        let mut graph = SocialGraph::new();
+        let a1 = contact(None, a, &b.id, true);
+        let a2 = contact(Some(&a1), a, &d.id, true);
+        let a3 = contact(Some(&a2), a, &d.id, false);
+        let b1 = contact(None, b, &c.id, true);
+        let b2 = contact(Some(&b1), b, &d.id, true);
+        // applied out of order, the unfollow still wins
+        for msg in [&a1, &a3, &a2, &b1, &b2] {
+            assert!(graph.apply(msg));
+        }
+        assert!(!graph.apply(&Message::sign(None, c, json!({"type": "post"}))?));
+
+        assert_eq!(graph.relation(&a.id, &d.id), Relation::Neutral);
+        let hops = graph.hops(&a.id, 2);
+        assert_eq!(hops[&b.id], 1);
+        assert_eq!(hops[&c.id], 2);
+        assert_eq!(hops[&d.id], 2);
+        assert_eq!(graph.hops(&a.id, 1).len(), 2);
+
+        let block = json!({"type": "contact", "contact": c.id, "blocking": true});
+        graph.apply(&Message::sign(Some(&a3), a, block)?);
+        assert_eq!(graph.blocks(&a.id), vec![c.id.as_str()]);
+        assert!(!graph.hops(&a.id, 2).contains_key(&c.id));
+        Ok(())
+    }
+}
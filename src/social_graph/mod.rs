@@ -0,0 +1,5 @@
+//! Client-side social graph built from contact messages.
+
+mod graph;
+
+pub use graph::{Relation, SocialGraph};
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use crate::{
+    api::dto::content::{Image, TypedMessage},
+    feed::Message,
+    uri::normalize_id,
+};
+
+/// Whose about messages win when resolving the name or image of a feed.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Precedence {
+    /// What the feed says about itself, then the latest set by others.
+    #[default]
+    SelfAssigned,
+    /// What `assigner` (usually the local feed) says, then what the feed
+    /// says about itself, then the latest set by others.
+    Assigner(String),
+}
+
+#[derive(Debug, Default)]
+struct Assigned {
+    // value and timestamp of the message setting it
+    name: Option<(String, f64)>,
+    image: Option<(String, f64)>,
+}
+
+fn set_latest(field: &mut Option<(String, f64)>, value: String, timestamp: f64) {
+    if !matches!(field, Some((_, current)) if *current > timestamp) {
+        *field = Some((value, timestamp));
+    }
+}
+
+/// Resolves names and images of feeds from about messages.
+#[derive(Debug, Default)]
+pub struct AboutIndex {
+    precedence: Precedence,
+    // feed -> author of the about -> assigned values
+    abouts: HashMap<String, HashMap<String, Assigned>>,
+}
+
+impl AboutIndex {
+    pub fn new(precedence: Precedence) -> Self {
+        AboutIndex {
+            precedence,
+            abouts: HashMap::new(),
+        }
+    }
+
+    /// Adds `msg` to the index, returning whether it was an about message.
+    pub fn apply(&mut self, msg: &Message) -> bool {
+        let (about, name, image) = match msg.typed_content() {
+            Some(TypedMessage::About {
+                about, name, image, ..
+            }) => (about, name, image),
+            _ => return false,
+        };
+        let assigned = self
+            .abouts
+            .entry(about)
+            .or_default()
+            .entry(msg.author().clone())
+            .or_default();
+        if let Some(name) = name {
+            set_latest(&mut assigned.name, name, msg.timestamp());
+        }
+        let image = image.map(|image| match image {
+            Image::OnlyLink(link) => link,
+            Image::Complete { link, .. } => link,
+        });
+        if let Some(image) = image {
+            set_latest(&mut assigned.image, image, msg.timestamp());
+        }
+        true
+    }
+
+    fn resolve<F>(&self, feed: &str, field: F) -> Option<&str>
+    where
+        F: Fn(&Assigned) -> &Option<(String, f64)>,
+    {
+        let abouts = self.abouts.get(feed)?;
+        let by = |author: &str| {
+            abouts
+                .get(author)
+                .and_then(|assigned| field(assigned).as_ref())
+                .map(|(value, _)| value.as_str())
+        };
+        let social = || {
+            abouts
+                .iter()
+                .filter(|(author, _)| author.as_str() != feed)
+                .filter_map(|(_, assigned)| field(assigned).as_ref())
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(value, _)| value.as_str())
+        };
+        match &self.precedence {
+            Precedence::SelfAssigned => by(feed).or_else(social),
+            Precedence::Assigner(assigner) => by(assigner).or_else(|| by(feed)).or_else(social),
+        }
+    }
+
+    pub fn name(&self, feed: &str) -> Option<&str> {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        self.resolve(feed, |assigned| &assigned.name)
+    }
+
+    /// The blob id of the image of `feed`.
+    pub fn image(&self, feed: &str) -> Option<&str> {
+        let feed = normalize_id(feed);
+        let feed = feed.as_ref();
+        self.resolve(feed, |assigned| &assigned.image)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Result, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_about_resolution() -> Result<()> {
+        let (alice, bob, carol) = (
+            OwnedIdentity::create(),
+            OwnedIdentity::create(),
+            OwnedIdentity::create(),
+        );
+        let about = |name: &str| json!({"type": "about", "about": alice.id, "name": name});
+
+        let by_bob = Message::sign_at(None, &bob, about("al"), 1.0)?;
+        let by_carol = Message::sign_at(None, &carol, about("ally"), 2.0)?;
+        let mut index = AboutIndex::new(Precedence::SelfAssigned);
+        index.apply(&by_bob);
+        index.apply(&by_carol);
+        assert_eq!(index.name(&alice.id), Some("ally"));
+
+        let image = json!({"type": "about", "about": alice.id, "image": {"link": "&img.sha256", "size": 1, "type": "image/png"}});
+        let self1 = Message::sign_at(None, &alice, about("alice"), 3.0)?;
+        let self2 = Message::sign_at(Some(&self1), &alice, image, 4.0)?;
+        index.apply(&self1);
+        index.apply(&self2);
+        assert_eq!(index.name(&alice.id), Some("alice"));
+        assert_eq!(index.image(&alice.id), Some("&img.sha256"));
+
+        let mut index = AboutIndex::new(Precedence::Assigner(bob.id.clone()));
+        for msg in [&by_bob, &by_carol, &self1] {
+            index.apply(msg);
+        }
+        assert_eq!(index.name(&alice.id), Some("al"));
+        assert_eq!(index.name(&bob.id), None);
+        Ok(())
+    }
+}
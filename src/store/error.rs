@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("expected sequence {expected} of {author}, found {actual}")]
+    OutOfOrder {
+        author: String,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("previous of {0} does not match the last stored message")]
+    PreviousMismatch(String),
+    #[error("{0}")]
+    Fork(crate::feed::Fork),
+    #[error("message {0} is already stored")]
+    DuplicateKey(String),
+    #[error("corrupt log record at offset {0}")]
+    CorruptLog(u64),
+    #[error("feed")]
+    Feed(#[from] crate::feed::Error),
+    #[error("i/o")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,242 @@
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
+
+use super::{FeedStore, Result};
+use crate::{feed::KvtMessage, uri::normalize_id};
+
+/// Wraps a `FeedStore`, maintaining indexes of its messages by author, by
+/// content type and by the ids they link to as messages are appended.
+pub struct IndexedStore<S> {
+    store: S,
+    // author -> latest sequence
+    authors: HashMap<String, u64>,
+    // content type -> keys
+    types: HashMap<String, Vec<String>>,
+    // linked id -> keys
+    links: HashMap<String, Vec<String>>,
+    // tombstoned key -> author of the tombstone
+    tombstones: HashMap<String, String>,
+    deleted: HashSet<String>,
+}
+
+/// Whether `s` looks like a feed, message or blob id.
+fn is_link(s: &str) -> bool {
+    (s.starts_with('@') && s.ends_with(".ed25519"))
+        || ((s.starts_with('%') || s.starts_with('&')) && s.ends_with(".sha256"))
+}
+
+fn collect_links<'a>(value: &'a Value, links: &mut HashSet<&'a str>) {
+    match value {
+        Value::String(s) if is_link(s) => {
+            links.insert(s);
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_links(item, links)),
+        Value::Object(entries) => entries.values().for_each(|v| collect_links(v, links)),
+        _ => {}
+    }
+}
+
+impl<S: FeedStore> IndexedStore<S> {
+    /// Wraps `store`, indexing the messages already in it.
+    pub fn new(store: S) -> Result<Self> {
+        let mut indexed = Self {
+            store,
+            authors: HashMap::new(),
+            types: HashMap::new(),
+            links: HashMap::new(),
+            tombstones: HashMap::new(),
+            deleted: HashSet::new(),
+        };
+        let mut msgs = Vec::new();
+        for author in indexed.store.authors()? {
+            msgs.extend(indexed.store.iter_feed(&author, 1)?);
+        }
+        for msg in &msgs {
+            indexed.index(msg)?;
+        }
+        Ok(indexed)
+    }
+
+    fn index(&mut self, msg: &KvtMessage) -> Result<()> {
+        let sequence = self.authors.entry(msg.value.author().clone()).or_default();
+        *sequence = (*sequence).max(msg.value.sequence());
+
+        let content = msg.value.content();
+        if let Some(Value::String(content_type)) = content.get("type") {
+            self.types
+                .entry(content_type.clone())
+                .or_default()
+                .push(msg.key.clone());
+        }
+        let mut links = HashSet::new();
+        collect_links(content, &mut links);
+        for link in links {
+            self.links
+                .entry(link.to_string())
+                .or_default()
+                .push(msg.key.clone());
+        }
+
+        // tombstones only apply to messages of their own author, which may
+        // be indexed before or after them
+        if self.tombstones.get(&msg.key) == Some(msg.value.author()) {
+            self.delete(&msg.key);
+        }
+        if let Some(tombstone) = msg.value.as_tombstone() {
+            self.tombstones
+                .insert(tombstone.target.clone(), msg.value.author().clone());
+            if let Some(target) = self.store.get_by_key(&tombstone.target)? {
+                if target.value.author() == msg.value.author() {
+                    self.delete(&tombstone.target);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks `key` as deleted, removing it from the type and link indexes.
+    pub fn delete(&mut self, key: &str) {
+        let key = normalize_id(key);
+        let key = key.as_ref();
+        self.deleted.insert(key.to_string());
+        for keys in self.types.values_mut().chain(self.links.values_mut()) {
+            keys.retain(|k| k != key);
+        }
+    }
+
+    /// Whether `key` was deleted by a tombstone of its author.
+    pub fn is_deleted(&self, key: &str) -> bool {
+        let key = normalize_id(key);
+        let key = key.as_ref();
+        self.deleted.contains(key)
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// The sequence of the latest message of `author`.
+    pub fn latest_sequence(&self, author: &str) -> Option<u64> {
+        self.authors.get(author).copied()
+    }
+
+    /// Keys of the messages with content of type `content_type`, in the
+    /// order they were indexed.
+    pub fn by_type(&self, content_type: &str) -> &[String] {
+        self.types.get(content_type).map_or(&[], |keys| &keys[..])
+    }
+
+    /// Keys of the messages whose content mentions `id`.
+    pub fn backlinks(&self, id: &str) -> &[String] {
+        self.links.get(id).map_or(&[], |keys| &keys[..])
+    }
+}
+
+impl<S: FeedStore> FeedStore for IndexedStore<S> {
+    fn append(&mut self, msg: KvtMessage) -> Result<()> {
+        self.store.append(msg.clone())?;
+        self.index(&msg)
+    }
+
+    fn get(&self, author: &str, sequence: u64) -> Result<Option<KvtMessage>> {
+        self.store.get(author, sequence)
+    }
+
+    fn get_by_key(&self, key: &str) -> Result<Option<KvtMessage>> {
+        self.store.get_by_key(key)
+    }
+
+    fn latest(&self, author: &str) -> Result<Option<KvtMessage>> {
+        self.store.latest(author)
+    }
+
+    fn iter_feed<'a>(
+        &'a self,
+        author: &str,
+        from_seq: u64,
+    ) -> Result<Box<dyn Iterator<Item = KvtMessage> + 'a>> {
+        self.store.iter_feed(author, from_seq)
+    }
+
+    fn authors(&self) -> Result<Vec<String>> {
+        self.store.authors()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        feed::{Feed, Message},
+        keystore::OwnedIdentity,
+        store::MemoryStore,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_indexes() -> Result<()> {
+        let alice = OwnedIdentity::create();
+        let bob = OwnedIdentity::create();
+        let post = Message::sign(None, &alice, json!({"type": "post", "text": "hi"}))?;
+        let reply = Message::sign(
+            None,
+            &bob,
+            json!({"type": "post", "root": post.id().to_string(), "mentions": [alice.id]}),
+        )?;
+        let follow = Message::sign(
+            Some(&reply),
+            &bob,
+            json!({"type": "contact", "contact": alice.id, "following": true}),
+        )?;
+
+        let mut store = MemoryStore::new();
+        store.append(KvtMessage::from_feed(Feed::new(post.clone()))?)?;
+        let mut store = IndexedStore::new(store)?;
+        store.append(KvtMessage::from_feed(Feed::new(reply.clone()))?)?;
+        store.append(KvtMessage::from_feed(Feed::new(follow.clone()))?)?;
+
+        assert_eq!(store.latest_sequence(&alice.id), Some(1));
+        assert_eq!(store.latest_sequence(&bob.id), Some(2));
+        assert_eq!(store.by_type("post").len(), 2);
+        assert_eq!(store.by_type("contact"), &[follow.id().to_string()]);
+        assert_eq!(
+            store.backlinks(&post.id().to_string()),
+            &[reply.id().to_string()]
+        );
+        assert_eq!(store.backlinks(&alice.id).len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tombstones() -> Result<()> {
+        let alice = OwnedIdentity::create();
+        let bob = OwnedIdentity::create();
+        let post = Message::sign(None, &alice, json!({"type": "post", "text": "oops"}))?;
+        let key = post.id().to_string();
+        let forged = Message::sign(None, &bob, json!({"type": "tombstone", "target": key}))?;
+        let tombstone = Message::sign(
+            Some(&post),
+            &alice,
+            json!({"type": "tombstone", "target": key, "reason": "typo"}),
+        )?;
+
+        let mut store = IndexedStore::new(MemoryStore::new())?;
+        store.append(KvtMessage::from_feed(Feed::new(post))?)?;
+        store.append(KvtMessage::from_feed(Feed::new(forged))?)?;
+        assert!(!store.is_deleted(&key));
+        assert_eq!(store.by_type("post"), &[key.clone()]);
+
+        store.append(KvtMessage::from_feed(Feed::new(tombstone))?)?;
+        assert!(store.is_deleted(&key));
+        assert!(store.by_type("post").is_empty());
+
+        let reindexed = IndexedStore::new(store.into_inner())?;
+        assert!(reindexed.is_deleted(&key));
+        Ok(())
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use super::{check_next, Error, FeedStore, Result};
+use crate::feed::KvtMessage;
+
+/// A `FeedStore` that keeps everything in memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    feeds: HashMap<String, Vec<KvtMessage>>,
+    // key -> (author, sequence)
+    keys: HashMap<String, (String, u64)>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FeedStore for MemoryStore {
+    fn append(&mut self, msg: KvtMessage) -> Result<()> {
+        if self.keys.contains_key(&msg.key) {
+            return Err(Error::DuplicateKey(msg.key));
+        }
+        self.check_fork(&msg)?;
+        let author = msg.value.author().clone();
+        let feed = self.feeds.entry(author.clone()).or_default();
+        check_next(feed.last(), &msg)?;
+        self.keys
+            .insert(msg.key.clone(), (author, msg.value.sequence()));
+        feed.push(msg);
+        Ok(())
+    }
+
+    fn get(&self, author: &str, sequence: u64) -> Result<Option<KvtMessage>> {
+        Ok(self
+            .feeds
+            .get(author)
+            .and_then(|feed| feed.get((sequence as usize).checked_sub(1)?))
+            .cloned())
+    }
+
+    fn get_by_key(&self, key: &str) -> Result<Option<KvtMessage>> {
+        match self.keys.get(key) {
+            Some((author, sequence)) => self.get(author, *sequence),
+            None => Ok(None),
+        }
+    }
+
+    fn latest(&self, author: &str) -> Result<Option<KvtMessage>> {
+        Ok(self.feeds.get(author).and_then(|feed| feed.last()).cloned())
+    }
+
+    fn iter_feed<'a>(
+        &'a self,
+        author: &str,
+        from_seq: u64,
+    ) -> Result<Box<dyn Iterator<Item = KvtMessage> + 'a>> {
+        let feed = self.feeds.get(author).map_or(&[][..], |feed| &feed[..]);
+        let skip = from_seq.saturating_sub(1) as usize;
+        Ok(Box::new(feed.iter().skip(skip).cloned()))
+    }
+
+    fn authors(&self) -> Result<Vec<String>> {
+        Ok(self.feeds.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        feed::{Feed, Message},
+        keystore::OwnedIdentity,
+    };
+    use serde_json::json;
+
+    fn kvt(msg: &Message) -> KvtMessage {
+        KvtMessage::from_feed(Feed::new(msg.clone())).unwrap()
+    }
+
+    #[test]
+    fn test_memory_store() -> Result<()> {
+        let id = OwnedIdentity::create();
+        let msg1 = Message::sign(None, &id, json!({"type": "post"}))?;
+        let msg2 = Message::sign(Some(&msg1), &id, json!({"type": "post"}))?;
+        let msg3 = Message::sign(Some(&msg2), &id, json!({"type": "post"}))?;
+
+        let mut store = MemoryStore::new();
+        assert!(matches!(
+            store.append(kvt(&msg2)),
+            Err(Error::OutOfOrder {
+                expected: 1,
+                actual: 2,
+                ..
+            })
+        ));
+        store.append(kvt(&msg1))?;
+        assert!(matches!(
+            store.append(kvt(&msg1)),
+            Err(Error::DuplicateKey(_))
+        ));
+        store.append(kvt(&msg2))?;
+        store.append(kvt(&msg3))?;
+        let forked = Message::sign(Some(&msg1), &id, json!({"type": "vote"}))?;
+        assert!(matches!(store.append(kvt(&forked)), Err(Error::Fork(_))));
+
+        assert_eq!(store.latest(&id.id)?.unwrap().value.sequence(), 3);
+        assert_eq!(store.get(&id.id, 2)?.unwrap().key, msg2.id().to_string());
+        assert!(store.get(&id.id, 0)?.is_none());
+        let by_key = store.get_by_key(&msg1.id().to_string())?.unwrap();
+        assert_eq!(by_key.value.sequence(), 1);
+        let seqs: Vec<u64> = store
+            .iter_feed(&id.id, 2)?
+            .map(|msg| msg.value.sequence())
+            .collect();
+        assert_eq!(seqs, vec![2, 3]);
+        assert_eq!(store.authors()?, vec![id.id]);
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+//! Storage of feeds, so replication and serving history streams can be
+//! written against any backend.
+
+mod about;
+mod error;
+mod index;
+mod memory;
+mod offset_log;
+mod votes;
+
+pub use about::{AboutIndex, Precedence};
+pub use error::{Error, Result};
+pub use index::IndexedStore;
+pub use memory::MemoryStore;
+pub use offset_log::{OffsetLog, OffsetLogIter, OffsetLogStore};
+pub use votes::{Tally, VoteIndex};
+
+use crate::feed::{detect_fork, KvtMessage};
+
+/// Messages of several feeds, each one stored in sequence order.
+pub trait FeedStore {
+    /// Store the next message of the feed of its author; it must follow the
+    /// latest stored message of the feed, or be the first one.
+    fn append(&mut self, msg: KvtMessage) -> Result<()>;
+
+    fn get(&self, author: &str, sequence: u64) -> Result<Option<KvtMessage>>;
+
+    fn get_by_key(&self, key: &str) -> Result<Option<KvtMessage>>;
+
+    /// The last stored message of the feed.
+    fn latest(&self, author: &str) -> Result<Option<KvtMessage>>;
+
+    /// The messages of the feed with sequence `from_seq` and later.
+    fn iter_feed<'a>(
+        &'a self,
+        author: &str,
+        from_seq: u64,
+    ) -> Result<Box<dyn Iterator<Item = KvtMessage> + 'a>>;
+
+    /// Authors of the stored feeds.
+    fn authors(&self) -> Result<Vec<String>>;
+
+    /// Fails with `Error::Fork` if a different message with the author and
+    /// sequence of `msg` is already stored.
+    fn check_fork(&self, msg: &KvtMessage) -> Result<()> {
+        match self.get(msg.value.author(), msg.value.sequence())? {
+            Some(stored) => match detect_fork(&stored.value, &msg.value) {
+                Some(fork) => Err(Error::Fork(fork)),
+                None => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+/// Checks that `msg` can be appended after `latest`.
+pub(crate) fn check_next(latest: Option<&KvtMessage>, msg: &KvtMessage) -> Result<()> {
+    let expected = latest.map_or(1, |latest| latest.value.sequence() + 1);
+    if msg.value.sequence() != expected {
+        return Err(Error::OutOfOrder {
+            author: msg.value.author().clone(),
+            expected,
+            actual: msg.value.sequence(),
+        });
+    }
+    if msg.value.previous() != latest.map(|latest| &latest.key) {
+        return Err(Error::PreviousMismatch(msg.key.clone()));
+    }
+    Ok(())
+}
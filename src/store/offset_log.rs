@@ -0,0 +1,258 @@
+//! The legacy flume `log.offset` format, as written by ssb-server.
+//!
+//! Each record is `[length][data][length][end]`, all numbers being 32 bit
+//! big endian, where `end` is the file position after the record. Records
+//! are addressed by the position of their first byte, and deleted records
+//! are overwritten with zeros.
+
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use super::{check_next, Error, FeedStore, Result};
+use crate::feed::KvtMessage;
+
+/// Size of the framing around the data of a record.
+const FRAME_LEN: u64 = 12;
+
+pub struct OffsetLog {
+    file: File,
+    len: u64,
+}
+
+impl OffsetLog {
+    /// Opens the log at `path`, creating it if it does not exist.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let len = file.metadata()?.len();
+        Ok(Self { file, len })
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends a record, returning its offset.
+    pub fn append(&mut self, data: &[u8]) -> Result<u64> {
+        let offset = self.len;
+        let end = offset + FRAME_LEN + data.len() as u64;
+        if end > u32::MAX as u64 {
+            return Err(Error::CorruptLog(offset));
+        }
+        let mut record = Vec::with_capacity((FRAME_LEN as usize) + data.len());
+        record.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        record.extend_from_slice(data);
+        record.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        record.extend_from_slice(&(end as u32).to_be_bytes());
+        self.file.write_all(&record)?;
+        self.len = end;
+        Ok(offset)
+    }
+
+    /// Reads the record at `offset`, returning its data and the offset of
+    /// the next record.
+    pub fn read_at(&self, offset: u64) -> Result<(Vec<u8>, u64)> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header)?;
+        let len = u32::from_be_bytes(header) as u64;
+        let next = offset + FRAME_LEN + len;
+        if next > self.len {
+            return Err(Error::CorruptLog(offset));
+        }
+        let mut data = vec![0u8; len as usize];
+        file.read_exact(&mut data)?;
+        let mut trailer = [0u8; 8];
+        file.read_exact(&mut trailer)?;
+        if trailer[..4] != header || trailer[4..] != (next as u32).to_be_bytes() {
+            return Err(Error::CorruptLog(offset));
+        }
+        Ok((data, next))
+    }
+
+    /// Iterates the `(offset, data)` of the records, skipping deleted ones.
+    pub fn iter(&self) -> OffsetLogIter<'_> {
+        OffsetLogIter {
+            log: self,
+            offset: 0,
+        }
+    }
+}
+
+pub struct OffsetLogIter<'a> {
+    log: &'a OffsetLog,
+    offset: u64,
+}
+
+impl<'a> Iterator for OffsetLogIter<'a> {
+    type Item = Result<(u64, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.offset < self.log.len {
+            let offset = self.offset;
+            match self.log.read_at(offset) {
+                Ok((data, next)) => {
+                    self.offset = next;
+                    if data.iter().all(|b| *b == 0) {
+                        continue;
+                    }
+                    return Some(Ok((offset, data)));
+                }
+                Err(err) => {
+                    // stop on the first broken record
+                    self.offset = self.log.len;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// A `FeedStore` over an `OffsetLog` of json `{key, value, timestamp}`
+/// records; only the offsets of the messages are kept in memory.
+pub struct OffsetLogStore {
+    log: OffsetLog,
+    feeds: HashMap<String, Vec<u64>>,
+    keys: HashMap<String, u64>,
+}
+
+impl OffsetLogStore {
+    /// Opens the log and indexes the messages in it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut store = Self {
+            log: OffsetLog::open(path)?,
+            feeds: HashMap::new(),
+            keys: HashMap::new(),
+        };
+        let mut records = Vec::new();
+        for record in store.log.iter() {
+            let (offset, data) = record?;
+            records.push((offset, KvtMessage::from_slice(&data)?));
+        }
+        for (offset, msg) in records {
+            store.index(offset, &msg);
+        }
+        Ok(store)
+    }
+
+    fn index(&mut self, offset: u64, msg: &KvtMessage) {
+        self.keys.insert(msg.key.clone(), offset);
+        self.feeds
+            .entry(msg.value.author().clone())
+            .or_default()
+            .push(offset);
+    }
+
+    fn read(&self, offset: u64) -> Result<KvtMessage> {
+        let (data, _) = self.log.read_at(offset)?;
+        Ok(KvtMessage::from_slice(&data)?)
+    }
+
+    fn offset(&self, author: &str, sequence: u64) -> Option<u64> {
+        let feed = self.feeds.get(author)?;
+        feed.get((sequence as usize).checked_sub(1)?).copied()
+    }
+}
+
+impl FeedStore for OffsetLogStore {
+    fn append(&mut self, msg: KvtMessage) -> Result<()> {
+        if self.keys.contains_key(&msg.key) {
+            return Err(Error::DuplicateKey(msg.key));
+        }
+        self.check_fork(&msg)?;
+        let latest = self.latest(msg.value.author())?;
+        check_next(latest.as_ref(), &msg)?;
+        let data = msg.clone().into_feed().to_string();
+        let offset = self.log.append(data.as_bytes())?;
+        self.index(offset, &msg);
+        Ok(())
+    }
+
+    fn get(&self, author: &str, sequence: u64) -> Result<Option<KvtMessage>> {
+        self.offset(author, sequence)
+            .map(|offset| self.read(offset))
+            .transpose()
+    }
+
+    fn get_by_key(&self, key: &str) -> Result<Option<KvtMessage>> {
+        self.keys
+            .get(key)
+            .map(|offset| self.read(*offset))
+            .transpose()
+    }
+
+    fn latest(&self, author: &str) -> Result<Option<KvtMessage>> {
+        self.feeds
+            .get(author)
+            .and_then(|feed| feed.last())
+            .map(|offset| self.read(*offset))
+            .transpose()
+    }
+
+    fn iter_feed<'a>(
+        &'a self,
+        author: &str,
+        from_seq: u64,
+    ) -> Result<Box<dyn Iterator<Item = KvtMessage> + 'a>> {
+        let offsets = self.feeds.get(author).map_or(&[][..], |feed| &feed[..]);
+        let skip = from_seq.saturating_sub(1) as usize;
+        let msgs = offsets
+            .iter()
+            .skip(skip)
+            .map(|offset| self.read(*offset))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Box::new(msgs.into_iter()))
+    }
+
+    fn authors(&self) -> Result<Vec<String>> {
+        Ok(self.feeds.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        feed::{Feed, Message},
+        keystore::OwnedIdentity,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn test_offset_log_store() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("kuska-log-{}.offset", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let id = OwnedIdentity::create();
+        let msg1 = Message::sign(None, &id, json!({"type": "post"}))?;
+        let msg2 = Message::sign(Some(&msg1), &id, json!({"type": "post"}))?;
+        {
+            let mut store = OffsetLogStore::open(&path)?;
+            store.append(KvtMessage::from_feed(Feed::new(msg1.clone()))?)?;
+            store.append(KvtMessage::from_feed(Feed::new(msg2.clone()))?)?;
+        }
+
+        let store = OffsetLogStore::open(&path)?;
+        assert_eq!(store.latest(&id.id)?.unwrap().key, msg2.id().to_string());
+        assert_eq!(store.get(&id.id, 1)?.unwrap().key, msg1.id().to_string());
+        let offsets: Vec<u64> = store.log.iter().map(|r| r.unwrap().0).collect();
+        assert_eq!(offsets[0], 0);
+        assert_eq!(store.log.read_at(0)?.1, offsets[1]);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use crate::{api::dto::content::TypedMessage, feed::Message, uri::normalize_id};
+
+#[derive(Debug)]
+struct VoteEntry {
+    positive: bool,
+    expression: Option<String>,
+    timestamp: f64,
+    sequence: u64,
+}
+
+/// The votes of a message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tally {
+    pub count: usize,
+    /// Authors of the positive votes.
+    pub voters: Vec<String>,
+    /// Expression of the most recent positive vote, e.g. `Like`.
+    pub latest_expression: Option<String>,
+}
+
+/// Aggregates vote messages per voted message; only the latest vote of
+/// each author counts.
+#[derive(Debug, Default)]
+pub struct VoteIndex {
+    // target -> voter -> vote
+    votes: HashMap<String, HashMap<String, VoteEntry>>,
+}
+
+impl VoteIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `msg` to the index, returning whether it was a vote.
+    pub fn apply(&mut self, msg: &Message) -> bool {
+        let vote = match msg.typed_content() {
+            Some(TypedMessage::Vote { vote }) => vote,
+            _ => return false,
+        };
+        let voters = self.votes.entry(vote.link).or_default();
+        match voters.get(msg.author()) {
+            Some(entry) if entry.sequence > msg.sequence() => {}
+            _ => {
+                let entry = VoteEntry {
+                    positive: vote.value.is_positive(),
+                    expression: vote.expression,
+                    timestamp: msg.timestamp(),
+                    sequence: msg.sequence(),
+                };
+                voters.insert(msg.author().clone(), entry);
+            }
+        }
+        true
+    }
+
+    pub fn tally(&self, target: &str) -> Tally {
+        let target = normalize_id(target);
+        let target = target.as_ref();
+        let mut positive: Vec<_> = self
+            .votes
+            .get(target)
+            .into_iter()
+            .flatten()
+            .filter(|(_, entry)| entry.positive)
+            .collect();
+        positive.sort_by(|(_, a), (_, b)| a.timestamp.total_cmp(&b.timestamp));
+        Tally {
+            count: positive.len(),
+            latest_expression: positive
+                .last()
+                .and_then(|(_, entry)| entry.expression.clone()),
+            voters: positive
+                .into_iter()
+                .map(|(voter, _)| voter.clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{feed::Result, keystore::OwnedIdentity};
+    use serde_json::json;
+
+    #[test]
+    fn test_vote_tally() -> Result<()> {
+        let (alice, bob) = (OwnedIdentity::create(), OwnedIdentity::create());
+        let post = Message::sign_at(None, &alice, json!({"type": "post", "text": "hi"}), 0.0)?;
+        let target = post.id().to_string();
+        let vote = |value: i64, expression: &str| json!({"type": "vote", "vote": {"link": target, "value": value, "expression": expression}});
+
+        let mut index = VoteIndex::new();
+        let like = Message::sign_at(Some(&post), &alice, vote(1, "Like"), 1.0)?;
+        let bob_like = Message::sign_at(None, &bob, vote(1, "Yup"), 2.0)?;
+        let unlike = Message::sign_at(Some(&bob_like), &bob, vote(0, "Unlike"), 3.0)?;
+        assert!(index.apply(&like));
+        assert!(index.apply(&bob_like));
+        assert!(!index.apply(&post));
+        assert_eq!(
+            index.tally(&target).latest_expression.as_deref(),
+            Some("Yup")
+        );
+
+        assert!(index.apply(&unlike));
+        assert!(index.apply(&bob_like));
+        let tally = index.tally(&target);
+        assert_eq!(tally.count, 1);
+        assert_eq!(tally.voters, vec![alice.id.clone()]);
+        assert_eq!(tally.latest_expression.as_deref(), Some("Like"));
+        assert_eq!(index.tally("%unknown").count, 0);
+        Ok(())
+    }
+}
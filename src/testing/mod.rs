@@ -0,0 +1,183 @@
+//! `Arbitrary` implementations for the protocol types, to generate
+//! structured inputs in property tests and fuzz targets. Enabled by the
+//! `testing` feature.
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+use serde_json::{Map, Number, Value};
+
+use crate::{
+    api::dto::{BlobsGetIn, CreateHistoryStreamIn, LatestOut, WhoAmIOut},
+    feed::Message,
+    keystore::OwnedIdentity,
+    rpc::{Body, BodyType, Header, RpcErrorBody, RpcType},
+};
+
+const MAX_DEPTH: usize = 3;
+
+/// An arbitrary json value nested at most `depth` levels.
+pub fn json_value(u: &mut Unstructured, depth: usize) -> Result<Value> {
+    let kinds = if depth == 0 { 4 } else { 6 };
+    Ok(match u.int_in_range(0..=kinds - 1)? {
+        0 => Value::Null,
+        1 => Value::Bool(u.arbitrary()?),
+        2 => Value::Number(
+            Number::from_f64(u.arbitrary::<f64>()?).unwrap_or_else(|| Number::from(0)),
+        ),
+        3 => Value::String(u.arbitrary()?),
+        4 => Value::Array(
+            (0..u.int_in_range(0..=4)?)
+                .map(|_| json_value(u, depth - 1))
+                .collect::<Result<_>>()?,
+        ),
+        _ => Value::Object(json_object(u, depth - 1)?),
+    })
+}
+
+fn json_object(u: &mut Unstructured, depth: usize) -> Result<Map<String, Value>> {
+    (0..u.int_in_range(0..=4)?)
+        .map(|_| Ok((u.arbitrary()?, json_value(u, depth)?)))
+        .collect()
+}
+
+/// A string of `min..=max` lowercase letters.
+fn word(u: &mut Unstructured, min: usize, max: usize) -> Result<String> {
+    (0..u.int_in_range(min..=max)?)
+        .map(|_| Ok(char::from(u.int_in_range(b'a'..=b'z')?)))
+        .collect()
+}
+
+impl<'a> Arbitrary<'a> for OwnedIdentity {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(OwnedIdentity::from_seed(&u.arbitrary()?))
+    }
+}
+
+/// The first message of a feed, validly signed, with an object content
+/// of a valid type.
+impl<'a> Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let identity: OwnedIdentity = u.arbitrary()?;
+        let mut content = json_object(u, MAX_DEPTH - 1)?;
+        content.insert("type".to_string(), Value::String(word(u, 3, 52)?));
+        let timestamp = u.int_in_range(0..=u32::MAX as u64)? as f64;
+        Message::sign_at(None, &identity, Value::Object(content), timestamp)
+            .map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
+impl<'a> Arbitrary<'a> for RpcType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[
+            RpcType::Async,
+            RpcType::Source,
+            RpcType::Duplex,
+            RpcType::Sync,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for BodyType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(*u.choose(&[BodyType::Binary, BodyType::UTF8, BodyType::JSON])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for Header {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Header {
+            req_no: u.arbitrary()?,
+            is_stream: u.arbitrary()?,
+            is_end_or_error: u.arbitrary()?,
+            body_type: u.arbitrary()?,
+            body_len: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Body {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Body {
+            name: (0..u.int_in_range(1..=3)?)
+                .map(|_| word(u, 1, 16))
+                .collect::<Result<_>>()?,
+            rpc_type: u.arbitrary()?,
+            args: Value::Array(vec![json_value(u, MAX_DEPTH)?]),
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for RpcErrorBody {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(
+            RpcErrorBody::with_name(word(u, 1, 16)?, u.arbitrary::<String>()?)
+                .stack(u.arbitrary::<String>()?),
+        )
+    }
+}
+
+impl<'a> Arbitrary<'a> for CreateHistoryStreamIn {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let identity: OwnedIdentity = u.arbitrary()?;
+        Ok(CreateHistoryStreamIn {
+            id: identity.id,
+            seq: u.arbitrary()?,
+            live: u.arbitrary()?,
+            keys: u.arbitrary()?,
+            values: u.arbitrary()?,
+            limit: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for BlobsGetIn {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let hash: [u8; 32] = u.arbitrary()?;
+        Ok(BlobsGetIn {
+            key: format!("&{}.sha256", base64::encode(&hash)),
+            size: u.arbitrary()?,
+            max: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for LatestOut {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let identity: OwnedIdentity = u.arbitrary()?;
+        Ok(LatestOut {
+            id: identity.id,
+            sequence: u.arbitrary()?,
+            ts: u.int_in_range(0..=u32::MAX as u64)? as f64,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for WhoAmIOut {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let identity: OwnedIdentity = u.arbitrary()?;
+        Ok(WhoAmIOut { id: identity.id })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_arbitrary_round_trips() -> Result<()> {
+        let seed: Vec<u8> = (0..4096u32).map(|n| (n * 7919 % 251) as u8).collect();
+        let mut u = Unstructured::new(&seed);
+        for _ in 0..8 {
+            let msg: Message = u.arbitrary()?;
+            assert!(Message::from_slice(&msg.as_received_bytes()).is_ok());
+
+            let header: Header = u.arbitrary()?;
+            assert_eq!(Header::from_slice(&header.to_array()).unwrap(), header);
+
+            let body: Body = u.arbitrary()?;
+            let encoded = serde_json::to_vec(&body).unwrap();
+            let decoded: Body = serde_json::from_slice(&encoded).unwrap();
+            assert_eq!(decoded.name, body.name);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,17 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("not an ssb uri: {0}")]
+    InvalidUri(String),
+    #[error("unknown format {0}")]
+    UnknownFormat(String),
+    #[error("invalid sigil id: {0}")]
+    InvalidSigil(String),
+    #[error("not a {expected} id: {id}")]
+    UnexpectedKind { expected: &'static str, id: String },
+    #[error("base64 decoding")]
+    Base64Decode(#[from] base64::DecodeError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
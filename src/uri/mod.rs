@@ -0,0 +1,347 @@
+//! `ssb:` URIs, as parsed and produced by ssb-uri2, and their conversion to
+//! and from sigil ids.
+
+mod error;
+mod refs;
+
+use std::{fmt, str::FromStr};
+
+pub use error::{Error, Result};
+pub use refs::{normalize_id, BlobRef, FeedRef, MsgRef};
+
+/// `ssb:experimental?action=consume-alias&...`, claiming a room alias.
+pub const ACTION_CONSUME_ALIAS: &str = "consume-alias";
+/// `ssb:experimental?action=start-http-auth&...`, signing in to a web page.
+pub const ACTION_START_HTTP_AUTH: &str = "start-http-auth";
+/// `ssb:experimental?action=claim-http-invite&...`, joining a room with an
+/// invite.
+pub const ACTION_CLAIM_HTTP_INVITE: &str = "claim-http-invite";
+/// `ssb:experimental?action=join-room&...`, joining an open room.
+pub const ACTION_JOIN_ROOM: &str = "join-room";
+
+/// Format of a feed or message, the second segment of the uri path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Classic,
+    BendyButtV1,
+    GabbyGroveV1,
+    ButtwooV1,
+}
+
+impl Format {
+    fn as_str(self) -> &'static str {
+        match self {
+            Format::Classic => "classic",
+            Format::BendyButtV1 => "bendybutt-v1",
+            Format::GabbyGroveV1 => "gabbygrove-v1",
+            Format::ButtwooV1 => "buttwoo-v1",
+        }
+    }
+
+    fn parse(format: &str) -> Result<Self> {
+        match format {
+            // `ed25519` and `sha256` are the names used by the first ssb-uri
+            "classic" | "ed25519" | "sha256" => Ok(Format::Classic),
+            "bendybutt-v1" => Ok(Format::BendyButtV1),
+            "gabbygrove-v1" => Ok(Format::GabbyGroveV1),
+            "buttwoo-v1" => Ok(Format::ButtwooV1),
+            _ => Err(Error::UnknownFormat(format.to_string())),
+        }
+    }
+
+    fn feed_suffix(self) -> &'static str {
+        match self {
+            Format::Classic => ".ed25519",
+            Format::BendyButtV1 => ".bbfeed-v1",
+            Format::GabbyGroveV1 => ".ggfeed-v1",
+            Format::ButtwooV1 => ".buttwoo-v1",
+        }
+    }
+
+    fn message_suffix(self) -> &'static str {
+        match self {
+            Format::Classic => ".sha256",
+            Format::BendyButtV1 => ".bbmsg-v1",
+            Format::GabbyGroveV1 => ".ggmsg-v1",
+            Format::ButtwooV1 => ".buttwoo-v1",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SsbUri {
+    /// `data` is the standard base64 of the key.
+    Feed {
+        format: Format,
+        data: String,
+    },
+    /// `data` is the standard base64 of the hash.
+    Message {
+        format: Format,
+        data: String,
+    },
+    Blob {
+        data: String,
+    },
+    /// `ssb:address/multiserver?multiserverAddress=...`
+    Address {
+        multiserver_address: String,
+    },
+    /// `ssb:experimental?action=...`, with the rest of the query.
+    Experimental {
+        action: String,
+        params: Vec<(String, String)>,
+    },
+}
+
+fn to_url_base64(data: &str) -> String {
+    data.replace('+', "-").replace('/', "_")
+}
+
+fn from_url_base64(data: &str) -> Result<String> {
+    let data = percent_decode(data)?.replace('-', "+").replace('_', "/");
+    base64::decode(&data)?;
+    Ok(data)
+}
+
+/// Percent-encodes like `encodeURIComponent`.
+pub(crate) fn percent_encode(s: &str) -> String {
+    let mut encoded = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'_'
+            | b'.'
+            | b'!'
+            | b'~'
+            | b'*'
+            | b'\''
+            | b'('
+            | b')' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let invalid = || Error::InvalidUri(s.to_string());
+    let mut bytes = Vec::new();
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail.get(..2).ok_or_else(invalid)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| invalid())?;
+            bytes.push(u8::from_str_radix(hex, 16).map_err(|_| invalid())?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+pub(crate) fn parse_query(query: &str) -> Result<Vec<(String, String)>> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+impl SsbUri {
+    /// The uri of a sigil id: `@...` feed, `%...` message or `&...` blob.
+    pub fn from_sigil(id: &str) -> Result<Self> {
+        let invalid = || Error::InvalidSigil(id.to_string());
+        let (data, suffix) = id
+            .get(1..)
+            .and_then(|rest| rest.rsplit_once('.'))
+            .ok_or_else(invalid)?;
+        base64::decode(data)?;
+        let data = data.to_string();
+        let suffix = format!(".{}", suffix);
+        let formats = [
+            Format::Classic,
+            Format::BendyButtV1,
+            Format::GabbyGroveV1,
+            Format::ButtwooV1,
+        ];
+        match &id[..1] {
+            "@" => formats
+                .into_iter()
+                .find(|format| format.feed_suffix() == suffix)
+                .map(|format| SsbUri::Feed { format, data })
+                .ok_or_else(invalid),
+            "%" => formats
+                .into_iter()
+                .find(|format| format.message_suffix() == suffix)
+                .map(|format| SsbUri::Message { format, data })
+                .ok_or_else(invalid),
+            "&" if suffix == ".sha256" => Ok(SsbUri::Blob { data }),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// The sigil id of a feed, message or blob uri.
+    pub fn to_sigil(&self) -> Option<String> {
+        match self {
+            SsbUri::Feed { format, data } => Some(format!("@{}{}", data, format.feed_suffix())),
+            SsbUri::Message { format, data } => {
+                Some(format!("%{}{}", data, format.message_suffix()))
+            }
+            SsbUri::Blob { data } => Some(format!("&{}.sha256", data)),
+            _ => None,
+        }
+    }
+
+    /// A query parameter of an experimental uri.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        match self {
+            SsbUri::Experimental { params, .. } => params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.as_str()),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for SsbUri {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<Self> {
+        let invalid = || Error::InvalidUri(uri.to_string());
+        let rest = uri
+            .strip_prefix("ssb:")
+            .ok_or_else(invalid)?
+            .trim_start_matches("//");
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let segments: Vec<_> = path.split('/').collect();
+        match segments.as_slice() {
+            ["feed", format, data] => Ok(SsbUri::Feed {
+                format: Format::parse(format)?,
+                data: from_url_base64(data)?,
+            }),
+            ["message", format, data] => Ok(SsbUri::Message {
+                format: Format::parse(format)?,
+                data: from_url_base64(data)?,
+            }),
+            ["blob", "classic" | "sha256", data] => Ok(SsbUri::Blob {
+                data: from_url_base64(data)?,
+            }),
+            ["address", "multiserver"] => parse_query(query)?
+                .into_iter()
+                .find(|(key, _)| key == "multiserverAddress")
+                .map(|(_, multiserver_address)| SsbUri::Address {
+                    multiserver_address,
+                })
+                .ok_or_else(invalid),
+            ["experimental"] => {
+                let mut params = parse_query(query)?;
+                let action = params
+                    .iter()
+                    .position(|(key, _)| key == "action")
+                    .ok_or_else(invalid)?;
+                let (_, action) = params.remove(action);
+                Ok(SsbUri::Experimental { action, params })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for SsbUri {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SsbUri::Feed { format, data } => {
+                write!(f, "ssb:feed/{}/{}", format.as_str(), to_url_base64(data))
+            }
+            SsbUri::Message { format, data } => {
+                write!(f, "ssb:message/{}/{}", format.as_str(), to_url_base64(data))
+            }
+            SsbUri::Blob { data } => write!(f, "ssb:blob/classic/{}", to_url_base64(data)),
+            SsbUri::Address {
+                multiserver_address,
+            } => write!(
+                f,
+                "ssb:address/multiserver?multiserverAddress={}",
+                percent_encode(multiserver_address)
+            ),
+            SsbUri::Experimental { action, params } => {
+                write!(f, "ssb:experimental?action={}", percent_encode(action))?;
+                for (key, value) in params {
+                    write!(f, "&{}={}", percent_encode(key), percent_encode(value))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sigil_uris() -> Result<()> {
+        let feed = "@+oaWWDs8g73EZFUMfW37R/ULtFEjwKN/DczvdYlwI2E=.ed25519";
+        let uri = SsbUri::from_sigil(feed)?;
+        assert_eq!(
+            uri.to_string(),
+            "ssb:feed/classic/-oaWWDs8g73EZFUMfW37R_ULtFEjwKN_DczvdYlwI2E="
+        );
+        assert_eq!(uri.to_string().parse::<SsbUri>()?, uri);
+        assert_eq!(uri.to_sigil().as_deref(), Some(feed));
+
+        // the names of the first ssb-uri are still accepted
+        let msg: SsbUri =
+            "ssb:message/sha256/g3hPVPDEO1Aj_uPl0-J2NlhFB2bbFLIHlty-YuqFZ3w=".parse()?;
+        assert_eq!(
+            msg.to_sigil().as_deref(),
+            Some("%g3hPVPDEO1Aj/uPl0+J2NlhFB2bbFLIHlty+YuqFZ3w=.sha256")
+        );
+
+        let bendybutt = "@6CAxOI3f+LUOVrbAl0IemqiS7ATpQvr9Mdw9LC4+Uv0=.bbfeed-v1";
+        let uri = SsbUri::from_sigil(bendybutt)?;
+        assert!(matches!(
+            uri,
+            SsbUri::Feed {
+                format: Format::BendyButtV1,
+                ..
+            }
+        ));
+        assert_eq!(uri.to_sigil().as_deref(), Some(bendybutt));
+
+        assert!(SsbUri::from_sigil("@abc.rsa").is_err());
+        assert!("ssb:feed/rsa/AAAA".parse::<SsbUri>().is_err());
+        assert!("http:feed/classic/AAAA".parse::<SsbUri>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_uris() -> Result<()> {
+        let address = "net:192.168.1.2:8008~shs:+oaWWDs8g73EZFUMfW37R/ULtFEjwKN/DczvdYlwI2E=";
+        let uri = SsbUri::Address {
+            multiserver_address: address.to_string(),
+        };
+        assert_eq!(uri.to_string().parse::<SsbUri>()?, uri);
+
+        let uri: SsbUri =
+            "ssb:experimental?action=start-http-auth&sid=%40abc%3D.ed25519&sc=xyz".parse()?;
+        assert!(
+            matches!(&uri, SsbUri::Experimental { action, .. } if action == ACTION_START_HTTP_AUTH)
+        );
+        assert_eq!(uri.param("sid"), Some("@abc=.ed25519"));
+        assert_eq!(uri.param("sc"), Some("xyz"));
+        assert_eq!(uri.to_string().parse::<SsbUri>()?, uri);
+        assert!("ssb:experimental?sid=abc".parse::<SsbUri>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+//! Feed, message and blob ids that parse from either their sigil form or
+//! their `ssb:` uri, and are always kept and printed as sigils.
+
+use std::{borrow::Cow, convert::TryFrom, fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    error::{Error, Result},
+    SsbUri,
+};
+
+macro_rules! ssb_ref {
+    ($(#[$doc:meta])* $name:ident, $variant:ident, $kind:literal) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(String);
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            pub fn to_uri(&self) -> SsbUri {
+                // checked when parsed
+                SsbUri::from_sigil(&self.0).unwrap()
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                let uri = if s.starts_with("ssb:") {
+                    s.parse()?
+                } else {
+                    SsbUri::from_sigil(s)?
+                };
+                match uri {
+                    SsbUri::$variant { .. } => Ok($name(uri.to_sigil().unwrap())),
+                    _ => Err(Error::UnexpectedKind {
+                        expected: $kind,
+                        id: s.to_string(),
+                    }),
+                }
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = Error;
+
+            fn try_from(s: String) -> Result<Self> {
+                s.parse()
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(id: $name) -> String {
+                id.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+    };
+}
+
+/// `id` as a sigil if it is the uri of a feed, message or blob; as is
+/// otherwise. For lookups, where an unknown id is not an error.
+pub fn normalize_id(id: &str) -> Cow<'_, str> {
+    if id.starts_with("ssb:") {
+        if let Some(sigil) = id.parse::<SsbUri>().ok().and_then(|uri| uri.to_sigil()) {
+            return Cow::Owned(sigil);
+        }
+    }
+    Cow::Borrowed(id)
+}
+
+ssb_ref!(
+    /// `@...` feed id, from a sigil or a `ssb:feed/...` uri.
+    FeedRef,
+    Feed,
+    "feed"
+);
+ssb_ref!(
+    /// `%...` message id, from a sigil or a `ssb:message/...` uri.
+    MsgRef,
+    Message,
+    "message"
+);
+ssb_ref!(
+    /// `&...` blob id, from a sigil or a `ssb:blob/...` uri.
+    BlobRef,
+    Blob,
+    "blob"
+);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_refs() -> Result<()> {
+        let sigil = "@+oaWWDs8g73EZFUMfW37R/ULtFEjwKN/DczvdYlwI2E=.ed25519";
+        let uri = "ssb:feed/classic/-oaWWDs8g73EZFUMfW37R_ULtFEjwKN_DczvdYlwI2E=";
+        let feed: FeedRef = uri.parse()?;
+        assert_eq!(feed, sigil.parse()?);
+        assert_eq!(feed.to_string(), sigil);
+        assert_eq!(feed.to_uri().to_string(), uri);
+        assert_eq!(
+            serde_json::to_string(&feed).unwrap(),
+            format!("\"{}\"", sigil)
+        );
+        assert!(uri.parse::<MsgRef>().is_err());
+        assert!("@nope".parse::<FeedRef>().is_err());
+        assert_eq!(normalize_id(uri), sigil);
+        assert_eq!(normalize_id("ssb:feed/nope"), "ssb:feed/nope");
+        Ok(())
+    }
+}